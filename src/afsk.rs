@@ -0,0 +1,79 @@
+use std::collections::VecDeque;
+use std::{f32::consts::PI, ops::Rem};
+
+/// Non-coherent FSK demodulator, in the same streaming style as `V21RX`:
+/// the input is correlated against both tones over the last symbol, and
+/// each output sample tells which of them is stronger.
+pub struct AfskRX {
+    sampling_period: f32,
+    omega_mark: f32,
+    omega_space: f32,
+    mark_phase: f32,
+    space_phase: f32,
+    /// In-phase and quadrature products for the mark and space tones
+    window: VecDeque<[f32; 4]>,
+    window_len: usize,
+    sums: [f64; 4],
+}
+
+impl AfskRX {
+    pub fn new(
+        sampling_period: f32,
+        samples_per_symbol: usize,
+        omega_mark: f32,
+        omega_space: f32,
+    ) -> Self {
+        Self {
+            sampling_period,
+            omega_mark,
+            omega_space,
+            mark_phase: 0.,
+            space_phase: 0.,
+            window: VecDeque::with_capacity(samples_per_symbol + 1),
+            window_len: samples_per_symbol,
+            sums: [0.; 4],
+        }
+    }
+
+    /// Switches to other tones, discarding the correlations of the last
+    /// symbol.
+    pub fn set_omegas(&mut self, omega_mark: f32, omega_space: f32) {
+        *self = Self::new(
+            self.sampling_period,
+            self.window_len,
+            omega_mark,
+            omega_space,
+        );
+    }
+
+    pub fn demodulate(&mut self, in_samples: &[f32], out_samples: &mut [u8]) {
+        debug_assert!(in_samples.len() == out_samples.len());
+
+        for (sample, out) in in_samples.iter().zip(out_samples.iter_mut()) {
+            let products = [
+                sample * self.mark_phase.cos(),
+                sample * self.mark_phase.sin(),
+                sample * self.space_phase.cos(),
+                sample * self.space_phase.sin(),
+            ];
+            self.mark_phase =
+                (self.mark_phase + self.sampling_period * self.omega_mark).rem(2. * PI);
+            self.space_phase =
+                (self.space_phase + self.sampling_period * self.omega_space).rem(2. * PI);
+
+            for (sum, product) in self.sums.iter_mut().zip(products) {
+                *sum += product as f64;
+            }
+            self.window.push_back(products);
+            if self.window.len() > self.window_len {
+                let old = self.window.pop_front().unwrap();
+                for (sum, product) in self.sums.iter_mut().zip(old) {
+                    *sum -= product as f64;
+                }
+            }
+
+            let [mi, mq, si, sq] = self.sums;
+            *out = (mi * mi + mq * mq > si * si + sq * sq) as u8;
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::iter::Peekable;
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Echo(bool),
+    Quiet(bool),
+    Verbose(bool),
+    Reset,
+    Online,
+    /// `D` with the rest of the line: the digits, optionally after `T` or `P`
+    /// for tone or pulse dialing, and a final `;` to stay in command mode
+    Dial(String),
+    /// `A`, answer without waiting for the rings counted by `S0`
+    Answer,
+    /// `H` or `H0`
+    HangUp,
+    /// `&Kn`, 0 for no flow control, 3 for RTS/CTS and 4 for XON/XOFF
+    FlowControl(u8),
+    /// `\Nn`, 0 for no error control, 2 for MNP only, 3 for V.42 with a
+    /// fallback to MNP then plain async and 4 for V.42 only
+    ErrorControl(u8),
+    /// `Sn=v`
+    SetRegister(u8, u8),
+    /// `Sn?`
+    QueryRegister(u8),
+    Modulation(String),
+    QueryModulation,
+    /// `+FCLASS=n`, 0 for data and 1 for fax class 1
+    FaxClass(u8),
+    QueryFaxClass,
+    FaxClassRange,
+    /// `+FTH`, `+FRH`, `+FTM` or `+FRM` with the modulation to use
+    Fax(FaxCommand, u8),
+    /// The same commands followed by `=?`, which list the modulations
+    FaxRange(FaxCommand),
+    /// `+FTS=n`, stop transmitting and wait n times 10 ms
+    FaxSilence(u8),
+    /// `+FRS=n`, wait for n times 10 ms of silence
+    FaxWaitSilence(u8),
+    /// `+VCID=n`, 0 to disable the caller ID report, 1 to format it and 2 to
+    /// send the message as received
+    CallerId(u8),
+    QueryCallerId,
+    CallerIdRange,
+    /// `+DS=p0,n,p1,p2`, the V.42bis direction (0 for none and 3 for both),
+    /// whether to hang up without it, the dictionary size and the longest
+    /// string, any of which may be left out to keep its value
+    DataCompression([Option<u16>; 4]),
+    QueryDataCompression,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaxCommand {
+    TransmitHdlc,
+    ReceiveHdlc,
+    TransmitData,
+    ReceiveData,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyntaxError;
+
+/// Number of S-registers, `S0` to `S39`.
+pub const REGISTERS: usize = 40;
+/// Rings after which the modem answers, 0 to never answer.
+pub const S_AUTO_ANSWER: usize = 0;
+/// Rings counted since the phone started ringing.
+pub const S_RING_COUNT: usize = 1;
+/// Character repeated three times to escape to command mode, none above 127.
+pub const S_ESCAPE: usize = 2;
+/// Seconds to wait for the carrier of the far end before giving up a call.
+pub const S_CARRIER_WAIT: usize = 7;
+/// Tenths of second without carrier before hanging up, 255 to never hang up.
+pub const S_CARRIER_LOSS: usize = 10;
+/// Fiftieths of second of silence around the escape sequence.
+pub const S_GUARD_TIME: usize = 12;
+
+fn default_registers() -> [u8; REGISTERS] {
+    let mut registers = [0; REGISTERS];
+    registers[2] = b'+'; // escape character
+    registers[3] = b'\r'; // line terminator
+    registers[4] = b'\n'; // response formatting
+    registers[5] = 0x08; // backspace
+    registers[6] = 2; // seconds to wait for dial tone
+    registers[7] = 50; // seconds to wait for the carrier
+    registers[8] = 2; // seconds of comma dial pause
+    registers[9] = 6; // tenths of second of carrier to detect it
+    registers[10] = 14; // tenths of second of carrier loss before hanging up
+    registers[12] = 50; // fiftieths of second of escape guard time
+    registers
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResultCode {
+    Ok,
+    Connect,
+    Ring,
+    NoCarrier,
+    Error,
+}
+
+impl ResultCode {
+    fn numeric(&self) -> u8 {
+        match self {
+            ResultCode::Ok => 0,
+            ResultCode::Connect => 1,
+            ResultCode::Ring => 2,
+            ResultCode::NoCarrier => 3,
+            ResultCode::Error => 4,
+        }
+    }
+
+    fn verbose(&self) -> &'static str {
+        match self {
+            ResultCode::Ok => "OK",
+            ResultCode::Connect => "CONNECT",
+            ResultCode::Ring => "RING",
+            ResultCode::NoCarrier => "NO CARRIER",
+            ResultCode::Error => "ERROR",
+        }
+    }
+}
+
+pub struct AtInterpreter {
+    line: Vec<u8>,
+    pub echo: bool,
+    pub quiet: bool,
+    pub verbose: bool,
+    pub registers: [u8; REGISTERS],
+}
+
+impl Default for AtInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtInterpreter {
+    pub fn new() -> Self {
+        Self {
+            line: vec![],
+            echo: true,
+            quiet: false,
+            verbose: true,
+            registers: default_registers(),
+        }
+    }
+
+    /// Feeds a byte received from the DTE while in command mode. Returns the
+    /// parsed commands once a complete `AT` line has been received.
+    pub fn put_byte(
+        &mut self,
+        byte: u8,
+        to_dte: &mut Vec<u8>,
+    ) -> Option<Result<Vec<Command>, SyntaxError>> {
+        if self.echo {
+            to_dte.push(byte);
+        }
+        match byte {
+            b'\r' => {
+                let line = String::from_utf8_lossy(&self.line).into_owned();
+                self.line.clear();
+                let upper = line.trim().to_ascii_uppercase();
+                upper.strip_prefix("AT").map(parse)
+            }
+            b'\n' => None,
+            0x08 | 0x7f => {
+                self.line.pop();
+                None
+            }
+            _ => {
+                self.line.push(byte);
+                None
+            }
+        }
+    }
+
+    pub fn result(&self, code: ResultCode) -> Vec<u8> {
+        if self.quiet {
+            vec![]
+        } else if self.verbose {
+            format!("\r\n{}\r\n", code.verbose()).into_bytes()
+        } else {
+            format!("{}\r", code.numeric()).into_bytes()
+        }
+    }
+
+    pub fn info(&self, text: &str) -> Vec<u8> {
+        format!("\r\n{}\r\n", text).into_bytes()
+    }
+}
+
+/// Parses the part of a command line that follows the `AT` prefix.
+pub fn parse(line: &str) -> Result<Vec<Command>, SyntaxError> {
+    let mut commands = vec![];
+    let mut chars = line.chars().filter(|c| *c != ' ').peekable();
+
+    while let Some(c) = chars.next() {
+        let command = match c {
+            'E' => Command::Echo(flag(&mut chars)?),
+            'Q' => Command::Quiet(flag(&mut chars)?),
+            'V' => Command::Verbose(flag(&mut chars)?),
+            'Z' => {
+                flag(&mut chars)?;
+                Command::Reset
+            }
+            'O' => {
+                flag(&mut chars)?;
+                Command::Online
+            }
+            'D' => Command::Dial(chars.by_ref().collect()),
+            'A' => Command::Answer,
+            // going off-hook with `H1` is left to `D` and `A`
+            'H' if !flag(&mut chars)? => Command::HangUp,
+            '&' => match chars.next() {
+                Some('K') => Command::FlowControl(digits(&mut chars)?.unwrap_or(0)),
+                _ => return Err(SyntaxError),
+            },
+            '\\' => match chars.next() {
+                Some('N') => Command::ErrorControl(digits(&mut chars)?.unwrap_or(0)),
+                _ => return Err(SyntaxError),
+            },
+            'S' => {
+                let register = digits(&mut chars)?.ok_or(SyntaxError)?;
+                match chars.next() {
+                    Some('=') => Command::SetRegister(register, digits(&mut chars)?.unwrap_or(0)),
+                    Some('?') => Command::QueryRegister(register),
+                    _ => return Err(SyntaxError),
+                }
+            }
+            '+' => {
+                let ext: String = chars.by_ref().take_while(|c| *c != ';').collect();
+                parse_extended(&ext)?
+            }
+            _ => return Err(SyntaxError),
+        };
+        commands.push(command);
+    }
+
+    Ok(commands)
+}
+
+fn flag<I: Iterator<Item = char>>(chars: &mut Peekable<I>) -> Result<bool, SyntaxError> {
+    match chars.peek() {
+        Some('0') => {
+            chars.next();
+            Ok(false)
+        }
+        Some('1') => {
+            chars.next();
+            Ok(true)
+        }
+        Some(d) if d.is_ascii_digit() => Err(SyntaxError),
+        _ => Ok(false),
+    }
+}
+
+/// Parses an optional decimal number.
+fn digits<I: Iterator<Item = char>>(chars: &mut Peekable<I>) -> Result<Option<u8>, SyntaxError> {
+    let mut number = String::new();
+    while let Some(d) = chars.next_if(|c| c.is_ascii_digit()) {
+        number.push(d);
+    }
+    if number.is_empty() {
+        return Ok(None);
+    }
+    number.parse().map(Some).map_err(|_| SyntaxError)
+}
+
+fn parse_extended(ext: &str) -> Result<Command, SyntaxError> {
+    if let Some((name, args)) = ext.split_at_checked(3) {
+        let fax_command = match name {
+            "FTH" => Some(FaxCommand::TransmitHdlc),
+            "FRH" => Some(FaxCommand::ReceiveHdlc),
+            "FTM" => Some(FaxCommand::TransmitData),
+            "FRM" => Some(FaxCommand::ReceiveData),
+            _ => None,
+        };
+        if let Some(fax_command) = fax_command {
+            return if args == "=?" {
+                Ok(Command::FaxRange(fax_command))
+            } else {
+                Ok(Command::Fax(fax_command, number(args)?))
+            };
+        }
+        match name {
+            "FTS" => return Ok(Command::FaxSilence(number(args)?)),
+            "FRS" => return Ok(Command::FaxWaitSilence(number(args)?)),
+            _ => {}
+        }
+    }
+
+    if ext == "MS?" {
+        Ok(Command::QueryModulation)
+    } else if let Some(args) = ext.strip_prefix("MS=") {
+        match args.split(',').next() {
+            Some(carrier) if !carrier.is_empty() => Ok(Command::Modulation(carrier.to_string())),
+            _ => Err(SyntaxError),
+        }
+    } else if ext == "FCLASS?" {
+        Ok(Command::QueryFaxClass)
+    } else if ext == "FCLASS=?" {
+        Ok(Command::FaxClassRange)
+    } else if let Some(args) = ext.strip_prefix("FCLASS") {
+        Ok(Command::FaxClass(number(args)?))
+    } else if ext == "VCID?" {
+        Ok(Command::QueryCallerId)
+    } else if ext == "VCID=?" {
+        Ok(Command::CallerIdRange)
+    } else if let Some(args) = ext.strip_prefix("VCID") {
+        Ok(Command::CallerId(number(args)?))
+    } else if ext == "DS?" {
+        Ok(Command::QueryDataCompression)
+    } else if let Some(args) = ext.strip_prefix("DS=") {
+        let mut values = [None; 4];
+        let mut args = args.split(',');
+        for (value, arg) in values.iter_mut().zip(args.by_ref()) {
+            if !arg.is_empty() {
+                *value = Some(arg.parse().map_err(|_| SyntaxError)?);
+            }
+        }
+        if args.next().is_some() {
+            return Err(SyntaxError);
+        }
+        Ok(Command::DataCompression(values))
+    } else {
+        Err(SyntaxError)
+    }
+}
+
+/// Parses the `=n` argument of an extended command.
+fn number(args: &str) -> Result<u8, SyntaxError> {
+    args.strip_prefix('=')
+        .and_then(|n| n.parse().ok())
+        .ok_or(SyntaxError)
+}
+
+/// Detects the `+++` escape sequence surrounded by guard times, which returns
+/// the modem from data mode to command mode without hanging up.
+pub struct EscapeDetector {
+    character: Option<u8>,
+    guard_time: Duration,
+    last_byte: Option<Instant>,
+    count: usize,
+}
+
+impl EscapeDetector {
+    pub fn new(guard_time: Duration) -> Self {
+        Self::with_character(b'+', guard_time)
+    }
+
+    /// Detects `character` in place of `+`, or nothing if it is not ASCII.
+    pub fn with_character(character: u8, guard_time: Duration) -> Self {
+        Self {
+            character: character.is_ascii().then_some(character),
+            guard_time,
+            last_byte: None,
+            count: 0,
+        }
+    }
+
+    pub fn put_byte(&mut self, byte: u8, now: Instant) {
+        let quiet_before = self
+            .last_byte
+            .is_none_or(|t| now.duration_since(t) >= self.guard_time);
+        self.count = if Some(byte) != self.character {
+            0
+        } else if quiet_before {
+            1
+        } else if self.count > 0 && self.count < 3 {
+            self.count + 1
+        } else {
+            0
+        };
+        self.last_byte = Some(now);
+    }
+
+    /// Follows `S2` and `S12`.
+    pub fn from_registers(registers: &[u8; REGISTERS]) -> Self {
+        Self::with_character(
+            registers[S_ESCAPE],
+            Duration::from_millis(20 * registers[S_GUARD_TIME] as u64),
+        )
+    }
+
+    pub fn poll(&mut self, now: Instant) -> bool {
+        let quiet_after = self
+            .last_byte
+            .is_some_and(|t| now.duration_since(t) >= self.guard_time);
+        if self.count == 3 && quiet_after {
+            self.count = 0;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Protocol identifier for frames without a layer 3 protocol, used by APRS.
+pub const PID_NO_LAYER3: u8 = 0xf0;
+/// Control field of an unnumbered information frame.
+pub const CONTROL_UI: u8 = 0x03;
+
+const MAX_DIGIPEATERS: usize = 8;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Address {
+    pub callsign: String,
+    pub ssid: u8,
+    /// Command/response bit for the destination and source, has-been-repeated
+    /// bit for digipeaters
+    pub flag: bool,
+}
+
+impl Address {
+    fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let callsign: String = bytes[..6]
+            .iter()
+            .map(|b| (b >> 1) as char)
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+        anyhow::ensure!(
+            callsign.chars().all(|c| c.is_ascii_alphanumeric()),
+            "invalid callsign {:?}",
+            callsign
+        );
+        Ok(Self {
+            callsign,
+            ssid: (bytes[6] >> 1) & 0x0f,
+            flag: bytes[6] & 0x80 != 0,
+        })
+    }
+
+    fn encode(&self, last: bool, out: &mut Vec<u8>) {
+        let callsign = format!("{:6}", self.callsign);
+        out.extend(callsign.bytes().take(6).map(|b| b << 1));
+        out.push(((self.flag as u8) << 7) | 0x60 | (self.ssid << 1) | last as u8);
+    }
+}
+
+impl FromStr for Address {
+    type Err = anyhow::Error;
+
+    /// Parses addresses written as `CALL`, `CALL-SSID` or, for digipeaters
+    /// which already repeated the frame, `CALL-SSID*`.
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (s, flag) = match s.strip_suffix('*') {
+            Some(s) => (s, true),
+            None => (s, false),
+        };
+        let (callsign, ssid) = match s.split_once('-') {
+            Some((callsign, ssid)) => (callsign, ssid.parse()?),
+            None => (s, 0),
+        };
+        anyhow::ensure!(
+            !callsign.is_empty()
+                && callsign.len() <= 6
+                && callsign.chars().all(|c| c.is_ascii_alphanumeric()),
+            "invalid callsign {:?}",
+            callsign
+        );
+        anyhow::ensure!(ssid <= 15, "SSID {} out of range", ssid);
+        Ok(Self {
+            callsign: callsign.to_ascii_uppercase(),
+            ssid,
+            flag,
+        })
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.callsign)?;
+        if self.ssid != 0 {
+            write!(f, "-{}", self.ssid)?;
+        }
+        Ok(())
+    }
+}
+
+/// AX.25 frame, without the FCS which is handled by the HDLC layer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub destination: Address,
+    pub source: Address,
+    pub digipeaters: Vec<Address>,
+    pub control: u8,
+    pub pid: Option<u8>,
+    pub info: Vec<u8>,
+}
+
+impl Frame {
+    /// Builds an APRS-style UI frame.
+    pub fn ui(
+        source: Address,
+        destination: Address,
+        digipeaters: Vec<Address>,
+        info: &[u8],
+    ) -> Self {
+        Self {
+            destination,
+            source,
+            digipeaters,
+            control: CONTROL_UI,
+            pid: Some(PID_NO_LAYER3),
+            info: info.to_vec(),
+        }
+    }
+
+    pub fn parse(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut addresses = vec![];
+        let mut pos = 0;
+        loop {
+            anyhow::ensure!(bytes.len() >= pos + 7, "truncated address field");
+            addresses.push(Address::parse(&bytes[pos..pos + 7])?);
+            let last = bytes[pos + 6] & 1 != 0;
+            pos += 7;
+            if last {
+                break;
+            }
+            anyhow::ensure!(
+                addresses.len() < 2 + MAX_DIGIPEATERS,
+                "too many digipeaters"
+            );
+        }
+        anyhow::ensure!(addresses.len() >= 2, "missing source address");
+        anyhow::ensure!(bytes.len() > pos, "missing control field");
+
+        let control = bytes[pos];
+        pos += 1;
+        // only I and UI frames carry a protocol identifier
+        let has_pid = control & 1 == 0 || control & !0x10 == CONTROL_UI;
+        let pid = if has_pid {
+            anyhow::ensure!(bytes.len() > pos, "missing protocol identifier");
+            pos += 1;
+            Some(bytes[pos - 1])
+        } else {
+            None
+        };
+
+        let mut addresses = addresses.into_iter();
+        Ok(Self {
+            destination: addresses.next().unwrap(),
+            source: addresses.next().unwrap(),
+            digipeaters: addresses.collect(),
+            control,
+            pid,
+            info: bytes[pos..].to_vec(),
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.destination.encode(false, &mut out);
+        self.source.encode(self.digipeaters.is_empty(), &mut out);
+        for (i, digipeater) in self.digipeaters.iter().enumerate() {
+            digipeater.encode(i == self.digipeaters.len() - 1, &mut out);
+        }
+        out.push(self.control);
+        out.extend(self.pid);
+        out.extend(&self.info);
+        out
+    }
+}
+
+/// Monitor format used by TNC2 and APRS-IS, e.g. `N0CALL>APRS,WIDE1-1*:info`.
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}>{}", self.source, self.destination)?;
+        for digipeater in &self.digipeaters {
+            write!(f, ",{}", digipeater)?;
+            if digipeater.flag {
+                write!(f, "*")?;
+            }
+        }
+        write!(f, ":{}", String::from_utf8_lossy(&self.info))
+    }
+}
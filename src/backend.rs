@@ -0,0 +1,161 @@
+use crate::flow::FlowControl;
+use crate::ip::IpBackend;
+use crate::port::{Device, PortOptions};
+use crate::serial::Serial;
+use crate::stack::Stack;
+use crate::tcp::Tcp;
+use crossbeam_channel::{select, Receiver, Sender};
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The modem end of the DTE interface, handed to a backend.
+pub struct DteChannels {
+    /// Bytes the modem sends to the DTE
+    pub from_modem: Receiver<u8>,
+    /// Bytes the DTE sends to the modem
+    pub to_modem: Sender<u8>,
+    /// DTR, as raised or dropped by the DTE
+    pub dtr: Sender<bool>,
+    /// Cleared by the modem while the DTE must hold what it sends
+    pub cts: Arc<AtomicBool>,
+    /// Flow control chosen with `AT&K`, which serial ports apply to the line
+    pub flow_control: Receiver<FlowControl>,
+}
+
+/// Carries the data and control lines between the modem and the DTE.
+pub trait DteBackend {
+    /// Runs until the DTE goes away for good, or an error occurs.
+    fn event_loop(&mut self) -> anyhow::Result<()>;
+}
+
+/// Opens the backend matching the device in `options`.
+pub fn open(options: &PortOptions, channels: DteChannels) -> anyhow::Result<Box<dyn DteBackend>> {
+    Ok(match &options.device {
+        Device::Pty | Device::Path(_) => Box::new(Serial::open(options, channels)?),
+        Device::TcpListen(_) | Device::TcpConnect(_) => Box::new(Tcp::open(options, channels)?),
+        Device::Stdio => Box::new(Stdio::open(options, channels)?),
+        #[cfg(target_os = "linux")]
+        Device::Tun(name) => {
+            let tun = crate::tun::Tun::open(name.as_deref())?;
+            let from_tun = tun.spawn_reader()?;
+            Box::new(IpBackend::open(options, channels, Box::new(tun), from_tun)?)
+        }
+        #[cfg(not(target_os = "linux"))]
+        Device::Tun(_) => anyhow::bail!("TUN interfaces are only supported on Linux"),
+        Device::Stack => {
+            let (stack, from_stack) = Stack::open(options)?;
+            Box::new(IpBackend::open(
+                options,
+                channels,
+                Box::new(stack),
+                from_stack,
+            )?)
+        }
+    })
+}
+
+/// The DTE on standard input and output, e.g. for a modem driven by a
+/// script. DTR is raised at start and dropped at the end of the input.
+pub struct Stdio {
+    channels: DteChannels,
+}
+
+impl Stdio {
+    pub fn open(options: &PortOptions, channels: DteChannels) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            options.mode.is_none() && options.link.is_none(),
+            "mode and link only apply to a pty"
+        );
+        let from_modem = channels.from_modem.clone();
+        std::thread::spawn(move || {
+            let mut stdout = std::io::stdout();
+            for b in from_modem.iter() {
+                let _ = stdout.write_all(&[b]);
+                if from_modem.is_empty() {
+                    let _ = stdout.flush();
+                }
+            }
+        });
+        Ok(Self { channels })
+    }
+}
+
+impl DteBackend for Stdio {
+    fn event_loop(&mut self) -> anyhow::Result<()> {
+        self.channels.dtr.send(true).unwrap();
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = [0u8; 1];
+        loop {
+            // stdin cannot be polled, so the host is held by not reading
+            while !self.channels.cts.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            match stdin.read(&mut buf) {
+                Ok(0) => break,
+                Ok(_) => self.channels.to_modem.send(buf[0]).unwrap(),
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.channels.dtr.send(false).unwrap();
+        Ok(())
+    }
+}
+
+/// The DTE as channels of the caller's own, for tests and for applications
+/// which embed the modem. It stops when the caller drops `from_dte`.
+pub struct MemoryBackend {
+    channels: DteChannels,
+    from_dte: Receiver<u8>,
+    to_dte: Sender<u8>,
+    dtr: Receiver<bool>,
+}
+
+impl MemoryBackend {
+    pub fn new(
+        channels: DteChannels,
+        from_dte: Receiver<u8>,
+        to_dte: Sender<u8>,
+        dtr: Receiver<bool>,
+    ) -> Self {
+        Self {
+            channels,
+            from_dte,
+            to_dte,
+            dtr,
+        }
+    }
+}
+
+impl DteBackend for MemoryBackend {
+    fn event_loop(&mut self) -> anyhow::Result<()> {
+        let never = crossbeam_channel::never();
+        loop {
+            // while CTS is off, what the DTE sends waits in `from_dte`
+            let from_dte = if self.channels.cts.load(Ordering::Relaxed) {
+                &self.from_dte
+            } else {
+                &never
+            };
+            select! {
+                recv(from_dte) -> b => match b {
+                    Ok(b) => self.channels.to_modem.send(b).unwrap(),
+                    Err(_) => return Ok(()),
+                },
+                recv(self.channels.from_modem) -> b => match b {
+                    // nobody may be listening any more
+                    Ok(b) => _ = self.to_dte.send(b),
+                    Err(_) => return Ok(()),
+                },
+                recv(self.dtr) -> dtr => match dtr {
+                    Ok(dtr) => self.channels.dtr.send(dtr).unwrap(),
+                    // DTR stays as it was
+                    Err(_) => self.dtr = crossbeam_channel::never(),
+                },
+                default(Duration::from_millis(10)) => {}
+            }
+        }
+    }
+}
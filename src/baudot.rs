@@ -0,0 +1,140 @@
+/// Shift code that selects the letters case.
+pub const LTRS: u8 = 0x1f;
+/// Shift code that selects the figures case.
+pub const FIGS: u8 = 0x1b;
+
+const SPACE: u8 = 0x04;
+
+const NUL: u8 = 0x00;
+const LF: u8 = b'\n';
+const CR: u8 = b'\r';
+const BEL: u8 = 0x07;
+const ENQ: u8 = 0x05;
+
+const LETTERS: [u8; 32] = [
+    NUL, b'E', LF, b'A', b' ', b'S', b'I', b'U', CR, b'D', b'R', b'J', b'N', b'F', b'C', b'K',
+    b'T', b'Z', b'L', b'W', b'H', b'Y', b'P', b'Q', b'O', b'B', b'G', NUL, b'M', b'X', b'V', NUL,
+];
+
+// Unassigned positions (national use in ITA2) and the shift codes are NUL.
+const FIGURES_ITA2: [u8; 32] = [
+    NUL, b'3', LF, b'-', b' ', b'\'', b'8', b'7', CR, ENQ, b'4', BEL, b',', NUL, b':', b'(', b'5',
+    b'+', b')', b'2', NUL, b'6', b'0', b'1', b'9', b'?', NUL, NUL, b'.', b'/', b'=', NUL,
+];
+
+const FIGURES_US_TTY: [u8; 32] = [
+    NUL, b'3', LF, b'-', b' ', BEL, b'8', b'7', CR, b'$', b'4', b'\'', b',', b'!', b':', b'(',
+    b'5', b'"', b')', b'2', b'#', b'6', b'0', b'1', b'9', b'?', b'&', NUL, b'.', b'/', b';', NUL,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Ita2,
+    UsTty,
+}
+
+impl Variant {
+    fn figures(&self) -> &'static [u8; 32] {
+        match self {
+            Variant::Ita2 => &FIGURES_ITA2,
+            Variant::UsTty => &FIGURES_US_TTY,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Shift {
+    Letters,
+    Figures,
+}
+
+/// Translates ASCII into 5-bit Baudot codes, inserting LTRS/FIGS whenever
+/// the character to be sent is not available in the current case.
+pub struct BaudotEncoder {
+    variant: Variant,
+    unshift_on_space: bool,
+    shift: Option<Shift>,
+}
+
+impl BaudotEncoder {
+    pub fn new(variant: Variant, unshift_on_space: bool) -> Self {
+        Self {
+            variant,
+            unshift_on_space,
+            shift: None,
+        }
+    }
+
+    pub fn encode(&mut self, ascii: u8, out: &mut Vec<u8>) {
+        let ascii = ascii.to_ascii_uppercase();
+        if ascii == NUL {
+            return;
+        }
+
+        let letter = LETTERS.iter().position(|c| *c == ascii);
+        let figure = self.variant.figures().iter().position(|c| *c == ascii);
+        let (shift, code) = match (letter, figure) {
+            // space, CR and LF exist in both cases and do not require a shift
+            (Some(code), Some(_)) => {
+                if code as u8 == SPACE && self.unshift_on_space {
+                    self.shift = Some(Shift::Letters);
+                }
+                out.push(code as u8);
+                return;
+            }
+            (Some(code), None) => (Shift::Letters, code),
+            (None, Some(code)) => (Shift::Figures, code),
+            (None, None) => return,
+        };
+
+        if self.shift != Some(shift) {
+            out.push(match shift {
+                Shift::Letters => LTRS,
+                Shift::Figures => FIGS,
+            });
+            self.shift = Some(shift);
+        }
+        out.push(code as u8);
+    }
+}
+
+/// Translates 5-bit Baudot codes back into ASCII, tracking the LTRS/FIGS
+/// shift state.
+pub struct BaudotDecoder {
+    variant: Variant,
+    unshift_on_space: bool,
+    shift: Shift,
+}
+
+impl BaudotDecoder {
+    pub fn new(variant: Variant, unshift_on_space: bool) -> Self {
+        Self {
+            variant,
+            unshift_on_space,
+            shift: Shift::Letters,
+        }
+    }
+
+    pub fn decode(&mut self, code: u8) -> Option<u8> {
+        if code > 0x1f {
+            return None;
+        }
+        match code {
+            LTRS => self.shift = Shift::Letters,
+            FIGS => self.shift = Shift::Figures,
+            _ => {
+                if code == SPACE && self.unshift_on_space {
+                    self.shift = Shift::Letters;
+                }
+                let ascii = match self.shift {
+                    Shift::Letters => LETTERS[code as usize],
+                    Shift::Figures => self.variant.figures()[code as usize],
+                };
+                if ascii != NUL {
+                    return Some(ascii);
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,289 @@
+use crate::afsk::AfskRX;
+use crate::dtmf::DtmfRX;
+use crate::fsk::FskChannel;
+use crate::uart::UartRx;
+use crossbeam_channel::{unbounded, Receiver};
+
+/// Single data message format: date, time and number only.
+pub const SDMF: u8 = 0x04;
+/// Multiple data message format, also the ETSI call set-up message.
+pub const MDMF: u8 = 0x80;
+
+const PARAM_DATE_TIME: u8 = 0x01;
+const PARAM_NUMBER: u8 = 0x02;
+const PARAM_NUMBER_ABSENCE: u8 = 0x04;
+const PARAM_NAME: u8 = 0x07;
+const PARAM_NAME_ABSENCE: u8 = 0x08;
+
+/// Signal which carries the caller ID between the first and second rings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    /// Bell 202 FSK, as used in North America
+    Bell202,
+    /// ETSI EN 300 659, V.23 FSK
+    V23,
+    /// Digits sent as DTMF tones, as in Brazil and the Nordic countries
+    Dtmf,
+}
+
+/// Why the number or name is missing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Absence {
+    Private,
+    Unavailable,
+}
+
+impl Absence {
+    fn parse(code: &[u8]) -> Self {
+        match code {
+            b"P" | b"10" => Absence::Private,
+            _ => Absence::Unavailable,
+        }
+    }
+
+    /// Code reported by `+VCID`.
+    fn code(&self) -> &'static str {
+        match self {
+            Absence::Private => "P",
+            Absence::Unavailable => "O",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CallerId {
+    /// `MMDDHHMM`
+    pub date_time: Option<String>,
+    pub number: Option<String>,
+    pub number_absence: Option<Absence>,
+    pub name: Option<String>,
+    pub name_absence: Option<Absence>,
+    /// Message as received, including the checksum for FSK variants
+    pub message: Vec<u8>,
+}
+
+impl CallerId {
+    /// Parses an SDMF or MDMF message, from its type to its checksum.
+    pub fn parse(message: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(message.len() >= 3, "truncated message");
+        let len = message[1] as usize;
+        anyhow::ensure!(message.len() == len + 3, "wrong message length");
+        let sum = message.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        anyhow::ensure!(sum == 0, "wrong checksum");
+
+        let body = &message[2..message.len() - 1];
+        let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+        let mut caller_id = Self {
+            message: message.to_vec(),
+            ..Self::default()
+        };
+        match message[0] {
+            SDMF => {
+                anyhow::ensure!(body.len() >= 8, "truncated date and time");
+                let (date_time, number) = body.split_at(8);
+                caller_id.date_time = Some(text(date_time));
+                match number {
+                    b"O" | b"P" => caller_id.number_absence = Some(Absence::parse(number)),
+                    _ => caller_id.number = Some(text(number)),
+                }
+            }
+            MDMF => {
+                let mut params = body;
+                while let [param, len, rest @ ..] = params {
+                    let len = *len as usize;
+                    anyhow::ensure!(rest.len() >= len, "truncated parameter");
+                    let (value, rest) = rest.split_at(len);
+                    match *param {
+                        PARAM_DATE_TIME => caller_id.date_time = Some(text(value)),
+                        PARAM_NUMBER => caller_id.number = Some(text(value)),
+                        PARAM_NUMBER_ABSENCE => {
+                            caller_id.number_absence = Some(Absence::parse(value))
+                        }
+                        PARAM_NAME => caller_id.name = Some(text(value)),
+                        PARAM_NAME_ABSENCE => caller_id.name_absence = Some(Absence::parse(value)),
+                        _ => {}
+                    }
+                    params = rest;
+                }
+                anyhow::ensure!(params.is_empty(), "truncated parameter");
+            }
+            other => anyhow::bail!("unknown message type {:#04x}", other),
+        }
+        Ok(caller_id)
+    }
+
+    /// Parses digits sent with DTMF, from the start code (`A` or `D` before a
+    /// number, `B` before the reason why it is missing) to the final `C`.
+    pub fn parse_dtmf(digits: &str) -> anyhow::Result<Self> {
+        let body = digits
+            .strip_suffix('C')
+            .ok_or_else(|| anyhow::anyhow!("missing end code"))?;
+        let mut caller_id = Self {
+            message: digits.as_bytes().to_vec(),
+            ..Self::default()
+        };
+        let mut chars = body.chars();
+        match chars.next() {
+            Some('A' | 'D') => {
+                let number = chars.as_str();
+                anyhow::ensure!(
+                    number.chars().all(|c| c.is_ascii_digit()),
+                    "invalid number {:?}",
+                    number
+                );
+                caller_id.number = Some(number.to_string());
+            }
+            Some('B') => caller_id.number_absence = Some(Absence::parse(chars.as_str().as_bytes())),
+            _ => anyhow::bail!("missing start code"),
+        }
+        Ok(caller_id)
+    }
+
+    /// Formats the fields as reported by `+VCID=1`, one per line.
+    pub fn formatted(&self) -> String {
+        let mut lines = vec![];
+        if let Some(date_time) = &self.date_time {
+            let date = date_time.get(..4).unwrap_or(date_time);
+            let time = date_time.get(4..).unwrap_or_default();
+            lines.push(format!("DATE = {}", date));
+            lines.push(format!("TIME = {}", time));
+        }
+        match (&self.number, self.number_absence) {
+            (Some(number), _) => lines.push(format!("NMBR = {}", number)),
+            (None, Some(absence)) => lines.push(format!("NMBR = {}", absence.code())),
+            _ => {}
+        }
+        match (&self.name, self.name_absence) {
+            (Some(name), _) => lines.push(format!("NAME = {}", name)),
+            (None, Some(absence)) => lines.push(format!("NAME = {}", absence.code())),
+            _ => {}
+        }
+        lines.join("\r\n")
+    }
+
+    /// Formats the raw message as reported by `+VCID=2`.
+    pub fn unformatted(&self) -> String {
+        let hex: String = self.message.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("MESG = {}", hex)
+    }
+}
+
+/// Extracts SDMF and MDMF messages from the bytes received, skipping the
+/// `0x55` bytes of the channel seizure signal.
+pub struct MessageDecoder {
+    message: Vec<u8>,
+}
+
+impl MessageDecoder {
+    pub fn new() -> Self {
+        Self { message: vec![] }
+    }
+
+    /// Returns a whole message, from its type to its checksum.
+    pub fn put_byte(&mut self, byte: u8) -> Option<Vec<u8>> {
+        if self.message.is_empty() && byte != SDMF && byte != MDMF {
+            return None;
+        }
+        self.message.push(byte);
+        match self.message.get(1) {
+            Some(len) if self.message.len() == *len as usize + 3 => {
+                Some(std::mem::take(&mut self.message))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Default for MessageDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum Decoder {
+    Fsk {
+        afsk_rx: AfskRX,
+        uart_rx: UartRx,
+        from_uart_rx: Receiver<u8>,
+        messages: MessageDecoder,
+    },
+    Dtmf {
+        dtmf_rx: DtmfRX,
+        digits: String,
+    },
+}
+
+/// Decodes the caller ID sent while the line is on-hook.
+pub struct CallerIdRx {
+    decoder: Decoder,
+}
+
+impl CallerIdRx {
+    pub fn new(variant: Variant, srate: usize) -> anyhow::Result<Self> {
+        let speriod = 1. / srate as f32;
+        let channel = match variant {
+            Variant::Bell202 => FskChannel::new(1200., 2200., 1200.),
+            Variant::V23 => FskChannel::new(1300., 2100., 1200.),
+            Variant::Dtmf => {
+                return Ok(Self {
+                    decoder: Decoder::Dtmf {
+                        dtmf_rx: DtmfRX::new(speriod),
+                        digits: String::new(),
+                    },
+                })
+            }
+        };
+        let samples_per_symbol = channel.samples_per_symbol(srate)?;
+        let (to_decoder, from_uart_rx) = unbounded();
+        Ok(Self {
+            decoder: Decoder::Fsk {
+                afsk_rx: AfskRX::new(
+                    speriod,
+                    samples_per_symbol,
+                    channel.omega_mark(),
+                    channel.omega_space(),
+                ),
+                uart_rx: UartRx::new(samples_per_symbol, to_decoder),
+                from_uart_rx,
+                messages: MessageDecoder::new(),
+            },
+        })
+    }
+
+    /// Appends the caller IDs received to `out`, or an error for corrupted
+    /// messages.
+    pub fn put_samples(&mut self, in_samples: &[f32], out: &mut Vec<anyhow::Result<CallerId>>) {
+        match &mut self.decoder {
+            Decoder::Fsk {
+                afsk_rx,
+                uart_rx,
+                from_uart_rx,
+                messages,
+            } => {
+                let mut levels = vec![1; in_samples.len()];
+                afsk_rx.demodulate(in_samples, &mut levels);
+                uart_rx.put_samples(&levels);
+                for byte in from_uart_rx.try_iter() {
+                    if let Some(message) = messages.put_byte(byte) {
+                        out.push(CallerId::parse(&message));
+                    }
+                }
+            }
+            Decoder::Dtmf { dtmf_rx, digits } => {
+                let mut received = vec![];
+                dtmf_rx.demodulate(in_samples, &mut received);
+                for digit in received {
+                    match digit {
+                        'A' | 'B' | 'D' => *digits = digit.to_string(),
+                        _ if digits.is_empty() => {}
+                        'C' => {
+                            digits.push(digit);
+                            out.push(CallerId::parse_dtmf(&std::mem::take(digits)));
+                        }
+                        _ => digits.push(digit),
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+
+/// The detector works on blocks of this duration.
+const BLOCK_DURATION: f32 = 0.01;
+
+/// Tells whether the far end is sending its carrier from the received power,
+/// with hysteresis between the levels at which the carrier is detected and
+/// lost.
+pub struct CarrierDetector {
+    block_len: usize,
+    /// Powers relative to a full-scale sine wave
+    on_level: f32,
+    off_level: f32,
+    energy: f32,
+    count: usize,
+    carrier: bool,
+}
+
+impl CarrierDetector {
+    pub fn new(sampling_period: f32, on_dbfs: f32, off_dbfs: f32) -> Self {
+        Self {
+            block_len: (BLOCK_DURATION / sampling_period) as usize,
+            on_level: 10f32.powf(on_dbfs / 10.),
+            off_level: 10f32.powf(off_dbfs / 10.),
+            energy: 0.,
+            count: 0,
+            carrier: false,
+        }
+    }
+
+    pub fn carrier(&self) -> bool {
+        self.carrier
+    }
+
+    pub fn put_samples(&mut self, in_samples: &[f32]) {
+        for sample in in_samples {
+            self.energy += sample * sample;
+            self.count += 1;
+            if self.count < self.block_len {
+                continue;
+            }
+
+            let level = 2. * self.energy / self.block_len as f32;
+            if level >= self.on_level {
+                self.carrier = true;
+            } else if level < self.off_level {
+                self.carrier = false;
+            }
+            self.energy = 0.;
+            self.count = 0;
+        }
+    }
+}
+
+/// Ends a call once the far end carrier has been missing for longer than
+/// the `S10` register allows. Only calls on profiles with a continuous
+/// carrier are watched, from their very start: until a carrier is heard, the
+/// call counts as having lost it.
+#[derive(Debug, Default)]
+pub struct CarrierWatch {
+    watching: bool,
+    lost_since: Option<Instant>,
+}
+
+impl CarrierWatch {
+    /// Follows the state of the line, telling whether a [`CarrierDetector`]
+    /// has to listen.
+    pub fn update(&mut self, in_call: bool, carrier_on_demand: bool, now: Instant) -> bool {
+        let watching = in_call && !carrier_on_demand;
+        if !watching {
+            self.lost_since = None;
+        } else if !self.watching {
+            self.lost_since = Some(now);
+        }
+        self.watching = watching;
+        self.watching
+    }
+
+    pub fn put_carrier(&mut self, carrier: bool, now: Instant) {
+        self.lost_since = (self.watching && !carrier).then_some(now);
+    }
+
+    /// Tells whether the call is over, `carrier_loss` being in tenths of a
+    /// second, with 255 never ending it.
+    pub fn expired(&self, carrier_loss: u8, now: Instant) -> bool {
+        self.lost_since.is_some_and(|lost_since| {
+            carrier_loss != 255
+                && now - lost_since >= Duration::from_millis(100 * carrier_loss as u64)
+        })
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Context;
+use crossbeam_channel::unbounded;
+use modem::kcs::{KcsFormat, KcsRX, KcsTX};
+use modem::uart::{UartRx, UartTx};
+use std::path::Path;
+
+/// Amplitude of the recorded tones, leaving some headroom.
+const LEVEL: f32 = 0.5;
+
+fn samples_per_symbol(srate: usize, format: KcsFormat) -> usize {
+    (srate as f32 / format.baud_rate() as f32).round() as usize
+}
+
+/// Records `input` as a cassette image, preceded and followed by a mark
+/// tone so that the loader is able to synchronize.
+pub fn encode(
+    input: &Path,
+    output: &Path,
+    format: KcsFormat,
+    srate: usize,
+    leader: f32,
+) -> anyhow::Result<()> {
+    let data =
+        std::fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
+
+    let mut uart_tx = UartTx::with_framing(
+        samples_per_symbol(srate, format),
+        format.data_bits(),
+        format.stop_bits(),
+    );
+    let mut kcs_tx = KcsTX::new(1. / srate as f32);
+
+    let leader_samples = (leader * srate as f32) as usize;
+    uart_tx.put_idle(leader_samples);
+    for byte in data {
+        uart_tx.put_byte(byte);
+    }
+    let mut bits = vec![1; uart_tx.pending_samples() + srate / 2];
+    uart_tx.get_samples(&mut bits);
+
+    let mut samples = vec![0.; bits.len()];
+    kcs_tx.modulate(&bits, &mut samples);
+
+    let spec = hound::WavSpec {
+        channels: 1,
+        sample_rate: srate as u32,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(output, spec)
+        .with_context(|| format!("failed to create {}", output.display()))?;
+    for sample in samples {
+        writer.write_sample((LEVEL * sample * i16::MAX as f32) as i16)?;
+    }
+    writer.finalize()?;
+    Ok(())
+}
+
+/// Plays back a cassette image and writes the bytes found in it. Only the
+/// first channel of a stereo recording is used.
+pub fn decode(input: &Path, output: &Path, format: KcsFormat) -> anyhow::Result<()> {
+    let mut reader = hound::WavReader::open(input)
+        .with_context(|| format!("failed to open {}", input.display()))?;
+    let spec = reader.spec();
+    let srate = spec.sample_rate as usize;
+
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|s| s.map(|s| s as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+    let samples: Vec<f32> = samples
+        .into_iter()
+        .step_by(spec.channels as usize)
+        .collect();
+
+    let (to_host, from_uart_rx) = unbounded();
+    let mut uart_rx = UartRx::with_framing(
+        samples_per_symbol(srate, format),
+        format.data_bits(),
+        format.stop_bits(),
+        to_host,
+    );
+    let mut kcs_rx = KcsRX::new(1. / srate as f32);
+
+    let mut bits = vec![0; samples.len()];
+    kcs_rx.demodulate(&samples, &mut bits);
+    uart_rx.put_samples(&bits);
+    drop(uart_rx);
+
+    let data: Vec<u8> = from_uart_rx.iter().collect();
+    std::fs::write(output, &data)
+        .with_context(|| format!("failed to write {}", output.display()))?;
+    eprintln!("{} bytes decoded", data.len());
+    Ok(())
+}
@@ -0,0 +1,81 @@
+pub const DLE: u8 = 0x10;
+pub const ETX: u8 = 0x03;
+
+/// V.21 channel 2, 300 bps, used by `+FTH` and `+FRH`.
+pub const V21_CHANNEL2: u8 = 3;
+/// Modulations supported by `+FTH` and `+FRH`.
+pub const HDLC_MODULATIONS: &[u8] = &[V21_CHANNEL2];
+/// Modulations supported by `+FTM` and `+FRM`: V.27ter at 2400 and 4800 bps,
+/// and V.29 at 7200 and 9600 bps.
+pub const DATA_MODULATIONS: &[u8] = &[24, 48, 72, 96];
+
+/// Appends `data` to `out` in the form exchanged with the host by fax class 1
+/// (T.31) commands: each `<DLE>` is doubled, and `<DLE><ETX>` terminates it.
+pub fn encode(data: &[u8], out: &mut Vec<u8>) {
+    escape(data, out);
+    out.extend([DLE, ETX]);
+}
+
+/// Like [`encode`], for data which continues after `data`.
+pub fn escape(data: &[u8], out: &mut Vec<u8>) {
+    for byte in data {
+        if *byte == DLE {
+            out.push(DLE);
+        }
+        out.push(*byte);
+    }
+}
+
+/// Formats a list of modulations as answered to the `=?` commands.
+pub fn range(modulations: &[u8]) -> String {
+    modulations
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Undoes [`encode`] on the bytes sent by the host.
+pub struct DleDecoder {
+    data: Vec<u8>,
+    dle: bool,
+}
+
+impl DleDecoder {
+    pub fn new() -> Self {
+        Self {
+            data: vec![],
+            dle: false,
+        }
+    }
+
+    /// Returns the data received so far once `<DLE><ETX>` is found. Other
+    /// `<DLE>` sequences are ignored.
+    pub fn put_byte(&mut self, byte: u8) -> Option<Vec<u8>> {
+        if self.dle {
+            self.dle = false;
+            match byte {
+                ETX => return Some(std::mem::take(&mut self.data)),
+                DLE => self.data.push(DLE),
+                _ => {}
+            }
+        } else if byte == DLE {
+            self.dle = true;
+        } else {
+            self.data.push(byte);
+        }
+        None
+    }
+
+    /// Takes the data received so far, for streams which are consumed before
+    /// `<DLE><ETX>` arrives.
+    pub fn take_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.data)
+    }
+}
+
+impl Default for DleDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+const ROWS: [f32; 4] = [697., 770., 852., 941.];
+const COLUMNS: [f32; 4] = [1209., 1336., 1477., 1633.];
+const KEYS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+
+const TONE_DURATION: f32 = 0.07;
+const PAUSE_DURATION: f32 = 0.07;
+/// Amplitude of each of the two tones of a digit.
+const TONE_LEVEL: f32 = 0.25;
+
+/// The detector works on blocks of this duration, shorter than half a tone
+/// so that every tone fills at least two of them.
+const BLOCK_DURATION: f32 = 0.02;
+/// Minimum power, relative to a full-scale sine wave, of a block holding a
+/// digit (-40 dBFS).
+const MIN_LEVEL: f32 = 1e-4;
+/// Fraction of the block energy which must be in the two tones of a digit.
+const MIN_PURITY: f32 = 0.8;
+/// Minimum ratio between the powers of the weaker and the stronger tone
+/// (-8 dB).
+const MIN_TWIST: f32 = 0.16;
+
+fn key(digit: char) -> Option<(usize, usize)> {
+    let digit = digit.to_ascii_uppercase();
+    KEYS.iter().enumerate().find_map(|(row, keys)| {
+        keys.iter()
+            .position(|key| *key == digit)
+            .map(|column| (row, column))
+    })
+}
+
+/// Generates DTMF digits, in the same streaming style as `V21TX`.
+pub struct DtmfTX {
+    sampling_period: f32,
+    /// Frequencies of the tones still to be sent, `None` for pauses, one
+    /// entry per sample
+    samples: VecDeque<Option<(f32, f32)>>,
+    time: f32,
+}
+
+impl DtmfTX {
+    pub fn new(sampling_period: f32) -> Self {
+        Self {
+            sampling_period,
+            samples: VecDeque::new(),
+            time: 0.,
+        }
+    }
+
+    /// Queues the digits in `digits`, ignoring characters which are not on
+    /// the keypad.
+    pub fn put_digits(&mut self, digits: &str) {
+        let tone_samples = (TONE_DURATION / self.sampling_period) as usize;
+        let pause_samples = (PAUSE_DURATION / self.sampling_period) as usize;
+        for (row, column) in digits.chars().filter_map(key) {
+            let tones = (ROWS[row], COLUMNS[column]);
+            self.samples
+                .extend(std::iter::repeat_n(Some(tones), tone_samples));
+            self.samples
+                .extend(std::iter::repeat_n(None, pause_samples));
+        }
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    pub fn modulate(&mut self, out_samples: &mut [f32]) {
+        for out in out_samples.iter_mut() {
+            *out = match self.samples.pop_front().flatten() {
+                Some((row, column)) => {
+                    TONE_LEVEL
+                        * ((2. * PI * row * self.time).sin() + (2. * PI * column * self.time).sin())
+                }
+                None => 0.,
+            };
+            self.time = (self.time + self.sampling_period) % 1.;
+        }
+    }
+}
+
+/// Detects DTMF digits using the Goertzel algorithm on fixed blocks of
+/// samples. A digit is reported once it has been present in two blocks in a
+/// row, and again only after a block without it.
+pub struct DtmfRX {
+    coefficients: Vec<f32>,
+    block_len: usize,
+    /// Goertzel state for each of the row and column frequencies
+    state: Vec<(f32, f32)>,
+    energy: f32,
+    count: usize,
+    last: Option<char>,
+    reported: bool,
+}
+
+impl DtmfRX {
+    pub fn new(sampling_period: f32) -> Self {
+        Self {
+            coefficients: ROWS
+                .iter()
+                .chain(COLUMNS.iter())
+                .map(|freq| 2. * (2. * PI * freq * sampling_period).cos())
+                .collect(),
+            block_len: (BLOCK_DURATION / sampling_period) as usize,
+            state: vec![(0., 0.); ROWS.len() + COLUMNS.len()],
+            energy: 0.,
+            count: 0,
+            last: None,
+            reported: false,
+        }
+    }
+
+    pub fn demodulate(&mut self, in_samples: &[f32], digits: &mut Vec<char>) {
+        for sample in in_samples {
+            for ((s1, s2), coefficient) in self.state.iter_mut().zip(&self.coefficients) {
+                let s0 = sample + coefficient * *s1 - *s2;
+                *s2 = *s1;
+                *s1 = s0;
+            }
+            self.energy += sample * sample;
+            self.count += 1;
+
+            if self.count == self.block_len {
+                let digit = self.detect();
+                if digit.is_some() && digit == self.last && !self.reported {
+                    digits.extend(digit);
+                    self.reported = true;
+                } else if digit != self.last {
+                    self.reported = false;
+                }
+                self.last = digit;
+
+                self.state.fill((0., 0.));
+                self.energy = 0.;
+                self.count = 0;
+            }
+        }
+    }
+
+    fn detect(&self) -> Option<char> {
+        let n = self.block_len as f32;
+        if 2. * self.energy / n < MIN_LEVEL {
+            return None;
+        }
+        // power of each tone as a fraction of the energy of the block
+        let powers: Vec<f32> = self
+            .state
+            .iter()
+            .zip(&self.coefficients)
+            .map(|((s1, s2), coefficient)| {
+                let power = s1 * s1 + s2 * s2 - coefficient * s1 * s2;
+                2. * power / (n * self.energy)
+            })
+            .collect();
+        let strongest = |powers: &[f32]| {
+            powers
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap()
+        };
+        let (row, row_power) = strongest(&powers[..ROWS.len()]);
+        let (column, column_power) = strongest(&powers[ROWS.len()..]);
+        let twist = row_power.min(column_power) / row_power.max(column_power);
+        (row_power + column_power >= MIN_PURITY && twist >= MIN_TWIST).then_some(KEYS[row][column])
+    }
+}
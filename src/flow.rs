@@ -0,0 +1,90 @@
+pub const XON: u8 = 0x11;
+pub const XOFF: u8 = 0x13;
+/// Bytes still accepted above the high watermark, which the host may send
+/// before it reacts to being stopped.
+pub const SLACK: usize = 128;
+
+/// How the host is told to stop sending while the line is slower than the
+/// serial port, as chosen with `&K`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlowControl {
+    None,
+    /// Our RTS, which the host sees as CTS
+    #[default]
+    RtsCts,
+    /// XON and XOFF characters sent to the host
+    XonXoff,
+}
+
+impl FlowControl {
+    /// Parses the argument of `&K`.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(FlowControl::None),
+            3 => Some(FlowControl::RtsCts),
+            4 => Some(FlowControl::XonXoff),
+            _ => None,
+        }
+    }
+}
+
+/// Stops the host once more than `high` bytes are waiting to be sent, and
+/// lets it go on once they drop to `low`.
+pub struct Watermarks {
+    high: usize,
+    low: usize,
+    stopped: bool,
+    /// Bytes dropped because the host went on sending while stopped
+    overruns: usize,
+}
+
+impl Watermarks {
+    pub fn new(high: usize, low: usize) -> Self {
+        debug_assert!(low <= high);
+        Self {
+            high,
+            low,
+            stopped: false,
+            overruns: 0,
+        }
+    }
+
+    pub fn high(&self) -> usize {
+        self.high
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Tells whether one more byte from the host may be queued on top of
+    /// `pending`. Without flow control the host cannot be held back, so
+    /// nothing is refused; otherwise what comes beyond [`SLACK`] is dropped
+    /// and counted.
+    pub fn admit(&mut self, pending: usize, flow_control: FlowControl) -> bool {
+        if flow_control == FlowControl::None || pending < self.high + SLACK {
+            return true;
+        }
+        self.overruns += 1;
+        false
+    }
+
+    /// Returns the bytes dropped since the last call.
+    pub fn take_overruns(&mut self) -> usize {
+        std::mem::take(&mut self.overruns)
+    }
+
+    /// Returns `Some(true)` when the host must be stopped and `Some(false)`
+    /// when it may send again.
+    pub fn update(&mut self, pending: usize) -> Option<bool> {
+        if !self.stopped && pending > self.high {
+            self.stopped = true;
+            Some(true)
+        } else if self.stopped && pending <= self.low {
+            self.stopped = false;
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,321 @@
+use anyhow::Context;
+use serde::Deserialize;
+use std::f32::consts::PI;
+use std::path::Path;
+
+/// Maximum relative error tolerated when rounding the number of samples per
+/// symbol, well within what an UART receiver is able to compensate.
+const MAX_SYMBOL_RATE_ERROR: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Charset {
+    /// 8 data bits, 1 stop bit
+    #[default]
+    Ascii,
+    /// 5-bit ITA2 codes, 1.5 stop bits
+    Baudot,
+    /// 7 data bits followed by an even parity bit, 2 stop bits, as sent by
+    /// the European Deaf Telephone
+    Edt,
+}
+
+impl Charset {
+    pub fn data_bits(&self) -> usize {
+        match self {
+            Charset::Ascii | Charset::Edt => 8,
+            Charset::Baudot => 5,
+        }
+    }
+
+    pub fn stop_bits(&self) -> f32 {
+        match self {
+            Charset::Ascii => 1.,
+            Charset::Edt => 2.,
+            Charset::Baudot => 1.5,
+        }
+    }
+}
+
+/// How bytes are carried over the line.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    /// Start/stop characters, as sent by an UART
+    #[default]
+    Async,
+    /// Synchronous HDLC frames
+    Hdlc,
+    /// Synchronous HDLC frames, NRZI coded as in AX.25
+    HdlcNrzi,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Originate,
+    Answer,
+}
+
+/// Tones and symbol rate used to transmit in one direction of a link.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct FskChannel {
+    /// Mark (logical 1) frequency in Hz
+    pub mark: f32,
+    /// Space (logical 0) frequency in Hz
+    pub space: f32,
+    /// Symbols per second
+    pub baud_rate: f32,
+}
+
+impl FskChannel {
+    pub const fn new(mark: f32, space: f32, baud_rate: f32) -> Self {
+        Self {
+            mark,
+            space,
+            baud_rate,
+        }
+    }
+
+    pub fn omega_mark(&self) -> f32 {
+        2. * PI * self.mark
+    }
+
+    pub fn omega_space(&self) -> f32 {
+        2. * PI * self.space
+    }
+
+    pub fn samples_per_symbol(&self, srate: usize) -> anyhow::Result<usize> {
+        let exact = srate as f32 / self.baud_rate;
+        let rounded = exact.round();
+        anyhow::ensure!(
+            rounded >= 1. && ((exact - rounded) / exact).abs() <= MAX_SYMBOL_RATE_ERROR,
+            "sampling rate {} is not a multiple of the baud rate {}",
+            srate,
+            self.baud_rate
+        );
+        Ok(rounded as usize)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FskProfile {
+    pub name: String,
+    /// Channel transmitted by the side that places the call
+    pub originate: FskChannel,
+    /// Channel transmitted by the side that answers the call
+    pub answer: FskChannel,
+    pub charset: Charset,
+    pub framing: Framing,
+    /// Half-duplex operation where the carrier is only sent while there is
+    /// data to transmit, and the receiver is muted meanwhile
+    pub carrier_on_demand: bool,
+    /// Received level (dBFS) above which the carrier is considered present
+    pub carrier_on_dbfs: f32,
+    /// Received level (dBFS) below which the carrier is considered lost
+    pub carrier_off_dbfs: f32,
+}
+
+impl FskProfile {
+    pub fn full_duplex(name: &str, originate: FskChannel, answer: FskChannel) -> Self {
+        Self {
+            name: name.to_string(),
+            originate,
+            answer,
+            charset: Charset::Ascii,
+            framing: Framing::Async,
+            carrier_on_demand: false,
+            carrier_on_dbfs: DEFAULT_CARRIER_ON_DBFS,
+            carrier_off_dbfs: DEFAULT_CARRIER_OFF_DBFS,
+        }
+    }
+
+    pub fn half_duplex(name: &str, channel: FskChannel) -> Self {
+        Self::full_duplex(name, channel, channel)
+    }
+
+    pub fn v21() -> Self {
+        Self::full_duplex(
+            "V21",
+            FskChannel::new(980., 1180., 300.),
+            FskChannel::new(1650., 1850., 300.),
+        )
+    }
+
+    pub fn bell103() -> Self {
+        Self::full_duplex(
+            "BELL103",
+            FskChannel::new(1270., 1070., 300.),
+            FskChannel::new(2225., 2025., 300.),
+        )
+    }
+
+    pub fn bell202() -> Self {
+        Self::half_duplex("BELL202", FskChannel::new(1200., 2200., 1200.))
+    }
+
+    /// The videotex terminal places the call and transmits on the 75 bps
+    /// backward channel, while the host answers on the 1200 bps forward channel.
+    pub fn v23() -> Self {
+        Self::full_duplex(
+            "V23",
+            FskChannel::new(390., 450., 75.),
+            FskChannel::new(1300., 2100., 1200.),
+        )
+    }
+
+    /// Amateur radio AFSK teleprinter, 170 Hz shift at 45.45 baud.
+    pub fn rtty() -> Self {
+        Self {
+            charset: Charset::Baudot,
+            ..Self::half_duplex("RTTY", FskChannel::new(2125., 2295., 1000. / 22.))
+        }
+    }
+
+    /// Same as [`FskProfile::rtty`], at the 50 baud rate common in Europe.
+    pub fn rtty50() -> Self {
+        Self {
+            charset: Charset::Baudot,
+            ..Self::half_duplex("RTTY50", FskChannel::new(2125., 2295., 50.))
+        }
+    }
+
+    /// Text telephone for the deaf (V.18 Annex A), Baudot at 45.45 baud.
+    pub fn tdd() -> Self {
+        Self {
+            charset: Charset::Baudot,
+            carrier_on_demand: true,
+            ..Self::half_duplex("TDD", FskChannel::new(1400., 1800., 1000. / 22.))
+        }
+    }
+
+    /// European Deaf Telephone (V.18 Annex B), 110 baud on the V.21
+    /// channel 1 tones.
+    pub fn edt() -> Self {
+        Self {
+            charset: Charset::Edt,
+            carrier_on_demand: true,
+            ..Self::half_duplex("EDT", FskChannel::new(980., 1180., 110.))
+        }
+    }
+
+    /// Kansas City Standard cassette encoding at 300 baud.
+    pub fn kcs() -> Self {
+        Self::half_duplex("KCS", FskChannel::new(2400., 1200., 300.))
+    }
+
+    /// Bell 202 AFSK carrying AX.25 frames, as used by APRS.
+    pub fn ax25() -> Self {
+        Self {
+            framing: Framing::HdlcNrzi,
+            carrier_on_demand: true,
+            ..Self::half_duplex("AX25", FskChannel::new(1200., 2200., 1200.))
+        }
+    }
+
+    /// Fax T.30 signalling, HDLC frames on V.21 channel 2.
+    pub fn t30() -> Self {
+        Self {
+            framing: Framing::Hdlc,
+            carrier_on_demand: true,
+            ..Self::half_duplex("T30", FskChannel::new(1650., 1850., 300.))
+        }
+    }
+
+    pub fn presets() -> Vec<Self> {
+        vec![
+            Self::v21(),
+            Self::bell103(),
+            Self::bell202(),
+            Self::v23(),
+            Self::rtty(),
+            Self::rtty50(),
+            Self::tdd(),
+            Self::edt(),
+            Self::kcs(),
+            Self::ax25(),
+            Self::t30(),
+        ]
+    }
+
+    pub fn tx_channel(&self, role: Role) -> &FskChannel {
+        match role {
+            Role::Originate => &self.originate,
+            Role::Answer => &self.answer,
+        }
+    }
+
+    pub fn rx_channel(&self, role: Role) -> &FskChannel {
+        match role {
+            Role::Originate => &self.answer,
+            Role::Answer => &self.originate,
+        }
+    }
+
+    /// Parses a TOML document containing one or more `[[profile]]` tables.
+    /// The `answer` channel may be omitted for half-duplex profiles, and
+    /// `charset` defaults to `"ascii"` (other options are `"baudot"` and
+    /// `"edt"`), and `framing` to `"async"` (other options are
+    /// `"hdlc"` and `"hdlc_nrzi"`).
+    pub fn from_toml(s: &str) -> anyhow::Result<Vec<Self>> {
+        let file: ProfileFile = toml::from_str(s)?;
+        Ok(file
+            .profile
+            .into_iter()
+            .map(|p| Self {
+                name: p.name.to_ascii_uppercase(),
+                originate: p.originate,
+                answer: p.answer.unwrap_or(p.originate),
+                charset: p.charset,
+                framing: p.framing,
+                carrier_on_demand: p.carrier_on_demand,
+                carrier_on_dbfs: p.carrier_on_dbfs,
+                carrier_off_dbfs: p.carrier_off_dbfs,
+            })
+            .collect())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Vec<Self>> {
+        let s = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read profiles from {}", path.display()))?;
+        Self::from_toml(&s).with_context(|| format!("invalid profiles file {}", path.display()))
+    }
+
+    /// Looks a profile up by name, case-insensitively.
+    pub fn find<'a>(profiles: &'a [Self], name: &str) -> Option<&'a Self> {
+        profiles.iter().find(|p| p.name.eq_ignore_ascii_case(name))
+    }
+}
+
+const DEFAULT_CARRIER_ON_DBFS: f32 = -40.;
+const DEFAULT_CARRIER_OFF_DBFS: f32 = -45.;
+
+fn default_carrier_on_dbfs() -> f32 {
+    DEFAULT_CARRIER_ON_DBFS
+}
+
+fn default_carrier_off_dbfs() -> f32 {
+    DEFAULT_CARRIER_OFF_DBFS
+}
+
+#[derive(Deserialize)]
+struct ProfileFile {
+    profile: Vec<ProfileEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProfileEntry {
+    name: String,
+    originate: FskChannel,
+    answer: Option<FskChannel>,
+    #[serde(default)]
+    charset: Charset,
+    #[serde(default)]
+    framing: Framing,
+    #[serde(default)]
+    carrier_on_demand: bool,
+    #[serde(default = "default_carrier_on_dbfs")]
+    carrier_on_dbfs: f32,
+    #[serde(default = "default_carrier_off_dbfs")]
+    carrier_off_dbfs: f32,
+}
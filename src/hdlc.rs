@@ -0,0 +1,298 @@
+use crossbeam_channel::Sender;
+use std::collections::VecDeque;
+
+pub const FLAG: u8 = 0x7e;
+pub const ESCAPE: u8 = 0x7d;
+
+/// Frames longer than this are discarded while hunting for the next flag.
+const MAX_FRAME_LEN: usize = 1024;
+/// Shortest frame accepted, i.e. address, control and FCS.
+const MIN_FRAME_LEN: usize = 4;
+
+/// CRC-16/X.25 frame check sequence, transmitted least significant byte
+/// first.
+pub fn fcs(data: &[u8]) -> u16 {
+    let mut crc = 0xffffu16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x8408
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Frame received with a bad frame check sequence. The contents are kept,
+/// without the FCS, for diagnostic purposes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FcsError {
+    pub frame: Vec<u8>,
+}
+
+pub type Frame = Result<Vec<u8>, FcsError>;
+
+/// Appends `count` flags to `bits`, least significant bit first.
+pub fn encode_flags(count: usize, bits: &mut Vec<u8>) {
+    for _ in 0..count {
+        bits.extend((0..8).map(|i| (FLAG >> i) & 1));
+    }
+}
+
+/// Appends `data` followed by its FCS to `bits`, inserting a zero after
+/// every five consecutive ones. Flags are not included.
+pub fn encode_frame(data: &[u8], bits: &mut Vec<u8>) {
+    let fcs = fcs(data).to_le_bytes();
+    let mut ones = 0;
+    for byte in data.iter().chain(fcs.iter()) {
+        for i in 0..8 {
+            let bit = (byte >> i) & 1;
+            bits.push(bit);
+            if bit == 1 {
+                ones += 1;
+                if ones == 5 {
+                    bits.push(0);
+                    ones = 0;
+                }
+            } else {
+                ones = 0;
+            }
+        }
+    }
+}
+
+/// Bit level deframer: finds flags, removes stuffed zeros and checks the
+/// FCS of each frame.
+pub struct HdlcDecoder {
+    bits: Vec<u8>,
+    ones: usize,
+    hunting: bool,
+}
+
+impl HdlcDecoder {
+    pub fn new() -> Self {
+        Self {
+            bits: vec![],
+            ones: 0,
+            hunting: true,
+        }
+    }
+
+    pub fn put_bit(&mut self, bit: u8) -> Option<Frame> {
+        if bit == 1 {
+            self.ones += 1;
+            if self.ones >= 7 {
+                // abort sequence or idle line
+                self.hunting = true;
+                self.bits.clear();
+                return None;
+            }
+        } else {
+            let ones = self.ones;
+            self.ones = 0;
+            if ones == 5 {
+                // stuffed zero
+                return None;
+            }
+            if ones == 6 {
+                let frame = self.end_frame();
+                self.hunting = false;
+                return frame;
+            }
+        }
+
+        if !self.hunting {
+            self.bits.push(bit);
+            if self.bits.len() > 8 * (MAX_FRAME_LEN + 2) {
+                self.hunting = true;
+                self.bits.clear();
+            }
+        }
+        None
+    }
+
+    fn end_frame(&mut self) -> Option<Frame> {
+        let mut bits = std::mem::take(&mut self.bits);
+        if self.hunting || bits.len() < 7 {
+            return None;
+        }
+        // the leading zero and the six ones of the closing flag
+        bits.truncate(bits.len() - 7);
+        if !bits.len().is_multiple_of(8) || bits.len() < 8 * MIN_FRAME_LEN {
+            return None;
+        }
+
+        let mut bytes: Vec<u8> = bits
+            .chunks(8)
+            .map(|byte| byte.iter().rev().fold(0, |acc, bit| (acc << 1) | bit))
+            .collect();
+        let received = u16::from_le_bytes([bytes[bytes.len() - 2], bytes[bytes.len() - 1]]);
+        bytes.truncate(bytes.len() - 2);
+        if fcs(&bytes) == received {
+            Some(Ok(bytes))
+        } else {
+            Some(Err(FcsError { frame: bytes }))
+        }
+    }
+}
+
+impl Default for HdlcDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// NRZI as used by AX.25: a zero is sent as a change of level, a one as no
+/// change.
+#[derive(Clone, Copy, Debug)]
+pub struct Nrzi {
+    level: u8,
+}
+
+impl Nrzi {
+    pub fn new() -> Self {
+        Self { level: 1 }
+    }
+
+    pub fn encode(&mut self, bit: u8) -> u8 {
+        if bit == 0 {
+            self.level ^= 1;
+        }
+        self.level
+    }
+
+    pub fn decode(&mut self, level: u8) -> u8 {
+        let bit = (level == self.level) as u8;
+        self.level = level;
+        bit
+    }
+}
+
+impl Default for Nrzi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Synchronous counterpart of `UartTx`, producing one line level per audio
+/// sample.
+pub struct HdlcTx {
+    samples_per_symbol: usize,
+    nrzi: Option<Nrzi>,
+    level: u8,
+    samples: VecDeque<u8>,
+}
+
+impl HdlcTx {
+    pub fn new(samples_per_symbol: usize, nrzi: bool) -> Self {
+        Self {
+            samples_per_symbol,
+            nrzi: nrzi.then(Nrzi::new),
+            level: 1,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn put_flags(&mut self, count: usize) {
+        let mut bits = vec![];
+        encode_flags(count, &mut bits);
+        self.put_bits(&bits);
+    }
+
+    /// Queues a frame followed by a closing flag. Opening flags should be
+    /// queued beforehand with [`HdlcTx::put_flags`].
+    pub fn put_frame(&mut self, data: &[u8]) {
+        let mut bits = vec![];
+        encode_frame(data, &mut bits);
+        encode_flags(1, &mut bits);
+        self.put_bits(&bits);
+    }
+
+    fn put_bits(&mut self, bits: &[u8]) {
+        for bit in bits {
+            self.level = match &mut self.nrzi {
+                Some(nrzi) => nrzi.encode(*bit),
+                None => *bit,
+            };
+            self.samples
+                .extend(std::iter::repeat_n(self.level, self.samples_per_symbol));
+        }
+    }
+
+    pub fn pending_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Fills `buffer` with the queued samples, holding the line at its last
+    /// level afterwards.
+    pub fn get_samples(&mut self, buffer: &mut [u8]) {
+        for sample in buffer.iter_mut() {
+            *sample = self.samples.pop_front().unwrap_or(self.level);
+        }
+    }
+}
+
+/// Synchronous counterpart of `UartRx`, recovering the bit clock from the
+/// transitions of the demodulated signal and sending each frame received to
+/// `to_host`.
+pub struct HdlcRx {
+    samples_per_symbol: f32,
+    nrzi: Option<Nrzi>,
+    decoder: HdlcDecoder,
+    /// Samples elapsed since the estimated start of the current bit
+    clock: f32,
+    last_sample: u8,
+    to_host: Sender<Frame>,
+}
+
+impl HdlcRx {
+    pub fn new(samples_per_symbol: usize, nrzi: bool, to_host: Sender<Frame>) -> Self {
+        Self {
+            samples_per_symbol: samples_per_symbol as f32,
+            nrzi: nrzi.then(Nrzi::new),
+            decoder: HdlcDecoder::new(),
+            clock: 0.,
+            last_sample: 1,
+            to_host,
+        }
+    }
+
+    pub fn put_samples(&mut self, buffer: &[u8]) {
+        let sps = self.samples_per_symbol;
+        for &sample in buffer {
+            if sample != self.last_sample {
+                // nudge the clock towards the transition, which should
+                // happen at the start of a bit
+                let error = if self.clock < sps / 2. {
+                    self.clock
+                } else {
+                    self.clock - sps
+                };
+                self.clock -= 0.5 * error;
+                self.last_sample = sample;
+            }
+
+            let before = self.clock;
+            self.clock += 1.;
+            if before < sps / 2. && self.clock >= sps / 2. {
+                self.put_level(sample);
+            }
+            if self.clock >= sps {
+                self.clock -= sps;
+            }
+        }
+    }
+
+    fn put_level(&mut self, level: u8) {
+        let bit = match &mut self.nrzi {
+            Some(nrzi) => nrzi.decode(level),
+            None => level,
+        };
+        if let Some(frame) = self.decoder.put_bit(bit) {
+            self.to_host.send(frame).unwrap();
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use crate::backend::{DteBackend, DteChannels};
+use crate::port::PortOptions;
+use crate::ppp::{Event as PppEvent, Ppp, PppConfig};
+use crate::slip::{self, PacketStats, SlipDecoder};
+use crossbeam_channel::{select, Receiver};
+use std::net::Ipv4Addr;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+
+/// Pause before negotiating again a PPP link which went down.
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+
+/// Where the IP packets carried over the modem come from and go to.
+pub trait Network {
+    /// Name for the log, e.g. of the interface.
+    fn name(&self) -> String;
+
+    /// Sets the MTU and the addresses of the link, once they are known.
+    fn up(
+        &mut self,
+        mtu: usize,
+        address: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()>;
+
+    /// Delivers a packet received over the modem.
+    fn send(&mut self, packet: &[u8]) -> anyhow::Result<()>;
+}
+
+/// Source, destination and protocol of an IPv4 packet, for the log.
+pub fn describe(packet: &[u8]) -> String {
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return "non-IPv4".to_string();
+    }
+    let address = |b: &[u8]| Ipv4Addr::new(b[0], b[1], b[2], b[3]);
+    let protocol = match packet[9] {
+        1 => "ICMP".to_string(),
+        6 => "TCP".to_string(),
+        17 => "UDP".to_string(),
+        other => format!("protocol {}", other),
+    };
+    format!(
+        "{} > {} {}",
+        address(&packet[12..16]),
+        address(&packet[16..20]),
+        protocol
+    )
+}
+
+/// Link layer spoken over the modem.
+enum Framing {
+    Slip(SlipDecoder),
+    Ppp(Box<Ppp>),
+}
+
+/// IP over the modem: the DTE side speaks SLIP or PPP, whose packets go to
+/// and come from a network.
+pub struct IpBackend {
+    network: Box<dyn Network>,
+    framing: Framing,
+    channels: DteChannels,
+    /// Packets the network sends over the modem
+    from_network: Receiver<Vec<u8>>,
+    rx_stats: PacketStats,
+    tx_stats: PacketStats,
+    /// When to negotiate again after the PPP link went down
+    reopen: Option<Instant>,
+}
+
+impl IpBackend {
+    pub fn open(
+        options: &PortOptions,
+        channels: DteChannels,
+        mut network: Box<dyn Network>,
+        from_network: Receiver<Vec<u8>>,
+    ) -> anyhow::Result<Self> {
+        let framing = if options.ppp {
+            // the addresses are only known once IPCP is done
+            network.up(options.mtu, None, None)?;
+            Framing::Ppp(Box::new(Ppp::new(PppConfig {
+                address: options.address,
+                peer: options.peer,
+                mru: options.mtu as u16,
+                user: options.user.clone(),
+                password: options.password.clone(),
+                auth: options.auth,
+            })))
+        } else {
+            network.up(options.mtu, options.address, options.peer)?;
+            Framing::Slip(SlipDecoder::new(options.mtu))
+        };
+
+        let backend = Self {
+            network,
+            framing,
+            channels,
+            from_network,
+            rx_stats: PacketStats::default(),
+            tx_stats: PacketStats::default(),
+            reopen: None,
+        };
+        eprintln!(
+            "serial port: {} on {}",
+            backend.name(),
+            backend.network.name()
+        );
+        Ok(backend)
+    }
+
+    fn name(&self) -> &'static str {
+        match self.framing {
+            Framing::Slip(_) => "slip",
+            Framing::Ppp(_) => "ppp",
+        }
+    }
+
+    /// Sends to the modem a byte at a time, as a host honouring CTS would.
+    fn send_to_modem(&self, bytes: &[u8]) {
+        for b in bytes {
+            while !self.channels.cts.load(Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            self.channels.to_modem.send(*b).unwrap();
+        }
+    }
+
+    fn put_byte(&mut self, byte: u8, now: Instant) {
+        let mut out = vec![];
+        let packet = match &mut self.framing {
+            Framing::Slip(decoder) => decoder.put_byte(byte),
+            Framing::Ppp(ppp) => match ppp.put_byte(byte, now, &mut out) {
+                Some(PppEvent::Packet(packet)) => Some(Ok(packet)),
+                Some(event) => {
+                    self.send_to_modem(&out);
+                    self.ppp_event(event, now);
+                    return;
+                }
+                None => None,
+            },
+        };
+        self.send_to_modem(&out);
+        match packet {
+            Some(Ok(packet)) => {
+                self.rx_stats.count(&packet);
+                self.log("in", &packet, self.rx_stats);
+                if let Err(err) = self.network.send(&packet) {
+                    eprintln!("{}: {}", self.name(), err);
+                }
+            }
+            Some(Err(err)) => {
+                self.rx_stats.errors += 1;
+                eprintln!(
+                    "{}: in {} ({} errors)",
+                    self.name(),
+                    err,
+                    self.rx_stats.errors
+                );
+            }
+            None => {}
+        }
+    }
+
+    fn send_packet(&mut self, packet: &[u8]) {
+        let mut out = vec![];
+        match &mut self.framing {
+            Framing::Slip(_) => slip::encode(packet, &mut out),
+            // IP waits for IPCP
+            Framing::Ppp(ppp) => {
+                if !ppp.send_packet(packet, &mut out) {
+                    return;
+                }
+            }
+        }
+        self.tx_stats.count(packet);
+        self.log("out", packet, self.tx_stats);
+        self.send_to_modem(&out);
+    }
+
+    fn ppp_event(&mut self, event: PppEvent, now: Instant) {
+        match event {
+            PppEvent::Up { address, peer, mtu } => {
+                eprintln!("ppp: up, {} > {}, MTU {}", address, peer, mtu);
+                if let Err(err) = self.network.up(mtu, Some(address), Some(peer)) {
+                    eprintln!("ppp: {}", err);
+                }
+            }
+            PppEvent::Down(reason) => {
+                eprintln!("ppp: down, {}", reason);
+                self.reopen = Some(now + REOPEN_DELAY);
+            }
+            PppEvent::Packet(_) => unreachable!(),
+        }
+    }
+
+    fn log(&self, direction: &str, packet: &[u8], stats: PacketStats) {
+        eprintln!(
+            "{}: {} {}, {} bytes ({} packets, {} bytes)",
+            self.name(),
+            direction,
+            describe(packet),
+            packet.len(),
+            stats.packets,
+            stats.bytes
+        );
+    }
+}
+
+impl DteBackend for IpBackend {
+    fn event_loop(&mut self) -> anyhow::Result<()> {
+        // the network is there for as long as we run
+        self.channels.dtr.send(true).unwrap();
+        if matches!(self.framing, Framing::Ppp(_)) {
+            self.reopen = Some(Instant::now());
+        }
+        loop {
+            select! {
+                recv(self.channels.from_modem) -> b => self.put_byte(b?, Instant::now()),
+                recv(self.from_network) -> packet => self.send_packet(&packet?),
+                default(Duration::from_millis(100)) => {}
+            }
+
+            let now = Instant::now();
+            let Framing::Ppp(ppp) = &mut self.framing else {
+                continue;
+            };
+            let mut out = vec![];
+            if let Some(reopen) = self.reopen {
+                // unless the peer started over in the meantime
+                if !ppp.is_closed() {
+                    self.reopen = None;
+                } else if now >= reopen {
+                    ppp.open(now, &mut out);
+                    self.reopen = None;
+                }
+            }
+            let event = ppp.poll(now, &mut out);
+            self.send_to_modem(&out);
+            if let Some(event) = event {
+                self.ppp_event(event, now);
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::f32::consts::PI;
+
+/// Kansas City Standard and CUTS both represent a 1 (mark) by 2400 Hz cycles
+/// and a 0 (space) by 1200 Hz cycles, so that every bit holds a whole number
+/// of cycles: 8 or 4 cycles at 300 baud (KCS), 2 or 1 cycles at 1200 baud
+/// (CUTS).
+pub const MARK_FREQ: f32 = 2400.;
+pub const SPACE_FREQ: f32 = 1200.;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KcsFormat {
+    /// Kansas City Standard, 300 baud
+    Kcs,
+    /// Computer Users' Tape Standard, 1200 baud
+    Cuts,
+}
+
+impl KcsFormat {
+    pub fn baud_rate(&self) -> usize {
+        match self {
+            KcsFormat::Kcs => 300,
+            KcsFormat::Cuts => 1200,
+        }
+    }
+
+    pub fn data_bits(&self) -> usize {
+        8
+    }
+
+    pub fn stop_bits(&self) -> f32 {
+        2.
+    }
+}
+
+pub struct KcsTX {
+    sampling_period: f32,
+    phase: f32,
+    last_bit: u8,
+}
+
+impl KcsTX {
+    pub fn new(sampling_period: f32) -> Self {
+        Self {
+            sampling_period,
+            phase: 0.,
+            last_bit: 1,
+        }
+    }
+
+    pub fn modulate(&mut self, in_samples: &[u8], out_samples: &mut [f32]) {
+        debug_assert!(in_samples.len() == out_samples.len());
+
+        for (bit, out) in in_samples.iter().zip(out_samples.iter_mut()) {
+            // every bit starts at the beginning of a cycle
+            if *bit != self.last_bit {
+                self.phase = 0.;
+                self.last_bit = *bit;
+            }
+
+            *out = self.phase.sin();
+
+            let freq = if *bit == 0 { SPACE_FREQ } else { MARK_FREQ };
+            self.phase = (self.phase + 2. * PI * freq * self.sampling_period) % (2. * PI);
+        }
+    }
+}
+
+/// Cycle-counting demodulator: each half cycle is classified as mark or
+/// space according to its duration, which tolerates the speed variations of
+/// a tape transport much better than filtering around fixed frequencies.
+pub struct KcsRX {
+    sampling_period: f32,
+    envelope: f32,
+    positive: bool,
+    since_crossing: usize,
+    max_half_cycle: usize,
+    bit: u8,
+}
+
+impl KcsRX {
+    pub fn new(sampling_period: f32) -> Self {
+        Self {
+            sampling_period,
+            envelope: 0.,
+            positive: false,
+            since_crossing: 0,
+            // twice the half cycle of a space tone, after which the line is
+            // considered idle
+            max_half_cycle: (1. / (SPACE_FREQ * sampling_period)).round() as usize,
+            bit: 1,
+        }
+    }
+
+    pub fn demodulate(&mut self, in_samples: &[f32], out_samples: &mut [u8]) {
+        debug_assert!(in_samples.len() == out_samples.len());
+
+        // half cycle duration at the 1800 Hz midpoint between both tones
+        let threshold = 1. / (2. * 1800. * self.sampling_period);
+        // envelope follower time constant of about 10 ms
+        let decay = (-self.sampling_period / 0.01).exp();
+
+        for (sample, out) in in_samples.iter().zip(out_samples.iter_mut()) {
+            self.envelope = (self.envelope * decay).max(sample.abs());
+            self.since_crossing += 1;
+
+            // Schmitt trigger, so that noise around zero is not mistaken
+            // for extra cycles
+            let hysteresis = 0.3 * self.envelope;
+            let crossed = if self.positive {
+                *sample < -hysteresis
+            } else {
+                *sample > hysteresis
+            };
+            if crossed {
+                self.positive = !self.positive;
+                let half_cycle = self.since_crossing as f32;
+                self.bit = if half_cycle < threshold { 1 } else { 0 };
+                self.since_crossing = 0;
+            } else if self.since_crossing > self.max_half_cycle {
+                self.bit = 1;
+            }
+
+            *out = self.bit;
+        }
+    }
+}
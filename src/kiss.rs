@@ -0,0 +1,118 @@
+/// KISS framing between the host and the TNC (https://www.ax25.net/kiss.aspx).
+pub const FEND: u8 = 0xc0;
+pub const FESC: u8 = 0xdb;
+pub const TFEND: u8 = 0xdc;
+pub const TFESC: u8 = 0xdd;
+
+const MAX_FRAME_LEN: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Data(Vec<u8>),
+    /// Keyup delay, in units of 10 ms
+    TxDelay(u8),
+    Persistence(u8),
+    /// Slot interval, in units of 10 ms
+    SlotTime(u8),
+    TxTail(u8),
+    FullDuplex(bool),
+    SetHardware(Vec<u8>),
+    /// Leave KISS mode
+    Return,
+}
+
+impl Command {
+    fn parse(frame: &[u8]) -> Option<Self> {
+        let (&type_byte, data) = frame.split_first()?;
+        if type_byte == 0xff {
+            return Some(Command::Return);
+        }
+        // only port 0 exists
+        if type_byte >> 4 != 0 {
+            return None;
+        }
+        let param = data.first().copied();
+        match type_byte & 0x0f {
+            0 => Some(Command::Data(data.to_vec())),
+            1 => param.map(Command::TxDelay),
+            2 => param.map(Command::Persistence),
+            3 => param.map(Command::SlotTime),
+            4 => param.map(Command::TxTail),
+            5 => param.map(|p| Command::FullDuplex(p != 0)),
+            6 => Some(Command::SetHardware(data.to_vec())),
+            _ => None,
+        }
+    }
+}
+
+/// Extracts commands from the byte stream sent by the host.
+pub struct KissDecoder {
+    frame: Vec<u8>,
+    escape: bool,
+    overflow: bool,
+}
+
+impl KissDecoder {
+    pub fn new() -> Self {
+        Self {
+            frame: vec![],
+            escape: false,
+            overflow: false,
+        }
+    }
+
+    pub fn put_byte(&mut self, byte: u8) -> Option<Command> {
+        if byte == FEND {
+            let frame = std::mem::take(&mut self.frame);
+            let overflow = self.overflow;
+            self.escape = false;
+            self.overflow = false;
+            return if overflow {
+                None
+            } else {
+                Command::parse(&frame)
+            };
+        }
+
+        let byte = if self.escape {
+            self.escape = false;
+            match byte {
+                TFEND => FEND,
+                TFESC => FESC,
+                other => other,
+            }
+        } else if byte == FESC {
+            self.escape = true;
+            return None;
+        } else {
+            byte
+        };
+
+        if self.frame.len() > MAX_FRAME_LEN {
+            self.overflow = true;
+        } else {
+            self.frame.push(byte);
+        }
+        None
+    }
+}
+
+impl Default for KissDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Wraps a frame received from the line into a KISS data frame for port 0.
+pub fn encode(frame: &[u8], out: &mut Vec<u8>) {
+    out.push(FEND);
+    out.push(0x00);
+    for byte in frame {
+        match *byte {
+            FEND => out.extend([FESC, TFEND]),
+            FESC => out.extend([FESC, TFESC]),
+            other => out.push(other),
+        }
+    }
+    out.push(FEND);
+}
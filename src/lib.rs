@@ -1,2 +1,40 @@
+pub mod afsk;
+pub mod at;
+pub mod ax25;
+pub mod backend;
+pub mod baudot;
+pub mod callerid;
+pub mod carrier;
+pub mod class1;
+pub mod dtmf;
+pub mod flow;
+pub mod fsk;
+pub mod hdlc;
+pub mod ip;
+pub mod kcs;
+pub mod kiss;
+pub mod md5;
+pub mod mnp;
+pub mod mnp5;
+pub mod port;
+pub mod ppp;
+pub mod pump;
+pub mod ring;
+#[cfg_attr(unix, path = "serial_linux.rs")]
+#[cfg_attr(windows, path = "serial_windows.rs")]
+pub mod serial;
+pub mod slip;
+pub mod stack;
+pub mod t30;
+pub mod tcp;
+pub mod telnet;
+#[cfg(target_os = "linux")]
+pub mod tun;
+pub mod v18;
 pub mod v21;
+pub mod v25;
+pub mod v27ter;
+pub mod v29;
+pub mod v42;
+pub mod v42bis;
 pub mod uart;
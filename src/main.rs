@@ -1,31 +1,170 @@
-#[cfg_attr(unix, path = "serial_linux.rs")]
-#[cfg_attr(windows, path = "serial_windows.rs")]
-mod serial;
-mod uart;
-mod v21;
-
-use crate::serial::Serial;
-use crate::uart::{UartRx, UartTx};
-use crate::v21::{V21RX, V21TX};
-use anyhow;
-use clap::Parser;
+mod cassette;
+
+use anyhow::{self, Context};
+use clap::{Parser, Subcommand, ValueEnum};
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
     BuildStreamError, FromSample, SizedSample, Stream,
 };
-use crossbeam_channel::unbounded;
+use crossbeam_channel::{select, unbounded, Receiver, Sender};
+use modem::afsk::AfskRX;
+use modem::at::{
+    AtInterpreter, Command, EscapeDetector, FaxCommand, ResultCode, S_AUTO_ANSWER, S_CARRIER_LOSS,
+    S_CARRIER_WAIT, S_ESCAPE, S_GUARD_TIME, S_RING_COUNT,
+};
+use modem::ax25;
+use modem::backend::{self, DteChannels};
+use modem::baudot::{self, BaudotDecoder, BaudotEncoder};
+use modem::callerid::{self, CallerId, CallerIdRx};
+use modem::carrier::{CarrierDetector, CarrierWatch};
+use modem::class1::{self, DleDecoder, DLE, ETX};
+use modem::dtmf::DtmfTX;
+use modem::flow::{self, FlowControl, Watermarks};
+use modem::fsk::{Charset, Framing, FskChannel, FskProfile, Role};
+use modem::hdlc::{self, HdlcRx, HdlcTx};
+use modem::kcs::KcsFormat;
+use modem::kiss::{self, KissDecoder};
+use modem::mnp::{Mnp, MnpConfig};
+use modem::port::PortOptions;
+use modem::pump::{DataPumpRX, DataPumpTX, Modulation};
+use modem::ring::RingDetector;
+use modem::t30::{self, V21HdlcRx, V21HdlcTx};
+use modem::uart::{UartRx, UartTx};
+use modem::v18::{Automode, Detected};
+use modem::v21::{V21RX, V21TX};
+use modem::v25::AnswerSequence;
+use modem::v42::{self, ErrorControl, Lapm, LapmConfig};
+use modem::v42bis::{self, CompressionSettings, Counters, V42bisConfig};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::sync::Mutex;
-use std::{f32::consts::PI, sync::Arc};
+use std::time::{Duration, Instant};
+
+#[cfg(unix)]
+const DEFAULT_SERDEV: &str = "pty";
+#[cfg(windows)]
+const DEFAULT_SERDEV: &str = "\\\\.\\COM3";
+
+/// How often the timers of the DTE are looked at when nothing happens.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Time without rings after which the ring count starts again.
+const RING_TIMEOUT: Duration = Duration::from_secs(8);
+
+/// Pseudo-modulation which probes the far end with V.18 automode.
+const AUTOMODE: &str = "V18";
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum BaudotVariant {
+    Ita2,
+    UsTty,
+}
+
+impl From<BaudotVariant> for baudot::Variant {
+    fn from(variant: BaudotVariant) -> Self {
+        match variant {
+            BaudotVariant::Ita2 => baudot::Variant::Ita2,
+            BaudotVariant::UsTty => baudot::Variant::UsTty,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum CallerIdVariant {
+    /// Bell 202 FSK
+    Bell202,
+    /// ETSI V.23 FSK
+    V23,
+    /// DTMF digits
+    Dtmf,
+}
 
-const BAUD_RATE: usize = 300;
+impl From<CallerIdVariant> for callerid::Variant {
+    fn from(variant: CallerIdVariant) -> Self {
+        match variant {
+            CallerIdVariant::Bell202 => callerid::Variant::Bell202,
+            CallerIdVariant::V23 => callerid::Variant::V23,
+            CallerIdVariant::Dtmf => callerid::Variant::Dtmf,
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum TapeFormat {
+    /// Kansas City Standard, 300 baud
+    Kcs,
+    /// CUTS, 1200 baud
+    Cuts,
+}
+
+impl From<TapeFormat> for KcsFormat {
+    fn from(format: TapeFormat) -> Self {
+        match format {
+            TapeFormat::Kcs => KcsFormat::Kcs,
+            TapeFormat::Cuts => KcsFormat::Cuts,
+        }
+    }
+}
+
+#[derive(Subcommand, Debug)]
+enum Cassette {
+    /// Record a binary file as cassette audio
+    Encode {
+        input: PathBuf,
+        /// WAV file to write
+        output: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = TapeFormat::Kcs)]
+        format: TapeFormat,
+        /// Sampling rate of the WAV file
+        #[arg(long, default_value_t = 48000)]
+        srate: usize,
+        /// Seconds of mark tone before the data
+        #[arg(long, default_value_t = 5.)]
+        leader: f32,
+    },
+    /// Read the bytes recorded in cassette audio
+    Decode {
+        /// WAV file to read
+        input: PathBuf,
+        output: PathBuf,
+        #[arg(short, long, value_enum, default_value_t = TapeFormat::Kcs)]
+        format: TapeFormat,
+    },
+}
 
 #[derive(Parser, Debug)]
 #[command(version, about = "Dial-up modem", long_about = None)]
 struct Opt {
+    #[command(subcommand)]
+    cassette: Option<Cassette>,
+
     /// Answer side
     #[arg(short, long, default_value_t = false)]
     answer: bool,
 
+    /// FSK profile to use when the modem starts (V21, BELL103, BELL202, V23,
+    /// RTTY, RTTY50, TDD, EDT, KCS or the name of a custom profile), or V18 to
+    /// detect the text telephone on the far end
+    #[arg(short, long, default_value_t = String::from("V21"))]
+    mode: String,
+
+    /// TOML file containing custom FSK profiles
+    #[arg(short, long)]
+    profiles: Option<PathBuf>,
+
+    /// Figures table used by Baudot profiles
+    #[arg(long, value_enum, default_value_t = BaudotVariant::UsTty)]
+    baudot_variant: BaudotVariant,
+
+    /// Return Baudot profiles to the letters case after each space
+    #[arg(long, default_value_t = false)]
+    unshift_on_space: bool,
+
+    /// Signal carrying the caller ID reported by `AT+VCID`
+    #[arg(long, value_enum, default_value_t = CallerIdVariant::Bell202)]
+    caller_id: CallerIdVariant,
+
     /// Audio device to use for RX
     #[arg(short, long, default_value_t = String::from("default"))]
     rxdev: String,
@@ -34,14 +173,497 @@ struct Opt {
     #[arg(short, long, default_value_t = String::from("default"))]
     txdev: String,
 
-    /// Serial device (Windows-only)
-    #[arg(short, long, default_value_t = String::from("\\\\.\\COM3"))]
+    /// Serial port: `pty` for a new pseudo-terminal or a tty on Linux, a COM
+    /// port on Windows, `tcp-listen:ADDR:PORT`, `tcp:HOST:PORT`, `stdio`,
+    /// `tun[:NAME]` or `stack`, optionally followed by `,speed=N`, the
+    /// framing (e.g. `,8N1`), `,flow=none|rtscts|xonxoff`, for a pty
+    /// `,mode=0660` and `,link=PATH`, for TCP `,telnet`, for a TUN interface
+    /// or the stack `,addr=`, `,peer=`, `,mtu=` and `,ppp` with `,user=`,
+    /// `,password=` and `,auth=pap|chap`, and for the stack
+    /// `,socks=[ADDR:]PORT` and `,forward=[ADDR:]PORT:HOST:PORT`
+    #[arg(short, long, default_value_t = String::from(DEFAULT_SERDEV))]
     serdev: String,
+
+    /// Symlink to create, or to update, pointing to the pty (Linux-only)
+    #[arg(long)]
+    link: Option<PathBuf>,
+
+    /// Bytes waiting to be sent to the line above which the host is told to
+    /// stop sending
+    #[arg(long, default_value_t = 64)]
+    flow_high: usize,
+
+    /// Bytes waiting to be sent to the line at which the host may send again
+    #[arg(long, default_value_t = 16)]
+    flow_low: usize,
+
+    /// Start in data mode, as if a call had just been connected, instead of
+    /// on-hook in command mode
+    #[arg(long, default_value_t = false)]
+    online: bool,
+}
+
+/// Mark tone sent before the first character when a carrier-on-demand
+/// profile turns its carrier on, and kept after the last one.
+const CARRIER_LEAD_IN: Duration = Duration::from_millis(150);
+const CARRIER_HANGOVER: Duration = Duration::from_millis(300);
+
+/// Turns what the DTE sends into audio, each framer with its modulator.
+enum TxFramer {
+    Async(UartTx, V21TX),
+    /// Synchronous HDLC
+    Hdlc(V21HdlcTx),
+    /// AX.25 packets
+    Packet(HdlcTx, V21TX),
+}
+
+impl TxFramer {
+    fn pending_samples(&self) -> usize {
+        match self {
+            TxFramer::Async(uart_tx, _) => uart_tx.pending_samples(),
+            TxFramer::Hdlc(v21_hdlc_tx) => v21_hdlc_tx.pending_samples(),
+            TxFramer::Packet(hdlc_tx, _) => hdlc_tx.pending_samples(),
+        }
+    }
+
+    /// Samples the carrier stays on after the last queued one, on
+    /// carrier-on-demand profiles: `V21HdlcTx` drops it right after the
+    /// closing flag, and the others send marks for a while.
+    fn hangover_samples(&self, srate: usize) -> usize {
+        match self {
+            TxFramer::Hdlc(_) => 0,
+            _ => (CARRIER_HANGOVER.as_secs_f32() * srate as f32) as usize,
+        }
+    }
+
+    fn get_samples(&mut self, out: &mut [f32]) {
+        let mut levels = vec![1; out.len()];
+        let v21_tx = match self {
+            TxFramer::Async(uart_tx, v21_tx) => {
+                uart_tx.get_samples(&mut levels);
+                v21_tx
+            }
+            TxFramer::Hdlc(v21_hdlc_tx) => return v21_hdlc_tx.get_samples(out),
+            TxFramer::Packet(hdlc_tx, v21_tx) => {
+                hdlc_tx.get_samples(&mut levels);
+                v21_tx
+            }
+        };
+        v21_tx.modulate(&levels, out);
+    }
+
+    fn set_channel(&mut self, channel: &FskChannel) {
+        match self {
+            TxFramer::Async(_, v21_tx) | TxFramer::Packet(_, v21_tx) => {
+                v21_tx.set_omegas(channel.omega_mark(), channel.omega_space())
+            }
+            TxFramer::Hdlc(v21_hdlc_tx) => v21_hdlc_tx.set_channel(channel),
+        }
+    }
+}
+
+struct TxChain {
+    framer: TxFramer,
+    /// Fax data pump which, while present, replaces the FSK modulator
+    pump: Option<DataPumpTX>,
+    /// Sent before the carrier when answering a call
+    answer_sequence: Option<AnswerSequence>,
+    /// Digits sent before the carrier when placing a call
+    dialer: Option<DtmfTX>,
+    srate: usize,
+    samples_per_symbol: usize,
+    carrier_on_demand: bool,
+    lead_in_samples: usize,
+    hangover_samples: usize,
+    hangover_remaining: usize,
+    /// Cleared while there is no call, when nothing but the dialing digits
+    /// or the answer sequence is sent
+    carrier_enabled: bool,
+    transmitting: Arc<AtomicBool>,
+}
+
+impl TxChain {
+    fn new(
+        profile: &FskProfile,
+        role: Role,
+        srate: usize,
+        transmitting: Arc<AtomicBool>,
+    ) -> anyhow::Result<Self> {
+        let channel = profile.tx_channel(role);
+        let samples_per_symbol = channel
+            .samples_per_symbol(srate)
+            .context("TX configuration")?;
+        let v21_tx = V21TX::new(
+            1. / srate as f32,
+            channel.omega_mark(),
+            channel.omega_space(),
+        );
+        let framer = match profile.framing {
+            Framing::Async => TxFramer::Async(
+                UartTx::with_framing(
+                    samples_per_symbol,
+                    profile.charset.data_bits(),
+                    profile.charset.stop_bits(),
+                ),
+                v21_tx,
+            ),
+            Framing::Hdlc => {
+                let mut v21_hdlc_tx = V21HdlcTx::with_channel(srate, channel)?;
+                v21_hdlc_tx.set_carrier_on_demand(profile.carrier_on_demand);
+                TxFramer::Hdlc(v21_hdlc_tx)
+            }
+            Framing::HdlcNrzi => TxFramer::Packet(HdlcTx::new(samples_per_symbol, true), v21_tx),
+        };
+        let lead_in = match profile.framing {
+            Framing::Hdlc => t30::PREAMBLE,
+            _ => CARRIER_LEAD_IN,
+        };
+        transmitting.store(false, Ordering::Relaxed);
+        Ok(Self {
+            hangover_samples: framer.hangover_samples(srate),
+            framer,
+            pump: None,
+            answer_sequence: None,
+            dialer: None,
+            srate,
+            samples_per_symbol,
+            carrier_on_demand: profile.carrier_on_demand,
+            lead_in_samples: (lead_in.as_secs_f32() * srate as f32) as usize,
+            hangover_remaining: 0,
+            carrier_enabled: true,
+            transmitting,
+        })
+    }
+
+    fn carrier_off(&self) -> bool {
+        self.carrier_on_demand
+            && self.hangover_remaining == 0
+            && self.framer.pending_samples() == 0
+            && self.pump.is_none()
+    }
+
+    /// Characters or frame bytes still to be sent.
+    fn pending_bytes(&self) -> usize {
+        match &self.framer {
+            TxFramer::Async(uart_tx, _) => uart_tx.pending_bytes(),
+            framer => framer
+                .pending_samples()
+                .div_ceil(8 * self.samples_per_symbol),
+        }
+    }
+
+    fn put_byte(&mut self, byte: u8) {
+        let carrier_off = self.carrier_off();
+        if let TxFramer::Async(uart_tx, _) = &mut self.framer {
+            if carrier_off {
+                uart_tx.put_idle(self.lead_in_samples);
+            }
+            uart_tx.put_byte(byte);
+        }
+    }
+
+    fn put_frame(&mut self, frame: &[u8]) {
+        let carrier_off = self.carrier_off();
+        match &mut self.framer {
+            TxFramer::Hdlc(v21_hdlc_tx) => v21_hdlc_tx.send_frame(frame),
+            TxFramer::Packet(hdlc_tx, _) => {
+                // flags take the place of the mark tone as lead-in, and at
+                // least one is needed to open the frame
+                let flags = if carrier_off {
+                    self.lead_in_samples.div_ceil(8 * self.samples_per_symbol)
+                } else {
+                    0
+                };
+                hdlc_tx.put_flags(flags.max(1));
+                hdlc_tx.put_frame(frame);
+            }
+            TxFramer::Async(..) => {}
+        }
+    }
+
+    /// Retunes the modulator to the channel of another role.
+    fn set_channel(&mut self, channel: &FskChannel) {
+        self.framer.set_channel(channel);
+    }
+
+    fn start_answer(&mut self) {
+        self.answer_sequence = Some(AnswerSequence::new(1. / self.srate as f32));
+    }
+
+    fn start_dialing(&mut self, digits: &str) {
+        let mut dialer = DtmfTX::new(1. / self.srate as f32);
+        dialer.put_digits(digits);
+        self.dialer = Some(dialer);
+    }
+
+    /// Tells whether the digits or the answer sequence are still being sent.
+    fn connecting(&self) -> bool {
+        self.answer_sequence.is_some() || self.dialer.is_some()
+    }
+
+    fn set_lead_in(&mut self, lead_in: Duration) {
+        self.lead_in_samples = (lead_in.as_secs_f32() * self.srate as f32) as usize;
+        if let TxFramer::Hdlc(v21_hdlc_tx) = &mut self.framer {
+            v21_hdlc_tx.set_preamble(lead_in);
+        }
+    }
+
+    fn get_samples(&mut self, out: &mut [f32]) {
+        if let Some(dialer) = &mut self.dialer {
+            dialer.modulate(out);
+            if dialer.is_idle() {
+                self.dialer = None;
+            }
+            return;
+        }
+
+        let out = match &mut self.answer_sequence {
+            Some(answer_sequence) => {
+                let written = answer_sequence.modulate(out);
+                if !answer_sequence.is_done() {
+                    return;
+                }
+                self.answer_sequence = None;
+                &mut out[written..]
+            }
+            None => out,
+        };
+
+        if !self.carrier_enabled {
+            out.fill(0.);
+            self.transmitting.store(false, Ordering::Relaxed);
+            return;
+        }
+
+        if let Some(pump) = &mut self.pump {
+            pump.modulate(out);
+            if pump.is_idle() {
+                self.pump = None;
+            }
+            self.transmitting
+                .store(self.pump.is_some(), Ordering::Relaxed);
+            return;
+        }
+
+        let pending = self.framer.pending_samples();
+
+        self.framer.get_samples(out);
+
+        if self.carrier_on_demand {
+            for (i, sample) in out.iter_mut().enumerate() {
+                if i < pending {
+                    self.hangover_remaining = self.hangover_samples;
+                } else if self.hangover_remaining > 0 {
+                    self.hangover_remaining -= 1;
+                } else {
+                    *sample = 0.;
+                }
+            }
+            self.transmitting
+                .store(self.hangover_remaining > 0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Recovers what the DTE should receive from the demodulated line levels.
+enum RxFramer {
+    Async(V21RX, UartRx),
+    /// Synchronous HDLC, as used for fax signalling
+    Hdlc(V21HdlcRx),
+    /// AX.25 packets, demodulated without relying on `V21RX`
+    Packet(AfskRX, HdlcRx),
+}
+
+/// What the fax data pump receiver reports to the DTE.
+enum PumpEvent {
+    Carrier,
+    Data(Vec<u8>),
+    NoCarrier,
+}
+
+struct RxChain {
+    framer: RxFramer,
+    /// Fax data pump which, while present, replaces the FSK demodulator
+    pump: Option<(DataPumpRX, Sender<PumpEvent>)>,
+    /// Caller ID decoder, present while in command mode with `+VCID` enabled
+    caller_id: Option<(CallerIdRx, Sender<CallerId>)>,
+    /// Present while in command mode
+    ring: Option<(RingDetector, Sender<()>)>,
+    /// Present during a call, on profiles with a continuous carrier, and in
+    /// fax mode
+    carrier: Option<(CarrierDetector, Sender<bool>)>,
+    echo_suppression: Option<Arc<AtomicBool>>,
+    automode: Option<(Automode, Sender<Detected>)>,
+}
+
+impl RxChain {
+    fn new(
+        profile: &FskProfile,
+        role: Role,
+        srate: usize,
+        to_dte: Sender<u8>,
+        frames_to_dte: Sender<hdlc::Frame>,
+        transmitting: Arc<AtomicBool>,
+    ) -> anyhow::Result<Self> {
+        let channel = profile.rx_channel(role);
+        let samples_per_symbol = channel
+            .samples_per_symbol(srate)
+            .context("RX configuration")?;
+        let speriod = 1. / srate as f32;
+        let framer = match profile.framing {
+            Framing::Async => RxFramer::Async(
+                V21RX::new(
+                    speriod,
+                    samples_per_symbol,
+                    channel.omega_mark(),
+                    channel.omega_space(),
+                ),
+                UartRx::with_framing(
+                    samples_per_symbol,
+                    profile.charset.data_bits(),
+                    profile.charset.stop_bits(),
+                    to_dte,
+                ),
+            ),
+            Framing::Hdlc => {
+                RxFramer::Hdlc(V21HdlcRx::with_channel(srate, channel, frames_to_dte)?)
+            }
+            Framing::HdlcNrzi => RxFramer::Packet(
+                AfskRX::new(
+                    speriod,
+                    samples_per_symbol,
+                    channel.omega_mark(),
+                    channel.omega_space(),
+                ),
+                HdlcRx::new(samples_per_symbol, true, frames_to_dte),
+            ),
+        };
+        Ok(Self {
+            framer,
+            pump: None,
+            caller_id: None,
+            ring: None,
+            carrier: None,
+            echo_suppression: profile.carrier_on_demand.then_some(transmitting),
+            automode: None,
+        })
+    }
+
+    /// Retunes the demodulator to the channel of another role.
+    fn set_channel(&mut self, channel: &FskChannel) {
+        let (omega_mark, omega_space) = (channel.omega_mark(), channel.omega_space());
+        match &mut self.framer {
+            RxFramer::Async(v21_rx, _) => v21_rx.set_omegas(omega_mark, omega_space),
+            RxFramer::Hdlc(v21_hdlc_rx) => v21_hdlc_rx.set_channel(channel),
+            RxFramer::Packet(afsk_rx, _) => afsk_rx.set_omegas(omega_mark, omega_space),
+        }
+    }
+
+    fn put_samples(&mut self, in_samples: &[f32]) {
+        if let Some((automode, to_dte)) = &mut self.automode {
+            if let Some(detected) = automode.put_samples(in_samples) {
+                to_dte.send(detected).unwrap();
+                self.automode = None;
+            }
+            return;
+        }
+
+        if let Some((carrier_detector, to_dte)) = &mut self.carrier {
+            let carrier = carrier_detector.carrier();
+            carrier_detector.put_samples(in_samples);
+            if carrier_detector.carrier() != carrier {
+                to_dte.send(carrier_detector.carrier()).unwrap();
+            }
+        }
+
+        if let Some((ring_detector, to_dte)) = &mut self.ring {
+            for _ in 0..ring_detector.put_samples(in_samples) {
+                to_dte.send(()).unwrap();
+            }
+        }
+
+        if let Some((caller_id_rx, to_dte)) = &mut self.caller_id {
+            let mut received = vec![];
+            caller_id_rx.put_samples(in_samples, &mut received);
+            for caller_id in received {
+                match caller_id {
+                    Ok(caller_id) => to_dte.send(caller_id).unwrap(),
+                    Err(err) => eprintln!("caller ID: {}", err),
+                }
+            }
+            return;
+        }
+
+        if let Some((pump_rx, to_dte)) = &mut self.pump {
+            let carrier = pump_rx.carrier();
+            let mut data = vec![];
+            pump_rx.demodulate(in_samples, &mut data);
+            if !carrier && pump_rx.carrier() {
+                to_dte.send(PumpEvent::Carrier).unwrap();
+            }
+            if !data.is_empty() {
+                to_dte.send(PumpEvent::Data(data)).unwrap();
+            }
+            if carrier && !pump_rx.carrier() {
+                to_dte.send(PumpEvent::NoCarrier).unwrap();
+                self.pump = None;
+            }
+            return;
+        }
+
+        let mut framer_in = vec![1; in_samples.len()];
+        let muted = self
+            .echo_suppression
+            .as_ref()
+            .is_some_and(|transmitting| transmitting.load(Ordering::Relaxed));
+        match &mut self.framer {
+            RxFramer::Async(v21_rx, uart_rx) => {
+                if !muted {
+                    v21_rx.demodulate(in_samples, &mut framer_in);
+                }
+                uart_rx.put_samples(&framer_in);
+            }
+            RxFramer::Hdlc(v21_hdlc_rx) => {
+                // frames are only looked for while there is a carrier
+                let carrier = self
+                    .carrier
+                    .as_ref()
+                    .is_none_or(|(carrier_detector, _)| carrier_detector.carrier());
+                if muted || !carrier {
+                    v21_hdlc_rx.put_muted(in_samples.len());
+                } else {
+                    v21_hdlc_rx.put_samples(in_samples);
+                }
+            }
+            RxFramer::Packet(afsk_rx, hdlc_rx) => {
+                if !muted {
+                    afsk_rx.demodulate(in_samples, &mut framer_in);
+                }
+                hdlc_rx.put_samples(&framer_in);
+            }
+        }
+    }
 }
 
 fn main() -> anyhow::Result<()> {
     let opt = Opt::parse();
 
+    match &opt.cassette {
+        Some(Cassette::Encode {
+            input,
+            output,
+            format,
+            srate,
+            leader,
+        }) => return cassette::encode(input, output, (*format).into(), *srate, *leader),
+        Some(Cassette::Decode {
+            input,
+            output,
+            format,
+        }) => return cassette::decode(input, output, (*format).into()),
+        None => {}
+    }
+
     let host = cpal::default_host();
 
     let rxdev = if opt.rxdev == "default" {
@@ -64,97 +686,1449 @@ fn main() -> anyhow::Result<()> {
     let txcfg = txdev.default_output_config().unwrap();
     eprintln!("TX device: {}, config: {:?}", txdev.name()?, txcfg);
 
-    let (tx_omega0, tx_omega1, rx_omega0, rx_omega1) = if opt.answer {
-        (
-            2. * PI * (1750. + 100.),
-            2. * PI * (1750. - 100.),
-            2. * PI * (1080. + 100.),
-            2. * PI * (1080. - 100.),
-        )
+    let mut profiles = FskProfile::presets();
+    if let Some(path) = &opt.profiles {
+        profiles.extend(FskProfile::load(path)?);
+    }
+    let automode = opt.mode.eq_ignore_ascii_case(AUTOMODE);
+    let profile = if automode {
+        FskProfile::tdd()
     } else {
-        (
-            2. * PI * (1080. + 100.),
-            2. * PI * (1080. - 100.),
-            2. * PI * (1750. + 100.),
-            2. * PI * (1750. - 100.),
-        )
+        FskProfile::find(&profiles, &opt.mode)
+            .with_context(|| format!("unknown FSK profile {}", opt.mode))?
+            .clone()
+    };
+    let role = if opt.answer {
+        Role::Answer
+    } else {
+        Role::Originate
     };
 
-    let tx_srate = txcfg.sample_rate().0 as usize;
-    assert!(
-        tx_srate % BAUD_RATE == 0,
-        "TX sampling rate {} is not a multiple of the baud rate {}",
-        tx_srate,
-        BAUD_RATE
+    let (dte_to_pty, pty_from_dte) = unbounded();
+    let (pty_to_dte, dte_from_pty) = unbounded();
+    let (uart_rx_to_dte, dte_from_uart_rx) = unbounded();
+    let (hdlc_rx_to_dte, dte_from_hdlc_rx) = unbounded();
+    let (automode_to_dte, dte_from_automode) = unbounded();
+    let (pump_to_dte, dte_from_pump) = unbounded();
+    let (caller_id_to_dte, dte_from_caller_id) = unbounded();
+    let (ring_to_dte, dte_from_ring) = unbounded();
+    let (carrier_to_dte, dte_from_carrier) = unbounded();
+    let (dtr_to_dte, dte_from_dtr) = unbounded();
+    let (flow_to_pty, pty_from_flow) = unbounded();
+    let transmitting = Arc::new(AtomicBool::new(false));
+    anyhow::ensure!(
+        opt.flow_low <= opt.flow_high,
+        "--flow-low must not be above --flow-high"
     );
-    let tx_samples_per_symbol = tx_srate / BAUD_RATE;
-    let tx_speriod = 1. / tx_srate as f32;
-
-    let (pty_to_uart_tx, uart_tx_from_pty) = unbounded();
-    let (uart_rx_to_pty, pty_from_uart_rx) = unbounded();
-    let mut serial = Serial::open(&opt.serdev, pty_from_uart_rx, pty_to_uart_tx)?;
-
-    let uart_tx = Arc::new(Mutex::new(UartTx::new(tx_samples_per_symbol)));
-    let v21_tx = V21TX::new(tx_speriod, tx_omega1, tx_omega0);
-
-    {
-        let uart_tx = uart_tx.clone();
-        std::thread::spawn(move || loop {
-            let b = uart_tx_from_pty.recv().unwrap();
-            uart_tx.lock().unwrap().put_byte(b);
-        });
+    let mut port: PortOptions = opt.serdev.parse().context("--serdev")?;
+    if opt.link.is_some() {
+        port.link = opt.link.clone();
     }
+    let cts = Arc::new(AtomicBool::new(true));
+    let mut backend = backend::open(
+        &port,
+        DteChannels {
+            from_modem: pty_from_dte,
+            to_modem: pty_to_dte,
+            dtr: dtr_to_dte,
+            cts: cts.clone(),
+            flow_control: pty_from_flow,
+        },
+    )?;
+
+    let tx_srate = txcfg.sample_rate().0 as usize;
+    let tx_chain = Arc::new(Mutex::new(TxChain {
+        carrier_enabled: opt.online,
+        ..TxChain::new(&profile, role, tx_srate, transmitting.clone())?
+    }));
 
     let tx_stream = match txcfg.sample_format() {
-        cpal::SampleFormat::I8 => tx_run::<i8>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::I16 => tx_run::<i16>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::I32 => tx_run::<i32>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::I64 => tx_run::<i64>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::U8 => tx_run::<u8>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::U16 => tx_run::<u16>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::U32 => tx_run::<u32>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::U64 => tx_run::<u64>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::F32 => tx_run::<f32>(&txdev, &txcfg.into(), uart_tx, v21_tx),
-        cpal::SampleFormat::F64 => tx_run::<f64>(&txdev, &txcfg.into(), uart_tx, v21_tx),
+        cpal::SampleFormat::I8 => tx_run::<i8>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::I16 => tx_run::<i16>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::I32 => tx_run::<i32>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::I64 => tx_run::<i64>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::U8 => tx_run::<u8>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::U16 => tx_run::<u16>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::U32 => tx_run::<u32>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::U64 => tx_run::<u64>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::F32 => tx_run::<f32>(&txdev, &txcfg.into(), tx_chain.clone()),
+        cpal::SampleFormat::F64 => tx_run::<f64>(&txdev, &txcfg.into(), tx_chain.clone()),
         sample_format => panic!("TX: Unsupported sample format '{sample_format}'"),
     }?;
 
     let rx_srate = rxcfg.sample_rate().0 as usize;
-    assert!(
-        rx_srate % BAUD_RATE == 0,
-        "RX sampling rate {} is not a multiple of the baud rate {}",
+    let rx_chain = Arc::new(Mutex::new(RxChain::new(
+        &profile,
+        role,
         rx_srate,
-        BAUD_RATE
-    );
-    let rx_samples_per_symbol = rx_srate / BAUD_RATE;
-    let rx_speriod = 1. / rx_srate as f32;
+        uart_rx_to_dte.clone(),
+        hdlc_rx_to_dte.clone(),
+        transmitting.clone(),
+    )?));
 
-    let uart_rx = UartRx::new(rx_samples_per_symbol, uart_rx_to_pty);
-    let v21_rx = V21RX::new(rx_speriod, rx_samples_per_symbol, rx_omega1, rx_omega0);
     let rx_stream = match rxcfg.sample_format() {
-        cpal::SampleFormat::I8 => rx_run::<i8>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::I16 => rx_run::<i16>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::I32 => rx_run::<i32>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::I64 => rx_run::<i64>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::U8 => rx_run::<u8>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::U16 => rx_run::<u16>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::U32 => rx_run::<u32>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::U64 => rx_run::<u64>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::F32 => rx_run::<f32>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
-        cpal::SampleFormat::F64 => rx_run::<f64>(&rxdev, &rxcfg.into(), uart_rx, v21_rx),
+        cpal::SampleFormat::I8 => rx_run::<i8>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::I16 => rx_run::<i16>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::I32 => rx_run::<i32>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::I64 => rx_run::<i64>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::U8 => rx_run::<u8>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::U16 => rx_run::<u16>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::U32 => rx_run::<u32>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::U64 => rx_run::<u64>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::F32 => rx_run::<f32>(&rxdev, &rxcfg.into(), rx_chain.clone()),
+        cpal::SampleFormat::F64 => rx_run::<f64>(&rxdev, &rxcfg.into(), rx_chain.clone()),
         sample_format => panic!("RX: Unsupported sample format '{sample_format}'"),
     }?;
 
+    let at = AtInterpreter::new();
+    let mut dte = Dte {
+        profiles,
+        profile: profile.clone(),
+        role,
+        baudot_variant: opt.baudot_variant.into(),
+        unshift_on_space: opt.unshift_on_space,
+        caller_id_variant: opt.caller_id.into(),
+        caller_id_report: 0,
+        last_ring: None,
+        call_setup: None,
+        baudot: None,
+        charset: profile.charset,
+        kiss: None,
+        fax: None,
+        in_call: opt.online,
+        flow_control: port.flow_control,
+        port_flow_control: port.flow_control,
+        watermarks: Watermarks::new(opt.flow_high, opt.flow_low),
+        error_control: ErrorControl::Off,
+        link: None,
+        data_compression: CompressionSettings::default(),
+        compression: None,
+        cts,
+        flow_to_pty,
+        carrier: false,
+        carrier_watch: CarrierWatch::default(),
+        online: opt.online,
+        escape: EscapeDetector::from_registers(&at.registers),
+        at,
+        tx_chain,
+        tx_srate,
+        rx_chain,
+        rx_srate,
+        uart_rx_to_dte,
+        hdlc_rx_to_dte,
+        automode_to_dte,
+        pump_to_dte,
+        caller_id_to_dte,
+        ring_to_dte,
+        carrier_to_dte,
+        transmitting,
+        to_pty: dte_to_pty,
+    };
+    dte.set_charset(profile.charset);
+    dte.set_framing(profile.framing);
+    dte.update_line_monitors();
+    if automode {
+        dte.start_automode();
+    }
+    std::thread::spawn(move || {
+        dte.run(DteInputs {
+            from_pty: dte_from_pty,
+            from_uart_rx: dte_from_uart_rx,
+            from_hdlc_rx: dte_from_hdlc_rx,
+            from_automode: dte_from_automode,
+            from_pump: dte_from_pump,
+            from_caller_id: dte_from_caller_id,
+            from_ring: dte_from_ring,
+            from_carrier: dte_from_carrier,
+            from_dtr: dte_from_dtr,
+        })
+    });
+
     tx_stream.play()?;
     rx_stream.play()?;
-    serial.event_loop()
+    backend.event_loop()
+}
+
+/// Fax class 1 state, present while `+FCLASS=1` is in effect.
+struct Fax {
+    /// Profile restored by `+FCLASS=0`
+    data_profile: FskProfile,
+    mode: FaxMode,
+    /// Frames received but not yet requested by `+FRH`
+    received: VecDeque<hdlc::Frame>,
+}
+
+enum FaxMode {
+    Command,
+    /// After `+FTH`, receiving DLE-escaped frames from the host
+    TransmitHdlc(DleDecoder),
+    /// Waiting for the final frame to be sent
+    Draining,
+    /// After `+FRH`, waiting for a frame from the line
+    ReceiveHdlc,
+    /// After `+FTM`, receiving DLE-escaped data from the host
+    TransmitData(DleDecoder),
+    /// After `+FRM`, sending the data received to the host
+    ReceiveData,
+    /// After `+FTS`, until the given time
+    Silence(Instant),
+    /// After `+FRS`, until the line has been without carrier for the given
+    /// time, since the instant it went quiet
+    WaitSilence(Duration, Option<Instant>),
+}
+
+/// Call being placed by `ATD` or answered, until the modem goes online.
+enum CallSetup {
+    Dialing {
+        stay_in_command_mode: bool,
+    },
+    Answering,
+    /// Connected, until the far end carrier is heard or `S7` runs out
+    WaitingForCarrier(Instant),
+}
+
+/// Error-corrected link of a call.
+enum Link {
+    Lapm(Lapm),
+    Mnp(Mnp),
+}
+
+impl Link {
+    fn start(&mut self, now: Instant) {
+        match self {
+            Link::Lapm(lapm) => lapm.start(now),
+            Link::Mnp(mnp) => mnp.start(now),
+        }
+    }
+
+    fn put_byte(&mut self, byte: u8, now: Instant, out: &mut Vec<u8>) -> Option<v42::Event> {
+        match self {
+            Link::Lapm(lapm) => lapm.put_byte(byte, now, out),
+            Link::Mnp(mnp) => mnp.put_byte(byte, now, out),
+        }
+    }
+
+    fn poll(&mut self, now: Instant, out: &mut Vec<u8>) -> Option<v42::Event> {
+        match self {
+            Link::Lapm(lapm) => lapm.poll(now, out),
+            Link::Mnp(mnp) => mnp.poll(now, out),
+        }
+    }
+
+    fn send(&mut self, data: &[u8]) {
+        match self {
+            Link::Lapm(lapm) => lapm.send(data),
+            Link::Mnp(mnp) => mnp.send(data),
+        }
+    }
+
+    fn pending(&self) -> usize {
+        match self {
+            Link::Lapm(lapm) => lapm.pending(),
+            Link::Mnp(mnp) => mnp.pending(),
+        }
+    }
+
+    fn set_busy(&mut self, busy: bool) {
+        match self {
+            Link::Lapm(lapm) => lapm.set_busy(busy),
+            Link::Mnp(mnp) => mnp.set_busy(busy),
+        }
+    }
+
+    /// Whether frames go whole through the HDLC framing, as with MNP class 3.
+    fn is_sync(&self) -> bool {
+        matches!(self, Link::Mnp(mnp) if mnp.is_sync())
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Link::Lapm(_) => "V.42",
+            Link::Mnp(_) => "MNP",
+        }
+    }
+
+    /// Whether the link compresses the data itself, as MNP class 5 does.
+    fn is_compressed(&self) -> bool {
+        matches!(self, Link::Mnp(mnp) if mnp.is_compressed())
+    }
+
+    /// Compression agreed with the peer for the data given to the link,
+    /// once connected.
+    fn compression(&self) -> Option<V42bisConfig> {
+        match self {
+            Link::Lapm(lapm) => lapm.compression(),
+            Link::Mnp(_) => None,
+        }
+    }
+}
+
+/// Data compression over an error-corrected link.
+enum Compression {
+    V42bis(v42bis::Encoder, v42bis::Decoder),
+}
+
+impl Compression {
+    fn compress(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        match self {
+            Compression::V42bis(encoder, _) => encoder.put(data, out),
+        }
+    }
+
+    /// Puts out what the encoder holds back, once the link is idle.
+    fn flush(&mut self, out: &mut Vec<u8>) {
+        match self {
+            Compression::V42bis(encoder, _) => encoder.flush(out),
+        }
+    }
+
+    fn decompress(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        match self {
+            Compression::V42bis(_, decoder) => decoder.put(data, out),
+        }
+    }
+
+    /// Counters of what was sent and of what was received.
+    fn counters(&self) -> (Counters, Counters) {
+        match self {
+            Compression::V42bis(encoder, decoder) => (encoder.counters(), decoder.counters()),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Compression::V42bis(..) => "V.42bis",
+        }
+    }
+}
+
+/// Receiving ends of the channels which feed the [`Dte`].
+struct DteInputs {
+    from_pty: Receiver<u8>,
+    from_uart_rx: Receiver<u8>,
+    from_hdlc_rx: Receiver<hdlc::Frame>,
+    from_automode: Receiver<Detected>,
+    from_pump: Receiver<PumpEvent>,
+    from_caller_id: Receiver<CallerId>,
+    from_ring: Receiver<()>,
+    from_carrier: Receiver<bool>,
+    /// DTR state of the serial port, as far as it can be told
+    from_dtr: Receiver<bool>,
+}
+
+/// Sits between the serial port and the UART, either forwarding data to and
+/// from the line (data mode) or interpreting AT commands (command mode).
+struct Dte {
+    profiles: Vec<FskProfile>,
+    profile: FskProfile,
+    role: Role,
+    baudot_variant: baudot::Variant,
+    unshift_on_space: bool,
+    caller_id_variant: callerid::Variant,
+    /// Set by `+VCID`
+    caller_id_report: u8,
+    last_ring: Option<Instant>,
+    call_setup: Option<CallSetup>,
+    baudot: Option<(BaudotEncoder, BaudotDecoder)>,
+    charset: Charset,
+    /// Set when the profile carries HDLC frames, which are exchanged with the
+    /// host using the KISS protocol
+    kiss: Option<KissDecoder>,
+    fax: Option<Fax>,
+    /// Set from `CONNECT` until the call is hung up, including while in
+    /// command mode after the escape sequence
+    in_call: bool,
+    /// Last state told by the carrier detector, while there is one
+    carrier: bool,
+    carrier_watch: CarrierWatch,
+    /// In use, set by `&K`
+    flow_control: FlowControl,
+    /// Given with `flow=`, the method until `AT&K` changes it and again after
+    /// `ATZ`
+    port_flow_control: FlowControl,
+    watermarks: Watermarks,
+    /// Set by `\N`
+    error_control: ErrorControl,
+    /// V.42 or MNP link of the call, from the moment the modems are
+    /// connected, if error control is on and the profile allows it
+    link: Option<Link>,
+    /// Set by `+DS`
+    data_compression: CompressionSettings,
+    compression: Option<Compression>,
+    /// Cleared to stop the host with RTS/CTS flow control
+    cts: Arc<AtomicBool>,
+    /// Tells the backend about `AT&K`, for the port to follow
+    flow_to_pty: Sender<FlowControl>,
+    online: bool,
+    at: AtInterpreter,
+    escape: EscapeDetector,
+    tx_chain: Arc<Mutex<TxChain>>,
+    tx_srate: usize,
+    rx_chain: Arc<Mutex<RxChain>>,
+    rx_srate: usize,
+    uart_rx_to_dte: Sender<u8>,
+    hdlc_rx_to_dte: Sender<hdlc::Frame>,
+    automode_to_dte: Sender<Detected>,
+    pump_to_dte: Sender<PumpEvent>,
+    caller_id_to_dte: Sender<CallerId>,
+    ring_to_dte: Sender<()>,
+    carrier_to_dte: Sender<bool>,
+    transmitting: Arc<AtomicBool>,
+    to_pty: Sender<u8>,
 }
 
-pub fn tx_run<T>(
+impl Dte {
+    fn run(mut self, inputs: DteInputs) {
+        let DteInputs {
+            from_pty,
+            from_uart_rx,
+            from_hdlc_rx,
+            from_automode,
+            from_pump,
+            from_caller_id,
+            from_ring,
+            from_carrier,
+            from_dtr,
+        } = inputs;
+        loop {
+            select! {
+                recv(from_pty) -> b => self.put_dte_byte(b.unwrap()),
+                recv(from_uart_rx) -> b => self.put_line_byte(b.unwrap()),
+                recv(from_hdlc_rx) -> frame => self.put_line_frame(frame.unwrap()),
+                recv(from_automode) -> detected => self.automode_detected(detected.unwrap()),
+                recv(from_pump) -> event => self.put_pump_event(event.unwrap()),
+                recv(from_caller_id) -> caller_id => self.report_caller_id(caller_id.unwrap()),
+                recv(from_ring) -> _ => self.ring_detected(),
+                recv(from_carrier) -> carrier => self.put_carrier(carrier.unwrap()),
+                recv(from_dtr) -> dtr => self.put_dtr(dtr.unwrap()),
+                default(POLL_INTERVAL) => {}
+            }
+
+            if self.online && self.escape.poll(Instant::now()) {
+                self.set_online(false);
+                self.send_result(ResultCode::Ok);
+            }
+            self.poll_fax();
+            self.poll_call_setup();
+            self.poll_error_control();
+            self.poll_carrier();
+            self.poll_flow_control();
+        }
+    }
+
+    fn put_line_byte(&mut self, byte: u8) {
+        if let Some(link) = &mut self.link {
+            let mut out = vec![];
+            let event = link.put_byte(byte, Instant::now(), &mut out);
+            self.put_line_bytes(&out);
+            if let Some(event) = event {
+                self.link_event(event);
+            }
+            return;
+        }
+        if !self.online {
+            return;
+        }
+        let byte = match (self.charset, &mut self.baudot) {
+            (_, Some((_, decoder))) => decoder.decode(byte),
+            (Charset::Edt, _) => Some(byte & 0x7f),
+            _ => Some(byte),
+        };
+        if let Some(byte) = byte {
+            self.to_pty.send(byte).unwrap();
+        }
+    }
+
+    fn put_line_frame(&mut self, frame: hdlc::Frame) {
+        if let Some(fax) = &mut self.fax {
+            fax.received.push_back(frame);
+            self.deliver_fax_frame();
+            return;
+        }
+        let Ok(frame) = frame else {
+            return;
+        };
+        if let Some(Link::Mnp(mnp)) = &mut self.link {
+            let mut out = vec![];
+            let event = mnp.put_frame(&frame, Instant::now(), &mut out);
+            self.queue_line_frame(&out);
+            if let Some(event) = event {
+                self.link_event(event);
+            }
+            return;
+        }
+        if self.profile.framing == Framing::HdlcNrzi {
+            match ax25::Frame::parse(&frame) {
+                Ok(ax25_frame) => eprintln!("RX: {}", ax25_frame),
+                Err(err) => eprintln!("RX: {} byte frame ({})", frame.len(), err),
+            }
+        }
+        if self.online && self.kiss.is_some() {
+            let mut bytes = vec![];
+            kiss::encode(&frame, &mut bytes);
+            self.send(&bytes);
+        }
+    }
+
+    fn put_kiss_byte(&mut self, byte: u8) {
+        let Some(command) = self.kiss.as_mut().and_then(|kiss| kiss.put_byte(byte)) else {
+            return;
+        };
+        let mut tx_chain = self.tx_chain.lock().unwrap();
+        match command {
+            kiss::Command::Data(frame) => {
+                if let Ok(ax25_frame) = ax25::Frame::parse(&frame) {
+                    eprintln!("TX: {}", ax25_frame);
+                }
+                tx_chain.put_frame(&frame);
+            }
+            kiss::Command::TxDelay(delay) => {
+                tx_chain.set_lead_in(Duration::from_millis(10 * delay as u64))
+            }
+            kiss::Command::Return => {
+                drop(tx_chain);
+                self.set_online(false);
+                self.send_result(ResultCode::Ok);
+            }
+            // channel access is left to the host, as there is no carrier
+            // detection yet
+            _ => {}
+        }
+    }
+
+    fn put_dte_byte(&mut self, byte: u8) {
+        if self.online && self.kiss.is_some() {
+            self.put_kiss_byte(byte);
+            return;
+        }
+        if self.online {
+            self.escape.put_byte(byte, Instant::now());
+            if let Some(link) = &mut self.link {
+                if self.watermarks.admit(link.pending(), self.flow_control) {
+                    match &mut self.compression {
+                        Some(compression) => {
+                            let mut out = vec![];
+                            compression.compress(&[byte], &mut out);
+                            link.send(&out);
+                        }
+                        None => link.send(&[byte]),
+                    }
+                }
+                return;
+            }
+            let mut tx_chain = self.tx_chain.lock().unwrap();
+            if !self
+                .watermarks
+                .admit(tx_chain.pending_bytes(), self.flow_control)
+            {
+                return;
+            }
+            match (self.charset, &mut self.baudot) {
+                (_, Some((encoder, _))) => {
+                    let mut codes = vec![];
+                    encoder.encode(byte, &mut codes);
+                    for code in codes {
+                        tx_chain.put_byte(code);
+                    }
+                }
+                (Charset::Edt, _) => {
+                    let byte = byte & 0x7f;
+                    tx_chain.put_byte(byte | ((byte.count_ones() as u8 & 1) << 7));
+                }
+                _ => tx_chain.put_byte(byte),
+            }
+            return;
+        }
+
+        if self
+            .fax
+            .as_ref()
+            .is_some_and(|fax| !matches!(fax.mode, FaxMode::Command))
+        {
+            self.put_fax_byte(byte);
+            return;
+        }
+
+        let mut to_dte = vec![];
+        let res = self.at.put_byte(byte, &mut to_dte);
+        self.send(&to_dte);
+
+        match res {
+            None => {}
+            Some(Err(_)) => self.send_result(ResultCode::Error),
+            Some(Ok(commands)) => {
+                let mut code = Some(ResultCode::Ok);
+                for command in commands {
+                    code = self.execute(command);
+                    if code != Some(ResultCode::Ok) {
+                        break;
+                    }
+                }
+                if let Some(code) = code {
+                    self.send_result(code);
+                }
+            }
+        }
+    }
+
+    /// Returns the result code to send, or `None` if it will only be sent
+    /// once the command completes.
+    fn execute(&mut self, command: Command) -> Option<ResultCode> {
+        match command {
+            Command::Echo(echo) => self.at.echo = echo,
+            Command::Quiet(quiet) => self.at.quiet = quiet,
+            Command::Verbose(verbose) => self.at.verbose = verbose,
+            Command::Reset => {
+                self.at = AtInterpreter::new();
+                self.escape = EscapeDetector::from_registers(&self.at.registers);
+                self.set_flow_control(self.port_flow_control);
+            }
+            Command::Online if !self.in_call => return Some(ResultCode::NoCarrier),
+            Command::Online => {
+                self.set_online(true);
+                return Some(ResultCode::Connect);
+            }
+            Command::HangUp => self.hang_up(),
+            Command::FlowControl(code) => {
+                let Some(flow_control) = FlowControl::from_code(code) else {
+                    return Some(ResultCode::Error);
+                };
+                self.set_flow_control(flow_control);
+            }
+            Command::ErrorControl(code) => {
+                let Some(error_control) = ErrorControl::from_code(code) else {
+                    return Some(ResultCode::Error);
+                };
+                self.error_control = error_control;
+            }
+            Command::DataCompression(args) => {
+                if !self.data_compression.update(args) {
+                    return Some(ResultCode::Error);
+                }
+            }
+            Command::QueryDataCompression => {
+                self.send(&self.at.info(&self.data_compression.report()))
+            }
+            Command::Dial(dial_string) => {
+                if let Err(err) = self.dial(&dial_string) {
+                    eprintln!("{}", err);
+                    return Some(ResultCode::Error);
+                }
+                return None;
+            }
+            Command::Answer => {
+                if let Err(err) = self.answer() {
+                    eprintln!("{}", err);
+                    return Some(ResultCode::Error);
+                }
+                return None;
+            }
+            Command::SetRegister(register, value) => {
+                let Some(dest) = self.at.registers.get_mut(register as usize) else {
+                    return Some(ResultCode::Error);
+                };
+                *dest = value;
+                if matches!(register as usize, S_ESCAPE | S_GUARD_TIME) {
+                    self.escape = EscapeDetector::from_registers(&self.at.registers);
+                }
+            }
+            Command::QueryRegister(register) => {
+                let Some(value) = self.at.registers.get(register as usize) else {
+                    return Some(ResultCode::Error);
+                };
+                self.send(&self.at.info(&format!("{:03}", value)));
+            }
+            Command::Modulation(carrier) if carrier == AUTOMODE => self.start_automode(),
+            Command::Modulation(carrier) => {
+                let Some(profile) = FskProfile::find(&self.profiles, &carrier).cloned() else {
+                    return Some(ResultCode::Error);
+                };
+                if let Err(err) = self.set_profile(profile) {
+                    eprintln!("{}", err);
+                    return Some(ResultCode::Error);
+                }
+            }
+            Command::QueryModulation => {
+                let info = self.at.info(&format!("+MS: {}", self.profile.name));
+                self.send(&info);
+            }
+            Command::FaxClass(class) => return Some(self.set_fax_class(class)),
+            Command::QueryFaxClass => {
+                let class = if self.fax.is_some() { "1" } else { "0" };
+                self.send(&self.at.info(class));
+            }
+            Command::FaxClassRange => self.send(&self.at.info("0,1")),
+            Command::Fax(fax_command, modulation) => {
+                return self.execute_fax(fax_command, modulation)
+            }
+            Command::FaxRange(fax_command) => {
+                let modulations = match fax_command {
+                    FaxCommand::TransmitHdlc | FaxCommand::ReceiveHdlc => class1::HDLC_MODULATIONS,
+                    FaxCommand::TransmitData | FaxCommand::ReceiveData => class1::DATA_MODULATIONS,
+                };
+                if modulations.is_empty() {
+                    return Some(ResultCode::Error);
+                }
+                self.send(&self.at.info(&class1::range(modulations)));
+            }
+            Command::CallerId(report) if report <= 2 => {
+                self.caller_id_report = report;
+                self.update_line_monitors();
+            }
+            Command::CallerId(_) => return Some(ResultCode::Error),
+            Command::QueryCallerId => {
+                self.send(&self.at.info(&self.caller_id_report.to_string()));
+            }
+            Command::CallerIdRange => self.send(&self.at.info("0,1,2")),
+            Command::FaxSilence(delay) => {
+                let Some(fax) = &mut self.fax else {
+                    return Some(ResultCode::Error);
+                };
+                let delay = Duration::from_millis(10 * delay as u64);
+                fax.mode = FaxMode::Silence(Instant::now() + delay);
+                return None;
+            }
+            Command::FaxWaitSilence(delay) => {
+                let Some(fax) = &mut self.fax else {
+                    return Some(ResultCode::Error);
+                };
+                let delay = Duration::from_millis(10 * delay as u64);
+                let quiet_since = (!self.carrier).then(Instant::now);
+                fax.mode = FaxMode::WaitSilence(delay, quiet_since);
+                return None;
+            }
+        }
+        Some(ResultCode::Ok)
+    }
+
+    fn set_fax_class(&mut self, class: u8) -> ResultCode {
+        let res = match (class, self.fax.take()) {
+            (0, None) => Ok(()),
+            (0, Some(fax)) => self.set_profile(fax.data_profile),
+            (1, None) => {
+                let fax = Fax {
+                    data_profile: self.profile.clone(),
+                    mode: FaxMode::Command,
+                    received: VecDeque::new(),
+                };
+                let res = self.set_profile(FskProfile::t30());
+                self.fax = Some(fax);
+                self.update_line_monitors();
+                res
+            }
+            (1, fax) => {
+                self.fax = fax;
+                Ok(())
+            }
+            (_, fax) => {
+                self.fax = fax;
+                return ResultCode::Error;
+            }
+        };
+        match res {
+            Ok(()) => ResultCode::Ok,
+            Err(err) => {
+                eprintln!("{}", err);
+                ResultCode::Error
+            }
+        }
+    }
+
+    fn execute_fax(&mut self, fax_command: FaxCommand, modulation: u8) -> Option<ResultCode> {
+        let Some(fax) = &mut self.fax else {
+            return Some(ResultCode::Error);
+        };
+        match fax_command {
+            FaxCommand::TransmitHdlc if class1::HDLC_MODULATIONS.contains(&modulation) => {
+                fax.received.clear();
+                fax.mode = FaxMode::TransmitHdlc(DleDecoder::new());
+                Some(ResultCode::Connect)
+            }
+            FaxCommand::ReceiveHdlc if class1::HDLC_MODULATIONS.contains(&modulation) => {
+                fax.mode = FaxMode::ReceiveHdlc;
+                self.deliver_fax_frame();
+                None
+            }
+            FaxCommand::TransmitData => {
+                let Some(modulation) = Modulation::from_class1(modulation) else {
+                    return Some(ResultCode::Error);
+                };
+                fax.mode = FaxMode::TransmitData(DleDecoder::new());
+                self.tx_chain.lock().unwrap().pump =
+                    Some(DataPumpTX::new(1. / self.tx_srate as f32, modulation));
+                Some(ResultCode::Connect)
+            }
+            FaxCommand::ReceiveData => {
+                let Some(modulation) = Modulation::from_class1(modulation) else {
+                    return Some(ResultCode::Error);
+                };
+                fax.mode = FaxMode::ReceiveData;
+                let pump_rx = DataPumpRX::new(1. / self.rx_srate as f32, modulation);
+                self.rx_chain.lock().unwrap().pump = Some((pump_rx, self.pump_to_dte.clone()));
+                // CONNECT is sent once the carrier is detected
+                None
+            }
+            _ => Some(ResultCode::Error),
+        }
+    }
+
+    fn put_fax_byte(&mut self, byte: u8) {
+        let Some(fax) = &mut self.fax else {
+            return;
+        };
+        match &mut fax.mode {
+            FaxMode::TransmitHdlc(decoder) => {
+                let Some(frame) = decoder.put_byte(byte) else {
+                    return;
+                };
+                self.tx_chain.lock().unwrap().put_frame(&frame);
+                if frame.get(1).is_some_and(|control| control & 0x10 != 0) {
+                    fax.mode = FaxMode::Draining;
+                } else {
+                    self.send_result(ResultCode::Connect);
+                }
+            }
+            FaxMode::ReceiveHdlc => {
+                // any character cancels the reception
+                fax.mode = FaxMode::Command;
+                self.send_result(ResultCode::Ok);
+            }
+            FaxMode::TransmitData(decoder) => {
+                let mut tx_chain = self.tx_chain.lock().unwrap();
+                let Some(pump_tx) = &mut tx_chain.pump else {
+                    return;
+                };
+                match decoder.put_byte(byte) {
+                    Some(data) => {
+                        pump_tx.put_bytes(&data);
+                        pump_tx.finish();
+                        fax.mode = FaxMode::Draining;
+                    }
+                    None => pump_tx.put_bytes(&decoder.take_data()),
+                }
+            }
+            FaxMode::ReceiveData => {
+                self.rx_chain.lock().unwrap().pump = None;
+                fax.mode = FaxMode::Command;
+                self.send_result(ResultCode::Ok);
+            }
+            FaxMode::Draining
+            | FaxMode::Silence(_)
+            | FaxMode::WaitSilence(..)
+            | FaxMode::Command => {}
+        }
+    }
+
+    /// Sends the oldest frame received to the host, if it is waiting for one
+    /// after `+FRH`.
+    fn deliver_fax_frame(&mut self) {
+        let Some(fax) = &mut self.fax else {
+            return;
+        };
+        if !matches!(fax.mode, FaxMode::ReceiveHdlc) {
+            return;
+        }
+        let Some(frame) = fax.received.pop_front() else {
+            return;
+        };
+        fax.mode = FaxMode::Command;
+
+        // the host expects the FCS after the frame, and is told whether it
+        // was correct by the final result code
+        let (mut data, code) = match frame {
+            Ok(frame) => (frame, ResultCode::Ok),
+            Err(err) => (err.frame, ResultCode::Error),
+        };
+        data.extend(hdlc::fcs(&data).to_le_bytes());
+        let mut bytes = vec![];
+        class1::encode(&data, &mut bytes);
+
+        self.send_result(ResultCode::Connect);
+        self.send(&bytes);
+        self.send_result(code);
+    }
+
+    fn put_pump_event(&mut self, event: PumpEvent) {
+        let Some(fax) = &mut self.fax else {
+            return;
+        };
+        if !matches!(fax.mode, FaxMode::ReceiveData) {
+            return;
+        }
+        match event {
+            PumpEvent::Carrier => self.send_result(ResultCode::Connect),
+            PumpEvent::Data(data) => {
+                let mut bytes = vec![];
+                class1::escape(&data, &mut bytes);
+                self.send(&bytes);
+            }
+            PumpEvent::NoCarrier => {
+                fax.mode = FaxMode::Command;
+                self.send(&[DLE, ETX]);
+                self.send_result(ResultCode::NoCarrier);
+            }
+        }
+    }
+
+    fn poll_fax(&mut self) {
+        let Some(fax) = &mut self.fax else {
+            return;
+        };
+        let done = match fax.mode {
+            FaxMode::Draining => self.tx_chain.lock().unwrap().carrier_off(),
+            FaxMode::Silence(deadline) => Instant::now() >= deadline,
+            FaxMode::WaitSilence(delay, quiet_since) => {
+                quiet_since.is_some_and(|quiet_since| quiet_since.elapsed() >= delay)
+            }
+            _ => false,
+        };
+        if done {
+            fax.mode = FaxMode::Command;
+            self.send_result(ResultCode::Ok);
+        }
+    }
+
+    fn start_automode(&mut self) {
+        let speriod = 1. / self.rx_srate as f32;
+        self.rx_chain.lock().unwrap().automode =
+            Some((Automode::new(speriod), self.automode_to_dte.clone()));
+    }
+
+    fn automode_detected(&mut self, detected: Detected) {
+        let profile = detected.profile();
+        eprintln!("V.18 automode: far end is {}", profile.name);
+        self.role = detected.role();
+        if let Err(err) = self.set_profile(profile) {
+            eprintln!("{}", err);
+            return;
+        }
+        let info = self.at.info(&format!("+MS: {}", self.profile.name));
+        self.send(&info);
+        if self.online {
+            self.send_result(ResultCode::Connect);
+        }
+    }
+
+    fn set_profile(&mut self, profile: FskProfile) -> anyhow::Result<()> {
+        self.set_chains(&profile)?;
+        self.set_charset(profile.charset);
+        self.set_framing(profile.framing);
+        self.profile = profile;
+        self.update_line_monitors();
+        Ok(())
+    }
+
+    /// Replaces the chains with new ones for `profile`, keeping the carrier
+    /// on during calls.
+    fn set_chains(&mut self, profile: &FskProfile) -> anyhow::Result<()> {
+        let tx_chain = TxChain::new(profile, self.role, self.tx_srate, self.transmitting.clone())?;
+        let rx_chain = RxChain::new(
+            profile,
+            self.role,
+            self.rx_srate,
+            self.uart_rx_to_dte.clone(),
+            self.hdlc_rx_to_dte.clone(),
+            self.transmitting.clone(),
+        )?;
+        *self.tx_chain.lock().unwrap() = TxChain {
+            carrier_enabled: self.in_call,
+            ..tx_chain
+        };
+        *self.rx_chain.lock().unwrap() = rx_chain;
+        Ok(())
+    }
+
+    fn set_online(&mut self, online: bool) {
+        self.online = online;
+        // what the line sends waits until the host may have it
+        if let Some(link) = &mut self.link {
+            link.set_busy(!online);
+        }
+        self.update_line_monitors();
+    }
+
+    /// Listens for rings while there is no call, and for the caller ID if
+    /// `+VCID` asks for it, and watches the carrier during calls.
+    fn update_line_monitors(&mut self) {
+        let on_hook = !self.in_call && self.call_setup.is_none();
+        let caller_id = if self.caller_id_report != 0 && on_hook {
+            match CallerIdRx::new(self.caller_id_variant, self.rx_srate) {
+                Ok(caller_id_rx) => Some((caller_id_rx, self.caller_id_to_dte.clone())),
+                Err(err) => {
+                    eprintln!("caller ID: {}", err);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let ring = on_hook.then(|| {
+            (
+                RingDetector::new(1. / self.rx_srate as f32),
+                self.ring_to_dte.clone(),
+            )
+        });
+        let mut rx_chain = self.rx_chain.lock().unwrap();
+        rx_chain.caller_id = caller_id;
+        rx_chain.ring = ring;
+        let watching =
+            self.carrier_watch
+                .update(self.in_call, self.profile.carrier_on_demand, Instant::now());
+        // fax signalling is received, and silence waited for, by carrier
+        if !watching && self.fax.is_none() {
+            rx_chain.carrier = None;
+            self.carrier = false;
+        } else if rx_chain.carrier.is_none() {
+            self.carrier = false;
+            let carrier_detector = CarrierDetector::new(
+                1. / self.rx_srate as f32,
+                self.profile.carrier_on_dbfs,
+                self.profile.carrier_off_dbfs,
+            );
+            rx_chain.carrier = Some((carrier_detector, self.carrier_to_dte.clone()));
+        }
+    }
+
+    fn put_carrier(&mut self, carrier: bool) {
+        self.carrier = carrier;
+        self.carrier_watch.put_carrier(carrier, Instant::now());
+        if let Some(fax) = &mut self.fax {
+            if let FaxMode::WaitSilence(_, quiet_since) = &mut fax.mode {
+                *quiet_since = (!carrier).then(Instant::now);
+            }
+        }
+    }
+
+    /// Hangs up once the carrier has been lost for longer than `S10`, the
+    /// first carrier being waited for by [`Dte::poll_call_setup`] instead.
+    fn poll_carrier(&mut self) {
+        let carrier_loss = self.at.registers[S_CARRIER_LOSS];
+        if self.call_setup.is_none() && self.carrier_watch.expired(carrier_loss, Instant::now()) {
+            self.hang_up();
+            self.send_result(ResultCode::NoCarrier);
+        }
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) {
+        // the host must not be left stopped by the old method
+        if self.watermarks.stopped() {
+            self.signal_flow(false);
+        }
+        self.flow_control = flow_control;
+        if self.watermarks.stopped() {
+            self.signal_flow(true);
+        }
+        // backends without a port to configure drop the receiver
+        let _ = self.flow_to_pty.send(flow_control);
+    }
+
+    /// Stops the host while too much is waiting to be sent to the line.
+    fn poll_flow_control(&mut self) {
+        let pending = self.tx_chain.lock().unwrap().pending_bytes()
+            + self.link.as_ref().map_or(0, |link| link.pending());
+        if let Some(stop) = self.watermarks.update(pending) {
+            self.signal_flow(stop);
+            let overruns = self.watermarks.take_overruns();
+            if !stop && overruns > 0 {
+                eprintln!("host ignored flow control: {} bytes dropped", overruns);
+            }
+        }
+    }
+
+    fn signal_flow(&self, stop: bool) {
+        match self.flow_control {
+            FlowControl::None => {}
+            FlowControl::RtsCts => self.cts.store(!stop, Ordering::Relaxed),
+            FlowControl::XonXoff => self.send(&[if stop { flow::XOFF } else { flow::XON }]),
+        }
+    }
+
+    fn put_dtr(&mut self, dtr: bool) {
+        if !dtr && (self.in_call || self.call_setup.is_some()) {
+            self.hang_up();
+            self.send_result(ResultCode::NoCarrier);
+        }
+    }
+
+    /// Ends the call: the carrier stops, whatever was still queued to be
+    /// sent is dropped, the receiver starts again from a clean state and the
+    /// modem returns to command mode.
+    fn hang_up(&mut self) {
+        self.in_call = false;
+        self.call_setup = None;
+        self.carrier_watch = CarrierWatch::default();
+        self.online = false;
+        self.report_compression();
+        self.link = None;
+        self.compression = None;
+        if let Some(fax) = &mut self.fax {
+            fax.mode = FaxMode::Command;
+            fax.received.clear();
+        }
+        if let Err(err) = self.set_profile(self.profile.clone()) {
+            eprintln!("{}", err);
+        }
+    }
+
+    /// Shows how well the data of the call compressed.
+    fn report_compression(&self) {
+        let (name, (sent, received)) = match (&self.compression, &self.link) {
+            (Some(compression), _) => (compression.name(), compression.counters()),
+            (None, Some(Link::Mnp(mnp))) if mnp.is_compressed() => ("MNP5", mnp.counters()),
+            _ => return,
+        };
+        eprintln!(
+            "{}: {} characters sent in {} bytes ({:.2}:1), {} received in {} bytes ({:.2}:1)",
+            name,
+            sent.characters,
+            sent.bytes,
+            sent.ratio(),
+            received.characters,
+            received.bytes,
+            received.ratio()
+        );
+    }
+
+    fn ring_detected(&mut self) {
+        let now = Instant::now();
+        if self
+            .last_ring
+            .is_none_or(|last_ring| now - last_ring > RING_TIMEOUT)
+        {
+            self.at.registers[S_RING_COUNT] = 0;
+        }
+        self.last_ring = Some(now);
+        self.at.registers[S_RING_COUNT] = self.at.registers[S_RING_COUNT].saturating_add(1);
+        self.send_result(ResultCode::Ring);
+
+        let auto_answer = self.at.registers[S_AUTO_ANSWER];
+        if auto_answer != 0 && self.at.registers[S_RING_COUNT] >= auto_answer {
+            if let Err(err) = self.answer() {
+                eprintln!("{}", err);
+                self.send_result(ResultCode::Error);
+            }
+        }
+    }
+
+    /// Retunes both directions to the channels of `role`. Profiles whose
+    /// channels differ in baud rate, such as V.23, need new chains instead.
+    fn set_role(&mut self, role: Role) -> anyhow::Result<()> {
+        let profile = &self.profile;
+        let same_rates = profile.tx_channel(role).baud_rate
+            == profile.tx_channel(self.role).baud_rate
+            && profile.rx_channel(role).baud_rate == profile.rx_channel(self.role).baud_rate;
+        let old_role = std::mem::replace(&mut self.role, role);
+        if !same_rates {
+            return self.set_profile(self.profile.clone()).inspect_err(|_| {
+                self.role = old_role;
+            });
+        }
+        let tx_channel = self.profile.tx_channel(role);
+        let rx_channel = self.profile.rx_channel(role);
+        self.tx_chain.lock().unwrap().set_channel(tx_channel);
+        self.rx_chain.lock().unwrap().set_channel(rx_channel);
+        Ok(())
+    }
+
+    /// Switches to the originate role and sends the digits, after which
+    /// [`Dte::poll_call_setup`] goes online.
+    fn dial(&mut self, dial_string: &str) -> anyhow::Result<()> {
+        self.set_role(Role::Originate)?;
+        // pulse dialing is not possible over audio, so `P` also uses tones
+        let digits: String = dial_string
+            .chars()
+            .filter(|c| c.is_ascii_digit() || matches!(c, '*' | '#' | 'A'..='D'))
+            .collect();
+        self.tx_chain.lock().unwrap().start_dialing(&digits);
+        self.call_setup = Some(CallSetup::Dialing {
+            stay_in_command_mode: dial_string.ends_with(';'),
+        });
+        self.update_line_monitors();
+        Ok(())
+    }
+
+    /// Switches to the answer role and sends the answer sequence, after which
+    /// [`Dte::poll_call_setup`] goes online.
+    fn answer(&mut self) -> anyhow::Result<()> {
+        self.set_role(Role::Answer)?;
+        self.tx_chain.lock().unwrap().start_answer();
+        self.call_setup = Some(CallSetup::Answering);
+        self.update_line_monitors();
+        Ok(())
+    }
+
+    fn poll_call_setup(&mut self) {
+        if self.call_setup.is_none() || self.tx_chain.lock().unwrap().connecting() {
+            return;
+        }
+        self.at.registers[S_RING_COUNT] = 0;
+        match self.call_setup.take() {
+            Some(CallSetup::Dialing {
+                stay_in_command_mode: true,
+            }) => {
+                self.update_line_monitors();
+                self.send_result(ResultCode::Ok);
+            }
+            Some(CallSetup::WaitingForCarrier(deadline)) => {
+                if self.carrier {
+                    self.connect();
+                } else if Instant::now() >= deadline {
+                    self.hang_up();
+                    self.send_result(ResultCode::NoCarrier);
+                } else {
+                    self.call_setup = Some(CallSetup::WaitingForCarrier(deadline));
+                }
+            }
+            _ => {
+                self.in_call = true;
+                self.tx_chain.lock().unwrap().carrier_enabled = true;
+                self.update_line_monitors();
+                if !self.profile.carrier_on_demand && !self.carrier {
+                    let wait = Duration::from_secs(self.at.registers[S_CARRIER_WAIT] as u64);
+                    self.call_setup = Some(CallSetup::WaitingForCarrier(Instant::now() + wait));
+                    return;
+                }
+                self.connect();
+            }
+        }
+    }
+
+    /// Goes online once the far end is there.
+    fn connect(&mut self) {
+        if self.start_error_control() {
+            // `CONNECT` waits for the outcome of the detection phase
+            return;
+        }
+        // in fax mode the host goes on with `+FTH` or `+FRH`
+        self.set_online(self.fax.is_none());
+        self.send_result(ResultCode::Connect);
+    }
+
+    /// Sets up V.42 or MNP for a call in data mode over a profile carrying
+    /// 8-bit characters, returning whether it did.
+    fn start_error_control(&mut self) -> bool {
+        if self.error_control == ErrorControl::Off
+            || self.fax.is_some()
+            || self.profile.framing != Framing::Async
+            || self.profile.charset != Charset::Ascii
+        {
+            return false;
+        }
+        let mut link = if self.error_control == ErrorControl::Mnp {
+            Link::Mnp(self.new_mnp())
+        } else {
+            Link::Lapm(Lapm::new(
+                self.role,
+                LapmConfig {
+                    byte_time: self.byte_time(),
+                    compression: self.data_compression.offer(),
+                    ..LapmConfig::default()
+                },
+            ))
+        };
+        // the far end carrier has been waited for or, sent on demand, is not
+        // watched, so the detection phase can start
+        link.start(Instant::now());
+        self.link = Some(link);
+        true
+    }
+
+    /// Time the slower direction of the line takes to carry a byte.
+    fn byte_time(&self) -> Duration {
+        let baud_rate = self
+            .profile
+            .tx_channel(self.role)
+            .baud_rate
+            .min(self.profile.rx_channel(self.role).baud_rate);
+        Duration::from_secs_f32(10. / baud_rate)
+    }
+
+    fn new_mnp(&self) -> Mnp {
+        Mnp::new(
+            self.role,
+            MnpConfig {
+                // class 3 needs the HDLC framing at the same rate each way
+                sync: self.profile.tx_channel(self.role).baud_rate
+                    == self.profile.rx_channel(self.role).baud_rate,
+                byte_time: self.byte_time(),
+                compression: self.data_compression.enabled,
+                ..MnpConfig::default()
+            },
+        )
+    }
+
+    fn poll_error_control(&mut self) {
+        let Some(link) = &mut self.link else {
+            return;
+        };
+        if self.tx_chain.lock().unwrap().pending_bytes() > 0 {
+            return;
+        }
+        if let (0, Some(compression)) = (link.pending(), &mut self.compression) {
+            let mut out = vec![];
+            compression.flush(&mut out);
+            link.send(&out);
+        }
+        let sync = link.is_sync();
+        let mut out = vec![];
+        let event = link.poll(Instant::now(), &mut out);
+        if sync {
+            self.queue_line_frame(&out);
+        } else {
+            self.put_line_bytes(&out);
+        }
+        if let Some(event) = event {
+            self.link_event(event);
+        }
+    }
+
+    fn link_event(&mut self, event: v42::Event) {
+        let Some(link) = &self.link else {
+            return;
+        };
+        let name = link.name();
+        match event {
+            v42::Event::Connected => {
+                eprintln!("{}: link established", name);
+                self.compression = link.compression().map(|config| {
+                    eprintln!(
+                        "V.42bis: {} codewords, strings of up to {} characters",
+                        config.dictionary_size, config.max_string
+                    );
+                    Compression::V42bis(
+                        v42bis::Encoder::new(&config),
+                        v42bis::Decoder::new(&config),
+                    )
+                });
+                let compressed = self.compression.is_some() || link.is_compressed();
+                if link.is_sync() {
+                    self.set_line_framing(Framing::Hdlc);
+                }
+                if !compressed && self.data_compression.enabled && self.data_compression.required {
+                    eprintln!("{}: the peer does not compress", name);
+                    self.hang_up();
+                    self.send_result(ResultCode::NoCarrier);
+                    return;
+                }
+                self.set_online(true);
+                self.send_result(ResultCode::Connect);
+            }
+            v42::Event::Fallback(received)
+                if matches!(link, Link::Lapm(_)) && self.error_control == ErrorControl::Auto =>
+            {
+                eprintln!("V.42: the peer does not speak LAPM, trying MNP");
+                let mut mnp = self.new_mnp();
+                mnp.start(Instant::now());
+                self.link = Some(Link::Mnp(mnp));
+                for byte in received {
+                    self.put_line_byte(byte);
+                }
+            }
+            v42::Event::Fallback(_) if self.error_control != ErrorControl::Auto => {
+                eprintln!("{}: the peer does not speak it", name);
+                self.hang_up();
+                self.send_result(ResultCode::NoCarrier);
+            }
+            v42::Event::Fallback(received) => {
+                eprintln!(
+                    "{}: the peer does not speak it, going on without error control",
+                    name
+                );
+                self.link = None;
+                self.set_online(true);
+                self.send_result(ResultCode::Connect);
+                for byte in received {
+                    self.put_line_byte(byte);
+                }
+            }
+            v42::Event::Data(mut data) => {
+                // the decoder follows the whole stream, even what is dropped
+                if let Some(compression) = &mut self.compression {
+                    let mut out = vec![];
+                    compression.decompress(&data, &mut out);
+                    data = out;
+                }
+                if self.online {
+                    self.send(&data);
+                }
+            }
+            v42::Event::Disconnected(reason) => {
+                eprintln!("{}: {}", name, reason);
+                self.hang_up();
+                self.send_result(ResultCode::NoCarrier);
+            }
+        }
+    }
+
+    /// Switches the line to `framing` for the rest of the call, leaving the
+    /// host side as it is.
+    fn set_line_framing(&mut self, framing: Framing) {
+        let profile = FskProfile {
+            framing,
+            ..self.profile.clone()
+        };
+        if let Err(err) = self.set_chains(&profile) {
+            eprintln!("{}", err);
+        }
+        self.update_line_monitors();
+    }
+
+    /// Queues the bytes of the link layer as they are.
+    fn put_line_bytes(&self, bytes: &[u8]) {
+        let mut tx_chain = self.tx_chain.lock().unwrap();
+        for b in bytes {
+            tx_chain.put_byte(*b);
+        }
+    }
+
+    /// Queues a frame of the link layer, if there is one.
+    fn queue_line_frame(&self, frame: &[u8]) {
+        if !frame.is_empty() {
+            self.tx_chain.lock().unwrap().put_frame(frame);
+        }
+    }
+
+    fn report_caller_id(&self, caller_id: CallerId) {
+        let text = match self.caller_id_report {
+            1 => caller_id.formatted(),
+            2 => caller_id.unformatted(),
+            _ => return,
+        };
+        if !self.online {
+            self.send(&self.at.info(&text));
+        }
+    }
+
+    fn set_charset(&mut self, charset: Charset) {
+        self.charset = charset;
+        self.baudot = (charset == Charset::Baudot).then(|| {
+            (
+                BaudotEncoder::new(self.baudot_variant, self.unshift_on_space),
+                BaudotDecoder::new(self.baudot_variant, self.unshift_on_space),
+            )
+        });
+    }
+
+    fn set_framing(&mut self, framing: Framing) {
+        self.kiss = (framing != Framing::Async).then(KissDecoder::new);
+    }
+
+    fn send(&self, bytes: &[u8]) {
+        for b in bytes {
+            self.to_pty.send(*b).unwrap();
+        }
+    }
+
+    fn send_result(&self, code: ResultCode) {
+        let bytes = self.at.result(code);
+        self.send(&bytes);
+    }
+}
+
+fn tx_run<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    uart_tx: Arc<Mutex<UartTx>>,
-    mut v21_tx: V21TX,
+    tx_chain: Arc<Mutex<TxChain>>,
 ) -> Result<Stream, BuildStreamError>
 where
     T: SizedSample + FromSample<f32>,
@@ -167,11 +2141,8 @@ where
         config,
         move |audio_out: &mut [T], _: &cpal::OutputCallbackInfo| {
             let bufsize = audio_out.len() / channels;
-            let mut uart_out = vec![1; bufsize];
-            uart_tx.lock().unwrap().get_samples(&mut uart_out);
-
             let mut v21_out = vec![0.; bufsize];
-            v21_tx.modulate(&uart_out, &mut v21_out);
+            tx_chain.lock().unwrap().get_samples(&mut v21_out);
 
             for (frame, sample) in audio_out.chunks_mut(channels).zip(v21_out.iter()) {
                 for dest in frame.iter_mut() {
@@ -184,11 +2155,10 @@ where
     )
 }
 
-pub fn rx_run<T>(
+fn rx_run<T>(
     device: &cpal::Device,
     config: &cpal::StreamConfig,
-    mut uart_rx: UartRx,
-    mut v21_rx: V21RX,
+    rx_chain: Arc<Mutex<RxChain>>,
 ) -> Result<Stream, BuildStreamError>
 where
     T: SizedSample,
@@ -207,10 +2177,7 @@ where
                 *dest = frame.first().unwrap().to_sample::<f32>();
             }
 
-            let mut uart_in = vec![1; bufsize];
-            v21_rx.demodulate(&v21_in, &mut uart_in);
-
-            uart_rx.put_samples(&uart_in);
+            rx_chain.lock().unwrap().put_samples(&v21_in);
         },
         err_fn,
         None,
@@ -0,0 +1,698 @@
+use crate::fsk::Role;
+use crate::mnp5;
+use crate::v42::Event;
+use crate::v42bis::Counters;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// start/stop framing of class 2
+const SYN: u8 = 0x16;
+const DLE: u8 = 0x10;
+const STX: u8 = 0x02;
+const ETX: u8 = 0x03;
+
+// types of LPDU
+const LR: u8 = 1;
+const LD: u8 = 2;
+const LT: u8 = 4;
+const LA: u8 = 5;
+
+// parameters of LR
+const PARAM_CONSTANT: u8 = 1;
+const PARAM_CLASS: u8 = 2;
+const PARAM_CREDIT: u8 = 3;
+const PARAM_DATA_SIZE: u8 = 4;
+const PARAM_OPTIMIZATION: u8 = 8;
+const PARAM_COMPRESSION: u8 = 9;
+/// Value of the first parameter, the same for everyone.
+const CONSTANT: [u8; 6] = [1, 0, 0, 0, 0, 0xff];
+/// Bits of the data phase optimization: 256-byte LT and fixed-field LT and
+/// LA.
+const OPTIMIZATION_256: u8 = 0x01;
+const OPTIMIZATION_FIXED: u8 = 0x02;
+/// Bit of class 5 in the compression parameter.
+const COMPRESSION_MNP5: u8 = 0x01;
+
+// parameters of LT, LA and LD
+const PARAM_SEQUENCE: u8 = 1;
+const PARAM_CREDIT_WINDOW: u8 = 2;
+const PARAM_REASON: u8 = 1;
+/// Reasons for LD.
+const REASON_RETRIES: u8 = 4;
+
+/// LR sent by the originator before it gives up on the answerer, which
+/// waits as long for the first one.
+const LR_ATTEMPTS: u32 = 3;
+/// Smallest LT sent by the adaptive packet assembly of class 4.
+const MIN_DATA_SIZE: usize = 32;
+
+/// CRC-16 of the class 2 framing, with the 0x8005 polynomial, transmitted
+/// least significant byte first.
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xa001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// Appends a frame with the start/stop framing of class 2: SYN DLE STX, the
+/// frame with each DLE doubled, DLE ETX and the CRC of the frame and ETX.
+pub fn encode(frame: &[u8], out: &mut Vec<u8>) {
+    out.extend([SYN, DLE, STX]);
+    for &b in frame {
+        if b == DLE {
+            out.extend([DLE, DLE]);
+        } else {
+            out.push(b);
+        }
+    }
+    out.extend([DLE, ETX]);
+    let mut checked = frame.to_vec();
+    checked.push(ETX);
+    out.extend(crc16(&checked).to_le_bytes());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DecoderState {
+    Hunt,
+    Syn,
+    SynDle,
+    Data,
+    Dle,
+    Crc(Option<u8>),
+}
+
+pub struct FrameDecoder {
+    max_len: usize,
+    state: DecoderState,
+    frame: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            state: DecoderState::Hunt,
+            frame: vec![],
+        }
+    }
+
+    /// Returns each frame which passes the CRC, the others being line noise.
+    pub fn put_byte(&mut self, byte: u8) -> Option<Vec<u8>> {
+        self.state = match (self.state, byte) {
+            (DecoderState::Data, DLE) => DecoderState::Dle,
+            (DecoderState::Data, _) | (DecoderState::Dle, DLE) => {
+                if self.frame.len() == self.max_len {
+                    DecoderState::Hunt
+                } else {
+                    self.frame.push(byte);
+                    DecoderState::Data
+                }
+            }
+            (DecoderState::Dle, ETX) => DecoderState::Crc(None),
+            (DecoderState::Crc(None), _) => DecoderState::Crc(Some(byte)),
+            (DecoderState::Crc(Some(low)), high) => {
+                self.state = DecoderState::Hunt;
+                let mut frame = std::mem::take(&mut self.frame);
+                frame.push(ETX);
+                let good = crc16(&frame) == u16::from_le_bytes([low, high]);
+                frame.pop();
+                return good.then_some(frame);
+            }
+            (DecoderState::Syn | DecoderState::Dle, STX) | (DecoderState::SynDle, STX) => {
+                self.frame.clear();
+                DecoderState::Data
+            }
+            (DecoderState::Syn, DLE) => DecoderState::SynDle,
+            (_, SYN) => DecoderState::Syn,
+            _ => DecoderState::Hunt,
+        };
+        None
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MnpConfig {
+    /// Class 3, with the synchronous framing once the link is established,
+    /// instead of class 2
+    pub sync: bool,
+    /// Class 4: adaptive packet assembly and the optimized data phase, with
+    /// LT of up to 256 bytes and fixed-field LT and LA
+    pub optimized: bool,
+    /// LT sent before waiting for an acknowledgement, up to 8
+    pub credit: u8,
+    /// Class 5: MNP5 data compression, if the peer agrees
+    pub compression: bool,
+    /// Attempts after which the link is given up
+    pub max_retries: u32,
+    /// Time the slower direction of the line takes to carry a byte
+    pub byte_time: Duration,
+}
+
+impl Default for MnpConfig {
+    fn default() -> Self {
+        Self {
+            sync: true,
+            optimized: true,
+            credit: 8,
+            compression: true,
+            max_retries: 10,
+            byte_time: Duration::from_secs(1) / 30,
+        }
+    }
+}
+
+/// Parameters agreed in the LR exchange.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Parameters {
+    sync: bool,
+    credit: u8,
+    data_size: usize,
+    fixed_field: bool,
+    compression: bool,
+}
+
+impl Parameters {
+    fn new(config: &MnpConfig) -> Self {
+        Self {
+            sync: config.sync,
+            credit: config.credit.clamp(1, 8),
+            data_size: if config.optimized { 256 } else { 64 },
+            fixed_field: config.optimized,
+            compression: config.compression,
+        }
+    }
+
+    fn agree(&self, peer: &Self) -> Self {
+        Self {
+            sync: self.sync && peer.sync,
+            credit: self.credit.min(peer.credit).max(1),
+            data_size: self.data_size.min(peer.data_size),
+            fixed_field: self.fixed_field && peer.fixed_field,
+            compression: self.compression && peer.compression,
+        }
+    }
+}
+
+fn encode_lr(parameters: &Parameters) -> Vec<u8> {
+    let mut lr = vec![0, LR, 2, PARAM_CONSTANT, CONSTANT.len() as u8];
+    lr.extend(CONSTANT);
+    lr.extend([PARAM_CLASS, 1, if parameters.sync { 3 } else { 2 }]);
+    lr.extend([PARAM_CREDIT, 1, parameters.credit]);
+    lr.extend([PARAM_DATA_SIZE, 2]);
+    lr.extend((parameters.data_size.min(64) as u16).to_le_bytes());
+    let mut optimization = 0;
+    if parameters.data_size > 64 {
+        optimization |= OPTIMIZATION_256;
+    }
+    if parameters.fixed_field {
+        optimization |= OPTIMIZATION_FIXED;
+    }
+    lr.extend([PARAM_OPTIMIZATION, 1, optimization]);
+    if parameters.compression {
+        lr.extend([PARAM_COMPRESSION, 1, COMPRESSION_MNP5]);
+    }
+    lr[0] = lr.len() as u8 - 1;
+    lr
+}
+
+/// Splits the header of a LPDU into its type and variable part, and the
+/// data which follows.
+fn split_header(frame: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&li, rest) = frame.split_first()?;
+    let (header, data) = rest.split_at_checked(li as usize)?;
+    let (&kind, header) = header.split_first()?;
+    Some((kind, header, data))
+}
+
+/// Calls `f` with each parameter of a variable part.
+fn for_each_parameter(mut fields: &[u8], mut f: impl FnMut(u8, &[u8])) {
+    while let [pi, len, rest @ ..] = fields {
+        let Some((value, rest)) = rest.split_at_checked(*len as usize) else {
+            return;
+        };
+        f(*pi, value);
+        fields = rest;
+    }
+}
+
+fn parse_lr(header: &[u8]) -> Parameters {
+    // class 2 and 64-byte LT unless told otherwise
+    let mut parameters = Parameters {
+        sync: false,
+        credit: 1,
+        data_size: 64,
+        fixed_field: false,
+        compression: false,
+    };
+    let fields = header.strip_prefix(&[2]).unwrap_or(header);
+    for_each_parameter(fields, |pi, value| match (pi, value) {
+        (PARAM_CLASS, [class]) => parameters.sync = *class >= 3,
+        (PARAM_CREDIT, [credit]) => parameters.credit = (*credit).clamp(1, 8),
+        (PARAM_DATA_SIZE, [low, high]) => {
+            parameters.data_size = (u16::from_le_bytes([*low, *high]) as usize).clamp(1, 64)
+        }
+        (PARAM_OPTIMIZATION, [optimization]) => {
+            if optimization & OPTIMIZATION_256 != 0 {
+                parameters.data_size = 256;
+            }
+            parameters.fixed_field = optimization & OPTIMIZATION_FIXED != 0;
+        }
+        (PARAM_COMPRESSION, [compression]) => {
+            parameters.compression = compression & COMPRESSION_MNP5 != 0
+        }
+        _ => {}
+    });
+    parameters
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// LR sent by the originator, or awaited by the answerer
+    Requesting,
+    /// LR answered by the answerer, waiting for LA
+    Accepting,
+    /// Established, but not reported until what was queued before has been
+    /// sent, as the framing may change
+    Established,
+    Connected,
+    Closed,
+}
+
+/// MNP endpoint of classes 2 to 4: establishes the link with an LR exchange,
+/// then carries data in LT frames sent again, from the first missing one on,
+/// until LA frames acknowledge them.
+pub struct Mnp {
+    role: Role,
+    config: MnpConfig,
+    state: State,
+    decoder: FrameDecoder,
+    parameters: Parameters,
+    deadline: Option<Instant>,
+    retries: u32,
+    /// Bytes received while waiting for LR
+    received: Vec<u8>,
+    queue: VecDeque<u8>,
+    /// N(R) of the last LT acknowledged
+    va: u8,
+    /// LT not yet acknowledged, and how many of them were sent since the
+    /// last time the peer asked for them again
+    unacked: VecDeque<Vec<u8>>,
+    sent: usize,
+    /// Credit given by the peer
+    peer_credit: u8,
+    /// Whether to send an LT despite the lack of credit, in case the LA
+    /// which gave some was lost
+    probe: bool,
+    /// N(S) of the last LT received in sequence
+    vr: u8,
+    ack_owed: bool,
+    busy: bool,
+    /// Size of the next LT, which class 4 adapts to the line
+    data_size: usize,
+    /// Whether the data of the LT is compressed, once connected
+    compressed: bool,
+    compressor: mnp5::Encoder,
+    decompressor: mnp5::Decoder,
+}
+
+impl Mnp {
+    pub fn new(role: Role, config: MnpConfig) -> Self {
+        Self {
+            role,
+            state: State::Idle,
+            // header, data and the CRC
+            decoder: FrameDecoder::new(256 + 8),
+            parameters: Parameters::new(&config),
+            deadline: None,
+            retries: 0,
+            received: vec![],
+            queue: VecDeque::new(),
+            va: 0,
+            unacked: VecDeque::new(),
+            sent: 0,
+            peer_credit: 0,
+            probe: false,
+            vr: 0,
+            ack_owed: false,
+            busy: false,
+            data_size: 64,
+            compressed: false,
+            compressor: mnp5::Encoder::new(),
+            decompressor: mnp5::Decoder::new(),
+            config,
+        }
+    }
+
+    /// Retransmission timer, enough for a whole LT each way and for the
+    /// audio delays.
+    fn timeout(&self) -> Duration {
+        self.config.byte_time * (2 * (self.parameters.data_size as u32 + 12))
+            + Duration::from_secs(1)
+    }
+
+    /// Timer of the LR exchange, enough for an LR each way.
+    fn lr_timeout(&self) -> Duration {
+        self.config.byte_time * (2 * (encode_lr(&self.parameters).len() as u32 + 8))
+            + Duration::from_secs(1)
+    }
+
+    /// Starts the link establishment, after which the originator sends LR
+    /// and the answerer waits for it.
+    pub fn start(&mut self, now: Instant) {
+        if self.state != State::Idle {
+            return;
+        }
+        self.state = State::Requesting;
+        self.deadline = Some(match self.role {
+            Role::Originate => now,
+            Role::Answer => now + self.lr_timeout() * LR_ATTEMPTS,
+        });
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == State::Connected
+    }
+
+    /// Whether frames go with the synchronous framing, to be sent and
+    /// received whole, rather than as bytes.
+    pub fn is_sync(&self) -> bool {
+        self.state == State::Connected && self.parameters.sync
+    }
+
+    /// Whether class 5 compresses the data.
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    /// Counters of the data sent and of the data received.
+    pub fn counters(&self) -> (Counters, Counters) {
+        (self.compressor.counters(), self.decompressor.counters())
+    }
+
+    pub fn send(&mut self, data: &[u8]) {
+        self.queue.extend(data);
+    }
+
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// While busy, the peer is given no credit.
+    pub fn set_busy(&mut self, busy: bool) {
+        if busy != self.busy {
+            self.busy = busy;
+            self.ack_owed = true;
+        }
+    }
+
+    /// Feeds a byte of the start/stop framing. `out` receives the bytes to
+    /// send, framed likewise.
+    pub fn put_byte(&mut self, byte: u8, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        if self.is_sync() {
+            return None;
+        }
+        if self.state == State::Requesting && self.role == Role::Answer {
+            self.received.push(byte);
+        }
+        let frame = self.decoder.put_byte(byte)?;
+        let mut reply = vec![];
+        let event = self.put_frame(&frame, now, &mut reply);
+        if !reply.is_empty() {
+            encode(&reply, out);
+        }
+        event
+    }
+
+    /// Sends what is due, at most one frame at a time. To be called whenever
+    /// the line is ready for more. `out` receives, in synchronous mode, the
+    /// frame to send whole and otherwise the bytes to send.
+    pub fn poll(&mut self, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        if self.is_sync() {
+            return self.poll_frame(now, out);
+        }
+        let mut frame = vec![];
+        let event = self.poll_frame(now, &mut frame);
+        if !frame.is_empty() {
+            encode(&frame, out);
+        }
+        event
+    }
+
+    /// Handles a frame received whole, in synchronous mode, or decoded from
+    /// bytes. `out` receives the frame to send in reply, if any.
+    pub fn put_frame(&mut self, frame: &[u8], now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        let (kind, header, data) = split_header(frame)?;
+        match (kind, self.state) {
+            (LR, State::Requesting) if self.role == Role::Answer => {
+                self.parameters = self.parameters.agree(&parse_lr(header));
+                self.state = State::Accepting;
+                self.retries = 0;
+                self.deadline = Some(now + self.lr_timeout());
+                out.extend(encode_lr(&self.parameters));
+                None
+            }
+            // our answer was lost
+            (LR, State::Accepting) => {
+                out.extend(encode_lr(&self.parameters));
+                None
+            }
+            (LR, State::Requesting) => {
+                self.parameters = self.parameters.agree(&parse_lr(header));
+                self.state = State::Established;
+                self.send_la(out);
+                None
+            }
+            // our LA was lost
+            (LR, State::Established | State::Connected) if self.role == Role::Originate => {
+                self.send_la(out);
+                None
+            }
+            (LA, State::Accepting) => {
+                self.state = State::Established;
+                None
+            }
+            (LA, State::Connected) => {
+                self.receive_la(header, now);
+                None
+            }
+            (LT, State::Connected) => self.receive_lt(header, data),
+            (LD, State::Requesting | State::Accepting | State::Established | State::Connected) => {
+                Some(self.down("disconnected by the peer"))
+            }
+            _ => None,
+        }
+    }
+
+    fn down(&mut self, reason: &str) -> Event {
+        self.state = State::Closed;
+        self.deadline = None;
+        Event::Disconnected(reason.to_string())
+    }
+
+    /// Reads N(S), N(R) or the credit, from a fixed field or a parameter.
+    fn field(&self, header: &[u8], index: usize, pi: u8) -> Option<u8> {
+        if self.parameters.fixed_field && header.len() <= 2 {
+            return header.get(index).copied();
+        }
+        let mut value = None;
+        for_each_parameter(header, |p, v| {
+            if let (true, [v]) = (p == pi, v) {
+                value = Some(*v);
+            }
+        });
+        value
+    }
+
+    fn receive_lt(&mut self, header: &[u8], data: &[u8]) -> Option<Event> {
+        let ns = self.field(header, 0, PARAM_SEQUENCE)?;
+        self.ack_owed = true;
+        if self.busy || ns != self.vr.wrapping_add(1) {
+            return None;
+        }
+        self.vr = ns;
+        let data = if self.compressed {
+            let mut out = vec![];
+            self.decompressor.put_block(data, &mut out);
+            out
+        } else {
+            data.to_vec()
+        };
+        (!data.is_empty()).then_some(Event::Data(data))
+    }
+
+    fn receive_la(&mut self, header: &[u8], now: Instant) {
+        let (Some(nr), Some(credit)) = (
+            self.field(header, 0, PARAM_SEQUENCE),
+            self.field(header, 1, PARAM_CREDIT_WINDOW),
+        ) else {
+            return;
+        };
+        // the peer is there, whatever it says
+        self.retries = 0;
+        self.peer_credit = credit;
+        let acked = nr.wrapping_sub(self.va) as usize;
+        if acked > self.sent {
+            // acknowledges what was never sent
+            return;
+        }
+        if acked > 0 {
+            self.unacked.drain(..acked);
+            self.sent -= acked;
+            self.va = nr;
+            if self.config.optimized {
+                self.data_size = (self.data_size + 16).min(self.parameters.data_size);
+            }
+        } else if self.sent == self.unacked.len() && self.sent > 0 {
+            // the peer missed the LT after N(R)
+            self.go_back();
+        }
+        self.deadline = None;
+        self.update_timer(now);
+    }
+
+    /// Runs the retransmission timer while LT wait for an acknowledgement,
+    /// or for credit.
+    fn update_timer(&mut self, now: Instant) {
+        let waiting = !self.unacked.is_empty() || (self.peer_credit == 0 && !self.queue.is_empty());
+        if !waiting {
+            self.deadline = None;
+        } else if self.deadline.is_none() {
+            self.deadline = Some(now + self.timeout());
+        }
+    }
+
+    /// Sends again every LT not yet acknowledged, smaller from then on in
+    /// class 4.
+    fn go_back(&mut self) {
+        self.sent = 0;
+        if self.config.optimized {
+            self.data_size = (self.data_size / 2).max(MIN_DATA_SIZE);
+        }
+    }
+
+    fn send_la(&mut self, out: &mut Vec<u8>) {
+        let credit = if self.busy { 0 } else { self.parameters.credit };
+        if self.parameters.fixed_field && self.state == State::Connected {
+            out.extend([3, LA, self.vr, credit]);
+        } else {
+            out.extend([
+                7,
+                LA,
+                PARAM_SEQUENCE,
+                1,
+                self.vr,
+                PARAM_CREDIT_WINDOW,
+                1,
+                credit,
+            ]);
+        }
+        self.ack_owed = false;
+    }
+
+    /// Takes what the next LT carries, compressed as long as the worst case
+    /// fits.
+    fn take_data(&mut self) -> Vec<u8> {
+        if !self.compressed {
+            let len = self.queue.len().min(self.data_size);
+            return self.queue.drain(..len).collect();
+        }
+        let room = 8 * self.data_size - mnp5::MAX_END_BITS;
+        while self.compressor.len_bits() + mnp5::MAX_CHARACTER_BITS <= room {
+            let Some(character) = self.queue.pop_front() else {
+                break;
+            };
+            self.compressor.put(character);
+        }
+        self.compressor.finish()
+    }
+
+    fn send_lt(&self, index: usize, out: &mut Vec<u8>) {
+        let ns = self.va.wrapping_add(index as u8 + 1);
+        if self.parameters.fixed_field {
+            out.extend([2, LT, ns]);
+        } else {
+            out.extend([4, LT, PARAM_SEQUENCE, 1, ns]);
+        }
+        out.extend(&self.unacked[index]);
+    }
+
+    fn poll_frame(&mut self, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        let expired = self.deadline.is_some_and(|deadline| now >= deadline);
+        match self.state {
+            State::Idle | State::Closed => None,
+            State::Requesting | State::Accepting if expired => {
+                let attempts = match (self.role, self.state) {
+                    (Role::Originate, State::Requesting) => LR_ATTEMPTS,
+                    (Role::Answer, State::Requesting) => 0,
+                    _ => self.config.max_retries,
+                };
+                if self.retries == attempts {
+                    if self.state == State::Accepting {
+                        return Some(self.down("no answer from the peer"));
+                    }
+                    self.state = State::Closed;
+                    self.deadline = None;
+                    return Some(Event::Fallback(std::mem::take(&mut self.received)));
+                }
+                self.retries += 1;
+                self.deadline = Some(now + self.lr_timeout());
+                out.extend(encode_lr(&self.parameters));
+                None
+            }
+            State::Requesting | State::Accepting => None,
+            State::Established => {
+                self.state = State::Connected;
+                self.deadline = None;
+                self.retries = 0;
+                self.data_size = self.parameters.data_size;
+                self.peer_credit = self.parameters.credit;
+                self.compressed = self.parameters.compression;
+                Some(Event::Connected)
+            }
+            State::Connected => self.poll_connected(expired, now, out),
+        }
+    }
+
+    fn poll_connected(&mut self, expired: bool, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        if expired {
+            if self.retries == self.config.max_retries {
+                out.extend([4, LD, PARAM_REASON, 1, REASON_RETRIES]);
+                return Some(self.down("no acknowledgement from the peer"));
+            }
+            self.retries += 1;
+            self.go_back();
+            self.probe = self.peer_credit == 0;
+            self.deadline = Some(now + self.timeout());
+        }
+        self.update_timer(now);
+        if self.ack_owed {
+            self.send_la(out);
+            return None;
+        }
+        if self.sent < self.unacked.len() {
+            self.send_lt(self.sent, out);
+            self.sent += 1;
+            return None;
+        }
+        let credit = self.unacked.len() < self.peer_credit as usize || self.probe;
+        if credit && !self.queue.is_empty() {
+            self.probe = false;
+            let data = self.take_data();
+            self.unacked.push_back(data);
+            self.send_lt(self.sent, out);
+            self.sent += 1;
+            self.update_timer(now);
+        }
+        None
+    }
+}
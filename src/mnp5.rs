@@ -0,0 +1,244 @@
+use crate::v42bis::Counters;
+
+/// Characters after which a run goes on as a count.
+const RUN: u32 = 3;
+/// Largest count of a run, after its first three characters.
+const MAX_COUNT: u8 = 250;
+/// Bits of the header of a character code, which gives the size of the
+/// rank which follows.
+const HEADER_BITS: u32 = 3;
+/// Longest character code and count.
+const MAX_CODE_BITS: usize = 10;
+const COUNT_BITS: u32 = 8;
+/// Room an encoder needs for one more character, then for the end of the
+/// block.
+pub const MAX_CHARACTER_BITS: usize = MAX_CODE_BITS + COUNT_BITS as usize;
+pub const MAX_END_BITS: usize = COUNT_BITS as usize + 7;
+
+/// Characters ranked by how often they came, the most frequent having the
+/// shortest codes.
+struct Table {
+    rank: [u8; 256],
+    character: [u8; 256],
+    count: [u8; 256],
+}
+
+impl Table {
+    fn new() -> Self {
+        Self {
+            rank: std::array::from_fn(|i| i as u8),
+            character: std::array::from_fn(|i| i as u8),
+            count: [0; 256],
+        }
+    }
+
+    /// Counts `character`, moving it above those which came less often.
+    fn update(&mut self, character: u8) {
+        let c = character as usize;
+        if self.count[c] == u8::MAX {
+            for count in &mut self.count {
+                *count /= 2;
+            }
+        }
+        self.count[c] += 1;
+        let mut rank = self.rank[c] as usize;
+        while rank > 0 && self.count[self.character[rank - 1] as usize] < self.count[c] {
+            let other = self.character[rank - 1];
+            self.character[rank] = other;
+            self.rank[other as usize] = rank as u8;
+            rank -= 1;
+        }
+        self.character[rank] = character;
+        self.rank[c] = rank as u8;
+    }
+}
+
+/// Header and size of the rest of the code of a rank: 0 and 1 bit for the
+/// first two ranks, then n and n bits for ranks 2^n to 2^(n+1) - 1.
+fn code_size(rank: u8) -> (u32, u32) {
+    if rank < 2 {
+        (0, 1)
+    } else {
+        let header = 7 - rank.leading_zeros();
+        (header, header)
+    }
+}
+
+/// Compresses the data of the LT, one block at a time, with the table
+/// kept from block to block.
+pub struct Encoder {
+    table: Box<Table>,
+    bits: u32,
+    count: u32,
+    block: Vec<u8>,
+    last: Option<u8>,
+    /// Same characters in a row, up to [`RUN`], and how many followed
+    run: u32,
+    repeats: u8,
+    counters: Counters,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self {
+            table: Box::new(Table::new()),
+            bits: 0,
+            count: 0,
+            block: vec![],
+            last: None,
+            run: 0,
+            repeats: 0,
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    /// Bits of the block so far.
+    pub fn len_bits(&self) -> usize {
+        8 * self.block.len() + self.count as usize
+    }
+
+    pub fn put(&mut self, character: u8) {
+        self.counters.characters += 1;
+        if self.run == RUN {
+            if self.last == Some(character) && self.repeats < MAX_COUNT {
+                self.repeats += 1;
+                return;
+            }
+            self.end_run();
+        }
+        let rank = self.table.rank[character as usize];
+        let (header, size) = code_size(rank);
+        self.write(header, HEADER_BITS);
+        self.write(rank as u32 - (1 << header & !1), size);
+        self.table.update(character);
+        if self.last == Some(character) {
+            self.run += 1;
+        } else {
+            self.last = Some(character);
+            self.run = 1;
+        }
+    }
+
+    /// Ends the block, padded with ones which do not make up a code.
+    pub fn finish(&mut self) -> Vec<u8> {
+        if self.run == RUN {
+            self.end_run();
+        }
+        self.last = None;
+        self.run = 0;
+        if self.count > 0 {
+            self.write(u32::MAX, 8 - self.count);
+        }
+        self.counters.bytes += self.block.len() as u64;
+        std::mem::take(&mut self.block)
+    }
+
+    fn end_run(&mut self) {
+        self.write(self.repeats as u32, COUNT_BITS);
+        self.last = None;
+        self.run = 0;
+        self.repeats = 0;
+    }
+
+    fn write(&mut self, value: u32, size: u32) {
+        self.bits |= (value & ((1 << size) - 1)) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            self.block.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+}
+
+/// Reads the bits of a block, least significant first.
+struct BitReader<'a> {
+    block: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, size: u32) -> Option<u32> {
+        if self.position + size as usize > 8 * self.block.len() {
+            return None;
+        }
+        let mut value = 0;
+        for i in 0..size {
+            let bit = self.block[self.position / 8] >> (self.position % 8) & 1;
+            value |= (bit as u32) << i;
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+/// Decompresses the data of the LT received in sequence.
+pub struct Decoder {
+    table: Box<Table>,
+    counters: Counters,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder {
+    pub fn new() -> Self {
+        Self {
+            table: Box::new(Table::new()),
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    pub fn put_block(&mut self, block: &[u8], out: &mut Vec<u8>) {
+        let start = out.len();
+        let mut reader = BitReader { block, position: 0 };
+        let mut last = None;
+        let mut run = 0;
+        loop {
+            if run == RUN {
+                let (Some(repeats), Some(character)) = (reader.read(COUNT_BITS), last) else {
+                    break;
+                };
+                out.extend(std::iter::repeat_n(character, repeats as usize));
+                last = None;
+                run = 0;
+                continue;
+            }
+            let Some(header) = reader.read(HEADER_BITS) else {
+                break;
+            };
+            let Some(rest) = reader.read(header.max(1)) else {
+                break;
+            };
+            let rank = (rest + (1 << header & !1)) as u8;
+            let character = self.table.character[rank as usize];
+            self.table.update(character);
+            out.push(character);
+            if last == Some(character) {
+                run += 1;
+            } else {
+                last = Some(character);
+                run = 1;
+            }
+        }
+        self.counters.bytes += block.len() as u64;
+        self.counters.characters += (out.len() - start) as u64;
+    }
+}
@@ -0,0 +1,266 @@
+use crate::flow::FlowControl;
+use crate::ppp::AuthProtocol;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+/// Where the DTE connects to the modem.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Device {
+    /// A new pseudo-terminal, given as `pty` or nothing at all
+    Pty,
+    /// A serial port, e.g. `/dev/ttyUSB0` or `\\.\COM3`
+    Path(String),
+    /// `tcp-listen:ADDR:PORT`, a TCP server accepting one client at a time
+    TcpListen(String),
+    /// `tcp:HOST:PORT`, a TCP client which connects again when the
+    /// connection drops
+    TcpConnect(String),
+    /// `stdio`, standard input and output
+    Stdio,
+    /// `tun` or `tun:NAME`, IP over SLIP to a TUN interface (Linux-only)
+    Tun(Option<String>),
+    /// `stack`, IP over SLIP to a TCP/IP stack of our own, reached through
+    /// local SOCKS5 and forwarded ports
+    Stack,
+}
+
+/// Serial port settings, given as the device followed by comma-separated
+/// options, e.g. `/dev/ttyUSB0,speed=9600,7E1,flow=xonxoff`:
+///
+/// - `speed=N`, or just `N`, in bits per second
+/// - the framing, as data bits, parity (`N`, `E` or `O`) and stop bits
+/// - `flow=none`, `flow=rtscts` or `flow=xonxoff`
+/// - `mode=0660`, permissions of a pty
+/// - `link=PATH`, symlink to a pty
+/// - `telnet`, to speak Telnet with RFC 2217 port control over TCP
+/// - `addr=A.B.C.D`, `peer=A.B.C.D` and `mtu=N`, settings of a TUN interface
+///   or of the stack
+/// - `ppp`, to speak PPP rather than SLIP, with `user=NAME` and
+///   `password=SECRET` to authenticate and `auth=pap` or `auth=chap` to
+///   require the peer to authenticate
+/// - `socks=[ADDR:]PORT`, SOCKS5 server of the stack
+/// - `forward=[ADDR:]PORT:HOST:PORT`, local port of the stack forwarded to
+///   `HOST:PORT` across the link, which may be given more than once
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PortOptions {
+    pub device: Device,
+    pub speed: u32,
+    pub data_bits: u8,
+    pub parity: Parity,
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+    pub mode: Option<u32>,
+    pub link: Option<PathBuf>,
+    pub telnet: bool,
+    pub address: Option<Ipv4Addr>,
+    pub peer: Option<Ipv4Addr>,
+    pub mtu: usize,
+    pub ppp: bool,
+    pub user: String,
+    pub password: String,
+    pub auth: Option<AuthProtocol>,
+    pub socks: Option<SocketAddr>,
+    /// Local addresses and where their connections go
+    pub forwards: Vec<(SocketAddr, SocketAddrV4)>,
+}
+
+impl Default for PortOptions {
+    fn default() -> Self {
+        Self {
+            device: Device::Pty,
+            speed: 115200,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::default(),
+            mode: None,
+            link: None,
+            telnet: false,
+            address: None,
+            peer: None,
+            // the classic SLIP MTU, which keeps a packet under 10 s at 300 bps
+            mtu: 296,
+            ppp: false,
+            user: String::new(),
+            password: String::new(),
+            auth: None,
+            socks: None,
+            forwards: vec![],
+        }
+    }
+}
+
+impl FromStr for PortOptions {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut fields = s.split(',');
+        let device = match fields.next().unwrap_or_default() {
+            "" | "pty" => Device::Pty,
+            "stdio" => Device::Stdio,
+            "tun" => Device::Tun(None),
+            "stack" => Device::Stack,
+            device => match device.split_once(':') {
+                Some(("tcp-listen", addr)) => Device::TcpListen(addr.to_string()),
+                Some(("tcp", addr)) => Device::TcpConnect(addr.to_string()),
+                Some(("tun", name)) => Device::Tun(Some(name.to_string())),
+                _ => Device::Path(device.to_string()),
+            },
+        };
+        let mut options = Self {
+            device,
+            ..Self::default()
+        };
+        for field in fields {
+            match field.split_once('=') {
+                Some(("speed", speed)) => options.speed = parse_speed(speed)?,
+                Some(("flow", flow)) => {
+                    options.flow_control = match flow {
+                        "none" => FlowControl::None,
+                        "rtscts" => FlowControl::RtsCts,
+                        "xonxoff" => FlowControl::XonXoff,
+                        _ => anyhow::bail!("unknown flow control {}", flow),
+                    }
+                }
+                Some(("mode", mode)) => {
+                    let mode = u32::from_str_radix(mode, 8)
+                        .ok()
+                        .filter(|mode| *mode <= 0o777)
+                        .ok_or_else(|| anyhow::anyhow!("invalid mode {}", mode))?;
+                    options.mode = Some(mode);
+                }
+                Some(("link", link)) if !link.is_empty() => options.link = Some(link.into()),
+                Some(("addr", addr)) => options.address = Some(parse_address(addr)?),
+                Some(("peer", peer)) => options.peer = Some(parse_address(peer)?),
+                Some(("mtu", mtu)) => {
+                    options.mtu = mtu
+                        .parse()
+                        .ok()
+                        .filter(|mtu| (68..=65535).contains(mtu))
+                        .ok_or_else(|| anyhow::anyhow!("invalid MTU {}", mtu))?
+                }
+                Some(("user", user)) => options.user = user.to_string(),
+                Some(("password", password)) => options.password = password.to_string(),
+                Some(("auth", auth)) => {
+                    options.auth = Some(match auth {
+                        "pap" => AuthProtocol::Pap,
+                        "chap" => AuthProtocol::Chap,
+                        _ => anyhow::bail!("unknown authentication {}", auth),
+                    })
+                }
+                Some(("socks", addr)) => options.socks = Some(parse_listen(addr)?),
+                Some(("forward", forward)) => {
+                    let invalid = || anyhow::anyhow!("invalid forward {}", forward);
+                    let mut parts = forward.rsplitn(3, ':');
+                    let (Some(port), Some(host), Some(listen)) =
+                        (parts.next(), parts.next(), parts.next())
+                    else {
+                        return Err(invalid());
+                    };
+                    let target = SocketAddrV4::new(
+                        host.parse().map_err(|_| invalid())?,
+                        port.parse().map_err(|_| invalid())?,
+                    );
+                    options.forwards.push((parse_listen(listen)?, target));
+                }
+                Some(_) => anyhow::bail!("unknown serial port option {}", field),
+                None if field == "telnet" => options.telnet = true,
+                None if field == "ppp" => options.ppp = true,
+                None if field.bytes().all(|b| b.is_ascii_digit()) => {
+                    options.speed = parse_speed(field)?
+                }
+                None => options.set_framing(field)?,
+            }
+        }
+        let tcp = matches!(options.device, Device::TcpListen(_) | Device::TcpConnect(_));
+        anyhow::ensure!(tcp || !options.telnet, "telnet only applies to TCP");
+        let ip = matches!(options.device, Device::Tun(_) | Device::Stack);
+        anyhow::ensure!(
+            ip || (options.address.is_none() && options.peer.is_none()),
+            "addr and peer only apply to a TUN interface or the stack"
+        );
+        anyhow::ensure!(
+            ip || !options.ppp,
+            "ppp only applies to a TUN interface or the stack"
+        );
+        let stack = options.device == Device::Stack;
+        anyhow::ensure!(
+            stack || (options.socks.is_none() && options.forwards.is_empty()),
+            "socks and forward only apply to the stack"
+        );
+        anyhow::ensure!(
+            !stack || options.socks.is_some() || !options.forwards.is_empty(),
+            "the stack needs socks or forward"
+        );
+        anyhow::ensure!(
+            !stack || options.ppp || options.address.is_some(),
+            "the stack needs addr, unless PPP assigns it"
+        );
+        anyhow::ensure!(
+            options.ppp
+                || (options.user.is_empty()
+                    && options.password.is_empty()
+                    && options.auth.is_none()),
+            "user, password and auth only apply to PPP"
+        );
+        Ok(options)
+    }
+}
+
+impl PortOptions {
+    fn set_framing(&mut self, framing: &str) -> anyhow::Result<()> {
+        let &[data_bits, parity, stop_bits] = framing.as_bytes() else {
+            anyhow::bail!("unknown serial port option {}", framing);
+        };
+        anyhow::ensure!(
+            (b'5'..=b'8').contains(&data_bits),
+            "invalid data bits in {}",
+            framing
+        );
+        self.data_bits = data_bits - b'0';
+        self.parity = match parity.to_ascii_uppercase() {
+            b'N' => Parity::None,
+            b'E' => Parity::Even,
+            b'O' => Parity::Odd,
+            _ => anyhow::bail!("invalid parity in {}", framing),
+        };
+        anyhow::ensure!(
+            stop_bits == b'1' || stop_bits == b'2',
+            "invalid stop bits in {}",
+            framing
+        );
+        self.stop_bits = stop_bits - b'0';
+        Ok(())
+    }
+}
+
+fn parse_address(address: &str) -> anyhow::Result<Ipv4Addr> {
+    address
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid address {}", address))
+}
+
+/// `ADDR:PORT`, or just `PORT` on the loopback interface.
+fn parse_listen(addr: &str) -> anyhow::Result<SocketAddr> {
+    if let Ok(port) = addr.parse::<u16>() {
+        return Ok((Ipv4Addr::LOCALHOST, port).into());
+    }
+    addr.parse()
+        .map_err(|_| anyhow::anyhow!("invalid address {}", addr))
+}
+
+fn parse_speed(speed: &str) -> anyhow::Result<u32> {
+    speed
+        .parse()
+        .ok()
+        .filter(|speed| *speed > 0)
+        .ok_or_else(|| anyhow::anyhow!("invalid speed {}", speed))
+}
@@ -0,0 +1,939 @@
+use crate::hdlc::{fcs, ESCAPE, FLAG};
+use crate::md5::md5;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+pub const PROTO_IP: u16 = 0x0021;
+pub const PROTO_IPCP: u16 = 0x8021;
+pub const PROTO_LCP: u16 = 0xc021;
+pub const PROTO_PAP: u16 = 0xc023;
+pub const PROTO_CHAP: u16 = 0xc223;
+
+/// MRU assumed until the peer asks for another one.
+pub const DEFAULT_MRU: u16 = 1500;
+
+// codes of LCP and IPCP packets
+const CONFIGURE_REQUEST: u8 = 1;
+const CONFIGURE_ACK: u8 = 2;
+const CONFIGURE_NAK: u8 = 3;
+const CONFIGURE_REJECT: u8 = 4;
+const TERMINATE_REQUEST: u8 = 5;
+const TERMINATE_ACK: u8 = 6;
+const CODE_REJECT: u8 = 7;
+const PROTOCOL_REJECT: u8 = 8;
+const ECHO_REQUEST: u8 = 9;
+const ECHO_REPLY: u8 = 10;
+const DISCARD_REQUEST: u8 = 11;
+
+const LCP_MRU: u8 = 1;
+const LCP_ACCM: u8 = 2;
+const LCP_AUTH: u8 = 3;
+const LCP_MAGIC: u8 = 5;
+const IPCP_ADDRESS: u8 = 3;
+
+// codes of PAP and CHAP packets
+const AUTH_REQUEST: u8 = 1;
+const AUTH_ACK: u8 = 2;
+const AUTH_NAK: u8 = 3;
+const CHAP_CHALLENGE: u8 = 1;
+const CHAP_RESPONSE: u8 = 2;
+const CHAP_SUCCESS: u8 = 3;
+const CHAP_FAILURE: u8 = 4;
+const CHAP_MD5: u8 = 5;
+
+/// Restart timer and counters of RFC 1661.
+const RESTART_INTERVAL: Duration = Duration::from_secs(3);
+const MAX_CONFIGURE: u32 = 10;
+const MAX_TERMINATE: u32 = 2;
+
+/// Appends a frame with the HDLC-like framing of RFC 1662, escaping the
+/// control characters whose bits are set in `accm`.
+pub fn encode(protocol: u16, info: &[u8], accm: u32, out: &mut Vec<u8>) {
+    let mut frame = vec![0xff, 0x03];
+    frame.extend(protocol.to_be_bytes());
+    frame.extend(info);
+    frame.extend(fcs(&frame).to_le_bytes());
+    out.push(FLAG);
+    for b in frame {
+        if b == FLAG || b == ESCAPE || (b < 0x20 && accm & (1 << b) != 0) {
+            out.extend([ESCAPE, b ^ 0x20]);
+        } else {
+            out.push(b);
+        }
+    }
+    out.push(FLAG);
+}
+
+pub struct FrameDecoder {
+    max_len: usize,
+    frame: Vec<u8>,
+    escaped: bool,
+    /// Whether the frame is being skipped up to the next flag
+    discarding: bool,
+}
+
+impl FrameDecoder {
+    pub fn new(mru: u16) -> Self {
+        Self {
+            // address, control, protocol and FCS
+            max_len: mru as usize + 6,
+            frame: vec![],
+            escaped: false,
+            discarding: false,
+        }
+    }
+
+    /// Returns the protocol and information field of each frame, or an error
+    /// for a frame which is too long or fails the FCS.
+    pub fn put_byte(&mut self, byte: u8) -> Option<anyhow::Result<(u16, Vec<u8>)>> {
+        if byte == FLAG {
+            let frame = std::mem::take(&mut self.frame);
+            let discarding = std::mem::replace(&mut self.discarding, false);
+            self.escaped = false;
+            // flags shared between frames, or sent to flush line noise
+            if discarding || frame.is_empty() {
+                return None;
+            }
+            return Some(parse_frame(&frame));
+        }
+        if self.discarding {
+            return None;
+        }
+        let byte = if std::mem::replace(&mut self.escaped, false) {
+            byte ^ 0x20
+        } else if byte == ESCAPE {
+            self.escaped = true;
+            return None;
+        } else {
+            byte
+        };
+        if self.frame.len() == self.max_len {
+            self.frame.clear();
+            self.discarding = true;
+            return Some(Err(anyhow::anyhow!(
+                "frame longer than {} bytes",
+                self.max_len
+            )));
+        }
+        self.frame.push(byte);
+        None
+    }
+}
+
+fn parse_frame(frame: &[u8]) -> anyhow::Result<(u16, Vec<u8>)> {
+    anyhow::ensure!(frame.len() >= 4, "frame too short");
+    let (data, check) = frame.split_at(frame.len() - 2);
+    anyhow::ensure!(fcs(data).to_le_bytes() == check, "wrong FCS");
+    // address and control may be left out, and so may the first byte of the
+    // protocol, the only one which is odd
+    let data = data.strip_prefix(&[0xff, 0x03]).unwrap_or(data);
+    match data {
+        [protocol, info @ ..] if protocol & 1 == 1 => Ok((*protocol as u16, info.to_vec())),
+        [high, low, info @ ..] => Ok((u16::from_be_bytes([*high, *low]), info.to_vec())),
+        _ => anyhow::bail!("frame too short"),
+    }
+}
+
+fn packet(code: u8, id: u8, data: &[u8]) -> Vec<u8> {
+    let mut packet = vec![code, id];
+    packet.extend((data.len() as u16 + 4).to_be_bytes());
+    packet.extend(data);
+    packet
+}
+
+type ConfigOption = (u8, Vec<u8>);
+
+fn parse_options(mut data: &[u8]) -> Option<Vec<ConfigOption>> {
+    let mut options = vec![];
+    while let [kind, len, ..] = data {
+        let len = *len as usize;
+        if len < 2 || len > data.len() {
+            return None;
+        }
+        options.push((*kind, data[2..len].to_vec()));
+        data = &data[len..];
+    }
+    data.is_empty().then_some(options)
+}
+
+fn encode_options(options: &[ConfigOption]) -> Vec<u8> {
+    let mut data = vec![];
+    for (kind, value) in options {
+        data.extend([*kind, value.len() as u8 + 2]);
+        data.extend(value);
+    }
+    data
+}
+
+fn random_u32() -> u32 {
+    RandomState::new().build_hasher().finish() as u32
+}
+
+fn address(value: &[u8]) -> Option<Ipv4Addr> {
+    <[u8; 4]>::try_from(value).ok().map(Ipv4Addr::from)
+}
+
+/// Reads the fields of `data` preceded by their lengths.
+fn counted_fields<const N: usize>(mut data: &[u8]) -> Option<[&[u8]; N]> {
+    let mut fields = [&data[..0]; N];
+    for field in fields.iter_mut() {
+        let (len, rest) = data.split_first()?;
+        *field = rest.get(..*len as usize)?;
+        data = &rest[*len as usize..];
+    }
+    Some(fields)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthProtocol {
+    Pap,
+    /// CHAP with MD5
+    Chap,
+}
+
+impl AuthProtocol {
+    fn option(&self) -> Vec<u8> {
+        match self {
+            AuthProtocol::Pap => PROTO_PAP.to_be_bytes().to_vec(),
+            AuthProtocol::Chap => vec![0xc2, 0x23, CHAP_MD5],
+        }
+    }
+
+    fn from_option(value: &[u8]) -> Option<Self> {
+        match value {
+            [0xc0, 0x23] => Some(AuthProtocol::Pap),
+            [0xc2, 0x23, CHAP_MD5] => Some(AuthProtocol::Chap),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PppConfig {
+    /// Our address, or `None` to take the one assigned by the peer
+    pub address: Option<Ipv4Addr>,
+    /// Address assigned to a peer which asks for one
+    pub peer: Option<Ipv4Addr>,
+    pub mru: u16,
+    /// Sent when the peer asks us to authenticate, and expected from the
+    /// peer when we ask it to
+    pub user: String,
+    pub password: String,
+    /// Authentication required from the peer
+    pub auth: Option<AuthProtocol>,
+}
+
+impl Default for PppConfig {
+    fn default() -> Self {
+        Self {
+            address: None,
+            peer: None,
+            mru: DEFAULT_MRU,
+            user: String::new(),
+            password: String::new(),
+            auth: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// IPCP is open, so IP packets may flow. The MTU is the smaller of the
+    /// two MRUs.
+    Up {
+        address: Ipv4Addr,
+        peer: Ipv4Addr,
+        mtu: usize,
+    },
+    /// The link went down, for the reason given
+    Down(String),
+    /// IP packet received
+    Packet(Vec<u8>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FsmState {
+    Closed,
+    Negotiating,
+    Opened,
+    Closing,
+}
+
+/// Option negotiation automaton of RFC 1661, shared by LCP and IPCP, with
+/// the acknowledgements received and sent standing for its states between
+/// Req-Sent and Opened.
+struct Fsm {
+    protocol: u16,
+    state: FsmState,
+    /// Identifier of our last request
+    id: u8,
+    ack_received: bool,
+    ack_sent: bool,
+    /// Options of our requests which the peer rejected
+    rejected: Vec<u8>,
+    retries: u32,
+    deadline: Option<Instant>,
+}
+
+impl Fsm {
+    fn new(protocol: u16) -> Self {
+        Self {
+            protocol,
+            state: FsmState::Closed,
+            id: 0,
+            ack_received: false,
+            ack_sent: false,
+            rejected: vec![],
+            retries: 0,
+            deadline: None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.protocol {
+            PROTO_LCP => "LCP",
+            _ => "IPCP",
+        }
+    }
+
+    /// Starts a negotiation, keeping the identifier going.
+    fn reset(&mut self, state: FsmState) {
+        *self = Self {
+            id: self.id,
+            state,
+            ..Self::new(self.protocol)
+        };
+    }
+}
+
+enum Verdict {
+    Ack,
+    Nak(Vec<u8>),
+    Reject,
+}
+
+/// PPP endpoint: negotiates the link with LCP, authenticates with PAP or
+/// CHAP and assigns the addresses with IPCP, turning the bytes received into
+/// IP packets and IP packets into bytes to send.
+pub struct Ppp {
+    config: PppConfig,
+    decoder: FrameDecoder,
+    lcp: Fsm,
+    ipcp: Fsm,
+    magic: u32,
+    /// MRU and control characters to escape we ask of the peer
+    mru: u16,
+    rx_accm: u32,
+    /// Those asked of us
+    peer_mru: u16,
+    accm: u32,
+    /// Authentication asked of us, and the one we ask of the peer
+    peer_auth: Option<AuthProtocol>,
+    auth: Option<AuthProtocol>,
+    authenticated: bool,
+    peer_authenticated: bool,
+    auth_id: u8,
+    /// Our CHAP challenge, and its identifier
+    challenge: Vec<u8>,
+    challenge_id: u8,
+    /// PAP requests and CHAP challenges waiting for an answer
+    auth_pending: Vec<(u16, Vec<u8>)>,
+    auth_retries: u32,
+    auth_deadline: Option<Instant>,
+    address: Ipv4Addr,
+    peer_address: Ipv4Addr,
+}
+
+impl Ppp {
+    pub fn new(config: PppConfig) -> Self {
+        Self {
+            decoder: FrameDecoder::new(config.mru.max(DEFAULT_MRU)),
+            lcp: Fsm::new(PROTO_LCP),
+            ipcp: Fsm::new(PROTO_IPCP),
+            magic: random_u32(),
+            mru: config.mru,
+            rx_accm: 0,
+            peer_mru: DEFAULT_MRU,
+            accm: u32::MAX,
+            peer_auth: None,
+            auth: config.auth,
+            authenticated: false,
+            peer_authenticated: false,
+            auth_id: 0,
+            challenge: vec![],
+            challenge_id: 0,
+            auth_pending: vec![],
+            auth_retries: 0,
+            auth_deadline: None,
+            address: config.address.unwrap_or(Ipv4Addr::UNSPECIFIED),
+            peer_address: config.peer.unwrap_or(Ipv4Addr::UNSPECIFIED),
+            config,
+        }
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.ipcp.state == FsmState::Opened
+    }
+
+    /// Whether LCP is down, or going down.
+    pub fn is_closed(&self) -> bool {
+        matches!(self.lcp.state, FsmState::Closed | FsmState::Closing)
+    }
+
+    /// Starts negotiating the link. Until then, a request from the peer
+    /// starts it as well.
+    pub fn open(&mut self, now: Instant, out: &mut Vec<u8>) {
+        let config = self.config.clone();
+        let id = (self.lcp.id, self.ipcp.id, self.auth_id);
+        *self = Self::new(config);
+        (self.lcp.id, self.ipcp.id, self.auth_id) = id;
+        self.lcp.state = FsmState::Negotiating;
+        self.send_configure_request(PROTO_LCP, now, out);
+    }
+
+    /// Terminates the link.
+    pub fn close(&mut self, now: Instant, out: &mut Vec<u8>) {
+        if self.lcp.state == FsmState::Closed || self.lcp.state == FsmState::Closing {
+            return;
+        }
+        self.ipcp.reset(FsmState::Closed);
+        self.lcp.reset(FsmState::Closing);
+        self.auth_deadline = None;
+        self.send_terminate_request(now, out);
+    }
+
+    pub fn put_byte(&mut self, byte: u8, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        match self.decoder.put_byte(byte)? {
+            Ok((protocol, info)) => self.receive(protocol, &info, now, out),
+            // left to the layers above to notice, as with any line noise
+            Err(_) => None,
+        }
+    }
+
+    /// Queues an IP packet, returning false while the link is not up.
+    pub fn send_packet(&mut self, packet: &[u8], out: &mut Vec<u8>) -> bool {
+        if !self.is_up() {
+            return false;
+        }
+        self.send(PROTO_IP, packet, out);
+        true
+    }
+
+    /// Retransmits what the peer did not answer, giving up after a while.
+    pub fn poll(&mut self, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        for protocol in [PROTO_LCP, PROTO_IPCP] {
+            let fsm = self.fsm(protocol);
+            if fsm.deadline.is_none_or(|deadline| now < deadline) {
+                continue;
+            }
+            let (state, retries, name) = (fsm.state, fsm.retries, fsm.name());
+            match state {
+                FsmState::Negotiating if retries < MAX_CONFIGURE => {
+                    self.send_configure_request(protocol, now, out)
+                }
+                FsmState::Negotiating => {
+                    let reason = format!("{} negotiation timed out", name);
+                    return Some(self.fail(reason, now, out));
+                }
+                FsmState::Closing if retries < MAX_TERMINATE => {
+                    self.send_terminate_request(now, out)
+                }
+                _ => self.fsm_mut(protocol).reset(FsmState::Closed),
+            }
+        }
+
+        if self.auth_deadline.is_some_and(|deadline| now >= deadline) {
+            if self.auth_retries == MAX_CONFIGURE {
+                return Some(self.fail("authentication timed out".to_string(), now, out));
+            }
+            for (protocol, packet) in self.auth_pending.clone() {
+                self.send(protocol, &packet, out);
+            }
+            self.auth_retries += 1;
+            self.auth_deadline = Some(now + RESTART_INTERVAL);
+        }
+        None
+    }
+
+    fn fsm(&self, protocol: u16) -> &Fsm {
+        match protocol {
+            PROTO_LCP => &self.lcp,
+            _ => &self.ipcp,
+        }
+    }
+
+    fn fsm_mut(&mut self, protocol: u16) -> &mut Fsm {
+        match protocol {
+            PROTO_LCP => &mut self.lcp,
+            _ => &mut self.ipcp,
+        }
+    }
+
+    fn send(&mut self, protocol: u16, info: &[u8], out: &mut Vec<u8>) {
+        // LCP goes out with everything escaped, as the ACCM may be changing
+        let accm = match protocol {
+            PROTO_LCP => u32::MAX,
+            _ => self.accm,
+        };
+        encode(protocol, info, accm, out);
+    }
+
+    /// Closes the link, returning why.
+    fn fail(&mut self, reason: String, now: Instant, out: &mut Vec<u8>) -> Event {
+        self.close(now, out);
+        Event::Down(reason)
+    }
+
+    fn send_configure_request(&mut self, protocol: u16, now: Instant, out: &mut Vec<u8>) {
+        let options = encode_options(&self.request_options(protocol));
+        let fsm = self.fsm_mut(protocol);
+        fsm.id = fsm.id.wrapping_add(1);
+        fsm.ack_received = false;
+        fsm.retries += 1;
+        fsm.deadline = Some(now + RESTART_INTERVAL);
+        let packet = packet(CONFIGURE_REQUEST, fsm.id, &options);
+        self.send(protocol, &packet, out);
+    }
+
+    fn send_terminate_request(&mut self, now: Instant, out: &mut Vec<u8>) {
+        self.lcp.id = self.lcp.id.wrapping_add(1);
+        self.lcp.retries += 1;
+        self.lcp.deadline = Some(now + RESTART_INTERVAL);
+        let packet = packet(TERMINATE_REQUEST, self.lcp.id, &[]);
+        self.send(PROTO_LCP, &packet, out);
+    }
+
+    fn receive(
+        &mut self,
+        protocol: u16,
+        info: &[u8],
+        now: Instant,
+        out: &mut Vec<u8>,
+    ) -> Option<Event> {
+        let lcp_opened = self.lcp.state == FsmState::Opened;
+        match protocol {
+            PROTO_LCP => self.receive_control(PROTO_LCP, info, now, out),
+            PROTO_IPCP if lcp_opened && self.authenticated && self.peer_authenticated => {
+                self.receive_control(PROTO_IPCP, info, now, out)
+            }
+            PROTO_PAP if lcp_opened => self.receive_pap(info, now, out),
+            PROTO_CHAP if lcp_opened => self.receive_chap(info, now, out),
+            PROTO_IP if self.is_up() => Some(Event::Packet(info.to_vec())),
+            PROTO_IPCP | PROTO_PAP | PROTO_CHAP | PROTO_IP => None,
+            _ if lcp_opened => {
+                self.lcp.id = self.lcp.id.wrapping_add(1);
+                let mut data = protocol.to_be_bytes().to_vec();
+                data.extend(info);
+                let packet = packet(PROTOCOL_REJECT, self.lcp.id, &data);
+                self.send(PROTO_LCP, &packet, out);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    fn receive_control(
+        &mut self,
+        protocol: u16,
+        info: &[u8],
+        now: Instant,
+        out: &mut Vec<u8>,
+    ) -> Option<Event> {
+        let [code, id, len_high, len_low, ..] = *info else {
+            return None;
+        };
+        let len = u16::from_be_bytes([len_high, len_low]) as usize;
+        let data = info.get(4..len)?;
+        let fsm = self.fsm(protocol);
+        let state = fsm.state;
+        let our_id = fsm.id == id;
+
+        match code {
+            CONFIGURE_REQUEST => {
+                let options = parse_options(data)?;
+                let mut event = None;
+                match state {
+                    FsmState::Closing => return None,
+                    FsmState::Negotiating => {}
+                    // the peer starts, or starts over, and we follow
+                    FsmState::Closed | FsmState::Opened => {
+                        event = self.layer_down(protocol);
+                        self.fsm_mut(protocol).reset(FsmState::Negotiating);
+                        self.send_configure_request(protocol, now, out);
+                    }
+                }
+                let (code, options) = self.check_options(protocol, options);
+                self.fsm_mut(protocol).ack_sent = code == CONFIGURE_ACK;
+                let packet = packet(code, id, &encode_options(&options));
+                self.send(protocol, &packet, out);
+                event.or_else(|| self.check_opened(protocol, now, out))
+            }
+            CONFIGURE_ACK if our_id && state == FsmState::Negotiating => {
+                self.fsm_mut(protocol).ack_received = true;
+                self.check_opened(protocol, now, out)
+            }
+            CONFIGURE_NAK | CONFIGURE_REJECT if our_id && state == FsmState::Negotiating => {
+                let options = parse_options(data)?;
+                if let Err(reason) = self.apply_refusal(protocol, code, options) {
+                    return Some(self.fail(reason, now, out));
+                }
+                self.send_configure_request(protocol, now, out);
+                None
+            }
+            TERMINATE_REQUEST => {
+                let packet = packet(TERMINATE_ACK, id, &[]);
+                self.send(protocol, &packet, out);
+                let event = self.layer_down(protocol);
+                self.fsm_mut(protocol).reset(FsmState::Closed);
+                match protocol {
+                    PROTO_LCP if matches!(state, FsmState::Negotiating | FsmState::Opened) => {
+                        Some(Event::Down("terminated by the peer".to_string()))
+                    }
+                    _ => event,
+                }
+            }
+            TERMINATE_ACK if state == FsmState::Closing => {
+                self.fsm_mut(protocol).reset(FsmState::Closed);
+                None
+            }
+            PROTOCOL_REJECT if data.starts_with(&PROTO_IPCP.to_be_bytes()) => {
+                Some(self.fail("the peer does not support IP".to_string(), now, out))
+            }
+            ECHO_REQUEST if protocol == PROTO_LCP && state == FsmState::Opened => {
+                let mut reply = self.magic.to_be_bytes().to_vec();
+                reply.extend(data.get(4..).unwrap_or_default());
+                let packet = packet(ECHO_REPLY, id, &reply);
+                self.send(protocol, &packet, out);
+                None
+            }
+            CONFIGURE_ACK | CONFIGURE_NAK | CONFIGURE_REJECT | TERMINATE_ACK | CODE_REJECT
+            | PROTOCOL_REJECT => None,
+            ECHO_REQUEST | ECHO_REPLY | DISCARD_REQUEST if protocol == PROTO_LCP => None,
+            _ => {
+                let fsm = self.fsm_mut(protocol);
+                fsm.id = fsm.id.wrapping_add(1);
+                let packet = packet(CODE_REJECT, fsm.id, &info[..len]);
+                self.send(protocol, &packet, out);
+                None
+            }
+        }
+    }
+
+    fn check_opened(&mut self, protocol: u16, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        let fsm = self.fsm_mut(protocol);
+        if fsm.state != FsmState::Negotiating || !fsm.ack_received || !fsm.ack_sent {
+            return None;
+        }
+        fsm.state = FsmState::Opened;
+        fsm.deadline = None;
+        match protocol {
+            PROTO_LCP => {
+                self.authenticated = self.peer_auth.is_none();
+                self.peer_authenticated = self.auth.is_none();
+                self.auth_pending.clear();
+                match self.peer_auth {
+                    Some(AuthProtocol::Pap) => {
+                        let mut data = vec![];
+                        for field in [&self.config.user, &self.config.password] {
+                            data.push(field.len() as u8);
+                            data.extend(field.bytes());
+                        }
+                        self.send_auth(PROTO_PAP, AUTH_REQUEST, &data, now, out);
+                    }
+                    // CHAP waits for the challenge of the peer
+                    Some(AuthProtocol::Chap) | None => {}
+                }
+                if self.auth == Some(AuthProtocol::Chap) {
+                    self.challenge = (0..4).flat_map(|_| random_u32().to_be_bytes()).collect();
+                    let mut data = vec![self.challenge.len() as u8];
+                    data.extend(&self.challenge);
+                    data.extend(b"modem");
+                    self.send_auth(PROTO_CHAP, CHAP_CHALLENGE, &data, now, out);
+                    self.challenge_id = self.auth_id;
+                }
+                self.start_network(now, out);
+                None
+            }
+            _ => Some(Event::Up {
+                address: self.address,
+                peer: self.peer_address,
+                mtu: self.peer_mru.min(self.mru) as usize,
+            }),
+        }
+    }
+
+    /// Returns the event for a link which was up and no longer is.
+    fn layer_down(&mut self, protocol: u16) -> Option<Event> {
+        let was_up = self.is_up();
+        if protocol == PROTO_LCP {
+            self.ipcp.reset(FsmState::Closed);
+            self.authenticated = false;
+            self.peer_authenticated = false;
+            self.auth_pending.clear();
+            self.auth_deadline = None;
+        }
+        was_up.then(|| Event::Down("renegotiating".to_string()))
+    }
+
+    fn send_auth(&mut self, protocol: u16, code: u8, data: &[u8], now: Instant, out: &mut Vec<u8>) {
+        self.auth_id = self.auth_id.wrapping_add(1);
+        let packet = packet(code, self.auth_id, data);
+        self.send(protocol, &packet, out);
+        self.auth_pending.push((protocol, packet));
+        self.auth_retries = 1;
+        self.auth_deadline = Some(now + RESTART_INTERVAL);
+    }
+
+    fn auth_done(&mut self, protocol: u16, now: Instant, out: &mut Vec<u8>) {
+        self.auth_pending
+            .retain(|(pending, _)| *pending != protocol);
+        if self.auth_pending.is_empty() {
+            self.auth_deadline = None;
+        }
+        self.start_network(now, out);
+    }
+
+    /// Starts IPCP once both ends are authenticated.
+    fn start_network(&mut self, now: Instant, out: &mut Vec<u8>) {
+        if self.authenticated && self.peer_authenticated && self.ipcp.state == FsmState::Closed {
+            self.ipcp.reset(FsmState::Negotiating);
+            self.send_configure_request(PROTO_IPCP, now, out);
+        }
+    }
+
+    fn receive_pap(&mut self, info: &[u8], now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        let [code, id, _, _, ref data @ ..] = *info else {
+            return None;
+        };
+        match code {
+            AUTH_REQUEST if self.auth == Some(AuthProtocol::Pap) => {
+                let [user, password] = counted_fields(data)?;
+                let ok = user == self.config.user.as_bytes()
+                    && password == self.config.password.as_bytes();
+                let (code, message) = if ok {
+                    (AUTH_ACK, &b"Welcome"[..])
+                } else {
+                    (AUTH_NAK, &b"Wrong user or password"[..])
+                };
+                let mut reply = vec![message.len() as u8];
+                reply.extend(message);
+                let packet = packet(code, id, &reply);
+                self.send(PROTO_PAP, &packet, out);
+                if !ok {
+                    return Some(self.fail(
+                        "the peer failed to authenticate".to_string(),
+                        now,
+                        out,
+                    ));
+                }
+                self.peer_authenticated = true;
+                self.auth_done(PROTO_PAP, now, out);
+                None
+            }
+            AUTH_ACK
+                if self
+                    .auth_pending
+                    .iter()
+                    .any(|(pending, _)| *pending == PROTO_PAP) =>
+            {
+                self.authenticated = true;
+                self.auth_done(PROTO_PAP, now, out);
+                None
+            }
+            AUTH_NAK if self.peer_auth == Some(AuthProtocol::Pap) => {
+                Some(self.fail("authentication rejected by the peer".to_string(), now, out))
+            }
+            _ => None,
+        }
+    }
+
+    fn receive_chap(&mut self, info: &[u8], now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        let [code, id, _, _, ref data @ ..] = *info else {
+            return None;
+        };
+        let hash = |id: u8, password: &str, challenge: &[u8]| {
+            let mut message = vec![id];
+            message.extend(password.bytes());
+            message.extend(challenge);
+            md5(&message)
+        };
+        match code {
+            CHAP_CHALLENGE if self.peer_auth == Some(AuthProtocol::Chap) => {
+                let [challenge] = counted_fields(data)?;
+                let mut response = vec![16];
+                response.extend(hash(id, &self.config.password, challenge));
+                response.extend(self.config.user.bytes());
+                let packet = packet(CHAP_RESPONSE, id, &response);
+                self.send(PROTO_CHAP, &packet, out);
+                None
+            }
+            CHAP_RESPONSE if self.auth == Some(AuthProtocol::Chap) && id == self.challenge_id => {
+                let [response] = counted_fields(data)?;
+                let name = &data[1 + response.len()..];
+                let ok = response == hash(id, &self.config.password, &self.challenge)
+                    && name == self.config.user.as_bytes();
+                let (code, message) = if ok {
+                    (CHAP_SUCCESS, &b"Welcome"[..])
+                } else {
+                    (CHAP_FAILURE, &b"Wrong user or password"[..])
+                };
+                let packet = packet(code, id, message);
+                self.send(PROTO_CHAP, &packet, out);
+                if !ok {
+                    return Some(self.fail(
+                        "the peer failed to authenticate".to_string(),
+                        now,
+                        out,
+                    ));
+                }
+                self.peer_authenticated = true;
+                self.auth_done(PROTO_CHAP, now, out);
+                None
+            }
+            CHAP_SUCCESS if self.peer_auth == Some(AuthProtocol::Chap) => {
+                self.authenticated = true;
+                self.start_network(now, out);
+                None
+            }
+            CHAP_FAILURE if self.peer_auth == Some(AuthProtocol::Chap) => {
+                Some(self.fail("authentication rejected by the peer".to_string(), now, out))
+            }
+            _ => None,
+        }
+    }
+
+    fn request_options(&self, protocol: u16) -> Vec<ConfigOption> {
+        let rejected = &self.fsm(protocol).rejected;
+        let mut options = vec![];
+        let mut push = |kind: u8, value: Vec<u8>| {
+            if !rejected.contains(&kind) {
+                options.push((kind, value));
+            }
+        };
+        match protocol {
+            PROTO_LCP => {
+                if self.mru != DEFAULT_MRU {
+                    push(LCP_MRU, self.mru.to_be_bytes().to_vec());
+                }
+                push(LCP_ACCM, self.rx_accm.to_be_bytes().to_vec());
+                if let Some(auth) = self.auth {
+                    push(LCP_AUTH, auth.option());
+                }
+                push(LCP_MAGIC, self.magic.to_be_bytes().to_vec());
+            }
+            _ => push(IPCP_ADDRESS, self.address.octets().to_vec()),
+        }
+        options
+    }
+
+    /// Answers a request of the peer, applying its options once they are
+    /// all acceptable.
+    fn check_options(
+        &mut self,
+        protocol: u16,
+        options: Vec<ConfigOption>,
+    ) -> (u8, Vec<ConfigOption>) {
+        let mut naks = vec![];
+        let mut rejects = vec![];
+        for (kind, value) in &options {
+            match self.check_option(protocol, *kind, value) {
+                Verdict::Ack => {}
+                Verdict::Nak(hint) => naks.push((*kind, hint)),
+                Verdict::Reject => rejects.push((*kind, value.clone())),
+            }
+        }
+        if !rejects.is_empty() {
+            return (CONFIGURE_REJECT, rejects);
+        }
+        if !naks.is_empty() {
+            return (CONFIGURE_NAK, naks);
+        }
+
+        if protocol == PROTO_LCP {
+            self.peer_mru = DEFAULT_MRU;
+            self.accm = u32::MAX;
+            self.peer_auth = None;
+        }
+        for (kind, value) in &options {
+            match (protocol, *kind) {
+                (PROTO_LCP, LCP_MRU) => self.peer_mru = u16::from_be_bytes([value[0], value[1]]),
+                (PROTO_LCP, LCP_ACCM) => {
+                    self.accm = u32::from_be_bytes(value[..].try_into().unwrap())
+                }
+                (PROTO_LCP, LCP_AUTH) => self.peer_auth = AuthProtocol::from_option(value),
+                (PROTO_IPCP, IPCP_ADDRESS) => self.peer_address = address(value).unwrap(),
+                _ => {}
+            }
+        }
+        (CONFIGURE_ACK, options)
+    }
+
+    fn check_option(&self, protocol: u16, kind: u8, value: &[u8]) -> Verdict {
+        match (protocol, kind, value.len()) {
+            (PROTO_LCP, LCP_MRU, 2) | (PROTO_LCP, LCP_ACCM, 4) => Verdict::Ack,
+            (PROTO_LCP, LCP_AUTH, _) => match AuthProtocol::from_option(value) {
+                Some(_) => Verdict::Ack,
+                None => Verdict::Nak(AuthProtocol::Chap.option()),
+            },
+            // our own magic number coming back may be a looped-back line
+            (PROTO_LCP, LCP_MAGIC, 4) if value == self.magic.to_be_bytes() => {
+                Verdict::Nak(random_u32().to_be_bytes().to_vec())
+            }
+            (PROTO_LCP, LCP_MAGIC, 4) => Verdict::Ack,
+            (PROTO_IPCP, IPCP_ADDRESS, 4) => {
+                let requested = address(value).unwrap();
+                match self.config.peer {
+                    Some(peer) if peer != requested => Verdict::Nak(peer.octets().to_vec()),
+                    None if requested.is_unspecified() => Verdict::Reject,
+                    _ => Verdict::Ack,
+                }
+            }
+            _ => Verdict::Reject,
+        }
+    }
+
+    /// Adjusts our request to a Configure-Nak or Configure-Reject.
+    fn apply_refusal(
+        &mut self,
+        protocol: u16,
+        code: u8,
+        options: Vec<ConfigOption>,
+    ) -> Result<(), String> {
+        for (kind, value) in options {
+            if code == CONFIGURE_REJECT {
+                match (protocol, kind) {
+                    (PROTO_LCP, LCP_AUTH) => {
+                        return Err("the peer refuses to authenticate".to_string())
+                    }
+                    (PROTO_IPCP, IPCP_ADDRESS) if self.address.is_unspecified() => {
+                        return Err("no IP address assigned".to_string())
+                    }
+                    _ => self.fsm_mut(protocol).rejected.push(kind),
+                }
+                continue;
+            }
+            match (protocol, kind) {
+                (PROTO_LCP, LCP_MRU) if value.len() == 2 => {
+                    self.mru = u16::from_be_bytes([value[0], value[1]])
+                }
+                (PROTO_LCP, LCP_ACCM) if value.len() == 4 => {
+                    self.rx_accm = u32::from_be_bytes(value[..].try_into().unwrap())
+                }
+                (PROTO_LCP, LCP_AUTH) => match AuthProtocol::from_option(&value) {
+                    Some(auth) => self.auth = Some(auth),
+                    None => return Err("no authentication protocol in common".to_string()),
+                },
+                (PROTO_LCP, LCP_MAGIC) => self.magic = random_u32(),
+                (PROTO_IPCP, IPCP_ADDRESS) => {
+                    if let Some(address) = address(&value) {
+                        self.address = address;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,672 @@
+use crate::{v27ter, v29};
+use num_complex::Complex32;
+use std::collections::VecDeque;
+use std::f32::consts::PI;
+
+/// Phase change, in multiples of 45°, for each tribit taken with its first
+/// bit as the most significant one. Used by V.27ter at 4800 bit/s and V.29.
+pub(crate) const TRIBIT_PHASE: [u8; 8] = [1, 0, 2, 3, 6, 7, 5, 4];
+/// Phase change, in multiples of 90°, for each dibit of V.27ter at 2400 bit/s.
+pub(crate) const DIBIT_PHASE: [u8; 4] = [1, 0, 2, 3];
+
+/// Peak amplitude of a unit symbol in the transmitted audio.
+const AUDIO_LEVEL: f32 = 0.3;
+const CARRIER_ON_DBFS: f32 = -43.;
+const CARRIER_OFF_DBFS: f32 = -48.;
+/// Fall of the power, from the one measured during the acquisition, which
+/// ends the carrier (-20 dB).
+const CARRIER_DROP: f32 = 0.01;
+
+const EQUALIZER_TAPS: usize = 7;
+/// Symbols ignored after the carrier is detected, while the symbol clock
+/// settles, and then used to estimate the gain and the carrier phase.
+const SETTLING_SYMBOLS: usize = 8;
+const ACQUISITION_SYMBOLS: usize = 16;
+
+/// High-speed modulations used for fax image data, identified by their fax
+/// class 1 codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Modulation {
+    V27ter2400,
+    V27ter4800,
+    V29_7200,
+    V29_9600,
+}
+
+impl Modulation {
+    pub fn from_class1(code: u8) -> Option<Self> {
+        match code {
+            24 => Some(Modulation::V27ter2400),
+            48 => Some(Modulation::V27ter4800),
+            72 => Some(Modulation::V29_7200),
+            96 => Some(Modulation::V29_9600),
+            _ => None,
+        }
+    }
+
+    pub fn bit_rate(&self) -> usize {
+        match self {
+            Modulation::V27ter2400 => 2400,
+            Modulation::V27ter4800 => 4800,
+            Modulation::V29_7200 => 7200,
+            Modulation::V29_9600 => 9600,
+        }
+    }
+
+    fn config(&self) -> Config {
+        match self {
+            Modulation::V27ter2400 => v27ter::config(2400),
+            Modulation::V27ter4800 => v27ter::config(4800),
+            Modulation::V29_7200 => v29::config(7200),
+            Modulation::V29_9600 => v29::config(9600),
+        }
+    }
+}
+
+/// Self-synchronizing scrambler with polynomial `1 + x^-a + x^-b`.
+#[derive(Clone, Debug)]
+pub struct Scrambler {
+    a: u32,
+    b: u32,
+    state: u32,
+}
+
+impl Scrambler {
+    pub fn new(a: u32, b: u32, state: u32) -> Self {
+        Self { a, b, state }
+    }
+
+    fn feedback(&self) -> u8 {
+        (((self.state >> (self.a - 1)) ^ (self.state >> (self.b - 1))) & 1) as u8
+    }
+
+    pub fn scramble(&mut self, bit: u8) -> u8 {
+        let out = bit ^ self.feedback();
+        self.state = (self.state << 1) | out as u32;
+        out
+    }
+
+    pub fn descramble(&mut self, bit: u8) -> u8 {
+        let out = bit ^ self.feedback();
+        self.state = (self.state << 1) | bit as u32;
+        out
+    }
+}
+
+/// Differentially encoded signal constellation: the phase changes by a
+/// multiple of 45° according to two or three bits, and an optional leading
+/// bit selects one of two amplitudes.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Constellation {
+    pub phase_bits: usize,
+    pub amplitude_bit: bool,
+    /// Amplitudes for even and odd multiples of 45°, selected by the
+    /// amplitude bit
+    pub amplitudes: [[f32; 2]; 2],
+}
+
+impl Constellation {
+    fn bits_per_symbol(&self) -> usize {
+        self.phase_bits + self.amplitude_bit as usize
+    }
+
+    fn point(&self, phase: u8, amplitude_bit: u8) -> Complex32 {
+        let amplitude = self.amplitudes[phase as usize % 2][amplitude_bit as usize];
+        Complex32::from_polar(amplitude, phase as f32 * PI / 4.)
+    }
+
+    /// Maps the bits of a symbol, updating the absolute `phase`.
+    pub fn map(&self, bits: &[u8], phase: &mut u8) -> Complex32 {
+        let (amplitude_bit, phase_bits) = if self.amplitude_bit {
+            (bits[0], &bits[1..])
+        } else {
+            (0, bits)
+        };
+        let value = phase_bits.iter().fold(0, |acc, bit| (acc << 1) | bit) as usize;
+        let change = match self.phase_bits {
+            3 => TRIBIT_PHASE[value],
+            _ => 2 * DIBIT_PHASE[value],
+        };
+        *phase = (*phase + change) % 8;
+        self.point(*phase, amplitude_bit)
+    }
+
+    /// Every point which may be received, with its phase and amplitude bit.
+    fn points(&self) -> Vec<(Complex32, u8, u8)> {
+        let phase_step = if self.phase_bits == 3 { 1 } else { 2 };
+        let mut points = vec![];
+        for phase in (0..8).step_by(phase_step) {
+            for amplitude_bit in 0..=self.amplitude_bit as u8 {
+                points.push((self.point(phase, amplitude_bit), phase, amplitude_bit));
+            }
+        }
+        points
+    }
+
+    fn demap(&self, phase: u8, amplitude_bit: u8, last_phase: &mut u8, bits: &mut Vec<u8>) {
+        let change = (phase + 8 - *last_phase) % 8;
+        *last_phase = phase;
+        if self.amplitude_bit {
+            bits.push(amplitude_bit);
+        }
+        let value = match self.phase_bits {
+            3 => TRIBIT_PHASE.iter().position(|p| *p == change),
+            _ => DIBIT_PHASE.iter().position(|p| 2 * *p == change),
+        }
+        .unwrap_or(0);
+        for i in (0..self.phase_bits).rev() {
+            bits.push(((value >> i) & 1) as u8);
+        }
+    }
+}
+
+/// Training sequence sent before the data, after which the scrambled data
+/// is differentially encoded starting from `end_phase`.
+#[derive(Clone, Debug)]
+pub(crate) struct Training {
+    /// Symbol intervals of silence
+    pub silence: usize,
+    /// Alternation of two symbols, during which the receiver acquires the
+    /// symbol clock, the gain and the carrier phase
+    pub alternation: [Complex32; 2],
+    pub alternation_len: usize,
+    /// Known symbols used to train the equalizer
+    pub equalizer: Vec<Complex32>,
+    /// Symbol intervals of scrambled ones, which synchronize the
+    /// descrambler
+    pub scrambled_ones: usize,
+    pub end_phase: u8,
+    /// Scrambler state after the equalizer training
+    pub scrambler: Scrambler,
+}
+
+impl Training {
+    fn alternation_symbol(&self, index: usize) -> Complex32 {
+        self.alternation[index % 2]
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Config {
+    pub carrier: f32,
+    pub baud_rate: f32,
+    pub rolloff: f32,
+    /// Length of the pulse shaping filters, in symbols
+    pub span: usize,
+    pub constellation: Constellation,
+    pub training: Training,
+}
+
+/// Root raised cosine pulse, with unit energy for a symbol period of 1.
+fn rrc(t: f32, beta: f32) -> f32 {
+    if t.abs() < 1e-6 {
+        return 1. - beta + 4. * beta / PI;
+    }
+    let x = 4. * beta * t;
+    if (x.abs() - 1.).abs() < 1e-4 {
+        let a = PI / (4. * beta);
+        return beta / 2f32.sqrt() * ((1. + 2. / PI) * a.sin() + (1. - 2. / PI) * a.cos());
+    }
+    ((PI * t * (1. - beta)).sin() + x * (PI * t * (1. + beta)).cos()) / (PI * t * (1. - x * x))
+}
+
+/// Transmitter for the fax data pumps, in the same streaming style as
+/// `V21TX`. The training sequence is sent as soon as samples are requested,
+/// followed by the queued data, and by zeros whenever the queue runs out
+/// before [`DataPumpTX::finish`] is called.
+pub struct DataPumpTX {
+    config: Config,
+    sampling_period: f32,
+    samples_per_symbol: f64,
+    training: VecDeque<Complex32>,
+    scrambled_ones: usize,
+    scrambler: Scrambler,
+    phase: u8,
+    bits: VecDeque<u8>,
+    finished: bool,
+    /// Symbols within the span of the pulse shaping filter, the first of
+    /// which has index `first_symbol`
+    symbols: VecDeque<Complex32>,
+    first_symbol: i64,
+    exhausted: Option<i64>,
+    sample: u64,
+    carrier_phase: f32,
+}
+
+impl DataPumpTX {
+    pub fn new(sampling_period: f32, modulation: Modulation) -> Self {
+        let config = modulation.config();
+        let t = &config.training;
+        let mut training: VecDeque<Complex32> =
+            std::iter::repeat_n(Complex32::new(0., 0.), t.silence).collect();
+        training.extend((0..t.alternation_len).map(|i| t.alternation_symbol(i)));
+        training.extend(&t.equalizer);
+        Self {
+            sampling_period,
+            samples_per_symbol: 1. / (sampling_period as f64 * config.baud_rate as f64),
+            training,
+            scrambled_ones: t.scrambled_ones,
+            scrambler: t.scrambler.clone(),
+            phase: t.end_phase,
+            bits: VecDeque::new(),
+            finished: false,
+            symbols: VecDeque::new(),
+            first_symbol: -(config.span as i64) / 2,
+            exhausted: None,
+            sample: 0,
+            carrier_phase: 0.,
+            config,
+        }
+    }
+
+    /// Queues data, which is sent least significant bit first.
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.bits.extend((0..8).map(|i| (byte >> i) & 1));
+        }
+    }
+
+    /// Turns the carrier off once the queued data has been sent.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.exhausted.is_some_and(|last| self.first_symbol > last)
+    }
+
+    fn next_symbol(&mut self) -> Option<Complex32> {
+        if let Some(symbol) = self.training.pop_front() {
+            return Some(symbol);
+        }
+        let constellation = self.config.constellation;
+        let n = constellation.bits_per_symbol();
+        let bits: Vec<u8> = if self.scrambled_ones > 0 {
+            self.scrambled_ones -= 1;
+            vec![1; n]
+        } else if !self.bits.is_empty() || !self.finished {
+            (0..n).map(|_| self.bits.pop_front().unwrap_or(0)).collect()
+        } else {
+            return None;
+        };
+        let bits: Vec<u8> = bits.iter().map(|b| self.scrambler.scramble(*b)).collect();
+        Some(constellation.map(&bits, &mut self.phase))
+    }
+
+    pub fn modulate(&mut self, out_samples: &mut [f32]) {
+        let half_span = self.config.span as f64 / 2.;
+        let omega = 2. * PI * self.config.carrier;
+
+        for out in out_samples.iter_mut() {
+            if self.is_idle() {
+                *out = 0.;
+                continue;
+            }
+
+            let t = self.sample as f64 / self.samples_per_symbol;
+            while self.first_symbol + (self.symbols.len() as i64) <= (t + half_span) as i64 {
+                let index = self.first_symbol + self.symbols.len() as i64;
+                let symbol = match self.exhausted {
+                    Some(_) => None,
+                    None if index < 0 => Some(Complex32::new(0., 0.)),
+                    None => self.next_symbol(),
+                };
+                if symbol.is_none() && self.exhausted.is_none() {
+                    self.exhausted = Some(index + self.config.span as i64);
+                }
+                self.symbols.push_back(symbol.unwrap_or_default());
+            }
+            while (self.first_symbol as f64) < t - half_span {
+                self.symbols.pop_front();
+                self.first_symbol += 1;
+            }
+
+            let mut baseband = Complex32::new(0., 0.);
+            for (i, symbol) in self.symbols.iter().enumerate() {
+                let dt = t - (self.first_symbol + i as i64) as f64;
+                baseband += symbol * rrc(dt as f32, self.config.rolloff);
+            }
+            *out = AUDIO_LEVEL * (baseband * Complex32::from_polar(1., self.carrier_phase)).re;
+
+            self.carrier_phase = (self.carrier_phase + omega * self.sampling_period) % (2. * PI);
+            self.sample += 1;
+        }
+    }
+}
+
+enum RxState {
+    /// Waiting for the carrier
+    Idle,
+    /// Symbols received since the carrier was detected
+    Acquisition(Vec<Complex32>),
+    /// Following the alternation, whose next symbol has the given index
+    Alternation(usize),
+    /// Training the equalizer with the symbol of the given index
+    Equalizer(usize),
+    /// Synchronizing the descrambler, with the given symbols left, this one
+    /// included
+    ScrambledOnes(usize),
+    Data,
+}
+
+/// Receiver for the fax data pumps, in the same streaming style as `V21RX`.
+///
+/// The signal is brought to baseband and passed through a matched filter,
+/// whose output is sampled at the symbol instants found by a Gardner timing
+/// error detector. The alternation at the start of the training sequence
+/// gives the gain and carrier phase, the known equalizer training symbols
+/// train an adaptive equalizer, and the decisions keep adapting it, along
+/// with the carrier phase, during the data.
+pub struct DataPumpRX {
+    config: Config,
+    points: Vec<(Complex32, u8, u8)>,
+    /// Index of the first equalizer training symbol which does not continue
+    /// the alternation, i.e. the first one which can be told apart from it
+    equalizer_start: usize,
+    sampling_period: f32,
+    samples_per_symbol: f32,
+    mixer_phase: f32,
+    taps: Vec<f32>,
+    history: VecDeque<Complex32>,
+    countdown: f32,
+    on_time: bool,
+    last_symbol: Complex32,
+    mid_symbol: Complex32,
+    power: f32,
+    /// Faster estimate of the power, and its value during the acquisition,
+    /// which detect the end of the carrier
+    level: f32,
+    reference_level: f32,
+    gain: f32,
+    carrier_phase: f32,
+    equalizer: Vec<Complex32>,
+    equalizer_in: VecDeque<Complex32>,
+    state: RxState,
+    last_phase: u8,
+    descrambler: Scrambler,
+    byte: u8,
+    bit_count: usize,
+}
+
+impl DataPumpRX {
+    pub fn new(sampling_period: f32, modulation: Modulation) -> Self {
+        let config = modulation.config();
+        let samples_per_symbol = 1. / (sampling_period * config.baud_rate);
+        let half_len = (config.span as f32 * samples_per_symbol / 2.) as i32;
+        let taps = (-half_len..=half_len)
+            .map(|n| 2. * rrc(n as f32 / samples_per_symbol, config.rolloff) / samples_per_symbol)
+            .collect();
+
+        let t = &config.training;
+        let equalizer_start = t
+            .equalizer
+            .iter()
+            .enumerate()
+            .position(|(i, symbol)| {
+                (symbol - t.alternation_symbol(t.alternation_len + i)).norm() > 1e-3
+            })
+            .unwrap_or(0);
+
+        let mut equalizer = vec![Complex32::new(0., 0.); EQUALIZER_TAPS];
+        equalizer[EQUALIZER_TAPS / 2] = Complex32::new(1., 0.);
+
+        Self {
+            points: config.constellation.points(),
+            equalizer_start,
+            sampling_period,
+            samples_per_symbol,
+            mixer_phase: 0.,
+            taps,
+            history: VecDeque::new(),
+            countdown: samples_per_symbol / 2.,
+            on_time: true,
+            last_symbol: Complex32::new(0., 0.),
+            mid_symbol: Complex32::new(0., 0.),
+            power: 0.,
+            level: 0.,
+            reference_level: 0.,
+            gain: 1.,
+            carrier_phase: 0.,
+            equalizer,
+            equalizer_in: std::iter::repeat_n(Complex32::new(0., 0.), EQUALIZER_TAPS).collect(),
+            state: RxState::Idle,
+            last_phase: 0,
+            descrambler: config.training.scrambler.clone(),
+            byte: 0,
+            bit_count: 0,
+            config,
+        }
+    }
+
+    /// Whether a carrier is being received.
+    pub fn carrier(&self) -> bool {
+        !matches!(self.state, RxState::Idle)
+    }
+
+    /// Appends the data received to `out`, least significant bit first.
+    pub fn demodulate(&mut self, in_samples: &[f32], out: &mut Vec<u8>) {
+        let omega = 2. * PI * self.config.carrier;
+        for sample in in_samples {
+            let mixed = Complex32::from_polar(*sample, -self.mixer_phase);
+            self.mixer_phase = (self.mixer_phase + omega * self.sampling_period) % (2. * PI);
+
+            self.history.push_back(mixed);
+            if self.history.len() > self.taps.len() + 1 {
+                self.history.pop_front();
+            }
+
+            if self.history.len() <= self.taps.len() {
+                continue;
+            }
+            self.countdown -= 1.;
+            if self.countdown > 0. {
+                continue;
+            }
+            // interpolate the matched filter output at the sampling instant,
+            // which lies between the last two input samples
+            let current = self.matched_filter(1);
+            let previous = self.matched_filter(0);
+            let value = current + (current - previous) * self.countdown;
+            self.countdown += self.samples_per_symbol / 2.;
+
+            self.on_time = !self.on_time;
+            if self.on_time {
+                self.mid_symbol = value;
+                continue;
+            }
+            self.update_timing(value);
+            self.put_symbol(value, out);
+        }
+    }
+
+    fn matched_filter(&self, offset: usize) -> Complex32 {
+        self.history
+            .iter()
+            .skip(offset)
+            .zip(self.taps.iter())
+            .map(|(x, tap)| x * tap)
+            .sum()
+    }
+
+    fn update_timing(&mut self, symbol: Complex32) {
+        let error = ((self.last_symbol - symbol) * self.mid_symbol.conj()).re
+            / self.power.max(f32::EPSILON);
+        self.last_symbol = symbol;
+
+        let gain = match self.state {
+            RxState::Idle | RxState::Acquisition(_) | RxState::Alternation(_) => 0.05,
+            _ => 0.01,
+        };
+        let max_step = self.samples_per_symbol / 8.;
+        self.countdown += (gain * self.samples_per_symbol * error).clamp(-max_step, max_step);
+
+        self.power = 0.9 * self.power + 0.1 * symbol.norm_sqr();
+        self.level = 0.5 * self.level + 0.5 * symbol.norm_sqr();
+    }
+
+    fn put_symbol(&mut self, symbol: Complex32, out: &mut Vec<u8>) {
+        let level_dbfs = 10. * self.power.max(f32::EPSILON).log10();
+        if matches!(self.state, RxState::Idle) {
+            if level_dbfs >= CARRIER_ON_DBFS {
+                self.state = RxState::Acquisition(vec![]);
+            }
+            return;
+        }
+        if level_dbfs < CARRIER_OFF_DBFS || self.level < CARRIER_DROP * self.reference_level {
+            self.reset();
+            return;
+        }
+
+        self.equalizer_in.pop_front();
+        self.equalizer_in.push_back(symbol * self.gain);
+        let equalized: Complex32 = self
+            .equalizer
+            .iter()
+            .zip(self.equalizer_in.iter().rev())
+            .map(|(w, x)| w * x)
+            .sum();
+        let rotated = equalized * Complex32::from_polar(1., -self.carrier_phase);
+
+        let t = &self.config.training;
+        match &mut self.state {
+            RxState::Idle => {}
+            RxState::Acquisition(symbols) => {
+                symbols.push(rotated);
+                if symbols.len() == SETTLING_SYMBOLS + ACQUISITION_SYMBOLS {
+                    let symbols = symbols.split_off(SETTLING_SYMBOLS);
+                    self.acquire(&symbols);
+                }
+            }
+            RxState::Alternation(index) => {
+                let expected = t.alternation_symbol(*index);
+                let max_error = 0.4 * expected.norm();
+                if (rotated - expected).norm() < max_error {
+                    *index += 1;
+                    self.adapt(rotated, expected);
+                } else {
+                    let expected = t.equalizer[self.equalizer_start];
+                    // the phase of an alternation of opposite symbols is only
+                    // known up to 180°
+                    if (rotated + expected).norm() < (rotated - expected).norm() {
+                        self.carrier_phase += PI;
+                    }
+                    self.state = RxState::Equalizer(self.equalizer_start + 1);
+                }
+            }
+            RxState::Equalizer(index) => {
+                let expected = t.equalizer[*index];
+                *index += 1;
+                if *index == t.equalizer.len() {
+                    self.state = RxState::ScrambledOnes(t.scrambled_ones);
+                    self.last_phase = t.end_phase;
+                    self.descrambler = t.scrambler.clone();
+                }
+                self.adapt(rotated, expected);
+            }
+            RxState::ScrambledOnes(_) | RxState::Data => {
+                let (decision, phase, amplitude_bit) = *self
+                    .points
+                    .iter()
+                    .min_by(|a, b| {
+                        (rotated - a.0)
+                            .norm_sqr()
+                            .total_cmp(&(rotated - b.0).norm_sqr())
+                    })
+                    .unwrap();
+                self.adapt(rotated, decision);
+
+                let mut bits = vec![];
+                self.config.constellation.demap(
+                    phase,
+                    amplitude_bit,
+                    &mut self.last_phase,
+                    &mut bits,
+                );
+                let bits: Vec<u8> = bits
+                    .iter()
+                    .map(|b| self.descrambler.descramble(*b))
+                    .collect();
+                match &mut self.state {
+                    RxState::ScrambledOnes(1) => self.state = RxState::Data,
+                    RxState::ScrambledOnes(left) => *left -= 1,
+                    _ => self.put_bits(&bits, out),
+                }
+            }
+        }
+    }
+
+    /// Estimates the gain and carrier phase from symbols of the alternation.
+    fn acquire(&mut self, symbols: &[Complex32]) {
+        let t = &self.config.training;
+        let correlation = |offset: usize| -> Complex32 {
+            symbols
+                .iter()
+                .enumerate()
+                .map(|(i, x)| x * t.alternation_symbol(i + offset).conj())
+                .sum()
+        };
+        let (offset, correlation) = [0, 1]
+            .into_iter()
+            .map(|offset| (offset, correlation(offset)))
+            .max_by(|a, b| a.1.norm().total_cmp(&b.1.norm()))
+            .unwrap();
+
+        let expected_power = (t.alternation[0].norm_sqr() + t.alternation[1].norm_sqr()) / 2.;
+        let power = symbols.iter().map(|x| x.norm_sqr()).sum::<f32>() / symbols.len() as f32;
+        let gain = (expected_power / power.max(f32::EPSILON)).sqrt();
+        self.reference_level = power;
+        self.gain *= gain;
+        self.equalizer_in.iter_mut().for_each(|x| *x *= gain);
+        self.carrier_phase += correlation.arg();
+        self.state = RxState::Alternation(offset + symbols.len());
+    }
+
+    /// Adapts the equalizer and the carrier phase towards the expected
+    /// symbol.
+    fn adapt(&mut self, received: Complex32, expected: Complex32) {
+        let norm = expected.norm_sqr().max(f32::EPSILON);
+        self.carrier_phase += 0.05 * (received * expected.conj()).im / norm;
+
+        let step = match self.state {
+            RxState::Data | RxState::ScrambledOnes(_) => 0.02,
+            _ => 0.1,
+        };
+        let energy: f32 = self.equalizer_in.iter().map(|x| x.norm_sqr()).sum();
+        let error = (expected - received) * Complex32::from_polar(1., self.carrier_phase);
+        for (w, x) in self
+            .equalizer
+            .iter_mut()
+            .zip(self.equalizer_in.iter().rev())
+        {
+            *w += step * error * x.conj() / energy.max(f32::EPSILON);
+        }
+    }
+
+    fn put_bits(&mut self, bits: &[u8], out: &mut Vec<u8>) {
+        for bit in bits {
+            self.byte |= bit << self.bit_count;
+            self.bit_count += 1;
+            if self.bit_count == 8 {
+                out.push(self.byte);
+                self.byte = 0;
+                self.bit_count = 0;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = RxState::Idle;
+        self.power = 0.;
+        self.level = 0.;
+        self.reference_level = 0.;
+        self.gain = 1.;
+        self.carrier_phase = 0.;
+        self.equalizer.fill(Complex32::new(0., 0.));
+        self.equalizer[EQUALIZER_TAPS / 2] = Complex32::new(1., 0.);
+        self.equalizer_in
+            .iter_mut()
+            .for_each(|x| *x = Complex32::new(0., 0.));
+        self.byte = 0;
+        self.bit_count = 0;
+    }
+}
@@ -0,0 +1,81 @@
+/// The detector works on blocks of this duration.
+const BLOCK_DURATION: f32 = 0.02;
+/// Minimum power, relative to a full-scale sine wave, of a block during a
+/// ring (-40 dBFS).
+const MIN_LEVEL: f32 = 1e-4;
+/// Shortest and longest bursts taken as rings, which rules out clicks and
+/// carriers.
+const MIN_RING: f32 = 0.2;
+const MAX_RING: f32 = 3.;
+/// Highest frequency of a ring: ring tones are at most 480 Hz, while the
+/// caller ID sent between rings, FSK or DTMF, is at 697 Hz or above.
+const MAX_FREQ: f32 = 600.;
+/// Silence which ends a ring, longer than the gap within the double rings
+/// used in some countries.
+const MIN_GAP: f32 = 0.5;
+
+/// Detects the rings played by a line simulator, either as the ring signal
+/// itself or as a ring tone, from their cadence: bursts of sound between
+/// [`MIN_RING`] and [`MAX_RING`] long, each followed by silence. Sound above
+/// [`MAX_FREQ`] counts as silence.
+pub struct RingDetector {
+    block_len: usize,
+    energy: f32,
+    count: usize,
+    /// Zero crossings in the current block, and the sample before
+    crossings: usize,
+    last: f32,
+    /// Blocks with sound in the current burst, and of silence after it
+    on_blocks: usize,
+    off_blocks: usize,
+}
+
+impl RingDetector {
+    pub fn new(sampling_period: f32) -> Self {
+        Self {
+            block_len: (BLOCK_DURATION / sampling_period) as usize,
+            energy: 0.,
+            count: 0,
+            crossings: 0,
+            last: 0.,
+            on_blocks: 0,
+            off_blocks: 0,
+        }
+    }
+
+    /// Returns the number of rings which ended within `in_samples`.
+    pub fn put_samples(&mut self, in_samples: &[f32]) -> usize {
+        let mut rings = 0;
+        for sample in in_samples {
+            self.energy += sample * sample;
+            self.count += 1;
+            if (*sample >= 0.) != (self.last >= 0.) {
+                self.crossings += 1;
+            }
+            self.last = *sample;
+            if self.count < self.block_len {
+                continue;
+            }
+
+            let freq = self.crossings as f32 / (2. * BLOCK_DURATION);
+            if 2. * self.energy / self.block_len as f32 >= MIN_LEVEL && freq <= MAX_FREQ {
+                self.on_blocks += 1;
+                self.off_blocks = 0;
+            } else if self.on_blocks > 0 {
+                self.off_blocks += 1;
+                if self.off_blocks as f32 * BLOCK_DURATION >= MIN_GAP {
+                    let on_time = self.on_blocks as f32 * BLOCK_DURATION;
+                    if (MIN_RING..=MAX_RING).contains(&on_time) {
+                        rings += 1;
+                    }
+                    self.on_blocks = 0;
+                    self.off_blocks = 0;
+                }
+            }
+            self.energy = 0.;
+            self.count = 0;
+            self.crossings = 0;
+        }
+        rings
+    }
+}
@@ -1,29 +1,44 @@
-use anyhow;
+use crate::backend::{DteBackend, DteChannels};
+use crate::flow::FlowControl;
+use crate::port::{Device, Parity, PortOptions};
+use anyhow::Context;
 use crossbeam_channel::{Receiver, Sender};
-use nix;
-use std::os::fd::{AsRawFd, OwnedFd};
+use nix::poll::{poll, PollFd, PollFlags};
+use nix::sys::termios::{self, BaudRate, ControlFlags, InputFlags, SetArg};
+use std::fs::{OpenOptions, Permissions};
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+/// A pty, or a tty of the Linux kernel.
 pub struct Serial {
     to_uart: Sender<u8>,
+    dtr: Sender<bool>,
+    cts: Arc<AtomicBool>,
+    flow_control: Receiver<FlowControl>,
+    options: PortOptions,
     pty: OwnedFd,
+    /// Whether `pty` is the master of a pseudo-terminal rather than a tty
+    is_pty: bool,
 }
 
 impl Serial {
-    pub fn open(
-        _options: &str,
-        from_uart: Receiver<u8>,
-        to_uart: Sender<u8>,
-    ) -> anyhow::Result<Self> {
-        let res = nix::pty::openpty(None, None)?;
-        let pty = res.master;
-
-        let mut termios = nix::sys::termios::tcgetattr(&pty)?;
-        nix::sys::termios::cfmakeraw(&mut termios);
-        nix::sys::termios::cfsetspeed(&mut termios, nix::sys::termios::BaudRate::B115200)?;
-        nix::sys::termios::tcsetattr(&pty, nix::sys::termios::SetArg::TCSANOW, &termios)?;
-
-        let pty_name = nix::unistd::ttyname(&res.slave)?;
-        eprintln!("criado porto serial em {}", pty_name.to_string_lossy());
+    pub fn open(options: &PortOptions, channels: DteChannels) -> anyhow::Result<Self> {
+        let DteChannels {
+            from_modem: from_uart,
+            to_modem: to_uart,
+            dtr,
+            cts,
+            flow_control,
+        } = channels;
+        let (pty, is_pty) = match &options.device {
+            Device::Pty => (open_pty(options)?, true),
+            Device::Path(device) => (open_tty(device, options, dtr.clone())?, false),
+            _ => anyhow::bail!("not a serial port"),
+        };
+        configure(&pty, options)?;
 
         {
             let pty = pty.try_clone().unwrap();
@@ -33,27 +48,190 @@ impl Serial {
             });
         }
 
-        Ok(Self { to_uart, pty })
+        Ok(Self {
+            to_uart,
+            dtr,
+            cts,
+            flow_control,
+            options: options.clone(),
+            pty,
+            is_pty,
+        })
     }
+}
 
-    pub fn event_loop(&mut self) -> anyhow::Result<()> {
+impl DteBackend for Serial {
+    fn event_loop(&mut self) -> anyhow::Result<()> {
+        // a pty has no DTR line, so it is taken as raised while the other
+        // end keeps the pty open, which is when the master stops reporting
+        // a hangup
+        let mut peer_open = false;
         loop {
-            let mut buf: [u8; 1] = [0];
-            let res = nix::unistd::read(self.pty.as_raw_fd(), &mut buf);
-            match res {
-                Ok(amount) => {
-                    if amount == 1 {
-                        self.to_uart.send(buf[0]).unwrap();
+            while let Ok(flow_control) = self.flow_control.try_recv() {
+                self.options.flow_control = flow_control;
+                configure(&self.pty, &self.options)?;
+            }
+
+            let mut fds = [PollFd::new(self.pty.as_fd(), PollFlags::POLLIN)];
+            match poll(&mut fds, 100u16) {
+                Ok(_) | Err(nix::errno::Errno::EINTR) => {}
+                Err(err) => return Err(err.into()),
+            }
+            let revents = fds[0].revents().unwrap_or(PollFlags::empty());
+            let hangup = self.is_pty && revents.contains(PollFlags::POLLHUP);
+            if self.is_pty && hangup == peer_open {
+                peer_open = !hangup;
+                eprintln!(
+                    "serial port: peer {}",
+                    if peer_open {
+                        "connected"
+                    } else {
+                        "disconnected"
                     }
+                );
+                self.dtr.send(peer_open).unwrap();
+            }
+
+            // neither is there a CTS line, but leaving the bytes in the pty
+            // blocks the host just the same once its buffer fills up
+            if revents.contains(PollFlags::POLLIN) && self.cts.load(Ordering::Relaxed) {
+                let mut buf: [u8; 1] = [0];
+                match nix::unistd::read(self.pty.as_raw_fd(), &mut buf) {
+                    Ok(1) => self.to_uart.send(buf[0]).unwrap(),
+                    Ok(_) => {}
+                    // the peer may close the pty between poll and read
+                    Err(nix::errno::Errno::EIO) if self.is_pty => {}
+                    Err(err) => return Err(err.into()),
                 }
-                Err(nix::errno::Errno::EIO) => {
-                    // ignora o EIO que acontece enquanto a outra ponta não conecta à pty
-                    std::thread::sleep(std::time::Duration::from_millis(100))
-                }
-                Err(_) => {
-                    res?;
-                }
+            } else if !revents.is_empty() {
+                // poll returns at once while nobody has the pty open, or
+                // while the host is stopped with bytes waiting
+                std::thread::sleep(std::time::Duration::from_millis(10));
             }
         }
     }
 }
+
+fn open_pty(options: &PortOptions) -> anyhow::Result<OwnedFd> {
+    let res = nix::pty::openpty(None, None)?;
+    let pty_name = nix::unistd::ttyname(&res.slave)?;
+    if let Some(mode) = options.mode {
+        std::fs::set_permissions(&pty_name, Permissions::from_mode(mode))?;
+    }
+    if let Some(link) = &options.link {
+        update_link(link, &pty_name)
+            .with_context(|| format!("failed to link {}", link.display()))?;
+    }
+    eprintln!("serial port: {}", pty_name.to_string_lossy());
+    // the only line written to stdout, for scripts to read
+    println!("{}", pty_name.to_string_lossy());
+    Ok(res.master)
+}
+
+/// Points `link` to the pty, replacing at once a link left behind by an
+/// earlier run, but never a file which is not a symlink.
+fn update_link(link: &Path, pty_name: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        link.is_symlink() || !link.exists(),
+        "it exists and is not a symlink"
+    );
+    let mut new_link = link.as_os_str().to_owned();
+    new_link.push(".new");
+    let _ = std::fs::remove_file(&new_link);
+    std::os::unix::fs::symlink(pty_name, &new_link)?;
+    std::fs::rename(&new_link, link)?;
+    Ok(())
+}
+
+/// Opens a real serial port, whose DSR tells whether the host raised DTR.
+fn open_tty(device: &str, options: &PortOptions, dtr: Sender<bool>) -> anyhow::Result<OwnedFd> {
+    anyhow::ensure!(
+        options.mode.is_none() && options.link.is_none(),
+        "mode and link only apply to a pty"
+    );
+    // O_NONBLOCK keeps the open from waiting for DCD until CLOCAL is set
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(nix::libc::O_NOCTTY | nix::libc::O_NONBLOCK)
+        .open(Path::new(device))?;
+    let tty = OwnedFd::from(file);
+    let mut termios = termios::tcgetattr(&tty)?;
+    termios.control_flags.insert(ControlFlags::CLOCAL);
+    termios::tcsetattr(&tty, SetArg::TCSANOW, &termios)?;
+    nix::errno::Errno::result(unsafe { nix::libc::fcntl(tty.as_raw_fd(), nix::libc::F_SETFL, 0) })?;
+
+    let status_tty = tty.try_clone()?;
+    std::thread::spawn(move || {
+        let mut dsr = false;
+        loop {
+            let mut status: nix::libc::c_int = 0;
+            let res = unsafe {
+                nix::libc::ioctl(status_tty.as_raw_fd(), nix::libc::TIOCMGET, &mut status)
+            };
+            if res == 0 && (status & nix::libc::TIOCM_DSR != 0) != dsr {
+                dsr = !dsr;
+                dtr.send(dsr).unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+        }
+    });
+    eprintln!("serial port: {}", device);
+    Ok(tty)
+}
+
+fn configure(fd: &OwnedFd, options: &PortOptions) -> anyhow::Result<()> {
+    let mut termios = termios::tcgetattr(fd)?;
+    termios::cfmakeraw(&mut termios);
+    termios::cfsetspeed(&mut termios, baud_rate(options.speed)?)?;
+
+    let control = &mut termios.control_flags;
+    control.remove(ControlFlags::CSIZE);
+    control.insert(match options.data_bits {
+        5 => ControlFlags::CS5,
+        6 => ControlFlags::CS6,
+        7 => ControlFlags::CS7,
+        _ => ControlFlags::CS8,
+    });
+    control.set(ControlFlags::PARENB, options.parity != Parity::None);
+    control.set(ControlFlags::PARODD, options.parity == Parity::Odd);
+    control.set(ControlFlags::CSTOPB, options.stop_bits == 2);
+    control.set(
+        ControlFlags::CRTSCTS,
+        options.flow_control == FlowControl::RtsCts,
+    );
+    control.insert(ControlFlags::CLOCAL | ControlFlags::CREAD);
+    termios.input_flags.set(
+        InputFlags::IXON | InputFlags::IXOFF,
+        options.flow_control == FlowControl::XonXoff,
+    );
+
+    termios::tcsetattr(fd, SetArg::TCSANOW, &termios)?;
+    Ok(())
+}
+
+fn baud_rate(speed: u32) -> anyhow::Result<BaudRate> {
+    Ok(match speed {
+        50 => BaudRate::B50,
+        75 => BaudRate::B75,
+        110 => BaudRate::B110,
+        134 => BaudRate::B134,
+        150 => BaudRate::B150,
+        200 => BaudRate::B200,
+        300 => BaudRate::B300,
+        600 => BaudRate::B600,
+        1200 => BaudRate::B1200,
+        1800 => BaudRate::B1800,
+        2400 => BaudRate::B2400,
+        4800 => BaudRate::B4800,
+        9600 => BaudRate::B9600,
+        19200 => BaudRate::B19200,
+        38400 => BaudRate::B38400,
+        57600 => BaudRate::B57600,
+        115200 => BaudRate::B115200,
+        230400 => BaudRate::B230400,
+        460800 => BaudRate::B460800,
+        921600 => BaudRate::B921600,
+        _ => anyhow::bail!("unsupported speed {}", speed),
+    })
+}
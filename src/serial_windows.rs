@@ -1,10 +1,16 @@
-use anyhow;
-use crossbeam_channel::{Receiver, Sender};
+use crate::backend::{DteBackend, DteChannels};
+use crate::flow::FlowControl;
+use crate::port::{Device, Parity, PortOptions};
+use crossbeam_channel::Sender;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 use std::{mem::zeroed, ptr::null_mut};
 use winapi::{
     shared::{minwindef::DWORD, ntdef::HANDLE, winerror::ERROR_IO_PENDING},
     um::{
-        commapi::{GetCommState, SetCommState, SetCommTimeouts},
+        commapi::{
+            EscapeCommFunction, GetCommModemStatus, GetCommState, SetCommState, SetCommTimeouts,
+        },
         errhandlingapi::GetLastError,
         fileapi::{CreateFileW, ReadFile, WriteFile, OPEN_EXISTING},
         handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
@@ -12,8 +18,9 @@ use winapi::{
         minwinbase::OVERLAPPED,
         synchapi::{CreateEventA, WaitForSingleObject},
         winbase::{
-            COMMTIMEOUTS, DCB, DTR_CONTROL_ENABLE, FILE_FLAG_OVERLAPPED, INFINITE, NOPARITY,
-            ONESTOPBIT, RTS_CONTROL_ENABLE, WAIT_OBJECT_0,
+            CLRRTS, COMMTIMEOUTS, DCB, DTR_CONTROL_ENABLE, EVENPARITY, FILE_FLAG_OVERLAPPED,
+            INFINITE, MS_DSR_ON, NOPARITY, ODDPARITY, ONESTOPBIT, RTS_CONTROL_ENABLE, SETRTS,
+            TWOSTOPBITS, WAIT_OBJECT_0,
         },
         winnt::{GENERIC_READ, GENERIC_WRITE},
     },
@@ -23,20 +30,31 @@ struct SendPtr<T>(pub *mut T);
 unsafe impl<T> Send for SendPtr<T> {}
 unsafe impl<T> Sync for SendPtr<T> {}
 
+/// A COM port.
 pub struct Serial {
     to_uart: Sender<u8>,
     h_comm: HANDLE,
 }
 
 impl Serial {
-    pub fn open(
-        options: &str,
-        from_uart: Receiver<u8>,
-        to_uart: Sender<u8>,
-    ) -> anyhow::Result<Self> {
+    pub fn open(options: &PortOptions, channels: DteChannels) -> anyhow::Result<Self> {
+        let DteChannels {
+            from_modem: from_uart,
+            to_modem: to_uart,
+            dtr,
+            cts,
+            flow_control,
+        } = channels;
+        let Device::Path(device) = &options.device else {
+            anyhow::bail!("a COM port is required");
+        };
+        anyhow::ensure!(
+            options.mode.is_none() && options.link.is_none(),
+            "mode and link only apply to a pty"
+        );
         unsafe {
             let h_comm = CreateFileW(
-                options
+                device
                     .encode_utf16()
                     .chain([0])
                     .collect::<Vec<_>>()
@@ -61,21 +79,31 @@ impl Serial {
                 GetLastError()
             );
 
-            dcb.BaudRate = 115_200;
-            dcb.ByteSize = 8;
-            dcb.StopBits = ONESTOPBIT;
-            dcb.Parity = NOPARITY;
+            dcb.BaudRate = options.speed;
+            dcb.ByteSize = options.data_bits;
+            dcb.StopBits = if options.stop_bits == 2 {
+                TWOSTOPBITS
+            } else {
+                ONESTOPBIT
+            };
+            dcb.Parity = match options.parity {
+                Parity::None => NOPARITY,
+                Parity::Even => EVENPARITY,
+                Parity::Odd => ODDPARITY,
+            };
+            dcb.set_fParity((options.parity != Parity::None) as u32);
             dcb.set_fBinary(1);
             dcb.set_fDtrControl(DTR_CONTROL_ENABLE);
             dcb.set_fRtsControl(RTS_CONTROL_ENABLE);
             dcb.set_fDsrSensitivity(0);
             dcb.set_fTXContinueOnXoff(0);
-            dcb.set_fOutX(0);
+            // the host may stop us too, while stopping it is left to the
+            // thread below and to the XON/XOFF characters sent by the modem
+            set_flow(&mut dcb, options.flow_control);
             dcb.set_fInX(0);
             dcb.set_fErrorChar(0);
             dcb.set_fNull(0);
             dcb.set_fAbortOnError(0);
-            dcb.set_fOutxCtsFlow(0);
             dcb.set_fOutxDsrFlow(0);
 
             assert!(
@@ -154,11 +182,53 @@ impl Serial {
                 CloseHandle(os_write.hEvent);
             });
 
+            // the DTR of the terminal reaches us as DSR through the null
+            // modem cable, and our RTS reaches it as CTS
+            let h_comm_status = SendPtr(h_comm);
+            std::thread::spawn(move || {
+                let _ = &h_comm_status;
+                let mut dsr = false;
+                let mut rts = true;
+                loop {
+                    while let Ok(flow_control) = flow_control.try_recv() {
+                        let mut dcb: DCB = zeroed();
+                        if GetCommState(h_comm_status.0, &mut dcb) == 0 {
+                            eprintln!("serial: error on GetCommState: {}", GetLastError());
+                            continue;
+                        }
+                        set_flow(&mut dcb, flow_control);
+                        if SetCommState(h_comm_status.0, &mut dcb) == 0 {
+                            eprintln!("serial: error on SetCommState: {}", GetLastError());
+                        }
+                    }
+                    if cts.load(Ordering::Relaxed) != rts {
+                        rts = !rts;
+                        EscapeCommFunction(h_comm_status.0, if rts { SETRTS } else { CLRRTS });
+                    }
+                    let mut status: DWORD = 0;
+                    if GetCommModemStatus(h_comm_status.0, &mut status) != 0
+                        && (status & MS_DSR_ON != 0) != dsr
+                    {
+                        dsr = !dsr;
+                        dtr.send(dsr).unwrap();
+                    }
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+            });
+
             Ok(Self { to_uart, h_comm })
         }
     }
+}
+
+/// Lets the host stop us with the method chosen by `AT&K`.
+fn set_flow(dcb: &mut DCB, flow_control: FlowControl) {
+    dcb.set_fOutX((flow_control == FlowControl::XonXoff) as u32);
+    dcb.set_fOutxCtsFlow((flow_control == FlowControl::RtsCts) as u32);
+}
 
-    pub fn event_loop(&mut self) -> anyhow::Result<()> {
+impl DteBackend for Serial {
+    fn event_loop(&mut self) -> anyhow::Result<()> {
         unsafe {
             let mut dw_read: DWORD = 0;
             let mut os_reader: OVERLAPPED = zeroed();
@@ -0,0 +1,95 @@
+/// Special characters of RFC 1055.
+pub const END: u8 = 0xc0;
+pub const ESC: u8 = 0xdb;
+pub const ESC_END: u8 = 0xdc;
+pub const ESC_ESC: u8 = 0xdd;
+
+/// Appends `packet` to `out`, with an END before it to flush any line noise
+/// received by the peer since the last packet.
+pub fn encode(packet: &[u8], out: &mut Vec<u8>) {
+    out.push(END);
+    for b in packet {
+        match *b {
+            END => out.extend([ESC, ESC_END]),
+            ESC => out.extend([ESC, ESC_ESC]),
+            b => out.push(b),
+        }
+    }
+    out.push(END);
+}
+
+pub struct SlipDecoder {
+    max_len: usize,
+    packet: Vec<u8>,
+    escaped: bool,
+    /// Whether the packet is being skipped up to the next END
+    discarding: bool,
+}
+
+impl SlipDecoder {
+    /// Packets longer than `max_len` are discarded.
+    pub fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            packet: vec![],
+            escaped: false,
+            discarding: false,
+        }
+    }
+
+    /// Returns a packet once its END is received, or an error for a packet
+    /// which is too long or badly escaped.
+    pub fn put_byte(&mut self, byte: u8) -> Option<anyhow::Result<Vec<u8>>> {
+        if byte == END {
+            let packet = std::mem::take(&mut self.packet);
+            let discarding = std::mem::replace(&mut self.discarding, false);
+            self.escaped = false;
+            return match discarding {
+                true => None,
+                // back-to-back ENDs
+                false if packet.is_empty() => None,
+                false => Some(Ok(packet)),
+            };
+        }
+        if self.discarding {
+            return None;
+        }
+
+        let byte = match (std::mem::replace(&mut self.escaped, false), byte) {
+            (false, ESC) => {
+                self.escaped = true;
+                return None;
+            }
+            (false, byte) => byte,
+            (true, ESC_END) => END,
+            (true, ESC_ESC) => ESC,
+            (true, byte) => return self.discard(format!("invalid escape {:#04x}", byte)),
+        };
+        if self.packet.len() == self.max_len {
+            return self.discard(format!("packet longer than {} bytes", self.max_len));
+        }
+        self.packet.push(byte);
+        None
+    }
+
+    fn discard(&mut self, reason: String) -> Option<anyhow::Result<Vec<u8>>> {
+        self.packet.clear();
+        self.discarding = true;
+        Some(Err(anyhow::anyhow!(reason)))
+    }
+}
+
+/// Counters of one direction of a packet link.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PacketStats {
+    pub packets: u64,
+    pub bytes: u64,
+    pub errors: u64,
+}
+
+impl PacketStats {
+    pub fn count(&mut self, packet: &[u8]) {
+        self.packets += 1;
+        self.bytes += packet.len() as u64;
+    }
+}
@@ -0,0 +1,527 @@
+use crate::ip::Network;
+use crate::port::PortOptions;
+use anyhow::Context;
+use crossbeam_channel::{bounded, select, unbounded, Receiver, Sender, TryRecvError};
+use smoltcp::iface::{Config, Interface, SocketHandle, SocketSet};
+use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+use smoltcp::socket::tcp;
+use smoltcp::time::Instant;
+use smoltcp::wire::{HardwareAddress, IpCidr};
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
+use std::time::Duration;
+
+/// Buffer of each direction of a connection. A few segments are plenty, as
+/// more would only wait for minutes in the modem at 300 bps.
+const BUFFER_SIZE: usize = 1024;
+/// Connections whose data goes unacknowledged for this long are dropped.
+const TIMEOUT: Duration = Duration::from_secs(120);
+/// How often the clients are looked at when nothing else happens.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// Longest wait for a SOCKS client to say where it wants to go.
+const SOCKS_TIMEOUT: Duration = Duration::from_secs(30);
+/// Local ports of our connections start here.
+const FIRST_PORT: u16 = 49152;
+
+// SOCKS5 of RFC 1928
+const SOCKS_VERSION: u8 = 5;
+const NO_AUTHENTICATION: u8 = 0;
+const NO_ACCEPTABLE_METHODS: u8 = 0xff;
+const CONNECT: u8 = 1;
+const ATYP_IPV4: u8 = 1;
+const ATYP_DOMAIN: u8 = 3;
+const ATYP_IPV6: u8 = 4;
+const SUCCEEDED: u8 = 0;
+const NETWORK_UNREACHABLE: u8 = 3;
+const HOST_UNREACHABLE: u8 = 4;
+const COMMAND_NOT_SUPPORTED: u8 = 7;
+const ADDRESS_NOT_SUPPORTED: u8 = 8;
+
+enum Input {
+    Up {
+        mtu: usize,
+        address: Ipv4Addr,
+        peer: Option<Ipv4Addr>,
+    },
+    Packet(Vec<u8>),
+}
+
+/// A client of a local port, to be connected across the link.
+struct Request {
+    stream: TcpStream,
+    target: SocketAddrV4,
+    /// Whether it came through SOCKS and awaits a reply
+    socks: bool,
+}
+
+/// A TCP/IP stack of our own, for hosts where a TUN interface is out of
+/// reach: applications connect to local SOCKS5 or forwarded ports, and the
+/// stack makes their connections across the link.
+pub struct Stack {
+    to_stack: Sender<Input>,
+    listeners: Vec<SocketAddr>,
+}
+
+impl Stack {
+    /// Binds the local ports in `options` and starts the stack, returning
+    /// it along with the packets it sends over the modem.
+    pub fn open(options: &PortOptions) -> anyhow::Result<(Self, Receiver<Vec<u8>>)> {
+        let (to_engine, requests) = unbounded();
+        let mut listeners = vec![];
+        if let Some(addr) = options.socks {
+            let listener = bind(addr)?;
+            listeners.push(listener.local_addr()?);
+            eprintln!("stack: SOCKS5 on {}", listener.local_addr()?);
+            let to_engine = to_engine.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let to_engine = to_engine.clone();
+                    std::thread::spawn(move || {
+                        if let Err(err) = socks_request(stream, &to_engine) {
+                            eprintln!("stack: SOCKS client: {}", err);
+                        }
+                    });
+                }
+            });
+        }
+        for &(addr, target) in &options.forwards {
+            let listener = bind(addr)?;
+            listeners.push(listener.local_addr()?);
+            eprintln!("stack: {} forwarded to {}", listener.local_addr()?, target);
+            let to_engine = to_engine.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let request = Request {
+                        stream,
+                        target,
+                        socks: false,
+                    };
+                    if to_engine.send(request).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        let (to_stack, inputs) = unbounded();
+        let (to_modem, packets) = unbounded();
+        std::thread::spawn(move || Engine::new(to_modem).run(inputs, requests));
+        Ok((
+            Self {
+                to_stack,
+                listeners,
+            },
+            packets,
+        ))
+    }
+
+    /// Addresses of the local ports, the SOCKS5 one first, for when they
+    /// were given as port 0.
+    pub fn listeners(&self) -> &[SocketAddr] {
+        &self.listeners
+    }
+}
+
+impl Network for Stack {
+    fn name(&self) -> String {
+        "the userspace TCP/IP stack".to_string()
+    }
+
+    fn up(
+        &mut self,
+        mtu: usize,
+        address: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        // without an address, e.g. until IPCP gives one, the stack stays down
+        if let Some(address) = address {
+            self.to_stack.send(Input::Up { mtu, address, peer })?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, packet: &[u8]) -> anyhow::Result<()> {
+        self.to_stack.send(Input::Packet(packet.to_vec()))?;
+        Ok(())
+    }
+}
+
+fn bind(addr: SocketAddr) -> anyhow::Result<TcpListener> {
+    TcpListener::bind(addr).with_context(|| format!("failed to listen on {}", addr))
+}
+
+/// Negotiates with a SOCKS5 client, which may only ask to connect to an
+/// IPv4 address, and hands it to the engine.
+fn socks_request(mut stream: TcpStream, to_engine: &Sender<Request>) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(SOCKS_TIMEOUT))?;
+    let mut greeting = [0; 2];
+    stream.read_exact(&mut greeting)?;
+    anyhow::ensure!(greeting[0] == SOCKS_VERSION, "not SOCKS5");
+    let mut methods = vec![0; greeting[1] as usize];
+    stream.read_exact(&mut methods)?;
+    if !methods.contains(&NO_AUTHENTICATION) {
+        stream.write_all(&[SOCKS_VERSION, NO_ACCEPTABLE_METHODS])?;
+        anyhow::bail!("no acceptable authentication method");
+    }
+    stream.write_all(&[SOCKS_VERSION, NO_AUTHENTICATION])?;
+
+    let mut request = [0; 4];
+    stream.read_exact(&mut request)?;
+    anyhow::ensure!(request[0] == SOCKS_VERSION, "not SOCKS5");
+    let host = match request[3] {
+        ATYP_IPV4 => {
+            let mut address = [0; 4];
+            stream.read_exact(&mut address)?;
+            Some(Ipv4Addr::from(address))
+        }
+        ATYP_DOMAIN => {
+            let mut len = [0; 1];
+            stream.read_exact(&mut len)?;
+            let mut name = vec![0; len[0] as usize];
+            stream.read_exact(&mut name)?;
+            // there is no DNS across the link
+            String::from_utf8_lossy(&name).parse().ok()
+        }
+        ATYP_IPV6 => {
+            stream.read_exact(&mut [0; 16])?;
+            None
+        }
+        _ => {
+            socks_reply(&mut stream, ADDRESS_NOT_SUPPORTED)?;
+            anyhow::bail!("unknown address type {}", request[3]);
+        }
+    };
+    let mut port = [0; 2];
+    stream.read_exact(&mut port)?;
+    let port = u16::from_be_bytes(port);
+
+    if request[1] != CONNECT {
+        socks_reply(&mut stream, COMMAND_NOT_SUPPORTED)?;
+        anyhow::bail!("unsupported command {}", request[1]);
+    }
+    let Some(host) = host else {
+        socks_reply(&mut stream, ADDRESS_NOT_SUPPORTED)?;
+        anyhow::bail!("only IPv4 addresses can be reached");
+    };
+    stream.set_read_timeout(None)?;
+    to_engine.send(Request {
+        stream,
+        target: SocketAddrV4::new(host, port),
+        socks: true,
+    })?;
+    Ok(())
+}
+
+fn socks_reply(stream: &mut impl Write, reply: u8) -> std::io::Result<()> {
+    stream.write_all(&[SOCKS_VERSION, reply, 0, ATYP_IPV4, 0, 0, 0, 0, 0, 0])
+}
+
+/// The modem side of the stack, which takes and gives bare IP packets.
+struct Link {
+    received: VecDeque<Vec<u8>>,
+    to_modem: Sender<Vec<u8>>,
+    mtu: usize,
+}
+
+struct Received(Vec<u8>);
+
+struct Transmit<'a>(&'a Sender<Vec<u8>>);
+
+impl phy::RxToken for Received {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        f(&self.0)
+    }
+}
+
+impl phy::TxToken for Transmit<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut packet = vec![0; len];
+        let result = f(&mut packet);
+        // the backend may be gone, with the stack soon to follow
+        let _ = self.0.send(packet);
+        result
+    }
+}
+
+impl Device for Link {
+    type RxToken<'a> = Received;
+    type TxToken<'a> = Transmit<'a>;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Received, Transmit<'_>)> {
+        let packet = self.received.pop_front()?;
+        Some((Received(packet), Transmit(&self.to_modem)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Transmit<'_>> {
+        Some(Transmit(&self.to_modem))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.medium = Medium::Ip;
+        capabilities.max_transmission_unit = self.mtu;
+        capabilities
+    }
+}
+
+/// A client connected across the link. Its stream is read and written by
+/// threads of its own, so that a slow client holds up no one else.
+struct Connection {
+    handle: SocketHandle,
+    target: SocketAddrV4,
+    stream: TcpStream,
+    socks: bool,
+    /// Whether the connection across the link was made
+    established: bool,
+    from_client: Receiver<Vec<u8>>,
+    /// Dropped once the peer has nothing more to send
+    to_client: Option<Sender<Vec<u8>>>,
+    /// Data from the client which the socket has not taken yet
+    pending: Vec<u8>,
+    /// Whether the client has nothing more to send
+    client_done: bool,
+}
+
+impl Connection {
+    fn new(request: Request, handle: SocketHandle) -> anyhow::Result<Self> {
+        let mut reader = request.stream.try_clone()?;
+        let mut writer = request.stream.try_clone()?;
+        // one chunk at a time, so that the client waits for the link
+        let (to_engine, from_client) = bounded(1);
+        std::thread::spawn(move || {
+            let mut buf = [0; BUFFER_SIZE];
+            while let Ok(len @ 1..) = reader.read(&mut buf) {
+                if to_engine.send(buf[..len].to_vec()).is_err() {
+                    break;
+                }
+            }
+        });
+        let (to_client, for_client) = unbounded::<Vec<u8>>();
+        std::thread::spawn(move || {
+            for data in for_client {
+                if writer.write_all(&data).is_err() {
+                    break;
+                }
+            }
+            let _ = writer.shutdown(Shutdown::Write);
+        });
+        Ok(Self {
+            handle,
+            target: request.target,
+            stream: request.stream,
+            socks: request.socks,
+            established: false,
+            from_client,
+            to_client: Some(to_client),
+            pending: vec![],
+            client_done: false,
+        })
+    }
+
+    fn send_to_client(&self, data: Vec<u8>) {
+        if let Some(to_client) = &self.to_client {
+            let _ = to_client.send(data);
+        }
+    }
+
+    /// Moves data between the client and the socket, returning whether the
+    /// connection is still there.
+    fn service(&mut self, socket: &mut tcp::Socket) -> bool {
+        if !self.established {
+            if socket.may_send() {
+                self.established = true;
+                eprintln!("stack: connected to {}", self.target);
+                if self.socks {
+                    let mut reply = vec![];
+                    socks_reply(&mut reply, SUCCEEDED).unwrap();
+                    self.send_to_client(reply);
+                }
+            } else if socket.is_active() {
+                return true;
+            } else {
+                eprintln!("stack: failed to connect to {}", self.target);
+                if self.socks {
+                    let _ = socks_reply(&mut self.stream, HOST_UNREACHABLE);
+                }
+                let _ = self.stream.shutdown(Shutdown::Both);
+                return false;
+            }
+        }
+
+        while !self.client_done && socket.can_send() {
+            if self.pending.is_empty() {
+                match self.from_client.try_recv() {
+                    Ok(data) => self.pending = data,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        self.client_done = true;
+                        socket.close();
+                        break;
+                    }
+                }
+            }
+            let len = socket.send_slice(&self.pending).unwrap_or_default();
+            self.pending.drain(..len);
+        }
+
+        while socket.can_recv() {
+            let data = socket.recv(|buf| (buf.len(), buf.to_vec())).unwrap();
+            self.send_to_client(data);
+        }
+        if !socket.may_recv() {
+            self.to_client = None;
+        }
+
+        match socket.state() {
+            tcp::State::Closed | tcp::State::TimeWait => {
+                if !self.client_done {
+                    // reset or timed out, so the client is cut off too
+                    eprintln!("stack: connection to {} lost", self.target);
+                    let _ = self.stream.shutdown(Shutdown::Both);
+                }
+                false
+            }
+            _ => true,
+        }
+    }
+}
+
+/// Runs the stack on a thread of its own.
+struct Engine {
+    link: Link,
+    /// Created once the address of the link is known
+    iface: Option<Interface>,
+    sockets: SocketSet<'static>,
+    connections: Vec<Connection>,
+    next_port: u16,
+}
+
+impl Engine {
+    fn new(to_modem: Sender<Vec<u8>>) -> Self {
+        Self {
+            link: Link {
+                received: VecDeque::new(),
+                to_modem,
+                mtu: 0,
+            },
+            iface: None,
+            sockets: SocketSet::new(vec![]),
+            connections: vec![],
+            next_port: FIRST_PORT,
+        }
+    }
+
+    fn run(mut self, inputs: Receiver<Input>, mut requests: Receiver<Request>) {
+        loop {
+            let delay = self
+                .iface
+                .as_mut()
+                .and_then(|iface| iface.poll_delay(Instant::now(), &self.sockets))
+                .map_or(POLL_INTERVAL, |delay| POLL_INTERVAL.min(delay.into()));
+            select! {
+                recv(inputs) -> input => match input {
+                    Ok(input) => self.input(input),
+                    // the backend is gone
+                    Err(_) => return,
+                },
+                recv(requests) -> request => match request {
+                    Ok(request) => self.connect(request),
+                    Err(_) => requests = crossbeam_channel::never(),
+                },
+                default(delay) => {}
+            }
+            self.poll();
+        }
+    }
+
+    fn input(&mut self, input: Input) {
+        match input {
+            Input::Up { mtu, address, peer } => {
+                eprintln!(
+                    "stack: up, {} > {}, MTU {}",
+                    address,
+                    peer.unwrap_or(address),
+                    mtu
+                );
+                self.link.mtu = mtu;
+                let config = Config::new(HardwareAddress::Ip);
+                let mut iface = Interface::new(config, &mut self.link, Instant::now());
+                iface.update_ip_addrs(|addrs| {
+                    addrs.push(IpCidr::new(address.into(), 32)).unwrap();
+                });
+                // on a point-to-point link, everything goes to the peer
+                iface
+                    .routes_mut()
+                    .add_default_ipv4_route(peer.unwrap_or(address))
+                    .unwrap();
+                self.iface = Some(iface);
+            }
+            Input::Packet(packet) => {
+                if self.iface.is_some() {
+                    self.link.received.push_back(packet);
+                }
+            }
+        }
+    }
+
+    fn connect(&mut self, mut request: Request) {
+        let Some(iface) = &mut self.iface else {
+            eprintln!("stack: link down, not connecting to {}", request.target);
+            if request.socks {
+                let _ = socks_reply(&mut request.stream, NETWORK_UNREACHABLE);
+            }
+            return;
+        };
+        let mut socket = tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0; BUFFER_SIZE]),
+            tcp::SocketBuffer::new(vec![0; BUFFER_SIZE]),
+        );
+        socket.set_timeout(Some(TIMEOUT.into()));
+        let port = self.next_port;
+        self.next_port = self.next_port.checked_add(1).unwrap_or(FIRST_PORT);
+        if let Err(err) = socket.connect(iface.context(), request.target, port) {
+            eprintln!("stack: failed to connect to {}: {}", request.target, err);
+            if request.socks {
+                let _ = socks_reply(&mut request.stream, NETWORK_UNREACHABLE);
+            }
+            return;
+        }
+
+        let target = request.target;
+        let handle = self.sockets.add(socket);
+        match Connection::new(request, handle) {
+            Ok(connection) => {
+                eprintln!("stack: connecting to {}", target);
+                self.connections.push(connection);
+            }
+            Err(err) => {
+                eprintln!("stack: {}", err);
+                self.sockets.remove(handle);
+            }
+        }
+    }
+
+    fn poll(&mut self) {
+        let Some(iface) = &mut self.iface else {
+            return;
+        };
+        iface.poll(Instant::now(), &mut self.link, &mut self.sockets);
+        let sockets = &mut self.sockets;
+        self.connections.retain_mut(|connection| {
+            let alive = connection.service(sockets.get_mut(connection.handle));
+            if !alive {
+                sockets.remove(connection.handle);
+            }
+            alive
+        });
+        // what the clients just sent
+        iface.poll(Instant::now(), &mut self.link, &mut self.sockets);
+    }
+}
@@ -0,0 +1,163 @@
+use crate::fsk::{FskChannel, FskProfile};
+use crate::hdlc::{Frame, HdlcRx, HdlcTx};
+use crate::v21::{V21RX, V21TX};
+use crossbeam_channel::Sender;
+use std::time::Duration;
+
+/// Flags sent after the carrier is turned on and before the first frame.
+pub const PREAMBLE: Duration = Duration::from_secs(1);
+/// Address field of every T.30 frame.
+pub const ADDRESS: u8 = 0xff;
+/// Control field of a frame which is followed by others.
+pub const CONTROL: u8 = 0x03;
+/// Control field of the last frame of a response.
+pub const CONTROL_FINAL: u8 = 0x13;
+/// Length of the training check (TCF) sent after DCS with the image data
+/// modulation.
+pub const TCF_DURATION: Duration = Duration::from_millis(1500);
+
+/// Zeros sent as the TCF at `bit_rate`.
+pub fn tcf(bit_rate: usize) -> Vec<u8> {
+    vec![0; (TCF_DURATION.as_secs_f32() * bit_rate as f32 / 8.) as usize]
+}
+
+/// Checks the TCF received at `bit_rate`, which is good enough to use that
+/// rate if it holds at least one second of consecutive zeros.
+pub fn tcf_ok(data: &[u8], bit_rate: usize) -> bool {
+    let mut run = 0;
+    let mut longest = 0;
+    for byte in data {
+        run = if *byte == 0 { run + 1 } else { 0 };
+        longest = usize::max(longest, run);
+    }
+    longest >= bit_rate / 8
+}
+
+/// Sends HDLC frames on V.21 channel 2, bypassing the UART. The carrier is
+/// only on while there are frames to send, unless told otherwise.
+pub struct V21HdlcTx {
+    hdlc_tx: HdlcTx,
+    v21_tx: V21TX,
+    baud_rate: f32,
+    preamble_flags: usize,
+    carrier_on_demand: bool,
+}
+
+impl V21HdlcTx {
+    pub fn new(srate: usize) -> anyhow::Result<Self> {
+        Self::with_channel(srate, &FskProfile::t30().originate)
+    }
+
+    /// Sends on another channel than V.21 channel 2, e.g. for a link layer
+    /// on top of a full-duplex profile.
+    pub fn with_channel(srate: usize, channel: &FskChannel) -> anyhow::Result<Self> {
+        let samples_per_symbol = channel.samples_per_symbol(srate)?;
+        let mut v21_hdlc_tx = Self {
+            hdlc_tx: HdlcTx::new(samples_per_symbol, false),
+            v21_tx: V21TX::new(
+                1. / srate as f32,
+                channel.omega_mark(),
+                channel.omega_space(),
+            ),
+            baud_rate: channel.baud_rate,
+            preamble_flags: 0,
+            carrier_on_demand: true,
+        };
+        v21_hdlc_tx.set_preamble(PREAMBLE);
+        Ok(v21_hdlc_tx)
+    }
+
+    /// Retunes the modulator, keeping the symbol rate.
+    pub fn set_channel(&mut self, channel: &FskChannel) {
+        self.v21_tx
+            .set_omegas(channel.omega_mark(), channel.omega_space());
+    }
+
+    pub fn set_preamble(&mut self, preamble: Duration) {
+        self.preamble_flags = (preamble.as_secs_f32() * self.baud_rate / 8.).ceil() as usize;
+    }
+
+    /// Keeps sending marks between frames when cleared, in which case no
+    /// preamble is needed.
+    pub fn set_carrier_on_demand(&mut self, carrier_on_demand: bool) {
+        self.carrier_on_demand = carrier_on_demand;
+    }
+
+    /// Queues a frame, without FCS, preceded by the preamble if the carrier
+    /// is currently off.
+    pub fn send_frame(&mut self, frame: &[u8]) {
+        let flags = if self.carrier_on_demand && self.is_idle() {
+            self.preamble_flags.max(1)
+        } else {
+            1
+        };
+        self.hdlc_tx.put_flags(flags);
+        self.hdlc_tx.put_frame(frame);
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.hdlc_tx.pending_samples() == 0
+    }
+
+    pub fn pending_samples(&self) -> usize {
+        self.hdlc_tx.pending_samples()
+    }
+
+    pub fn get_samples(&mut self, out: &mut [f32]) {
+        let pending = self.hdlc_tx.pending_samples();
+        let mut levels = vec![1; out.len()];
+        self.hdlc_tx.get_samples(&mut levels);
+        self.v21_tx.modulate(&levels, out);
+        if self.carrier_on_demand {
+            out.iter_mut().skip(pending).for_each(|x| *x = 0.);
+        }
+    }
+}
+
+/// Receives HDLC frames on V.21 channel 2, sending each of them to
+/// `to_host`, or an [`FcsError`](crate::hdlc::FcsError) if it was corrupted.
+pub struct V21HdlcRx {
+    v21_rx: V21RX,
+    hdlc_rx: HdlcRx,
+}
+
+impl V21HdlcRx {
+    pub fn new(srate: usize, to_host: Sender<Frame>) -> anyhow::Result<Self> {
+        Self::with_channel(srate, &FskProfile::t30().originate, to_host)
+    }
+
+    pub fn with_channel(
+        srate: usize,
+        channel: &FskChannel,
+        to_host: Sender<Frame>,
+    ) -> anyhow::Result<Self> {
+        let samples_per_symbol = channel.samples_per_symbol(srate)?;
+        Ok(Self {
+            v21_rx: V21RX::new(
+                1. / srate as f32,
+                samples_per_symbol,
+                channel.omega_mark(),
+                channel.omega_space(),
+            ),
+            hdlc_rx: HdlcRx::new(samples_per_symbol, false, to_host),
+        })
+    }
+
+    /// Retunes the demodulator, keeping the symbol rate.
+    pub fn set_channel(&mut self, channel: &FskChannel) {
+        self.v21_rx
+            .set_omegas(channel.omega_mark(), channel.omega_space());
+    }
+
+    pub fn put_samples(&mut self, in_samples: &[f32]) {
+        let mut levels = vec![1; in_samples.len()];
+        self.v21_rx.demodulate(in_samples, &mut levels);
+        self.hdlc_rx.put_samples(&levels);
+    }
+
+    /// Feeds marks in place of `len` samples, while the line only carries
+    /// what this end sends.
+    pub fn put_muted(&mut self, len: usize) {
+        self.hdlc_rx.put_samples(&vec![1; len]);
+    }
+}
@@ -0,0 +1,171 @@
+use crate::backend::{DteBackend, DteChannels};
+use crate::port::{Device, PortOptions};
+use crate::telnet::{self, Event, TelnetServer};
+use crossbeam_channel::Sender;
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Delay between attempts to reach the server in client mode.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// How often the reader looks at CTS while no data arrives.
+const READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// The DTE side of the modem over a TCP connection, which stands for DTR:
+/// connecting raises it and disconnecting drops it.
+pub struct Tcp {
+    options: PortOptions,
+    listener: Option<TcpListener>,
+    to_uart: Sender<u8>,
+    dtr: Sender<bool>,
+    cts: Arc<AtomicBool>,
+    /// Connection the writer thread sends to, if any
+    stream: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl Tcp {
+    pub fn open(options: &PortOptions, channels: DteChannels) -> anyhow::Result<Self> {
+        let DteChannels {
+            from_modem: from_uart,
+            to_modem: to_uart,
+            dtr,
+            cts,
+            ..
+        } = channels;
+        anyhow::ensure!(
+            options.mode.is_none() && options.link.is_none(),
+            "mode and link only apply to a pty"
+        );
+        let listener = match &options.device {
+            Device::TcpListen(addr) => {
+                let listener = TcpListener::bind(addr)?;
+                eprintln!("serial port: listening on {}", listener.local_addr()?);
+                Some(listener)
+            }
+            Device::TcpConnect(_) => None,
+            _ => anyhow::bail!("not a TCP address"),
+        };
+
+        let stream: Arc<Mutex<Option<TcpStream>>> = Arc::default();
+        {
+            let stream = stream.clone();
+            let telnet = options.telnet;
+            std::thread::spawn(move || loop {
+                let b = from_uart.recv().unwrap();
+                // what the modem says while nobody is connected is lost, as
+                // with a serial cable left unplugged
+                if let Some(stream) = stream.lock().unwrap().as_mut() {
+                    let mut out = vec![];
+                    if telnet {
+                        telnet::escape(&[b], &mut out);
+                    } else {
+                        out.push(b);
+                    }
+                    // the reader notices when the connection is gone
+                    let _ = stream.write_all(&out);
+                }
+            });
+        }
+
+        Ok(Self {
+            options: options.clone(),
+            listener,
+            to_uart,
+            dtr,
+            cts,
+            stream,
+        })
+    }
+
+    /// Forwards what the peer sends until it disconnects.
+    fn serve(&mut self, mut stream: TcpStream) -> anyhow::Result<()> {
+        stream.set_nodelay(true)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut telnet = self
+            .options
+            .telnet
+            .then(|| TelnetServer::new(&self.options));
+        if let Some(telnet) = &mut telnet {
+            stream.write_all(&telnet.greeting())?;
+        }
+        *self.stream.lock().unwrap() = Some(stream.try_clone()?);
+
+        let mut reader = BufReader::new(stream);
+        let mut cts = true;
+        loop {
+            if self.cts.load(Ordering::Relaxed) != cts {
+                cts = !cts;
+                // a raw connection is stopped by no longer reading from it
+                if telnet.is_some() {
+                    self.write(&telnet::flow_control(!cts))?;
+                }
+            }
+            if !cts {
+                std::thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+
+            let buf = match reader.fill_buf() {
+                Ok([]) => return Ok(()),
+                Ok(buf) => buf,
+                Err(err) if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    continue
+                }
+                Err(err) => return Err(err.into()),
+            };
+            // one byte at a time, so that CTS stops the host at once
+            let b = buf[0];
+            reader.consume(1);
+            let Some(telnet) = &mut telnet else {
+                self.to_uart.send(b).unwrap();
+                continue;
+            };
+            let mut reply = vec![];
+            match telnet.put_byte(b, &mut reply) {
+                Some(Event::Data(b)) => self.to_uart.send(b).unwrap(),
+                Some(Event::Dtr(dtr)) => self.dtr.send(dtr).unwrap(),
+                None => {}
+            }
+            if !reply.is_empty() {
+                self.write(&reply)?;
+            }
+        }
+    }
+
+    /// Writes to the peer without breaking up what the writer thread sends.
+    fn write(&self, data: &[u8]) -> anyhow::Result<()> {
+        if let Some(stream) = self.stream.lock().unwrap().as_mut() {
+            stream.write_all(data)?;
+        }
+        Ok(())
+    }
+}
+
+impl DteBackend for Tcp {
+    fn event_loop(&mut self) -> anyhow::Result<()> {
+        loop {
+            let stream = match (&self.listener, &self.options.device) {
+                (Some(listener), _) => listener.accept()?.0,
+                (None, Device::TcpConnect(addr)) => match TcpStream::connect(addr) {
+                    Ok(stream) => stream,
+                    Err(_) => {
+                        std::thread::sleep(RECONNECT_DELAY);
+                        continue;
+                    }
+                },
+                _ => unreachable!(),
+            };
+            let peer = stream.peer_addr()?;
+            eprintln!("serial port: {} connected", peer);
+            self.dtr.send(true).unwrap();
+            if let Err(err) = self.serve(stream) {
+                eprintln!("serial port: {}", err);
+            }
+            *self.stream.lock().unwrap() = None;
+            eprintln!("serial port: {} disconnected", peer);
+            self.dtr.send(false).unwrap();
+        }
+    }
+}
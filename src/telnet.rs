@@ -0,0 +1,252 @@
+use crate::flow::FlowControl;
+use crate::port::{Parity, PortOptions};
+
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+pub const SB: u8 = 250;
+pub const SE: u8 = 240;
+
+pub const BINARY: u8 = 0;
+pub const ECHO: u8 = 1;
+pub const SGA: u8 = 3;
+/// RFC 2217 COM port control
+pub const COM_PORT: u8 = 44;
+
+/// Options the server enables on its side, echo being left to the AT
+/// interpreter.
+const LOCAL_OPTIONS: [u8; 3] = [BINARY, ECHO, SGA];
+/// Options the server lets the client enable.
+const REMOTE_OPTIONS: [u8; 3] = [BINARY, SGA, COM_PORT];
+
+/// RFC 2217 commands sent by the client. The server answers them with the
+/// same code plus [`SERVER_OFFSET`].
+pub const SIGNATURE: u8 = 0;
+pub const SET_BAUDRATE: u8 = 1;
+pub const SET_DATASIZE: u8 = 2;
+pub const SET_PARITY: u8 = 3;
+pub const SET_STOPSIZE: u8 = 4;
+pub const SET_CONTROL: u8 = 5;
+pub const FLOWCONTROL_SUSPEND: u8 = 8;
+pub const FLOWCONTROL_RESUME: u8 = 9;
+pub const SET_LINESTATE_MASK: u8 = 10;
+pub const SET_MODEMSTATE_MASK: u8 = 11;
+pub const PURGE_DATA: u8 = 12;
+pub const SERVER_OFFSET: u8 = 100;
+
+/// What the client asked for, besides the negotiation answered right away.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Data(u8),
+    Dtr(bool),
+}
+
+enum State {
+    Data,
+    Iac,
+    /// After `DO`, `DONT`, `WILL` or `WONT`
+    Negotiation(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// Server side of a Telnet connection carrying the DTE data, with the serial
+/// port settings of RFC 2217.
+pub struct TelnetServer {
+    state: State,
+    subnegotiation: Vec<u8>,
+    /// Options enabled on each side, indexed by their codes
+    local: [bool; 256],
+    remote: [bool; 256],
+    speed: u32,
+    data_size: u8,
+    parity: u8,
+    stop_size: u8,
+    flow_control: u8,
+    dtr: bool,
+    rts: bool,
+}
+
+impl TelnetServer {
+    pub fn new(options: &PortOptions) -> Self {
+        Self {
+            state: State::Data,
+            subnegotiation: vec![],
+            local: [false; 256],
+            remote: [false; 256],
+            speed: options.speed,
+            data_size: options.data_bits,
+            parity: match options.parity {
+                Parity::None => 1,
+                Parity::Odd => 2,
+                Parity::Even => 3,
+            },
+            stop_size: options.stop_bits,
+            flow_control: match options.flow_control {
+                FlowControl::None => 1,
+                FlowControl::XonXoff => 2,
+                FlowControl::RtsCts => 3,
+            },
+            dtr: true,
+            rts: true,
+        }
+    }
+
+    /// Negotiation sent as soon as the client connects.
+    pub fn greeting(&mut self) -> Vec<u8> {
+        let mut out = vec![];
+        for option in LOCAL_OPTIONS {
+            self.local[option as usize] = true;
+            out.extend([IAC, WILL, option]);
+        }
+        for option in REMOTE_OPTIONS {
+            self.remote[option as usize] = true;
+            out.extend([IAC, DO, option]);
+        }
+        out
+    }
+
+    /// Appends the answers due to the client to `reply`.
+    pub fn put_byte(&mut self, byte: u8, reply: &mut Vec<u8>) -> Option<Event> {
+        match self.state {
+            State::Data if byte == IAC => self.state = State::Iac,
+            State::Data => return Some(Event::Data(byte)),
+            State::Iac => {
+                self.state = State::Data;
+                match byte {
+                    IAC => return Some(Event::Data(IAC)),
+                    DO | DONT | WILL | WONT => self.state = State::Negotiation(byte),
+                    SB => {
+                        self.subnegotiation.clear();
+                        self.state = State::Subnegotiation;
+                    }
+                    // NOP, break and the like
+                    _ => {}
+                }
+            }
+            State::Negotiation(verb) => {
+                self.state = State::Data;
+                self.negotiate(verb, byte, reply);
+            }
+            State::Subnegotiation if byte == IAC => self.state = State::SubnegotiationIac,
+            State::Subnegotiation => self.subnegotiation.push(byte),
+            State::SubnegotiationIac => match byte {
+                SE => {
+                    self.state = State::Data;
+                    let subnegotiation = std::mem::take(&mut self.subnegotiation);
+                    if let [COM_PORT, command, value @ ..] = subnegotiation.as_slice() {
+                        return self.com_port(*command, value, reply);
+                    }
+                }
+                _ => {
+                    self.subnegotiation.push(byte);
+                    self.state = State::Subnegotiation;
+                }
+            },
+        }
+        None
+    }
+
+    fn negotiate(&mut self, verb: u8, option: u8, reply: &mut Vec<u8>) {
+        let (enabled, supported) = match verb {
+            DO | DONT => (
+                &mut self.local[option as usize],
+                LOCAL_OPTIONS.contains(&option),
+            ),
+            _ => (
+                &mut self.remote[option as usize],
+                REMOTE_OPTIONS.contains(&option),
+            ),
+        };
+        // only changes are acknowledged, which keeps both ends from looping
+        let answer = match verb {
+            DO if supported && !*enabled => WILL,
+            DO if !supported => WONT,
+            WILL if supported && !*enabled => DO,
+            WILL if !supported => DONT,
+            DONT if *enabled => WONT,
+            WONT if *enabled => DONT,
+            _ => return,
+        };
+        *enabled = answer == WILL || answer == DO;
+        reply.extend([IAC, answer, option]);
+    }
+
+    fn com_port(&mut self, command: u8, value: &[u8], reply: &mut Vec<u8>) -> Option<Event> {
+        let byte = value.first().copied().unwrap_or(0);
+        let mut event = None;
+        let answer = match command {
+            SIGNATURE if value.is_empty() => b"modem".to_vec(),
+            SIGNATURE => return None,
+            SET_BAUDRATE => {
+                if let Ok(speed) = value.try_into().map(u32::from_be_bytes) {
+                    if speed != 0 {
+                        self.speed = speed;
+                    }
+                }
+                self.speed.to_be_bytes().to_vec()
+            }
+            SET_DATASIZE | SET_PARITY | SET_STOPSIZE => {
+                let setting = match command {
+                    SET_DATASIZE => &mut self.data_size,
+                    SET_PARITY => &mut self.parity,
+                    _ => &mut self.stop_size,
+                };
+                if byte != 0 {
+                    *setting = byte;
+                }
+                vec![*setting]
+            }
+            SET_CONTROL => vec![match byte {
+                0 => self.flow_control,
+                1..=3 => {
+                    self.flow_control = byte;
+                    byte
+                }
+                // no break is ever sent
+                4 => 6,
+                7 => 9 - self.dtr as u8,
+                8 | 9 => {
+                    self.dtr = byte == 8;
+                    event = Some(Event::Dtr(self.dtr));
+                    byte
+                }
+                10 => 12 - self.rts as u8,
+                11 | 12 => {
+                    self.rts = byte == 11;
+                    byte
+                }
+                _ => byte,
+            }],
+            SET_LINESTATE_MASK | SET_MODEMSTATE_MASK | PURGE_DATA => vec![byte],
+            // the client throttling us, and notifications, are not acted on
+            _ => return None,
+        };
+        reply.extend([IAC, SB, COM_PORT, command + SERVER_OFFSET]);
+        escape(&answer, reply);
+        reply.extend([IAC, SE]);
+        event
+    }
+}
+
+/// Doubles the IAC bytes in `data`.
+pub fn escape(data: &[u8], out: &mut Vec<u8>) {
+    for byte in data {
+        if *byte == IAC {
+            out.push(IAC);
+        }
+        out.push(*byte);
+    }
+}
+
+/// Asks the client to stop sending, or to go on.
+pub fn flow_control(suspend: bool) -> Vec<u8> {
+    let command = if suspend {
+        FLOWCONTROL_SUSPEND
+    } else {
+        FLOWCONTROL_RESUME
+    };
+    vec![IAC, SB, COM_PORT, command + SERVER_OFFSET, IAC, SE]
+}
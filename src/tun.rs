@@ -0,0 +1,168 @@
+use crate::ip::Network;
+use crossbeam_channel::{unbounded, Receiver};
+use nix::libc;
+use nix::poll::{poll, PollFd, PollFlags};
+use std::fs::OpenOptions;
+use std::net::Ipv4Addr;
+use std::os::fd::{AsFd, AsRawFd, OwnedFd};
+use std::time::Duration;
+
+/// Largest packet read from the interface.
+const MAX_PACKET: usize = 65535;
+
+/// A Linux TUN interface, carrying bare IP packets.
+pub struct Tun {
+    fd: OwnedFd,
+    name: String,
+}
+
+impl Tun {
+    /// Creates the interface, or attaches to a persistent one, with a name
+    /// chosen by the kernel when `name` is `None`.
+    pub fn open(name: Option<&str>) -> anyhow::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")?;
+        let fd = OwnedFd::from(file);
+        let mut req = ifreq(name.unwrap_or_default())?;
+        req.ifr_ifru.ifru_flags = (libc::IFF_TUN | libc::IFF_NO_PI) as libc::c_short;
+        let tunsetiff = nix::request_code_write!(b'T', 202, std::mem::size_of::<libc::c_int>());
+        nix::errno::Errno::result(unsafe { libc::ioctl(fd.as_raw_fd(), tunsetiff, &mut req) })?;
+        let name = unsafe { std::ffi::CStr::from_ptr(req.ifr_name.as_ptr()) };
+        Ok(Self {
+            fd,
+            name: name.to_string_lossy().into_owned(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets the MTU and the addresses of a point-to-point link, and brings
+    /// the interface up.
+    pub fn configure(
+        &self,
+        mtu: usize,
+        address: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        let socket = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+        let socket = unsafe {
+            <OwnedFd as std::os::fd::FromRawFd>::from_raw_fd(nix::errno::Errno::result(socket)?)
+        };
+        let set = |request, req: &mut libc::ifreq| {
+            nix::errno::Errno::result(unsafe { libc::ioctl(socket.as_raw_fd(), request, req) })
+        };
+
+        let mut req = ifreq(&self.name)?;
+        req.ifr_ifru.ifru_mtu = mtu as libc::c_int;
+        set(libc::SIOCSIFMTU, &mut req)?;
+        if let Some(address) = address {
+            req.ifr_ifru.ifru_addr = sockaddr(address);
+            set(libc::SIOCSIFADDR, &mut req)?;
+        }
+        if let Some(peer) = peer {
+            req.ifr_ifru.ifru_dstaddr = sockaddr(peer);
+            set(libc::SIOCSIFDSTADDR, &mut req)?;
+        }
+        set(libc::SIOCGIFFLAGS, &mut req)?;
+        unsafe {
+            req.ifr_ifru.ifru_flags |= (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_short;
+        }
+        set(libc::SIOCSIFFLAGS, &mut req)?;
+        Ok(())
+    }
+
+    pub fn try_clone(&self) -> anyhow::Result<Self> {
+        Ok(Self {
+            fd: self.fd.try_clone()?,
+            name: self.name.clone(),
+        })
+    }
+
+    /// Reads a packet, or returns `None` if none arrives within `timeout`.
+    pub fn recv(&self, timeout: Duration) -> anyhow::Result<Option<Vec<u8>>> {
+        let mut fds = [PollFd::new(self.fd.as_fd(), PollFlags::POLLIN)];
+        match poll(&mut fds, timeout.as_millis() as u16) {
+            Ok(0) | Err(nix::errno::Errno::EINTR) => return Ok(None),
+            Ok(_) => {}
+            Err(err) => return Err(err.into()),
+        }
+        let mut packet = vec![0; MAX_PACKET];
+        let len = nix::unistd::read(self.fd.as_raw_fd(), &mut packet)?;
+        packet.truncate(len);
+        Ok(Some(packet))
+    }
+
+    pub fn send(&self, packet: &[u8]) -> anyhow::Result<()> {
+        nix::unistd::write(&self.fd, packet)?;
+        Ok(())
+    }
+
+    /// Reads the packets of the interface on a thread of their own.
+    pub fn spawn_reader(&self) -> anyhow::Result<Receiver<Vec<u8>>> {
+        let (to_backend, from_tun) = unbounded();
+        let reader = self.try_clone()?;
+        std::thread::spawn(move || loop {
+            match reader.recv(Duration::from_millis(100)) {
+                Ok(Some(packet)) => {
+                    if to_backend.send(packet).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    eprintln!("tun: {}", err);
+                    break;
+                }
+            }
+        });
+        Ok(from_tun)
+    }
+}
+
+fn ifreq(name: &str) -> anyhow::Result<libc::ifreq> {
+    anyhow::ensure!(
+        name.len() < libc::IFNAMSIZ,
+        "interface name {} too long",
+        name
+    );
+    let mut req: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (c, b) in req.ifr_name.iter_mut().zip(name.bytes()) {
+        *c = b as libc::c_char;
+    }
+    Ok(req)
+}
+
+fn sockaddr(address: Ipv4Addr) -> libc::sockaddr {
+    let addr = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: libc::in_addr {
+            s_addr: u32::from(address).to_be(),
+        },
+        sin_zero: [0; 8],
+    };
+    unsafe { std::mem::transmute(addr) }
+}
+
+impl Network for Tun {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn up(
+        &mut self,
+        mtu: usize,
+        address: Option<Ipv4Addr>,
+        peer: Option<Ipv4Addr>,
+    ) -> anyhow::Result<()> {
+        self.configure(mtu, address, peer)
+    }
+
+    fn send(&mut self, packet: &[u8]) -> anyhow::Result<()> {
+        Tun::send(self, packet)
+    }
+}
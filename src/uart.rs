@@ -4,14 +4,28 @@ use std::collections::VecDeque;
 pub struct UartRx {
     // TODO: coloque outros atributos que você precisar aqui
     samples_per_symbol: usize,
+    data_bits: usize,
+    stop_bits: f32,
     to_pty: Sender<u8>,
 }
 
 impl UartRx {
     pub fn new(samples_per_symbol: usize, to_pty: Sender<u8>) -> Self {
+        Self::with_framing(samples_per_symbol, 8, 1., to_pty)
+    }
+
+    /// Same framing as [`UartTx::with_framing`].
+    pub fn with_framing(
+        samples_per_symbol: usize,
+        data_bits: usize,
+        stop_bits: f32,
+        to_pty: Sender<u8>,
+    ) -> Self {
         // TODO: inicialize seus novos atributos abaixo
         UartRx {
             samples_per_symbol,
+            data_bits,
+            stop_bits,
             to_pty,
         }
     }
@@ -24,13 +38,22 @@ impl UartRx {
 
 pub struct UartTx {
     samples_per_symbol: usize,
+    data_bits: usize,
+    stop_samples: usize,
     samples: VecDeque<u8>,
 }
 
 impl UartTx {
     pub fn new(samples_per_symbol: usize) -> Self {
+        Self::with_framing(samples_per_symbol, 8, 1.)
+    }
+
+    /// `stop_bits` may be fractional, e.g. 1.5 for Baudot teleprinters.
+    pub fn with_framing(samples_per_symbol: usize, data_bits: usize, stop_bits: f32) -> Self {
         Self {
             samples_per_symbol,
+            data_bits,
+            stop_samples: (stop_bits * samples_per_symbol as f32).round() as usize,
             samples: VecDeque::new(),
         }
     }
@@ -43,11 +66,31 @@ impl UartTx {
 
     pub fn put_byte(&mut self, mut byte: u8) {
         self.put_bit(0); // start bit
-        for _ in 0..8 {
+        for _ in 0..self.data_bits {
             self.put_bit(byte & 1);
             byte >>= 1;
         }
-        self.put_bit(1); // stop bit
+        for _ in 0..self.stop_samples {
+            self.samples.push_back(1); // stop bit
+        }
+    }
+
+    /// Queues idle (mark) samples, e.g. to let the far end receiver settle
+    /// before the first start bit.
+    pub fn put_idle(&mut self, samples: usize) {
+        for _ in 0..samples {
+            self.samples.push_back(1);
+        }
+    }
+
+    pub fn pending_samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// Characters still queued, counting one partly sent as a whole.
+    pub fn pending_bytes(&self) -> usize {
+        let samples_per_byte = (1 + self.data_bits) * self.samples_per_symbol + self.stop_samples;
+        self.samples.len().div_ceil(samples_per_byte)
     }
 
     pub fn get_samples(&mut self, buffer: &mut [u8]) {
@@ -0,0 +1,250 @@
+use crate::fsk::{FskProfile, Role};
+use fundsp::audionode::{AudioNode, Frame};
+use fundsp::filter::Resonator;
+use fundsp::prelude::U1;
+
+/// Number of analysis blocks per second, i.e. 20 ms blocks.
+const BLOCKS_PER_SECOND: usize = 50;
+/// Consecutive blocks carrying the same signal required for a decision.
+const DETECTION_BLOCKS: usize = 6;
+/// Fraction of the frequency estimates in a block that must match the tones.
+const MIN_PAIR_VOTES: f32 = 0.5;
+/// Maximum distance from a tone for a frequency estimate to vote for it.
+const MAX_TONE_ERROR: f32 = 60.;
+const MIN_LEVEL_DBFS: f32 = -45.;
+/// Transitions required before telling EDT (110 baud) from V.21 (300 baud)
+/// apart; a far end which keeps sending marks is assumed to be V.21.
+const MIN_TRANSITIONS: usize = 4;
+const MAX_UNDECIDED_BLOCKS: usize = 2 * BLOCKS_PER_SECOND;
+
+/// Far end signal recognized by the [`Automode`] prober, along with the role
+/// this modem should take in order to talk to it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Detected {
+    /// 45.45 baud Baudot TDD
+    Tdd,
+    /// European Deaf Telephone, 110 baud on V.21 channel 1 tones
+    Edt,
+    V21(Role),
+    Bell103(Role),
+}
+
+impl Detected {
+    pub fn profile(&self) -> FskProfile {
+        match self {
+            Detected::Tdd => FskProfile::tdd(),
+            Detected::Edt => FskProfile::edt(),
+            Detected::V21(_) => FskProfile::v21(),
+            Detected::Bell103(_) => FskProfile::bell103(),
+        }
+    }
+
+    pub fn role(&self) -> Role {
+        match self {
+            Detected::Tdd | Detected::Edt => Role::Originate,
+            Detected::V21(role) | Detected::Bell103(role) => *role,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Signal {
+    Tdd,
+    V21Channel1,
+    V21Channel2,
+    Bell103Originate,
+    Bell103Answer,
+}
+
+/// Mark and space tones of each recognized signal.
+const PAIRS: [(Signal, f32, f32); 5] = [
+    (Signal::Tdd, 1400., 1800.),
+    (Signal::V21Channel1, 980., 1180.),
+    (Signal::V21Channel2, 1650., 1850.),
+    (Signal::Bell103Originate, 1270., 1070.),
+    (Signal::Bell103Answer, 2225., 2025.),
+];
+
+/// Listens to the line and identifies which kind of text telephone is on the
+/// far end, following the V.18 automode approach of recognizing each
+/// modulation by its characteristic tones.
+///
+/// Detection is passive only: none of the V.18 probes are transmitted, so
+/// the far end has to speak first.
+///
+/// The instantaneous frequency is estimated from the interval between zero
+/// crossings, and each estimate votes for the nearest tone. Besides being
+/// cheap, this resolves tone plans which are only 50 Hz apart.
+pub struct Automode {
+    sampling_period: f32,
+    bandpass: Resonator<f32, f32, U1>,
+    block_size: usize,
+    block_pos: usize,
+    block_energy: f32,
+    votes: [[usize; 2]; PAIRS.len()],
+    estimates: usize,
+    candidate: Option<Signal>,
+    candidate_blocks: usize,
+    last_sample: f32,
+    since_crossing: f32,
+    last_half_period: f32,
+    // symbol rate estimation on the V.21 channel 1 tones, which are shared
+    // by V.21 and EDT
+    channel1_bit: bool,
+    run_length: usize,
+    shortest_run: usize,
+    transitions: usize,
+}
+
+impl Automode {
+    pub fn new(sampling_period: f32) -> Self {
+        let srate = (1. / sampling_period).round() as usize;
+        // passes all tones between 980 and 2225 Hz
+        let mut bandpass = Resonator::new(1600., 1400.);
+        bandpass.set_sample_rate(srate as f64);
+        Self {
+            sampling_period,
+            bandpass,
+            block_size: srate / BLOCKS_PER_SECOND,
+            block_pos: 0,
+            block_energy: 0.,
+            votes: [[0; 2]; PAIRS.len()],
+            estimates: 0,
+            candidate: None,
+            candidate_blocks: 0,
+            last_sample: 0.,
+            since_crossing: 0.,
+            last_half_period: 0.,
+            channel1_bit: true,
+            run_length: 0,
+            shortest_run: usize::MAX,
+            transitions: 0,
+        }
+    }
+
+    pub fn put_samples(&mut self, in_samples: &[f32]) -> Option<Detected> {
+        for &sample in in_samples {
+            let sample = *self.bandpass.tick(&Frame::from([sample])).first().unwrap();
+            self.estimate_frequency(sample);
+
+            self.block_energy += sample * sample;
+            self.block_pos += 1;
+
+            if self.block_pos == self.block_size {
+                if let Some(detected) = self.end_block() {
+                    return Some(detected);
+                }
+            }
+        }
+        None
+    }
+
+    fn end_block(&mut self) -> Option<Detected> {
+        let n = self.block_size as f32;
+        let energy = self.block_energy;
+        self.block_pos = 0;
+        self.block_energy = 0.;
+
+        let mut best = None;
+        let mut best_votes = 0;
+        for ((signal, _, _), votes) in PAIRS.iter().zip(self.votes.iter()) {
+            let votes = votes[0] + votes[1];
+            if votes > best_votes {
+                best_votes = votes;
+                best = Some(*signal);
+            }
+        }
+        let vote_fraction = best_votes as f32 / self.estimates.max(1) as f32;
+        self.votes = [[0; 2]; PAIRS.len()];
+        self.estimates = 0;
+
+        let level_dbfs = 10. * (2. * energy / n).max(f32::EPSILON).log10();
+        let signal =
+            best.filter(|_| vote_fraction >= MIN_PAIR_VOTES && level_dbfs >= MIN_LEVEL_DBFS);
+
+        if signal.is_some() && signal == self.candidate {
+            self.candidate_blocks += 1;
+        } else {
+            self.candidate = signal;
+            self.candidate_blocks = 1;
+            self.shortest_run = usize::MAX;
+            self.transitions = 0;
+        }
+
+        if self.candidate_blocks < DETECTION_BLOCKS {
+            return None;
+        }
+
+        match self.candidate? {
+            Signal::Tdd => Some(Detected::Tdd),
+            Signal::V21Channel2 => Some(Detected::V21(Role::Originate)),
+            Signal::Bell103Originate => Some(Detected::Bell103(Role::Answer)),
+            Signal::Bell103Answer => Some(Detected::Bell103(Role::Originate)),
+            Signal::V21Channel1 => {
+                if self.transitions >= MIN_TRANSITIONS {
+                    let baud_rate = 1. / (self.shortest_run as f32 * self.sampling_period);
+                    if baud_rate < 200. {
+                        Some(Detected::Edt)
+                    } else {
+                        Some(Detected::V21(Role::Answer))
+                    }
+                } else if self.candidate_blocks >= MAX_UNDECIDED_BLOCKS {
+                    Some(Detected::V21(Role::Answer))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn estimate_frequency(&mut self, sample: f32) {
+        self.since_crossing += 1.;
+        self.run_length += 1;
+
+        if (sample >= 0.) != (self.last_sample >= 0.) {
+            // interpolate the crossing instant between the last two samples
+            let frac = self.last_sample / (self.last_sample - sample);
+            let half_period = self.since_crossing - 1. + frac;
+            self.since_crossing = 1. - frac;
+
+            let period = (half_period + self.last_half_period) * self.sampling_period;
+            self.last_half_period = half_period;
+            self.vote(1. / period);
+        }
+        self.last_sample = sample;
+    }
+
+    fn vote(&mut self, freq: f32) {
+        self.estimates += 1;
+
+        let mut nearest = None;
+        let mut nearest_error = MAX_TONE_ERROR;
+        for (i, (_, mark, space)) in PAIRS.iter().enumerate() {
+            for (j, tone) in [mark, space].into_iter().enumerate() {
+                let error = (freq - tone).abs();
+                if error < nearest_error {
+                    nearest_error = error;
+                    nearest = Some((i, j));
+                }
+            }
+        }
+        let Some((i, j)) = nearest else {
+            return;
+        };
+        self.votes[i][j] += 1;
+
+        if PAIRS[i].0 == Signal::V21Channel1 {
+            let bit = j == 0;
+            if bit != self.channel1_bit {
+                self.channel1_bit = bit;
+                // ignore glitches shorter than half of a 300 baud symbol
+                let min_run = 1. / (600. * self.sampling_period);
+                if self.run_length as f32 >= min_run {
+                    self.shortest_run = self.shortest_run.min(self.run_length);
+                    self.transitions += 1;
+                }
+                self.run_length = 0;
+            }
+        }
+    }
+}
@@ -24,6 +24,17 @@ impl V21RX {
         }
     }
 
+    /// Switches to other tones, starting again from a clean filter state so
+    /// that nothing received with the old tones leaks into the output.
+    pub fn set_omegas(&mut self, omega_mark: f32, omega_space: f32) {
+        *self = Self::new(
+            self.sampling_period,
+            self.samples_per_symbol,
+            omega_mark,
+            omega_space,
+        );
+    }
+
     pub fn demodulate(&mut self, in_samples: &[f32], out_samples: &mut [u8]) {
         // TODO: seu código aqui
     }
@@ -46,6 +57,13 @@ impl V21TX {
         }
     }
 
+    /// Switches to other tones. The phase is kept, so the output stays
+    /// continuous across the change.
+    pub fn set_omegas(&mut self, omega_mark: f32, omega_space: f32) {
+        self.omega_mark = omega_mark;
+        self.omega_space = omega_space;
+    }
+
     pub fn modulate(&mut self, in_samples: &[u8], out_samples: &mut [f32]) {
         debug_assert!(in_samples.len() == out_samples.len());
 
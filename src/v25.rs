@@ -0,0 +1,58 @@
+use std::f32::consts::PI;
+use std::time::Duration;
+
+/// Silence kept after going off-hook, during which the network starts
+/// billing the call.
+pub const BILLING_DELAY: Duration = Duration::from_secs(2);
+pub const ANSWER_TONE: f32 = 2100.;
+pub const ANSWER_TONE_DURATION: Duration = Duration::from_millis(3300);
+/// Silence between the answer tone and the data carrier.
+pub const ANSWER_TONE_GAP: Duration = Duration::from_millis(75);
+
+const AUDIO_LEVEL: f32 = 0.5;
+
+/// Sequence sent by the answering modem before its carrier: silence, then
+/// the 2100 Hz answer tone which disables the echo suppressors of the
+/// network.
+pub struct AnswerSequence {
+    sampling_period: f32,
+    sample: usize,
+    tone_start: usize,
+    tone_end: usize,
+    end: usize,
+}
+
+impl AnswerSequence {
+    pub fn new(sampling_period: f32) -> Self {
+        let samples = |duration: Duration| (duration.as_secs_f32() / sampling_period) as usize;
+        let tone_start = samples(BILLING_DELAY);
+        let tone_end = tone_start + samples(ANSWER_TONE_DURATION);
+        Self {
+            sampling_period,
+            sample: 0,
+            tone_start,
+            tone_end,
+            end: tone_end + samples(ANSWER_TONE_GAP),
+        }
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.sample >= self.end
+    }
+
+    /// Fills `out_samples` until the end of the sequence, and returns how
+    /// many samples were written.
+    pub fn modulate(&mut self, out_samples: &mut [f32]) -> usize {
+        let len = out_samples.len().min(self.end - self.sample);
+        for out in out_samples[..len].iter_mut() {
+            *out = if (self.tone_start..self.tone_end).contains(&self.sample) {
+                let t = (self.sample - self.tone_start) as f32 * self.sampling_period;
+                AUDIO_LEVEL * (2. * PI * ANSWER_TONE * t).sin()
+            } else {
+                0.
+            };
+            self.sample += 1;
+        }
+        len
+    }
+}
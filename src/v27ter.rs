@@ -0,0 +1,54 @@
+use crate::pump::{Config, Constellation, Scrambler, Training};
+use num_complex::Complex32;
+
+pub const CARRIER: f32 = 1800.;
+
+/// Initial state of the scrambler, which must not be all ones since it only
+/// scrambles ones during the training.
+const SCRAMBLER_SEED: u32 = 0b0101010;
+const ALTERNATION_LEN: usize = 50;
+const EQUALIZER_LEN: usize = 1074;
+const SCRAMBLED_ONES: usize = 8;
+
+/// V.27ter at 2400 bit/s (1200 baud, four phases) or 4800 bit/s (1600 baud,
+/// eight phases).
+pub(crate) fn config(bit_rate: usize) -> Config {
+    let (baud_rate, phase_bits, rolloff) = match bit_rate {
+        2400 => (1200., 2, 0.9),
+        _ => (1600., 3, 0.5),
+    };
+    let constellation = Constellation {
+        phase_bits,
+        amplitude_bit: false,
+        amplitudes: [[1.; 2]; 2],
+    };
+
+    // the alternation ends on a 180° symbol, from which the equalizer
+    // training is differentially encoded
+    let mut phase = 4;
+    let mut scrambler = Scrambler::new(6, 7, SCRAMBLER_SEED);
+    let ones = vec![1; phase_bits];
+    let equalizer = (0..EQUALIZER_LEN)
+        .map(|_| {
+            let bits: Vec<u8> = ones.iter().map(|b| scrambler.scramble(*b)).collect();
+            constellation.map(&bits, &mut phase)
+        })
+        .collect();
+
+    Config {
+        carrier: CARRIER,
+        baud_rate,
+        rolloff,
+        span: 6,
+        constellation,
+        training: Training {
+            silence: 0,
+            alternation: [Complex32::new(1., 0.), Complex32::new(-1., 0.)],
+            alternation_len: ALTERNATION_LEN,
+            equalizer,
+            scrambled_ones: SCRAMBLED_ONES,
+            end_phase: phase,
+            scrambler,
+        },
+    }
+}
@@ -0,0 +1,63 @@
+use crate::pump::{Config, Constellation, Scrambler, Training};
+use num_complex::Complex32;
+
+pub const CARRIER: f32 = 1700.;
+pub const BAUD_RATE: f32 = 2400.;
+
+const SCRAMBLER_SEED: u32 = 0x2a_aaaa;
+/// Initial state of the sequence choosing the equalizer training symbols.
+const TRAINING_SEED: u32 = 0b0101010;
+const SILENCE_LEN: usize = 48;
+const ALTERNATION_LEN: usize = 128;
+const EQUALIZER_LEN: usize = 384;
+const SCRAMBLED_ONES: usize = 48;
+
+/// Points are scaled so that the largest one has an amplitude of about 1.
+const SCALE: f32 = 0.2;
+
+/// V.29 at 7200 bit/s (eight phases) or 9600 bit/s (eight phases and two
+/// amplitudes each).
+pub(crate) fn config(bit_rate: usize) -> Config {
+    let root2 = 2f32.sqrt();
+    let constellation = match bit_rate {
+        7200 => Constellation {
+            phase_bits: 3,
+            amplitude_bit: false,
+            amplitudes: [[3. * SCALE; 2], [root2 * SCALE; 2]],
+        },
+        _ => Constellation {
+            phase_bits: 3,
+            amplitude_bit: true,
+            amplitudes: [
+                [3. * SCALE, 5. * SCALE],
+                [root2 * SCALE, 3. * root2 * SCALE],
+            ],
+        },
+    };
+
+    let point = |re: f32, im: f32| Complex32::new(re * SCALE, im * SCALE);
+    let (a, b) = (point(-3., 0.), point(3., -3.));
+    let (c, d) = (point(3., 0.), point(-3., 3.));
+    let mut sequence = Scrambler::new(6, 7, TRAINING_SEED);
+    let equalizer: Vec<Complex32> = (0..EQUALIZER_LEN)
+        .map(|_| if sequence.scramble(0) == 0 { c } else { d })
+        .collect();
+    let end_phase = if equalizer.last() == Some(&c) { 0 } else { 3 };
+
+    Config {
+        carrier: CARRIER,
+        baud_rate: BAUD_RATE,
+        rolloff: 0.25,
+        span: 8,
+        constellation,
+        training: Training {
+            silence: SILENCE_LEN,
+            alternation: [a, b],
+            alternation_len: ALTERNATION_LEN,
+            equalizer,
+            scrambled_ones: SCRAMBLED_ONES,
+            end_phase,
+            scrambler: Scrambler::new(18, 23, SCRAMBLER_SEED),
+        },
+    }
+}
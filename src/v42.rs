@@ -0,0 +1,881 @@
+use crate::fsk::Role;
+use crate::hdlc::{fcs, ESCAPE, FLAG};
+use crate::v42bis::V42bisConfig;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Originator detection pattern: DC1 with even parity, then with odd parity.
+const ODP: [u8; 2] = [0x11, 0x91];
+/// Answerer detection pattern of an answerer which speaks LAPM, and of one
+/// which only speaks the alternative procedure.
+const ADP: [u8; 2] = [b'E', b'C'];
+const ADP_NO_LAPM: [u8; 2] = [b'E', 0];
+/// Times the answerer sends ADP.
+const ADP_COUNT: u32 = 10;
+/// How long the originator sends ODP before it gives up on the answerer,
+/// which waits twice as long for it.
+const T400: Duration = Duration::from_millis(750);
+
+/// Address of DLCI 0, with the C/R bit set on the commands of the
+/// originator and on the responses of the answerer.
+const ADDRESS: u8 = 0x01;
+const ADDRESS_CR: u8 = 0x03;
+
+// supervisory frames, modulo 128
+const RR: u8 = 0x01;
+const RNR: u8 = 0x05;
+const REJ: u8 = 0x09;
+const SREJ: u8 = 0x0d;
+
+// unnumbered frames, without the P/F bit
+const SABME: u8 = 0x6f;
+const UA: u8 = 0x63;
+const DM: u8 = 0x0f;
+const DISC: u8 = 0x43;
+const FRMR: u8 = 0x87;
+const XID: u8 = 0xaf;
+const PF: u8 = 0x10;
+
+// information field of XID, as in ISO/IEC 8885
+const XID_FORMAT: u8 = 0x82;
+const XID_PARAMETERS: u8 = 0x80;
+const PI_OPTIONAL_FUNCTIONS: u8 = 0x03;
+const PI_TX_INFO: u8 = 0x05;
+const PI_RX_INFO: u8 = 0x06;
+const PI_TX_WINDOW: u8 = 0x07;
+const PI_RX_WINDOW: u8 = 0x08;
+/// Functions V.42 requires, such as modulo 128 and the 16-bit FCS, to which
+/// the SREJ bit is added.
+const OPTIONAL_FUNCTIONS: [u8; 3] = [0x8a, 0x89, 0x00];
+const OPTIONAL_SREJ: u8 = 0x04;
+// V.42bis parameters, in a group of their own
+const XID_V42BIS: u8 = 0xf0;
+const PI_SET: u8 = 0x00;
+const PI_DIRECTION: u8 = 0x01;
+const PI_DICTIONARY_SIZE: u8 = 0x02;
+const PI_MAX_STRING: u8 = 0x03;
+const V42BIS_SET: [u8; 3] = *b"V42";
+const BOTH_DIRECTIONS: u8 = 3;
+
+/// Error control asked for with `\N`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorControl {
+    #[default]
+    Off,
+    /// LAPM if the peer speaks it, then MNP, plain async otherwise
+    Auto,
+    /// LAPM or hang up
+    Lapm,
+    /// MNP or hang up
+    Mnp,
+}
+
+impl ErrorControl {
+    /// Parses the argument of `\N`.
+    pub fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 | 1 => Some(ErrorControl::Off),
+            2 => Some(ErrorControl::Mnp),
+            3 => Some(ErrorControl::Auto),
+            4 => Some(ErrorControl::Lapm),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LapmConfig {
+    /// k, I frames sent before waiting for an acknowledgement, up to 127
+    pub window: u8,
+    /// N401, data bytes in an I frame
+    pub max_info: usize,
+    /// Whether a missing frame is asked for alone, with SREJ, or with all
+    /// those which follow it, with REJ
+    pub srej: bool,
+    /// N400, attempts after which the link is given up
+    pub max_retries: u32,
+    /// Time the slower direction of the line takes to carry a byte
+    pub byte_time: Duration,
+    /// V.42bis parameters to offer, if any
+    pub compression: Option<V42bisConfig>,
+}
+
+impl Default for LapmConfig {
+    fn default() -> Self {
+        Self {
+            window: 15,
+            max_info: 128,
+            srej: true,
+            max_retries: 10,
+            byte_time: Duration::from_secs(1) / 30,
+            compression: Some(V42bisConfig::default()),
+        }
+    }
+}
+
+impl LapmConfig {
+    /// T401, enough for a whole frame each way and for the audio delays.
+    fn t401(&self) -> Duration {
+        self.byte_time * (2 * (self.max_info as u32 + 8)) + Duration::from_secs(1)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The link is established, so the data is protected from now on
+    Connected,
+    /// The peer does not speak LAPM, so the data goes on unprotected,
+    /// starting with the bytes received during the detection phase
+    Fallback(Vec<u8>),
+    Data(Vec<u8>),
+    /// The link went down, for the reason given
+    Disconnected(String),
+}
+
+/// Parameters negotiated with XID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Parameters {
+    window: u8,
+    max_info: usize,
+    srej: bool,
+    compression: Option<V42bisConfig>,
+}
+
+impl Parameters {
+    /// Those in effect when the peer does not negotiate.
+    const DEFAULT: Self = Self {
+        window: 15,
+        max_info: 128,
+        srej: false,
+        compression: None,
+    };
+
+    fn agree(&self, peer: &Self) -> Self {
+        Self {
+            window: self.window.min(peer.window).max(1),
+            max_info: self.max_info.min(peer.max_info).max(1),
+            srej: self.srej && peer.srej,
+            compression: self
+                .compression
+                .zip(peer.compression)
+                .map(|(ours, theirs)| ours.agree(&theirs)),
+        }
+    }
+}
+
+fn encode_xid(parameters: &Parameters) -> Vec<u8> {
+    let mut functions = OPTIONAL_FUNCTIONS;
+    if parameters.srej {
+        functions[0] |= OPTIONAL_SREJ;
+    }
+    let mut fields = vec![PI_OPTIONAL_FUNCTIONS, functions.len() as u8];
+    fields.extend(functions);
+    let bits = (8 * parameters.max_info) as u16;
+    for pi in [PI_TX_INFO, PI_RX_INFO] {
+        fields.extend([pi, 2]);
+        fields.extend(bits.to_be_bytes());
+    }
+    for pi in [PI_TX_WINDOW, PI_RX_WINDOW] {
+        fields.extend([pi, 1, parameters.window]);
+    }
+    let mut info = vec![XID_FORMAT, XID_PARAMETERS];
+    info.extend((fields.len() as u16).to_be_bytes());
+    info.extend(fields);
+    if let Some(compression) = parameters.compression {
+        let mut fields = vec![PI_SET, V42BIS_SET.len() as u8];
+        fields.extend(V42BIS_SET);
+        fields.extend([PI_DIRECTION, 1, BOTH_DIRECTIONS, PI_DICTIONARY_SIZE, 2]);
+        fields.extend(compression.dictionary_size.to_be_bytes());
+        fields.extend([PI_MAX_STRING, 1, compression.max_string]);
+        info.push(XID_V42BIS);
+        info.extend((fields.len() as u16).to_be_bytes());
+        info.extend(fields);
+    }
+    info
+}
+
+/// Reads the V.42bis parameters of the peer, as long as it compresses both
+/// directions.
+fn parse_v42bis(mut fields: &[u8]) -> Option<V42bisConfig> {
+    let (mut set, mut direction, mut dictionary_size, mut max_string) = (None, None, None, None);
+    while let [pi, len, rest @ ..] = fields {
+        let (value, rest) = rest.split_at_checked(*len as usize)?;
+        fields = rest;
+        match (*pi, value) {
+            (PI_SET, _) => set = Some(value),
+            (PI_DIRECTION, [p0]) => direction = Some(*p0),
+            (PI_DICTIONARY_SIZE, [high, low]) => {
+                dictionary_size = Some(u16::from_be_bytes([*high, *low]))
+            }
+            (PI_MAX_STRING, [p2]) => max_string = Some(*p2),
+            _ => {}
+        }
+    }
+    if set != Some(&V42BIS_SET[..]) || direction != Some(BOTH_DIRECTIONS) {
+        return None;
+    }
+    let config = V42bisConfig {
+        dictionary_size: dictionary_size?,
+        max_string: max_string?,
+    };
+    config.is_valid().then_some(config)
+}
+
+/// Reads the parameters of the peer, taking the smaller of those for each
+/// direction.
+fn parse_xid(info: &[u8]) -> Option<Parameters> {
+    let (&XID_FORMAT, mut groups) = info.split_first()? else {
+        return None;
+    };
+    let mut parameters = Parameters {
+        window: u8::MAX,
+        max_info: usize::MAX,
+        srej: false,
+        compression: None,
+    };
+    while let [group, high, low, rest @ ..] = groups {
+        let (mut fields, rest) =
+            rest.split_at_checked(u16::from_be_bytes([*high, *low]) as usize)?;
+        groups = rest;
+        if *group == XID_V42BIS {
+            parameters.compression = parse_v42bis(fields);
+        }
+        if *group != XID_PARAMETERS {
+            continue;
+        }
+        while let [pi, len, rest @ ..] = fields {
+            let (value, rest) = rest.split_at_checked(*len as usize)?;
+            fields = rest;
+            match (*pi, value) {
+                (PI_OPTIONAL_FUNCTIONS, [first, ..]) => {
+                    parameters.srej = first & OPTIONAL_SREJ != 0
+                }
+                (PI_TX_INFO | PI_RX_INFO, [high, low]) => {
+                    let bytes = u16::from_be_bytes([*high, *low]) as usize / 8;
+                    parameters.max_info = parameters.max_info.min(bytes);
+                }
+                (PI_TX_WINDOW | PI_RX_WINDOW, [window]) => {
+                    parameters.window = parameters.window.min(*window)
+                }
+                _ => {}
+            }
+        }
+    }
+    if parameters.window == u8::MAX {
+        parameters.window = Parameters::DEFAULT.window;
+    }
+    if parameters.max_info == usize::MAX {
+        parameters.max_info = Parameters::DEFAULT.max_info;
+    }
+    Some(parameters)
+}
+
+/// Appends a frame with the start/stop octet framing of V.42.
+fn encode(frame: &[u8], out: &mut Vec<u8>) {
+    out.push(FLAG);
+    for &b in frame.iter().chain(&fcs(frame).to_le_bytes()) {
+        if b == FLAG || b == ESCAPE {
+            out.extend([ESCAPE, b ^ 0x20]);
+        } else {
+            out.push(b);
+        }
+    }
+    out.push(FLAG);
+}
+
+struct FrameDecoder {
+    max_len: usize,
+    frame: Vec<u8>,
+    escaped: bool,
+    discarding: bool,
+}
+
+impl FrameDecoder {
+    fn new(max_len: usize) -> Self {
+        Self {
+            max_len,
+            frame: vec![],
+            escaped: false,
+            discarding: false,
+        }
+    }
+
+    /// Returns the address, control and information fields of each frame
+    /// which passes the FCS. The others are line noise, which LAPM recovers
+    /// from.
+    fn put_byte(&mut self, byte: u8) -> Option<Vec<u8>> {
+        if byte == FLAG {
+            let mut frame = std::mem::take(&mut self.frame);
+            let discarding = std::mem::replace(&mut self.discarding, false);
+            self.escaped = false;
+            if discarding || frame.len() < 4 {
+                return None;
+            }
+            let check = frame.split_off(frame.len() - 2);
+            return (fcs(&frame).to_le_bytes() == *check).then_some(frame);
+        }
+        if self.discarding {
+            return None;
+        }
+        let byte = if std::mem::replace(&mut self.escaped, false) {
+            byte ^ 0x20
+        } else if byte == ESCAPE {
+            self.escaped = true;
+            return None;
+        } else {
+            byte
+        };
+        if self.frame.len() == self.max_len {
+            self.frame.clear();
+            self.discarding = true;
+            return None;
+        }
+        self.frame.push(byte);
+        None
+    }
+}
+
+fn next(n: u8) -> u8 {
+    (n + 1) & 0x7f
+}
+
+/// How far `to` is ahead of `from`, modulo 128.
+fn distance(from: u8, to: u8) -> u8 {
+    to.wrapping_sub(from) & 0x7f
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Waiting for the carrier of the peer
+    Idle,
+    /// Sending ODP until ADP comes back, or waiting for ODP
+    Detecting,
+    /// XID sent by the originator, waiting for the answer
+    Negotiating,
+    /// SABME sent by the originator, or awaited by the answerer
+    Establishing,
+    Connected,
+    Closed,
+}
+
+/// V.42 LAPM endpoint: detects whether the peer speaks it, negotiates the
+/// parameters of the link and establishes it, then carries data in I frames
+/// sent again until they are acknowledged.
+pub struct Lapm {
+    role: Role,
+    config: LapmConfig,
+    t401: Duration,
+    state: State,
+    decoder: FrameDecoder,
+    /// Bytes received during the detection phase
+    received: Vec<u8>,
+    previous: Option<u8>,
+    adp_left: u32,
+    deadline: Option<Instant>,
+    /// Since the last progress
+    retries: u32,
+    parameters: Parameters,
+    /// Data waiting to be sent in I frames
+    queue: VecDeque<u8>,
+    /// V(S), V(A) and V(R)
+    vs: u8,
+    va: u8,
+    vr: u8,
+    /// Frames not yet acknowledged, from V(A) on
+    unacked: VecDeque<Vec<u8>>,
+    /// N(S) of the frames to send again
+    retransmit: VecDeque<u8>,
+    /// Frames received after a missing one, by N(S)
+    out_of_order: HashMap<u8, Vec<u8>>,
+    /// Frames asked for with SREJ, and not received yet
+    requested: HashSet<u8>,
+    /// N(R) of the SREJ or REJ frames to send
+    rejects: VecDeque<u8>,
+    reject_sent: bool,
+    peer_busy: bool,
+    busy: bool,
+    /// Whether the peer has yet to hear that we became busy or not
+    busy_changed: bool,
+    /// Whether I frames were discarded while busy
+    discarded: bool,
+    /// Whether the peer polled us, and waits for our final response
+    final_owed: bool,
+    ack_owed: bool,
+    /// Whether we polled the peer, and wait for its final response
+    polled: bool,
+}
+
+impl Lapm {
+    pub fn new(role: Role, config: LapmConfig) -> Self {
+        Self {
+            role,
+            t401: config.t401(),
+            state: State::Idle,
+            // address, control, the longest of I and XID, and the FCS
+            decoder: FrameDecoder::new(config.max_info.max(64) + 5),
+            received: vec![],
+            previous: None,
+            adp_left: 0,
+            deadline: None,
+            retries: 0,
+            parameters: Parameters {
+                window: config.window.clamp(1, 127),
+                max_info: config.max_info,
+                srej: config.srej,
+                compression: config.compression,
+            },
+            queue: VecDeque::new(),
+            vs: 0,
+            va: 0,
+            vr: 0,
+            unacked: VecDeque::new(),
+            retransmit: VecDeque::new(),
+            out_of_order: HashMap::new(),
+            requested: HashSet::new(),
+            rejects: VecDeque::new(),
+            reject_sent: false,
+            peer_busy: false,
+            busy: false,
+            busy_changed: false,
+            discarded: false,
+            final_owed: false,
+            ack_owed: false,
+            polled: false,
+            config,
+        }
+    }
+
+    /// Starts the detection phase, once the carrier of the peer is there.
+    pub fn start(&mut self, now: Instant) {
+        if self.state != State::Idle {
+            return;
+        }
+        self.state = State::Detecting;
+        let wait = match self.role {
+            Role::Originate => T400,
+            Role::Answer => 2 * T400,
+        };
+        self.deadline = Some(now + wait);
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state == State::Connected
+    }
+
+    /// V.42bis parameters agreed with the peer, once connected.
+    pub fn compression(&self) -> Option<V42bisConfig> {
+        self.parameters.compression.filter(|_| self.is_connected())
+    }
+
+    /// Queues data to send once the link is established.
+    pub fn send(&mut self, data: &[u8]) {
+        self.queue.extend(data);
+    }
+
+    /// Bytes queued and not yet sent.
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// While busy, the peer is told to stop sending I frames.
+    pub fn set_busy(&mut self, busy: bool) {
+        if busy != self.busy {
+            self.busy = busy;
+            self.busy_changed = true;
+        }
+    }
+
+    pub fn put_byte(&mut self, byte: u8, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        match self.state {
+            State::Idle | State::Detecting => self.detect(byte, now, out),
+            State::Closed => None,
+            _ => {
+                let frame = self.decoder.put_byte(byte)?;
+                self.receive(&frame, now, out)
+            }
+        }
+    }
+
+    /// Sends what is due, at most one frame at a time so that the frames
+    /// sent reflect the latest state. To be called whenever the line is
+    /// ready for more.
+    pub fn poll(&mut self, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        let expired = self.deadline.is_some_and(|deadline| now >= deadline);
+        match self.state {
+            State::Idle | State::Closed => None,
+            State::Detecting if expired => Some(self.fall_back()),
+            State::Detecting => {
+                if self.role == Role::Originate {
+                    out.extend(ODP);
+                }
+                None
+            }
+            State::Negotiating | State::Establishing => {
+                if self.adp_left > 0 {
+                    self.adp_left -= 1;
+                    out.extend(ADP);
+                    return None;
+                }
+                if !expired {
+                    return None;
+                }
+                if self.retries == self.config.max_retries {
+                    return Some(self.down("no answer from the peer"));
+                }
+                self.retries += 1;
+                self.deadline = Some(now + self.t401);
+                if self.role == Role::Originate {
+                    self.send_setup(out);
+                }
+                None
+            }
+            State::Connected => self.poll_connected(expired, now, out),
+        }
+    }
+
+    fn detect(&mut self, byte: u8, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        let pair = self.previous.replace(byte).map(|previous| [previous, byte]);
+        match (self.role, pair) {
+            (Role::Answer, Some(ODP)) => {
+                self.received.clear();
+                self.adp_left = ADP_COUNT;
+                self.state = State::Establishing;
+                self.deadline = Some(now + self.t401);
+            }
+            (Role::Originate, Some(ADP)) if self.state == State::Detecting => {
+                self.received.clear();
+                self.state = State::Negotiating;
+                self.deadline = Some(now + self.t401);
+                self.send_setup(out);
+            }
+            (Role::Originate, Some(ADP_NO_LAPM)) if self.state == State::Detecting => {
+                self.received.pop();
+                return Some(self.fall_back());
+            }
+            _ => self.received.push(byte),
+        }
+        None
+    }
+
+    fn fall_back(&mut self) -> Event {
+        self.state = State::Closed;
+        self.deadline = None;
+        Event::Fallback(std::mem::take(&mut self.received))
+    }
+
+    fn down(&mut self, reason: &str) -> Event {
+        self.state = State::Closed;
+        self.deadline = None;
+        Event::Disconnected(reason.to_string())
+    }
+
+    /// Sends the frame the originator is waiting an answer for.
+    fn send_setup(&mut self, out: &mut Vec<u8>) {
+        if self.state == State::Negotiating {
+            let xid = encode_xid(&self.parameters);
+            self.send_unnumbered(true, XID | PF, &xid, out);
+        } else {
+            self.send_unnumbered(true, SABME | PF, &[], out);
+        }
+    }
+
+    fn address(&self, command: bool) -> u8 {
+        if command == (self.role == Role::Originate) {
+            ADDRESS_CR
+        } else {
+            ADDRESS
+        }
+    }
+
+    fn send_unnumbered(&self, command: bool, control: u8, info: &[u8], out: &mut Vec<u8>) {
+        let mut frame = vec![self.address(command), control];
+        frame.extend(info);
+        encode(&frame, out);
+    }
+
+    fn send_supervisory(&self, command: bool, kind: u8, nr: u8, pf: bool, out: &mut Vec<u8>) {
+        let frame = [self.address(command), kind, nr << 1 | pf as u8];
+        encode(&frame, out);
+    }
+
+    /// Tells the peer how far we received, and whether it may go on.
+    fn send_ack(&mut self, command: bool, pf: bool, out: &mut Vec<u8>) {
+        let kind = if self.busy {
+            RNR
+        } else if std::mem::take(&mut self.discarded) {
+            REJ
+        } else {
+            RR
+        };
+        self.send_supervisory(command, kind, self.vr, pf, out);
+        self.ack_owed = false;
+        self.busy_changed = false;
+    }
+
+    fn send_information(&mut self, ns: u8, out: &mut Vec<u8>) {
+        let info = &self.unacked[distance(self.va, ns) as usize];
+        let mut frame = vec![self.address(true), ns << 1, self.vr << 1];
+        frame.extend(info);
+        encode(&frame, out);
+        self.ack_owed = false;
+    }
+
+    fn reset_link(&mut self) {
+        self.vs = 0;
+        self.va = 0;
+        self.vr = 0;
+        self.unacked.clear();
+        self.retransmit.clear();
+        self.out_of_order.clear();
+        self.requested.clear();
+        self.rejects.clear();
+        self.reject_sent = false;
+        self.peer_busy = false;
+        self.busy_changed = self.busy;
+        self.discarded = false;
+        self.final_owed = false;
+        self.ack_owed = false;
+        self.polled = false;
+        self.retries = 0;
+        self.deadline = None;
+    }
+
+    fn connect(&mut self) -> Event {
+        self.state = State::Connected;
+        self.reset_link();
+        Event::Connected
+    }
+
+    fn receive(&mut self, frame: &[u8], now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        let [address, control, rest @ ..] = frame else {
+            return None;
+        };
+        if *address != ADDRESS && *address != ADDRESS_CR {
+            return None;
+        }
+        let command = (*address == ADDRESS_CR) == (self.role == Role::Answer);
+        if control & 1 == 0 {
+            let [nr, info @ ..] = rest else {
+                return None;
+            };
+            return self.receive_information(control >> 1, nr >> 1, nr & 1 != 0, info, now);
+        }
+        if control & 3 == 1 {
+            let [nr] = rest else {
+                return None;
+            };
+            self.receive_supervisory(*control, command, nr >> 1, nr & 1 != 0, now);
+            return None;
+        }
+        let pf = control & PF != 0;
+        match (control & !PF, command, self.state) {
+            (XID, true, _) if self.role == Role::Answer => {
+                if let Some(peer) = parse_xid(rest) {
+                    self.parameters = self.parameters.agree(&peer);
+                }
+                let xid = encode_xid(&self.parameters);
+                self.send_unnumbered(false, XID | control & PF, &xid, out);
+                None
+            }
+            (XID, false, State::Negotiating) => {
+                if let Some(peer) = parse_xid(rest) {
+                    self.parameters = self.parameters.agree(&peer);
+                }
+                self.state = State::Establishing;
+                self.retries = 0;
+                self.deadline = Some(now + self.t401);
+                self.send_setup(out);
+                None
+            }
+            // a peer which does not negotiate
+            (DM | FRMR, false, State::Negotiating) => {
+                self.parameters = self.parameters.agree(&Parameters::DEFAULT);
+                self.state = State::Establishing;
+                self.retries = 0;
+                self.deadline = Some(now + self.t401);
+                self.send_setup(out);
+                None
+            }
+            (SABME, true, _) => {
+                self.send_unnumbered(false, UA | control & PF, &[], out);
+                if self.state == State::Connected {
+                    // our UA was lost
+                    self.reset_link();
+                    None
+                } else {
+                    Some(self.connect())
+                }
+            }
+            (UA, false, State::Establishing) if self.role == Role::Originate => {
+                Some(self.connect())
+            }
+            (DISC, true, _) => {
+                self.send_unnumbered(false, UA | control & PF, &[], out);
+                Some(self.down("disconnected by the peer"))
+            }
+            (DM, false, _) if pf || self.state == State::Connected => {
+                Some(self.down("link refused by the peer"))
+            }
+            (FRMR, false, State::Connected) => Some(self.down("frame rejected by the peer")),
+            _ => None,
+        }
+    }
+
+    fn receive_information(
+        &mut self,
+        ns: u8,
+        nr: u8,
+        poll: bool,
+        info: &[u8],
+        now: Instant,
+    ) -> Option<Event> {
+        if self.state != State::Connected {
+            return None;
+        }
+        self.acknowledge(nr, now);
+        if poll {
+            self.final_owed = true;
+        }
+        if self.busy {
+            self.discarded = true;
+            self.ack_owed = true;
+            return None;
+        }
+        self.requested.remove(&ns);
+        if ns == self.vr {
+            let mut data = info.to_vec();
+            self.vr = next(self.vr);
+            while let Some(info) = self.out_of_order.remove(&self.vr) {
+                data.extend(info);
+                self.vr = next(self.vr);
+            }
+            self.reject_sent = false;
+            self.ack_owed = true;
+            return (!data.is_empty()).then_some(Event::Data(data));
+        }
+        if distance(self.vr, ns) >= self.parameters.window {
+            // sent again while its acknowledgement was on the way
+            self.ack_owed = true;
+        } else if self.parameters.srej {
+            let mut missing = self.vr;
+            while missing != ns {
+                if !self.out_of_order.contains_key(&missing) && self.requested.insert(missing) {
+                    self.rejects.push_back(missing);
+                }
+                missing = next(missing);
+            }
+            self.out_of_order.insert(ns, info.to_vec());
+        } else if !self.reject_sent {
+            self.reject_sent = true;
+            self.rejects.push_back(self.vr);
+        }
+        None
+    }
+
+    fn receive_supervisory(&mut self, kind: u8, command: bool, nr: u8, pf: bool, now: Instant) {
+        if self.state != State::Connected {
+            return;
+        }
+        if kind == SREJ {
+            if distance(self.va, nr) < distance(self.va, self.vs) {
+                self.retransmit.push_back(nr);
+            }
+            return;
+        }
+        self.peer_busy = kind == RNR;
+        self.acknowledge(nr, now);
+        if command && pf {
+            self.final_owed = true;
+        }
+        if !command && pf && self.polled {
+            self.polled = false;
+            self.retries = 0;
+            if !self.peer_busy {
+                self.go_back();
+            }
+        } else if kind == REJ {
+            self.go_back();
+        }
+        self.update_timer(now);
+    }
+
+    /// Takes the frames up to N(R) as acknowledged.
+    fn acknowledge(&mut self, nr: u8, now: Instant) {
+        let acked = distance(self.va, nr) as usize;
+        if acked == 0 || acked > self.unacked.len() {
+            return;
+        }
+        self.unacked.drain(..acked);
+        self.va = nr;
+        self.retries = 0;
+        self.deadline = None;
+        self.update_timer(now);
+    }
+
+    /// Sends again every frame not yet acknowledged.
+    fn go_back(&mut self) {
+        self.retransmit = (0..self.unacked.len() as u8)
+            .map(|i| (self.va + i) & 0x7f)
+            .collect();
+    }
+
+    /// Runs T401 while waiting for an acknowledgement, a final response or
+    /// the end of the busy condition of the peer.
+    fn update_timer(&mut self, now: Instant) {
+        if self.unacked.is_empty() && !self.polled && !self.peer_busy {
+            self.deadline = None;
+        } else if self.deadline.is_none() {
+            self.deadline = Some(now + self.t401);
+        }
+    }
+
+    fn poll_connected(&mut self, expired: bool, now: Instant, out: &mut Vec<u8>) -> Option<Event> {
+        if expired {
+            if self.retries == self.config.max_retries {
+                return Some(self.down("no acknowledgement from the peer"));
+            }
+            // ask the peer how far it received
+            self.retries += 1;
+            self.polled = true;
+            self.deadline = Some(now + self.t401);
+            self.send_ack(true, true, out);
+            return None;
+        }
+        if self.final_owed {
+            self.final_owed = false;
+            self.send_ack(false, true, out);
+            return None;
+        }
+        if self.busy_changed {
+            self.send_ack(false, false, out);
+            return None;
+        }
+        if let Some(nr) = self.rejects.pop_front() {
+            let kind = if self.parameters.srej { SREJ } else { REJ };
+            self.send_supervisory(false, kind, nr, false, out);
+            return None;
+        }
+        while let Some(ns) = self.retransmit.pop_front() {
+            if distance(self.va, ns) < distance(self.va, self.vs) {
+                self.send_information(ns, out);
+                return None;
+            }
+        }
+        if !self.peer_busy
+            && !self.polled
+            && self.unacked.len() < self.parameters.window as usize
+            && !self.queue.is_empty()
+        {
+            let len = self.queue.len().min(self.parameters.max_info);
+            self.unacked.push_back(self.queue.drain(..len).collect());
+            self.send_information(self.vs, out);
+            self.vs = next(self.vs);
+            self.update_timer(now);
+            return None;
+        }
+        if self.ack_owed {
+            self.send_ack(false, false, out);
+        }
+        None
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::HashMap;
+
+// control codewords of the compressed mode
+const ETM: u16 = 0;
+const FLUSH: u16 = 1;
+const STEPUP: u16 = 2;
+/// N6, control codewords, followed by one for each character.
+const CONTROL_CODEWORDS: u16 = 3;
+/// N5, first codeword of the strings of two characters or more.
+const FIRST_STRING: u16 = CONTROL_CODEWORDS + 256;
+/// N3, codeword size at the start.
+const INITIAL_CODEWORD_SIZE: u32 = 9;
+
+// commands which follow the escape character in transparent mode
+const ECM: u8 = 0;
+const EID: u8 = 1;
+/// Added to the escape character each time it appears in the data.
+const ESCAPE_STEP: u8 = 51;
+
+/// Characters after which the encoder weighs the two modes.
+const TEST_WINDOW: u32 = 256;
+
+pub const MIN_DICTIONARY_SIZE: u16 = 512;
+pub const MIN_STRING: u8 = 6;
+pub const MAX_STRING: u8 = 250;
+
+/// Parameters of the compression, one set for both directions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct V42bisConfig {
+    /// P1, codewords in the dictionary
+    pub dictionary_size: u16,
+    /// P2, characters in the longest string
+    pub max_string: u8,
+}
+
+impl Default for V42bisConfig {
+    fn default() -> Self {
+        Self {
+            dictionary_size: 2048,
+            max_string: 32,
+        }
+    }
+}
+
+impl V42bisConfig {
+    pub fn agree(&self, peer: &Self) -> Self {
+        Self {
+            dictionary_size: self.dictionary_size.min(peer.dictionary_size),
+            max_string: self.max_string.min(peer.max_string),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.dictionary_size >= MIN_DICTIONARY_SIZE
+            && (MIN_STRING..=MAX_STRING).contains(&self.max_string)
+    }
+}
+
+/// Settings of `+DS`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompressionSettings {
+    /// Whether the data is compressed, in both directions
+    pub enabled: bool,
+    /// Whether to hang up when the peer does not agree to compress
+    pub required: bool,
+    pub config: V42bisConfig,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            required: false,
+            config: V42bisConfig::default(),
+        }
+    }
+}
+
+impl CompressionSettings {
+    /// Applies the arguments of `+DS=`, those left out keeping their value,
+    /// unless one of them is out of range.
+    pub fn update(&mut self, args: [Option<u16>; 4]) -> bool {
+        let [direction, required, dictionary_size, max_string] = args;
+        let mut settings = *self;
+        match direction {
+            Some(0) => settings.enabled = false,
+            Some(3) => settings.enabled = true,
+            None => {}
+            _ => return false,
+        }
+        match required {
+            Some(0) => settings.required = false,
+            Some(1) => settings.required = true,
+            None => {}
+            _ => return false,
+        }
+        if let Some(dictionary_size) = dictionary_size {
+            settings.config.dictionary_size = dictionary_size;
+        }
+        if let Some(max_string) = max_string {
+            let Ok(max_string) = max_string.try_into() else {
+                return false;
+            };
+            settings.config.max_string = max_string;
+        }
+        if !settings.config.is_valid() {
+            return false;
+        }
+        *self = settings;
+        true
+    }
+
+    /// What `+DS?` answers.
+    pub fn report(&self) -> String {
+        format!(
+            "+DS: {},{},{},{}",
+            if self.enabled { 3 } else { 0 },
+            self.required as u8,
+            self.config.dictionary_size,
+            self.config.max_string
+        )
+    }
+
+    /// Parameters to offer the peer, if any.
+    pub fn offer(&self) -> Option<V42bisConfig> {
+        self.enabled.then_some(self.config)
+    }
+}
+
+/// Characters given to an encoder or put out by a decoder, and the bytes
+/// which carried them over the link.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub characters: u64,
+    pub bytes: u64,
+}
+
+impl Counters {
+    /// Characters per byte on the link.
+    pub fn ratio(&self) -> f64 {
+        if self.bytes == 0 {
+            1.
+        } else {
+            self.characters as f64 / self.bytes as f64
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    parent: u16,
+    character: u8,
+    /// Characters in the string
+    len: u8,
+}
+
+/// Strings known to both ends, a tree in which each string is its parent
+/// followed by one character. Once full, leaves are reused in turn.
+struct Dictionary {
+    max_string: u8,
+    /// Indexed by codeword, from [`FIRST_STRING`]
+    nodes: Vec<Option<Node>>,
+    children: HashMap<(u16, u8), u16>,
+    child_count: Vec<u16>,
+    /// C1, where to look first for the next free codeword
+    next: u16,
+}
+
+impl Dictionary {
+    fn new(config: &V42bisConfig) -> Self {
+        let size = config.dictionary_size as usize;
+        Self {
+            max_string: config.max_string,
+            nodes: vec![None; size],
+            children: HashMap::new(),
+            child_count: vec![0; size],
+            next: FIRST_STRING,
+        }
+    }
+
+    fn len(&self, codeword: u16) -> u8 {
+        if codeword < FIRST_STRING {
+            1
+        } else {
+            self.nodes
+                .get(codeword as usize)
+                .copied()
+                .flatten()
+                .map_or(0, |node| node.len)
+        }
+    }
+
+    fn child(&self, codeword: u16, character: u8) -> Option<u16> {
+        self.children.get(&(codeword, character)).copied()
+    }
+
+    /// Codeword which the next string added to `parent` will take: the
+    /// first one free or a leaf from C1 on.
+    fn next_free(&self, parent: u16) -> u16 {
+        let mut codeword = self.next;
+        loop {
+            match self.nodes[codeword as usize] {
+                None => return codeword,
+                Some(_) if self.child_count[codeword as usize] == 0 && codeword != parent => {
+                    return codeword
+                }
+                Some(_) => {}
+            }
+            codeword += 1;
+            if codeword as usize == self.nodes.len() {
+                codeword = FIRST_STRING;
+            }
+        }
+    }
+
+    /// Adds `parent` followed by `character`, unless it would be too long.
+    fn add(&mut self, parent: u16, character: u8) {
+        let len = self.len(parent);
+        if len >= self.max_string {
+            return;
+        }
+        let codeword = self.next_free(parent);
+        if let Some(old) = self.nodes[codeword as usize] {
+            self.children.remove(&(old.parent, old.character));
+            self.child_count[old.parent as usize] -= 1;
+        }
+        self.nodes[codeword as usize] = Some(Node {
+            parent,
+            character,
+            len: len + 1,
+        });
+        self.children.insert((parent, character), codeword);
+        self.child_count[parent as usize] += 1;
+        self.next = codeword + 1;
+        if self.next as usize == self.nodes.len() {
+            self.next = FIRST_STRING;
+        }
+    }
+
+    fn string(&self, mut codeword: u16) -> Vec<u8> {
+        let mut string = vec![];
+        while codeword >= FIRST_STRING {
+            let Some(node) = self.nodes.get(codeword as usize).copied().flatten() else {
+                return vec![];
+            };
+            string.push(node.character);
+            codeword = node.parent;
+        }
+        if codeword >= CONTROL_CODEWORDS {
+            string.push((codeword - CONTROL_CODEWORDS) as u8);
+        }
+        string.reverse();
+        string
+    }
+}
+
+fn codeword_of(character: u8) -> u16 {
+    CONTROL_CODEWORDS + character as u16
+}
+
+/// String matching of the encoder, which the decoder repeats in transparent
+/// mode so that the dictionaries stay the same.
+#[derive(Default)]
+struct Matcher {
+    /// Codeword of the longest string matched so far
+    current: Option<u16>,
+}
+
+impl Matcher {
+    /// Returns the codeword of the string which `character` does not extend.
+    fn put_character(&mut self, dictionary: &mut Dictionary, character: u8) -> Option<u16> {
+        let Some(current) = self.current else {
+            self.current = Some(codeword_of(character));
+            return None;
+        };
+        if dictionary.len(current) < dictionary.max_string {
+            if let Some(child) = dictionary.child(current, character) {
+                self.current = Some(child);
+                return None;
+            }
+        }
+        dictionary.add(current, character);
+        self.current = Some(codeword_of(character));
+        Some(current)
+    }
+}
+
+/// Packs codewords least significant bit first.
+#[derive(Default)]
+struct BitWriter {
+    bits: u32,
+    count: u32,
+}
+
+impl BitWriter {
+    fn put(&mut self, codeword: u16, size: u32, out: &mut Vec<u8>) {
+        self.bits |= (codeword as u32) << self.count;
+        self.count += size;
+        while self.count >= 8 {
+            out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    fn align(&mut self, out: &mut Vec<u8>) {
+        if self.count > 0 {
+            out.push(self.bits as u8);
+        }
+        self.bits = 0;
+        self.count = 0;
+    }
+}
+
+/// Compresses one direction of the link, switching to the transparent mode
+/// while the data does not compress.
+pub struct Encoder {
+    dictionary: Dictionary,
+    matcher: Matcher,
+    compressed: bool,
+    escape: u8,
+    /// C2
+    codeword_size: u32,
+    writer: BitWriter,
+    /// Characters of the test window, and the bits the compressed mode
+    /// takes for them
+    window_characters: u32,
+    window_bits: u32,
+    counters: Counters,
+}
+
+impl Encoder {
+    pub fn new(config: &V42bisConfig) -> Self {
+        Self {
+            dictionary: Dictionary::new(config),
+            matcher: Matcher::default(),
+            compressed: false,
+            escape: 0,
+            codeword_size: INITIAL_CODEWORD_SIZE,
+            writer: BitWriter::default(),
+            window_characters: 0,
+            window_bits: 0,
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    /// Encodes `data`, the end of which may wait in the encoder until
+    /// [`Encoder::flush`].
+    pub fn put(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let start = out.len();
+        for &character in data {
+            if let Some(codeword) = self.matcher.put_character(&mut self.dictionary, character) {
+                self.window_bits += self.codeword_size.max(16 - codeword.leading_zeros());
+                if self.compressed {
+                    self.put_codeword(codeword, out);
+                }
+            }
+            if !self.compressed {
+                self.put_transparent(character, out);
+            }
+            self.window_characters += 1;
+            if self.window_characters == TEST_WINDOW {
+                self.test(out);
+            }
+        }
+        self.counters.characters += data.len() as u64;
+        self.counters.bytes += (out.len() - start) as u64;
+    }
+
+    /// Sends what waits in the encoder, octet aligned.
+    pub fn flush(&mut self, out: &mut Vec<u8>) {
+        if !self.compressed {
+            return;
+        }
+        let Some(codeword) = self.matcher.current.take() else {
+            return;
+        };
+        let start = out.len();
+        self.put_codeword(codeword, out);
+        self.put_codeword(FLUSH, out);
+        self.writer.align(out);
+        self.counters.bytes += (out.len() - start) as u64;
+    }
+
+    /// Switches to the mode which suits the data of the test window, with a
+    /// margin against switching back and forth.
+    fn test(&mut self, out: &mut Vec<u8>) {
+        let transparent_bits = 8 * self.window_characters;
+        if self.compressed && self.window_bits > transparent_bits {
+            if let Some(codeword) = self.matcher.current.take() {
+                self.put_codeword(codeword, out);
+            }
+            self.put_codeword(ETM, out);
+            self.writer.align(out);
+            self.compressed = false;
+        } else if !self.compressed && self.window_bits < transparent_bits * 7 / 8 {
+            out.extend([self.escape, ECM]);
+            self.matcher.current = None;
+            self.compressed = true;
+        }
+        self.window_characters = 0;
+        self.window_bits = 0;
+    }
+
+    fn put_codeword(&mut self, codeword: u16, out: &mut Vec<u8>) {
+        while codeword as u32 >= 1 << self.codeword_size {
+            self.writer.put(STEPUP, self.codeword_size, out);
+            self.codeword_size += 1;
+        }
+        self.writer.put(codeword, self.codeword_size, out);
+    }
+
+    fn put_transparent(&mut self, character: u8, out: &mut Vec<u8>) {
+        if character == self.escape {
+            out.extend([self.escape, EID]);
+            self.escape = self.escape.wrapping_add(ESCAPE_STEP);
+        } else {
+            out.push(character);
+        }
+    }
+}
+
+/// Decompresses one direction of the link.
+pub struct Decoder {
+    dictionary: Dictionary,
+    matcher: Matcher,
+    compressed: bool,
+    escape: u8,
+    escaped: bool,
+    codeword_size: u32,
+    bits: u32,
+    count: u32,
+    /// Codeword received last, to which the dictionary adds the first
+    /// character of the next string
+    previous: Option<u16>,
+    counters: Counters,
+}
+
+impl Decoder {
+    pub fn new(config: &V42bisConfig) -> Self {
+        Self {
+            dictionary: Dictionary::new(config),
+            matcher: Matcher::default(),
+            compressed: false,
+            escape: 0,
+            escaped: false,
+            codeword_size: INITIAL_CODEWORD_SIZE,
+            bits: 0,
+            count: 0,
+            previous: None,
+            counters: Counters::default(),
+        }
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    pub fn put(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let start = out.len();
+        for &byte in data {
+            if self.compressed {
+                self.put_compressed(byte, out);
+            } else {
+                self.put_transparent(byte, out);
+            }
+        }
+        self.counters.bytes += data.len() as u64;
+        self.counters.characters += (out.len() - start) as u64;
+    }
+
+    fn put_transparent(&mut self, byte: u8, out: &mut Vec<u8>) {
+        if self.escaped {
+            self.escaped = false;
+            match byte {
+                ECM => {
+                    self.compressed = true;
+                    self.matcher.current = None;
+                    self.previous = None;
+                }
+                EID => {
+                    let character = self.escape;
+                    self.escape = self.escape.wrapping_add(ESCAPE_STEP);
+                    self.put_character(character, out);
+                }
+                _ => {}
+            }
+        } else if byte == self.escape {
+            self.escaped = true;
+        } else {
+            self.put_character(byte, out);
+        }
+    }
+
+    fn put_character(&mut self, character: u8, out: &mut Vec<u8>) {
+        out.push(character);
+        self.matcher.put_character(&mut self.dictionary, character);
+    }
+
+    fn put_compressed(&mut self, byte: u8, out: &mut Vec<u8>) {
+        self.bits |= (byte as u32) << self.count;
+        self.count += 8;
+        while self.compressed && self.count >= self.codeword_size {
+            let codeword = (self.bits & ((1 << self.codeword_size) - 1)) as u16;
+            self.bits >>= self.codeword_size;
+            self.count -= self.codeword_size;
+            match codeword {
+                ETM => {
+                    self.compressed = false;
+                    self.matcher.current = None;
+                    self.align();
+                }
+                FLUSH => self.align(),
+                STEPUP => self.codeword_size += 1,
+                _ => self.put_codeword(codeword, out),
+            }
+        }
+    }
+
+    /// Drops the rest of the octet, after which no string is extended.
+    fn align(&mut self) {
+        self.bits = 0;
+        self.count = 0;
+        self.previous = None;
+    }
+
+    fn put_codeword(&mut self, codeword: u16, out: &mut Vec<u8>) {
+        if codeword as usize >= self.dictionary.nodes.len() {
+            self.previous = None;
+            return;
+        }
+        let string = match self.previous {
+            Some(previous) if self.dictionary.len(previous) < self.dictionary.max_string => {
+                // the encoder may already use the string it just added
+                let string = if codeword == self.dictionary.next_free(previous) {
+                    let mut string = self.dictionary.string(previous);
+                    string.extend(string.first().copied());
+                    string
+                } else {
+                    self.dictionary.string(codeword)
+                };
+                if let Some(first) = string.first() {
+                    self.dictionary.add(previous, *first);
+                }
+                string
+            }
+            _ => self.dictionary.string(codeword),
+        };
+        out.extend(&string);
+        self.previous = Some(codeword);
+    }
+}
@@ -0,0 +1,222 @@
+use modem::at::{
+    parse, AtInterpreter, Command, EscapeDetector, FaxCommand, ResultCode, SyntaxError, S_ESCAPE,
+    S_GUARD_TIME,
+};
+use std::time::{Duration, Instant};
+
+#[test]
+fn at_parse_basic() {
+    assert_eq!(
+        parse("E0V1Q0"),
+        Ok(vec![
+            Command::Echo(false),
+            Command::Verbose(true),
+            Command::Quiet(false)
+        ])
+    );
+    assert_eq!(parse(""), Ok(vec![]));
+    assert_eq!(parse("E2"), Err(SyntaxError));
+    assert_eq!(parse("#"), Err(SyntaxError));
+}
+
+#[test]
+fn at_parse_registers() {
+    assert_eq!(
+        parse("S0=2S1?S7="),
+        Ok(vec![
+            Command::SetRegister(0, 2),
+            Command::QueryRegister(1),
+            Command::SetRegister(7, 0)
+        ])
+    );
+    assert_eq!(parse("S=1"), Err(SyntaxError));
+    assert_eq!(parse("S0"), Err(SyntaxError));
+    assert_eq!(parse("S0=256"), Err(SyntaxError));
+}
+
+#[test]
+fn at_parse_dial_answer() {
+    assert_eq!(
+        parse("E0DT 555-1234;"),
+        Ok(vec![
+            Command::Echo(false),
+            Command::Dial("T555-1234;".to_string())
+        ])
+    );
+    assert_eq!(parse("D"), Ok(vec![Command::Dial(String::new())]));
+    assert_eq!(
+        parse("S0=0A"),
+        Ok(vec![Command::SetRegister(0, 0), Command::Answer])
+    );
+    assert_eq!(parse("HH0"), Ok(vec![Command::HangUp, Command::HangUp]));
+    assert_eq!(parse("H1"), Err(SyntaxError));
+}
+
+#[test]
+fn at_parse_flow_control() {
+    assert_eq!(
+        parse("&K3&K"),
+        Ok(vec![Command::FlowControl(3), Command::FlowControl(0)])
+    );
+    assert_eq!(parse("&X"), Err(SyntaxError));
+}
+
+#[test]
+fn at_parse_error_control() {
+    assert_eq!(
+        parse("\\N3&K3\\N"),
+        Ok(vec![
+            Command::ErrorControl(3),
+            Command::FlowControl(3),
+            Command::ErrorControl(0)
+        ])
+    );
+    assert_eq!(parse("\\Q"), Err(SyntaxError));
+}
+
+#[test]
+fn at_parse_data_compression() {
+    assert_eq!(
+        parse("+DS=3,,4096;+DS?"),
+        Ok(vec![
+            Command::DataCompression([Some(3), None, Some(4096), None]),
+            Command::QueryDataCompression
+        ])
+    );
+    assert_eq!(parse("+DS=3,0,2048,32,1"), Err(SyntaxError));
+    assert_eq!(parse("+DS=x"), Err(SyntaxError));
+}
+
+#[test]
+fn at_parse_modulation() {
+    assert_eq!(
+        parse("+MS=V23,0;O"),
+        Ok(vec![
+            Command::Modulation("V23".to_string()),
+            Command::Online
+        ])
+    );
+    assert_eq!(parse("+MS?"), Ok(vec![Command::QueryModulation]));
+    assert_eq!(parse("+MS="), Err(SyntaxError));
+}
+
+#[test]
+fn at_parse_fax_class1() {
+    assert_eq!(
+        parse("+FCLASS=1;+FTH=3"),
+        Ok(vec![
+            Command::FaxClass(1),
+            Command::Fax(FaxCommand::TransmitHdlc, 3)
+        ])
+    );
+    assert_eq!(
+        parse("+FCLASS?;+FCLASS=?"),
+        Ok(vec![Command::QueryFaxClass, Command::FaxClassRange])
+    );
+    assert_eq!(
+        parse("+FRH=3;+FRM=?;+FTM=96"),
+        Ok(vec![
+            Command::Fax(FaxCommand::ReceiveHdlc, 3),
+            Command::FaxRange(FaxCommand::ReceiveData),
+            Command::Fax(FaxCommand::TransmitData, 96)
+        ])
+    );
+    assert_eq!(
+        parse("+FTS=8;+FRS=20"),
+        Ok(vec![Command::FaxSilence(8), Command::FaxWaitSilence(20)])
+    );
+    assert_eq!(parse("+FTH"), Err(SyntaxError));
+    assert_eq!(parse("+FRH=x"), Err(SyntaxError));
+}
+
+#[test]
+fn at_parse_caller_id() {
+    assert_eq!(
+        parse("+VCID=1;+VCID?;+VCID=?"),
+        Ok(vec![
+            Command::CallerId(1),
+            Command::QueryCallerId,
+            Command::CallerIdRange
+        ])
+    );
+    assert_eq!(parse("+VCID"), Err(SyntaxError));
+}
+
+#[test]
+fn at_interpreter_line() {
+    let mut at = AtInterpreter::new();
+    let mut to_dte = vec![];
+    let mut res = None;
+    for b in b"xx\rat+ms=v21\x08\x0823\r" {
+        res = at.put_byte(*b, &mut to_dte).or(res);
+    }
+    assert_eq!(res, Some(Ok(vec![Command::Modulation("V23".to_string())])));
+    assert_eq!(to_dte, b"xx\rat+ms=v21\x08\x0823\r");
+    assert_eq!(at.result(ResultCode::Ok), b"\r\nOK\r\n");
+    at.verbose = false;
+    assert_eq!(at.result(ResultCode::NoCarrier), b"3\r");
+}
+
+#[test]
+fn at_escape_guard_time() {
+    let guard = Duration::from_secs(1);
+    let t0 = Instant::now();
+    let ms = |ms| t0 + Duration::from_millis(ms);
+
+    let mut escape = EscapeDetector::new(guard);
+    escape.put_byte(b'a', ms(0));
+    escape.put_byte(b'+', ms(100));
+    escape.put_byte(b'+', ms(200));
+    escape.put_byte(b'+', ms(300));
+    assert!(!escape.poll(ms(2000)), "no guard time before escape");
+
+    let mut escape = EscapeDetector::new(guard);
+    escape.put_byte(b'a', ms(0));
+    escape.put_byte(b'+', ms(1500));
+    escape.put_byte(b'+', ms(1600));
+    escape.put_byte(b'+', ms(1700));
+    assert!(
+        !escape.poll(ms(2000)),
+        "guard time after escape not elapsed"
+    );
+    assert!(escape.poll(ms(2700)));
+    assert!(!escape.poll(ms(3000)));
+
+    let mut escape = EscapeDetector::new(guard);
+    escape.put_byte(b'+', ms(0));
+    escape.put_byte(b'+', ms(100));
+    escape.put_byte(b'+', ms(200));
+    escape.put_byte(b'+', ms(300));
+    assert!(!escape.poll(ms(2000)), "too many plus signs");
+}
+
+#[test]
+fn at_escape_registers() {
+    let t0 = Instant::now();
+    let ms = |ms| t0 + Duration::from_millis(ms);
+    let mut at = AtInterpreter::new();
+    at.registers[S_ESCAPE] = b'%';
+    at.registers[S_GUARD_TIME] = 10;
+
+    // 200 ms guard time
+    let mut escape = EscapeDetector::from_registers(&at.registers);
+    escape.put_byte(b'%', ms(300));
+    escape.put_byte(b'%', ms(400));
+    escape.put_byte(b'%', ms(500));
+    assert!(!escape.poll(ms(600)));
+    assert!(escape.poll(ms(700)));
+
+    let mut escape = EscapeDetector::from_registers(&at.registers);
+    for (i, byte) in b"+++".iter().enumerate() {
+        escape.put_byte(*byte, ms(300 + 100 * i as u64));
+    }
+    assert!(!escape.poll(ms(2000)));
+
+    // above 127, no escape at all
+    at.registers[S_ESCAPE] = 200;
+    let mut escape = EscapeDetector::from_registers(&at.registers);
+    for i in 0..3 {
+        escape.put_byte(200, ms(300 + 100 * i));
+    }
+    assert!(!escape.poll(ms(2000)));
+}
@@ -0,0 +1,52 @@
+use modem::ax25::{Address, Frame, CONTROL_UI, PID_NO_LAYER3};
+use modem::kiss::{self, Command, KissDecoder, FEND, FESC};
+
+#[test]
+fn ax25_aprs_frame() {
+    let frame = Frame::ui(
+        "PY2ABC-9".parse().unwrap(),
+        "APRS".parse().unwrap(),
+        vec!["WIDE1-1*".parse().unwrap(), "WIDE2-1".parse().unwrap()],
+        b"!2330.00S/04637.00W>test",
+    );
+    let bytes = frame.encode();
+    assert_eq!(&bytes[..7], b"\x82\xa0\xa4\xa6\x40\x40\x60");
+    assert_eq!(bytes[27] & 1, 1);
+    assert_eq!(&bytes[28..30], &[CONTROL_UI, PID_NO_LAYER3]);
+
+    let parsed = Frame::parse(&bytes).unwrap();
+    assert_eq!(parsed, frame);
+    assert_eq!(
+        parsed.to_string(),
+        "PY2ABC-9>APRS,WIDE1-1*,WIDE2-1:!2330.00S/04637.00W>test"
+    );
+}
+
+#[test]
+fn ax25_rejects_malformed_frames() {
+    assert!(Frame::parse(b"\x82\xa0\xa4\xa6\x40\x40\x61").is_err());
+    assert!(Frame::parse(&[0x82; 40]).is_err());
+    assert!("TOOLONGCALL".parse::<Address>().is_err());
+    assert!("N0CALL-16".parse::<Address>().is_err());
+}
+
+#[test]
+fn kiss_roundtrip() {
+    let frame = vec![0x01, FEND, 0x02, FESC, 0x03];
+    let mut bytes = vec![];
+    kiss::encode(&frame, &mut bytes);
+    assert_eq!(
+        bytes,
+        vec![FEND, 0x00, 0x01, FESC, 0xdc, 0x02, FESC, 0xdd, 0x03, FEND]
+    );
+
+    let mut decoder = KissDecoder::new();
+    let mut host = vec![FEND, FEND, 0x01, 50];
+    host.extend(&bytes);
+    host.extend([FEND, 0xff, FEND]);
+    let commands: Vec<Command> = host.iter().filter_map(|b| decoder.put_byte(*b)).collect();
+    assert_eq!(
+        commands,
+        vec![Command::TxDelay(50), Command::Data(frame), Command::Return]
+    );
+}
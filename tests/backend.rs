@@ -0,0 +1,50 @@
+use crossbeam_channel::unbounded;
+use modem::backend::{DteBackend, DteChannels, MemoryBackend};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[test]
+fn memory_backend() {
+    let (modem_to_dte, from_modem) = unbounded();
+    let (to_modem, modem_from_dte) = unbounded();
+    let (dtr, modem_dtr) = unbounded();
+    let cts = Arc::new(AtomicBool::new(true));
+    let (app_to_modem, from_dte) = unbounded();
+    let (to_dte, app_from_modem) = unbounded();
+    let (app_dtr, dtr_from_app) = unbounded();
+    let mut backend = MemoryBackend::new(
+        DteChannels {
+            from_modem,
+            to_modem,
+            dtr,
+            cts: cts.clone(),
+            flow_control: unbounded().1,
+        },
+        from_dte,
+        to_dte,
+        dtr_from_app,
+    );
+    let handle = std::thread::spawn(move || backend.event_loop());
+    let timeout = Duration::from_secs(1);
+
+    app_to_modem.send(b'A').unwrap();
+    assert_eq!(modem_from_dte.recv_timeout(timeout), Ok(b'A'));
+    modem_to_dte.send(b'B').unwrap();
+    assert_eq!(app_from_modem.recv_timeout(timeout), Ok(b'B'));
+    app_dtr.send(false).unwrap();
+    assert_eq!(modem_dtr.recv_timeout(timeout), Ok(false));
+
+    // held while CTS is off
+    cts.store(false, Ordering::Relaxed);
+    std::thread::sleep(Duration::from_millis(50));
+    app_to_modem.send(b'C').unwrap();
+    assert!(modem_from_dte
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+    cts.store(true, Ordering::Relaxed);
+    assert_eq!(modem_from_dte.recv_timeout(timeout), Ok(b'C'));
+
+    drop(app_to_modem);
+    handle.join().unwrap().unwrap();
+}
@@ -0,0 +1,84 @@
+use modem::baudot::{BaudotDecoder, BaudotEncoder, Variant, FIGS, LTRS};
+use modem::uart::UartTx;
+
+#[test]
+fn baudot_shifts() {
+    let mut encoder = BaudotEncoder::new(Variant::UsTty, false);
+    let mut codes = vec![];
+    for b in b"ry 73 de\r\n" {
+        encoder.encode(*b, &mut codes);
+    }
+    assert_eq!(
+        codes,
+        vec![LTRS, 0x0a, 0x15, 0x04, FIGS, 0x07, 0x01, 0x04, LTRS, 0x09, 0x01, 0x08, 0x02]
+    );
+}
+
+#[test]
+fn baudot_roundtrip() {
+    for variant in [Variant::Ita2, Variant::UsTty] {
+        for unshift_on_space in [false, true] {
+            let mut encoder = BaudotEncoder::new(variant, unshift_on_space);
+            let mut decoder = BaudotDecoder::new(variant, unshift_on_space);
+            let msg = b"THE QUICK BROWN FOX 1234567890 (-?:,./) JUMPS\r\n";
+
+            let mut codes = vec![];
+            for b in msg {
+                encoder.encode(*b, &mut codes);
+            }
+            let decoded: Vec<u8> = codes.iter().filter_map(|c| decoder.decode(*c)).collect();
+            assert_eq!(decoded, msg);
+        }
+    }
+}
+
+#[test]
+fn baudot_unshift_on_space() {
+    let mut encoder = BaudotEncoder::new(Variant::Ita2, true);
+    let mut codes = vec![];
+    for b in b"1 2" {
+        encoder.encode(*b, &mut codes);
+    }
+    assert_eq!(codes, vec![FIGS, 0x17, 0x04, FIGS, 0x13]);
+
+    let mut decoder = BaudotDecoder::new(Variant::Ita2, false);
+    let decoded: Vec<u8> = [FIGS, 0x17, 0x04, 0x13]
+        .iter()
+        .filter_map(|c| decoder.decode(*c))
+        .collect();
+    assert_eq!(decoded, b"1 2");
+
+    let mut decoder = BaudotDecoder::new(Variant::Ita2, true);
+    let decoded: Vec<u8> = [FIGS, 0x17, 0x04, 0x13]
+        .iter()
+        .filter_map(|c| decoder.decode(*c))
+        .collect();
+    assert_eq!(decoded, b"1 W");
+}
+
+#[test]
+fn baudot_variants() {
+    let mut ita2 = BaudotEncoder::new(Variant::Ita2, false);
+    let mut us_tty = BaudotEncoder::new(Variant::UsTty, false);
+    let (mut a, mut b) = (vec![], vec![]);
+    ita2.encode(b'$', &mut a);
+    us_tty.encode(b'$', &mut b);
+    assert_eq!(a, vec![]);
+    assert_eq!(b, vec![FIGS, 0x09]);
+}
+
+#[test]
+fn uart_tx_five_bits_one_and_a_half_stop_bits() {
+    let mut uart_tx = UartTx::with_framing(10, 5, 1.5);
+    uart_tx.put_byte(0b10110);
+
+    let mut samples = vec![0; 80];
+    uart_tx.get_samples(&mut samples);
+
+    let mut expected = vec![];
+    for bit in [0, 0, 1, 1, 0, 1] {
+        expected.extend(std::iter::repeat_n(bit, 10));
+    }
+    expected.extend(std::iter::repeat_n(1, 20));
+    assert_eq!(samples, expected);
+}
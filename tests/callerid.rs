@@ -0,0 +1,102 @@
+use modem::callerid::{Absence, CallerId, CallerIdRx, MessageDecoder, Variant, MDMF};
+use modem::dtmf::{DtmfRX, DtmfTX};
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+
+/// Appends the checksum which makes the bytes of a message add up to zero.
+fn with_checksum(mut message: Vec<u8>) -> Vec<u8> {
+    let sum = message.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+    message.push(sum.wrapping_neg());
+    message
+}
+
+#[test]
+fn callerid_sdmf() {
+    let mut message = vec![0x04, 0x12];
+    message.extend(b"093012246095551212");
+    message.push(0x51);
+
+    let caller_id = CallerId::parse(&message).unwrap();
+    assert_eq!(caller_id.date_time.as_deref(), Some("09301224"));
+    assert_eq!(caller_id.number.as_deref(), Some("6095551212"));
+    assert_eq!(
+        caller_id.formatted(),
+        "DATE = 0930\r\nTIME = 1224\r\nNMBR = 6095551212"
+    );
+    assert_eq!(
+        caller_id.unformatted(),
+        "MESG = 041230393330313232343630393535353132313251"
+    );
+
+    message[5] ^= 1;
+    assert!(CallerId::parse(&message).is_err());
+}
+
+#[test]
+fn callerid_mdmf() {
+    let mut body = vec![0x01, 8];
+    body.extend(b"03211405");
+    body.extend([0x04, 1, b'P', 0x07, 8]);
+    body.extend(b"JOHN DOE");
+    let mut message = vec![MDMF, body.len() as u8];
+    message.extend(&body);
+    let message = with_checksum(message);
+
+    // the message follows the channel seizure, received as 0x55 bytes
+    let mut decoder = MessageDecoder::new();
+    let messages: Vec<Vec<u8>> = [0x55; 30]
+        .iter()
+        .chain(&message)
+        .filter_map(|b| decoder.put_byte(*b))
+        .collect();
+    assert_eq!(messages, vec![message]);
+
+    let caller_id = CallerId::parse(&messages[0]).unwrap();
+    assert_eq!(caller_id.number, None);
+    assert_eq!(caller_id.number_absence, Some(Absence::Private));
+    assert_eq!(
+        caller_id.formatted(),
+        "DATE = 0321\r\nTIME = 1405\r\nNMBR = P\r\nNAME = JOHN DOE"
+    );
+
+    let truncated = with_checksum(vec![MDMF, 3, 0x02, 5, b'5']);
+    assert!(CallerId::parse(&truncated).is_err());
+}
+
+#[test]
+fn callerid_dtmf() {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(42);
+    let noise = Normal::new(0., 0.01).unwrap();
+
+    for srate in [44100, 48000] {
+        let mut tx = DtmfTX::new(1. / srate as f32);
+        tx.put_digits("A0800551234C");
+        let mut samples = vec![0.; srate / 10];
+        let mut chunk = vec![0.; 512];
+        while !tx.is_idle() {
+            tx.modulate(&mut chunk);
+            samples.extend(&chunk);
+        }
+        for x in samples.iter_mut() {
+            *x += noise.sample(&mut gen);
+        }
+
+        let mut dtmf_rx = DtmfRX::new(1. / srate as f32);
+        let mut digits = vec![];
+        dtmf_rx.demodulate(&samples, &mut digits);
+        assert_eq!(digits.iter().collect::<String>(), "A0800551234C");
+
+        let mut caller_id_rx = CallerIdRx::new(Variant::Dtmf, srate).unwrap();
+        let mut received = vec![];
+        for chunk in samples.chunks(1024) {
+            caller_id_rx.put_samples(chunk, &mut received);
+        }
+        let caller_id = received.pop().unwrap().unwrap();
+        assert!(received.is_empty());
+        assert_eq!(caller_id.number.as_deref(), Some("0800551234"));
+    }
+
+    let caller_id = CallerId::parse_dtmf("B10C").unwrap();
+    assert_eq!(caller_id.number_absence, Some(Absence::Private));
+    assert!(CallerId::parse_dtmf("A123").is_err());
+}
@@ -0,0 +1,92 @@
+use modem::carrier::{CarrierDetector, CarrierWatch};
+use modem::fsk::FskProfile;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+const SRATE: usize = 48000;
+
+fn tone(dbfs: f32, seconds: f32) -> Vec<f32> {
+    let amplitude = 10f32.powf(dbfs / 20.);
+    (0..(seconds * SRATE as f32) as usize)
+        .map(|n| amplitude * (2. * PI * 1650. * n as f32 / SRATE as f32).sin())
+        .collect()
+}
+
+#[test]
+fn carrier_hysteresis() {
+    let profile = FskProfile::v21();
+    let mut detector = CarrierDetector::new(
+        1. / SRATE as f32,
+        profile.carrier_on_dbfs,
+        profile.carrier_off_dbfs,
+    );
+    let mut carrier = |samples: Vec<f32>| {
+        detector.put_samples(&samples);
+        detector.carrier()
+    };
+
+    assert!(!carrier(tone(-43., 0.1)));
+    assert!(carrier(tone(-20., 0.1)));
+    // between the two thresholds the state is kept
+    assert!(carrier(tone(-43., 0.1)));
+    assert!(!carrier(vec![0.; SRATE / 10]));
+    assert!(!carrier(tone(-43., 0.1)));
+}
+
+#[test]
+fn carrier_loss_first_session() {
+    // a call in progress from the start, with no previous hang-up
+    let profile = FskProfile::v21();
+    let mut watch = CarrierWatch::default();
+    assert!(watch.update(true, profile.carrier_on_demand, Instant::now()));
+    let mut detector = CarrierDetector::new(
+        1. / SRATE as f32,
+        profile.carrier_on_dbfs,
+        profile.carrier_off_dbfs,
+    );
+    let start = Instant::now();
+    let mut now = start;
+    for block in [tone(-20., 1.), vec![0.; 2 * SRATE]] {
+        for chunk in block.chunks(SRATE / 10) {
+            let carrier = detector.carrier();
+            detector.put_samples(chunk);
+            if detector.carrier() != carrier {
+                watch.put_carrier(detector.carrier(), now);
+            }
+            now += Duration::from_millis(100);
+        }
+    }
+    // S10 defaults to 1.4 s
+    assert!(!watch.expired(14, start + Duration::from_millis(2000)));
+    assert!(watch.expired(14, now));
+    assert!(!watch.expired(255, now));
+
+    // the carrier coming back in time keeps the call
+    watch.put_carrier(true, now);
+    assert!(!watch.expired(14, now + Duration::from_secs(10)));
+
+    // nor are carrier-on-demand profiles watched
+    assert!(!watch.update(true, FskProfile::tdd().carrier_on_demand, now));
+    watch.put_carrier(false, now);
+    assert!(!watch.expired(14, now + Duration::from_secs(10)));
+}
+
+#[test]
+fn carrier_loss_silent_call() {
+    // the far end never sends its carrier
+    let profile = FskProfile::v21();
+    let mut watch = CarrierWatch::default();
+    let start = Instant::now();
+    assert!(watch.update(true, profile.carrier_on_demand, start));
+    // later updates do not restart the wait
+    let now = start + Duration::from_secs(1);
+    assert!(watch.update(true, profile.carrier_on_demand, now));
+    assert!(!watch.expired(14, now));
+    assert!(watch.expired(14, start + Duration::from_millis(1400)));
+
+    // a new call starts the wait anew
+    assert!(!watch.update(false, profile.carrier_on_demand, now));
+    assert!(watch.update(true, profile.carrier_on_demand, now));
+    assert!(!watch.expired(14, now + Duration::from_secs(1)));
+    assert!(watch.expired(14, now + Duration::from_secs(2)));
+}
@@ -0,0 +1,38 @@
+use modem::class1::{self, DleDecoder, DLE, ETX};
+
+#[test]
+fn class1_dle_escaping() {
+    let frame = [0xff, 0x13, DLE, 0x80, DLE, DLE, ETX];
+    let mut bytes = vec![];
+    class1::encode(&frame, &mut bytes);
+    assert_eq!(
+        bytes,
+        vec![0xff, 0x13, DLE, DLE, 0x80, DLE, DLE, DLE, DLE, ETX, DLE, ETX]
+    );
+
+    let mut decoder = DleDecoder::new();
+    let frames: Vec<Vec<u8>> = bytes
+        .iter()
+        .chain(&[0x01, DLE, ETX])
+        .filter_map(|b| decoder.put_byte(*b))
+        .collect();
+    assert_eq!(frames, vec![frame.to_vec(), vec![0x01]]);
+
+    // image data is consumed as it arrives
+    let mut data = vec![];
+    class1::escape(&[0x00, DLE], &mut data);
+    assert_eq!(data, vec![0x00, DLE, DLE]);
+    let mut decoder = DleDecoder::new();
+    assert_eq!(decoder.put_byte(data[0]), None);
+    assert_eq!(decoder.put_byte(data[1]), None);
+    assert_eq!(decoder.take_data(), vec![0x00]);
+    assert_eq!(decoder.put_byte(data[2]), None);
+    assert_eq!(decoder.put_byte(DLE), None);
+    assert_eq!(decoder.put_byte(ETX), Some(vec![DLE]));
+}
+
+#[test]
+fn class1_range() {
+    assert_eq!(class1::range(class1::HDLC_MODULATIONS), "3");
+    assert_eq!(class1::range(class1::DATA_MODULATIONS), "24,48,72,96");
+}
@@ -0,0 +1,68 @@
+use modem::flow::{self, FlowControl, Watermarks};
+use modem::uart::UartTx;
+
+#[test]
+fn flow_control_codes() {
+    assert_eq!(FlowControl::from_code(0), Some(FlowControl::None));
+    assert_eq!(FlowControl::from_code(3), Some(FlowControl::RtsCts));
+    assert_eq!(FlowControl::from_code(4), Some(FlowControl::XonXoff));
+    assert_eq!(FlowControl::from_code(1), None);
+}
+
+#[test]
+fn flow_watermarks() {
+    let mut watermarks = Watermarks::new(64, 16);
+    assert_eq!(watermarks.update(64), None);
+    assert_eq!(watermarks.update(65), Some(true));
+    assert_eq!(watermarks.update(80), None);
+    // still stopped between the two watermarks
+    assert_eq!(watermarks.update(17), None);
+    assert!(watermarks.stopped());
+    assert_eq!(watermarks.update(16), Some(false));
+    assert_eq!(watermarks.update(0), None);
+}
+
+/// Queues what a host which never stops sends while nothing reaches the
+/// line, returning how much got queued and whether it was told to stop.
+fn ignore_xoff(flow_control: FlowControl, watermarks: &mut Watermarks) -> (usize, bool) {
+    let mut pending = 0;
+    let mut stopped = false;
+    for _ in 0..1000 {
+        if watermarks.admit(pending, flow_control) {
+            pending += 1;
+        }
+        stopped |= watermarks.update(pending) == Some(true);
+    }
+    (pending, stopped)
+}
+
+#[test]
+fn flow_host_ignores_xoff() {
+    // what comes beyond the slack is dropped, and counted
+    let mut watermarks = Watermarks::new(64, 16);
+    let (pending, stopped) = ignore_xoff(FlowControl::XonXoff, &mut watermarks);
+    assert!(stopped);
+    assert_eq!(pending, 64 + flow::SLACK);
+    assert_eq!(watermarks.take_overruns(), 1000 - pending);
+    assert_eq!(watermarks.take_overruns(), 0);
+
+    // without flow control nothing is lost
+    let mut watermarks = Watermarks::new(64, 16);
+    let (pending, _) = ignore_xoff(FlowControl::None, &mut watermarks);
+    assert_eq!(pending, 1000);
+    assert_eq!(watermarks.take_overruns(), 0);
+}
+
+#[test]
+fn uart_tx_pending_bytes() {
+    let mut uart_tx = UartTx::new(160);
+    for byte in b"abc" {
+        uart_tx.put_byte(*byte);
+    }
+    assert_eq!(uart_tx.pending_bytes(), 3);
+    let mut samples = vec![0; 1610];
+    uart_tx.get_samples(&mut samples[..10]);
+    assert_eq!(uart_tx.pending_bytes(), 3);
+    uart_tx.get_samples(&mut samples[10..]);
+    assert_eq!(uart_tx.pending_bytes(), 2);
+}
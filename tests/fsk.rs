@@ -0,0 +1,124 @@
+use modem::afsk::AfskRX;
+use modem::fsk::{FskChannel, FskProfile, Role};
+use modem::v21::V21TX;
+
+#[test]
+fn fsk_presets_roles() {
+    let presets = FskProfile::presets();
+
+    let v21 = FskProfile::find(&presets, "v21").unwrap();
+    assert_eq!(v21.tx_channel(Role::Originate).mark, 980.);
+    assert_eq!(v21.rx_channel(Role::Originate).space, 1850.);
+    assert_eq!(
+        v21.tx_channel(Role::Answer),
+        v21.rx_channel(Role::Originate)
+    );
+
+    let v23 = FskProfile::find(&presets, "V23").unwrap();
+    assert_eq!(v23.tx_channel(Role::Originate).baud_rate, 75.);
+    assert_eq!(v23.rx_channel(Role::Originate).baud_rate, 1200.);
+
+    let bell202 = FskProfile::find(&presets, "Bell202").unwrap();
+    assert_eq!(bell202.originate, bell202.answer);
+
+    assert!(FskProfile::find(&presets, "V34").is_none());
+}
+
+#[test]
+fn fsk_samples_per_symbol() {
+    let v21 = FskProfile::v21();
+    assert_eq!(v21.originate.samples_per_symbol(48000).unwrap(), 160);
+    assert_eq!(v21.originate.samples_per_symbol(44100).unwrap(), 147);
+
+    let rtty = FskProfile::rtty();
+    assert_eq!(rtty.originate.samples_per_symbol(48000).unwrap(), 1056);
+    assert_eq!(rtty.originate.samples_per_symbol(44100).unwrap(), 970);
+
+    assert!(FskChannel::new(1300., 2100., 1100.)
+        .samples_per_symbol(8000)
+        .is_err());
+}
+
+#[test]
+fn fsk_text_telephone_framing() {
+    // 1 start, 7 data, even parity and 2 stop bits, 10 characters per second
+    let edt = FskProfile::edt();
+    let bits = 1. + edt.charset.data_bits() as f32 + edt.charset.stop_bits();
+    assert_eq!(edt.originate.baud_rate / bits, 10.);
+
+    let tdd = FskProfile::tdd();
+    assert_eq!(tdd.charset.data_bits(), 5);
+    assert_eq!(tdd.charset.stop_bits(), 1.5);
+}
+
+#[test]
+fn fsk_role_switch() {
+    let srate = 48000;
+    let speriod = 1. / srate as f32;
+    let v21 = FskProfile::v21();
+    let (originate, answer) = (
+        v21.tx_channel(Role::Originate),
+        v21.tx_channel(Role::Answer),
+    );
+    let samples_per_symbol = originate.samples_per_symbol(srate).unwrap();
+    let bits: Vec<u8> = (0..4 * samples_per_symbol)
+        .map(|i| (i / samples_per_symbol % 2) as u8)
+        .collect();
+
+    let mut v21_tx = V21TX::new(speriod, originate.omega_mark(), originate.omega_space());
+    let mut samples = vec![0.; 2 * bits.len()];
+    v21_tx.modulate(&bits, &mut samples[..bits.len()]);
+    v21_tx.set_omegas(answer.omega_mark(), answer.omega_space());
+    v21_tx.modulate(&bits, &mut samples[bits.len()..]);
+
+    // no phase jump where the tones change
+    let max_step = answer.omega_space() * speriod;
+    assert!(samples.windows(2).all(|w| (w[1] - w[0]).abs() <= max_step));
+
+    let mut afsk_rx = AfskRX::new(
+        speriod,
+        samples_per_symbol,
+        originate.omega_mark(),
+        originate.omega_space(),
+    );
+    let mut levels = vec![0; bits.len()];
+    afsk_rx.demodulate(&samples[..bits.len()], &mut levels);
+    afsk_rx.set_omegas(answer.omega_mark(), answer.omega_space());
+    afsk_rx.demodulate(&samples[bits.len()..], &mut levels);
+    // after a symbol, the level follows the bits sent with the new tones
+    for (i, (level, bit)) in levels.iter().zip(&bits).enumerate() {
+        if i % samples_per_symbol >= samples_per_symbol / 2 && i >= samples_per_symbol {
+            assert_eq!(level, bit, "sample {}", i);
+        }
+    }
+}
+
+#[test]
+fn fsk_profiles_from_toml() {
+    let profiles = FskProfile::from_toml(
+        r#"
+        [[profile]]
+        name = "custom"
+        carrier_on_dbfs = -30.0
+        originate = { mark = 1270.0, space = 1070.0, baud_rate = 110.0 }
+        answer = { mark = 2225.0, space = 2025.0, baud_rate = 110.0 }
+
+        [[profile]]
+        name = "half"
+        originate = { mark = 1200.0, space = 2200.0, baud_rate = 600.0 }
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(profiles.len(), 2);
+    assert_eq!(profiles[0].name, "CUSTOM");
+    assert_eq!(profiles[0].carrier_on_dbfs, -30.);
+    assert_eq!(profiles[0].answer.mark, 2225.);
+    assert_eq!(profiles[1].answer, profiles[1].originate);
+    assert_eq!(
+        profiles[1].carrier_off_dbfs,
+        FskProfile::v21().carrier_off_dbfs
+    );
+
+    assert!(FskProfile::from_toml("[[profile]]\nname = \"x\"\n").is_err());
+}
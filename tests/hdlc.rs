@@ -0,0 +1,115 @@
+use crossbeam_channel::unbounded;
+use modem::afsk::AfskRX;
+use modem::fsk::{FskProfile, Role};
+use modem::hdlc::{self, FcsError, HdlcDecoder, HdlcRx, HdlcTx};
+use modem::v21::V21TX;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+
+fn decode(bits: &[u8]) -> Vec<hdlc::Frame> {
+    let mut decoder = HdlcDecoder::new();
+    bits.iter().filter_map(|b| decoder.put_bit(*b)).collect()
+}
+
+#[test]
+fn hdlc_fcs() {
+    assert_eq!(hdlc::fcs(b"123456789"), 0x906e);
+}
+
+#[test]
+fn hdlc_bit_stuffing() {
+    let mut bits = vec![];
+    hdlc::encode_frame(&[0xff, 0x7e, 0x3f], &mut bits);
+    // no more than five consecutive ones outside of flags
+    assert!(bits.windows(6).all(|w| w.contains(&0)));
+
+    let mut framed = vec![];
+    hdlc::encode_flags(2, &mut framed);
+    framed.extend(&bits);
+    hdlc::encode_flags(1, &mut framed);
+    assert_eq!(decode(&framed), vec![Ok(vec![0xff, 0x7e, 0x3f])]);
+}
+
+#[test]
+fn hdlc_reports_fcs_errors_and_aborts() {
+    let frame = b"\x03\x13hello".to_vec();
+    let mut bits = vec![];
+    hdlc::encode_flags(1, &mut bits);
+    hdlc::encode_frame(&frame, &mut bits);
+    hdlc::encode_flags(1, &mut bits);
+
+    // flip a data bit
+    let mut corrupted = bits.clone();
+    corrupted[20] ^= 1;
+    assert_eq!(
+        decode(&corrupted),
+        vec![Err(FcsError {
+            frame: {
+                let mut frame = frame.clone();
+                frame[1] ^= 1 << 4;
+                frame
+            }
+        })]
+    );
+
+    // an abort sequence in the middle of the frame discards it
+    let mut aborted = bits[..40].to_vec();
+    aborted.extend([1; 8]);
+    aborted.extend(&bits);
+    assert_eq!(decode(&aborted), vec![Ok(frame)]);
+}
+
+#[test]
+fn hdlc_afsk1200_roundtrip() {
+    let profile = FskProfile::ax25();
+    let channel = profile.tx_channel(Role::Originate);
+    let frames: Vec<Vec<u8>> = vec![
+        b"first frame, \xff\xff\x7e\x7e".to_vec(),
+        (0..=255).collect(),
+        b"last".to_vec(),
+    ];
+
+    for srate in [44100, 48000] {
+        let samples_per_symbol = channel.samples_per_symbol(srate).unwrap();
+        let mut hdlc_tx = HdlcTx::new(samples_per_symbol, true);
+        hdlc_tx.put_flags(30);
+        for frame in &frames {
+            hdlc_tx.put_frame(frame);
+            hdlc_tx.put_flags(2);
+        }
+        let mut levels = vec![0; hdlc_tx.pending_samples() + srate / 10];
+        hdlc_tx.get_samples(&mut levels);
+
+        let mut v21_tx = V21TX::new(
+            1. / srate as f32,
+            channel.omega_mark(),
+            channel.omega_space(),
+        );
+        let mut samples = vec![0.; levels.len()];
+        v21_tx.modulate(&levels, &mut samples);
+        let mut gen = rand_pcg::Pcg32::seed_from_u64(42);
+        let noise = Normal::new(0., 0.1).unwrap();
+        samples
+            .iter_mut()
+            .for_each(|x| *x = 0.5 * *x + noise.sample(&mut gen));
+
+        let (to_host, from_hdlc_rx) = unbounded();
+        let mut afsk_rx = AfskRX::new(
+            1. / srate as f32,
+            samples_per_symbol,
+            channel.omega_mark(),
+            channel.omega_space(),
+        );
+        let mut hdlc_rx = HdlcRx::new(samples_per_symbol, true, to_host);
+        for chunk in samples.chunks(512) {
+            let mut demodulated = vec![0; chunk.len()];
+            afsk_rx.demodulate(chunk, &mut demodulated);
+            hdlc_rx.put_samples(&demodulated);
+        }
+        drop(hdlc_rx);
+
+        let received: Vec<hdlc::Frame> = from_hdlc_rx.iter().collect();
+        let expected: Vec<hdlc::Frame> = frames.iter().cloned().map(Ok).collect();
+        assert_eq!(received, expected, "at {} Hz", srate);
+    }
+}
@@ -0,0 +1,77 @@
+use modem::kcs::{KcsFormat, KcsRX, KcsTX};
+use modem::uart::UartTx;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+
+fn uart_bits(srate: usize, format: KcsFormat, msg: &[u8]) -> (usize, Vec<u8>) {
+    let samples_per_symbol = srate / format.baud_rate();
+    let mut uart_tx =
+        UartTx::with_framing(samples_per_symbol, format.data_bits(), format.stop_bits());
+    uart_tx.put_idle(30 * samples_per_symbol);
+    for b in msg {
+        uart_tx.put_byte(*b);
+    }
+    let mut bits = vec![1; uart_tx.pending_samples() + srate / 10];
+    uart_tx.get_samples(&mut bits);
+    (samples_per_symbol, bits)
+}
+
+#[test]
+fn kcs_whole_cycles_per_bit() {
+    let srate = 48000;
+    for (format, cycles) in [(KcsFormat::Kcs, [4, 8]), (KcsFormat::Cuts, [1, 2])] {
+        let (samples_per_symbol, bits) = uart_bits(srate, format, b"\x5a");
+        let mut kcs_tx = KcsTX::new(1. / srate as f32);
+        let mut samples = vec![0.; bits.len()];
+        kcs_tx.modulate(&bits, &mut samples);
+
+        for (bit, symbol) in bits
+            .chunks(samples_per_symbol)
+            .zip(samples.chunks(samples_per_symbol))
+        {
+            // rising zero crossings, plus the one that starts the symbol
+            let rising = symbol
+                .windows(2)
+                .filter(|w| w[0] < 0. && w[1] >= 0.)
+                .count();
+            assert_eq!(rising + 1, cycles[bit[0] as usize], "{:?}", format);
+        }
+    }
+}
+
+#[test]
+fn kcs_roundtrip_bits() {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(42);
+    let noise = Normal::new(0., 0.05).unwrap();
+
+    for srate in [44100, 48000] {
+        for format in [KcsFormat::Kcs, KcsFormat::Cuts] {
+            let (samples_per_symbol, bits) = uart_bits(srate, format, b"KCS \x00\xff\x55");
+            let mut kcs_tx = KcsTX::new(1. / srate as f32);
+            let mut samples = vec![0.; bits.len()];
+            kcs_tx.modulate(&bits, &mut samples);
+            // tape playback level and hiss
+            samples
+                .iter_mut()
+                .for_each(|x| *x = 0.3 * *x + noise.sample(&mut gen));
+
+            let mut kcs_rx = KcsRX::new(1. / srate as f32);
+            let mut demodulated = vec![0; samples.len()];
+            kcs_rx.demodulate(&samples, &mut demodulated);
+
+            // the decision is taken at the end of each half cycle, so sample
+            // each symbol close to its end
+            let delay = samples_per_symbol * 3 / 4;
+            for i in (0..bits.len() - delay).step_by(samples_per_symbol) {
+                assert_eq!(
+                    demodulated[i + delay],
+                    bits[i],
+                    "{:?} at {} Hz, sample {}",
+                    format,
+                    srate,
+                    i
+                );
+            }
+        }
+    }
+}
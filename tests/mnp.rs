@@ -0,0 +1,323 @@
+use modem::fsk::Role;
+use modem::mnp::{self, FrameDecoder, Mnp, MnpConfig};
+use modem::v42::Event;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const STEP: Duration = Duration::from_millis(10);
+
+/// Two ends of a call, with a line which loses or damages whatever the
+/// closure given to [`Call::run`] tells it to.
+struct Call {
+    ends: [Mnp; 2],
+    now: Instant,
+    events: [Vec<Event>; 2],
+    received: [Vec<u8>; 2],
+    /// What each end sent, one frame at a time, unframed
+    sent: [Vec<Vec<u8>>; 2],
+}
+
+impl Call {
+    fn new(originator: MnpConfig, answerer: MnpConfig) -> Self {
+        let now = Instant::now();
+        let mut ends = [
+            Mnp::new(Role::Originate, originator),
+            Mnp::new(Role::Answer, answerer),
+        ];
+        for end in &mut ends {
+            end.start(now);
+        }
+        Self {
+            ends,
+            now,
+            events: [vec![], vec![]],
+            received: [vec![], vec![]],
+            sent: [vec![], vec![]],
+        }
+    }
+
+    fn event(&mut self, end: usize, event: Option<Event>) {
+        match event {
+            Some(Event::Data(data)) => self.received[end].extend(data),
+            Some(event) => self.events[end].push(event),
+            None => {}
+        }
+    }
+
+    /// Delivers a frame whole in synchronous mode, and byte by byte
+    /// otherwise.
+    fn run(&mut self, duration: Duration, line: &mut impl FnMut(usize, &mut Vec<u8>)) {
+        let end_time = self.now + duration;
+        while self.now < end_time {
+            for end in 0..2 {
+                let sync = self.ends[end].is_sync();
+                let mut out = vec![];
+                let event = self.ends[end].poll(self.now, &mut out);
+                self.event(end, event);
+                // replies are answered in turn
+                let mut chunks = VecDeque::from([(end, sync, out)]);
+                while let Some((from, sync, mut chunk)) = chunks.pop_front() {
+                    if chunk.is_empty() {
+                        continue;
+                    }
+                    self.sent[from].push(if sync { chunk.clone() } else { decode(&chunk) });
+                    line(from, &mut chunk);
+                    let to = 1 - from;
+                    let mut reply = vec![];
+                    let reply_sync = self.ends[to].is_sync();
+                    if sync {
+                        let event = self.ends[to].put_frame(&chunk, self.now, &mut reply);
+                        self.event(to, event);
+                    } else {
+                        for byte in chunk {
+                            let event = self.ends[to].put_byte(byte, self.now, &mut reply);
+                            self.event(to, event);
+                        }
+                    }
+                    chunks.push_back((to, sync && reply_sync, reply));
+                }
+            }
+            self.now += STEP;
+        }
+    }
+
+    /// Counts the LPDU of a type an end sent.
+    fn frames(&self, end: usize, kind: u8) -> usize {
+        self.sent[end]
+            .iter()
+            .filter(|frame| frame.get(1) == Some(&kind))
+            .count()
+    }
+}
+
+fn decode(bytes: &[u8]) -> Vec<u8> {
+    let mut decoder = FrameDecoder::new(1024);
+    bytes
+        .iter()
+        .find_map(|byte| decoder.put_byte(*byte))
+        .unwrap()
+}
+
+fn perfect_line(_: usize, _: &mut Vec<u8>) {}
+
+fn connected_call(originator: MnpConfig, answerer: MnpConfig) -> Call {
+    let mut call = Call::new(originator, answerer);
+    call.run(Duration::from_secs(2), &mut perfect_line);
+    assert_eq!(
+        call.events,
+        [vec![Event::Connected], vec![Event::Connected]]
+    );
+    assert!(call.ends.iter().all(|end| end.is_connected()));
+    call
+}
+
+fn class_2() -> MnpConfig {
+    MnpConfig {
+        sync: false,
+        optimized: false,
+        compression: false,
+        ..MnpConfig::default()
+    }
+}
+
+fn class_4() -> MnpConfig {
+    MnpConfig {
+        compression: false,
+        ..MnpConfig::default()
+    }
+}
+
+fn text(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 256) as u8).collect()
+}
+
+const LT: u8 = 4;
+const LA: u8 = 5;
+
+#[test]
+fn mnp_framing() {
+    // CRC-16/ARC
+    assert_eq!(mnp::crc16(b"123456789"), 0xbb3d);
+    let frame = [2, LT, 0x10, b'a', 0x10, 0x10];
+    let mut bytes = vec![];
+    mnp::encode(&frame, &mut bytes);
+    assert_eq!(&bytes[..3], [0x16, 0x10, 0x02]);
+    // each DLE doubled
+    assert_eq!(bytes.len(), 3 + frame.len() + 3 + 2 + 2);
+    let mut decoder = FrameDecoder::new(256);
+    let mut frames = vec![];
+    for byte in b"noise".iter().chain(&bytes).chain(&bytes) {
+        frames.extend(decoder.put_byte(*byte));
+    }
+    assert_eq!(frames, [frame.to_vec(), frame.to_vec()]);
+    bytes[4] ^= 1;
+    assert!(bytes.iter().all(|byte| decoder.put_byte(*byte).is_none()));
+}
+
+#[test]
+fn mnp_transfer() {
+    let mut call = connected_call(class_4(), class_4());
+    assert!(call.ends.iter().all(|end| end.is_sync()));
+    let data = text(3000);
+    call.ends[0].send(&data);
+    call.ends[1].send(b"hello");
+    assert_eq!(call.ends[0].pending(), 3000);
+    call.run(Duration::from_secs(1), &mut perfect_line);
+    assert_eq!(call.received[1], data);
+    assert_eq!(call.received[0], b"hello");
+    assert_eq!(call.ends[0].pending(), 0);
+    // 256 bytes per LT, with fixed fields
+    assert_eq!(call.frames(0, LT), 12);
+    assert!(call.sent[0].iter().any(|frame| frame[..2] == [2, LT]));
+    assert!(call.sent[1].iter().any(|frame| frame[..2] == [3, LA]));
+}
+
+#[test]
+fn mnp_class_2() {
+    let mut call = connected_call(class_2(), MnpConfig::default());
+    assert!(call.ends.iter().all(|end| !end.is_sync()));
+    call.ends[1].send(&text(1000));
+    call.run(Duration::from_secs(1), &mut perfect_line);
+    assert_eq!(call.received[0], text(1000));
+    // 64 bytes per LT, with parameters
+    assert_eq!(call.frames(1, LT), 16);
+    assert!(call.sent[1].iter().any(|frame| frame[..2] == [4, LT]));
+}
+
+#[test]
+fn mnp_class_5() {
+    let call = connected_call(MnpConfig::default(), class_2());
+    assert!(call.ends.iter().all(|end| !end.is_compressed()));
+
+    let mut call = connected_call(MnpConfig::default(), MnpConfig::default());
+    assert!(call.ends.iter().all(|end| end.is_compressed()));
+    let mut data = b"AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\r\n".repeat(50);
+    data.extend(b"a line of text over the modem\r\n".repeat(50));
+    call.ends[0].send(&data);
+    call.run(Duration::from_secs(1), &mut drop_frame(2));
+    assert_eq!(call.received[1], data);
+    let (sent, _) = call.ends[0].counters();
+    let (_, received) = call.ends[1].counters();
+    assert_eq!(sent, received);
+    assert_eq!(sent.characters, data.len() as u64);
+    assert!(sent.ratio() > 2., "{:?}", sent);
+}
+
+/// Drops the n-th LT sent by the originator.
+fn drop_frame(n: usize) -> impl FnMut(usize, &mut Vec<u8>) {
+    let mut count = 0;
+    move |from, chunk| {
+        if from == 0 && chunk.get(1) == Some(&LT) {
+            count += 1;
+            if count == n {
+                chunk.clear();
+            }
+        }
+    }
+}
+
+#[test]
+fn mnp_lost_frame() {
+    let mut call = connected_call(MnpConfig::default(), MnpConfig::default());
+    call.ends[0].send(&text(2048));
+    call.run(Duration::from_secs(1), &mut drop_frame(3));
+    assert_eq!(call.received[1], text(2048));
+    // the missing LT and those after it, smaller from then on
+    assert!(call.frames(0, LT) > 8);
+}
+
+#[test]
+fn mnp_lost_last_frame() {
+    let mut call = connected_call(class_4(), class_4());
+    call.ends[0].send(&text(300));
+    call.run(Duration::from_secs(1), &mut drop_frame(2));
+    assert_eq!(call.received[1], text(256));
+    // nothing follows to reveal the loss, until the timer expires
+    call.run(Duration::from_secs(30), &mut perfect_line);
+    assert_eq!(call.received[1], text(300));
+}
+
+#[test]
+fn mnp_noisy_line() {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(42);
+    let mut call = connected_call(class_2(), class_2());
+    let data = text(20000);
+    call.ends[0].send(&data);
+    call.ends[1].send(&data);
+    call.run(Duration::from_secs(300), &mut |_, chunk| {
+        for byte in chunk.iter_mut() {
+            if gen.gen_bool(1e-3) {
+                *byte ^= 1 << gen.gen_range(0..8);
+            }
+        }
+    });
+    assert_eq!(call.received, [data.clone(), data]);
+}
+
+#[test]
+fn mnp_busy() {
+    let mut call = connected_call(MnpConfig::default(), MnpConfig::default());
+    call.ends[1].set_busy(true);
+    call.run(Duration::from_secs(1), &mut perfect_line);
+    call.ends[0].send(b"while busy");
+    call.run(Duration::from_secs(30), &mut perfect_line);
+    assert!(call.received[1].is_empty());
+    call.ends[1].set_busy(false);
+    call.run(Duration::from_secs(1), &mut perfect_line);
+    assert_eq!(call.received[1], b"while busy");
+    assert!(call.events.iter().all(|events| events.len() == 1));
+}
+
+#[test]
+fn mnp_fallback() {
+    // a peer which does not answer LR
+    let mut call = Call::new(MnpConfig::default(), MnpConfig::default());
+    call.run(Duration::from_secs(15), &mut |from, chunk| {
+        if from == 0 {
+            chunk.clear();
+        }
+    });
+    assert_eq!(call.events[0], [Event::Fallback(vec![])]);
+    assert_eq!(call.sent[0].len(), 3);
+
+    // nor sends it, but data
+    let now = Instant::now();
+    let mut answerer = Mnp::new(Role::Answer, MnpConfig::default());
+    let mut out = vec![];
+    answerer.start(now);
+    for byte in b"hello" {
+        assert_eq!(answerer.put_byte(*byte, now, &mut out), None);
+    }
+    assert_eq!(answerer.poll(now + Duration::from_secs(1), &mut out), None);
+    assert_eq!(
+        answerer.poll(now + Duration::from_secs(10), &mut out),
+        Some(Event::Fallback(b"hello".to_vec()))
+    );
+    assert!(out.is_empty());
+}
+
+#[test]
+fn mnp_disconnect() {
+    let mut call = connected_call(MnpConfig::default(), MnpConfig::default());
+    call.ends[0].send(b"lost");
+    call.run(Duration::from_secs(300), &mut |_, chunk| chunk.clear());
+    assert_eq!(
+        call.events[0][1],
+        Event::Disconnected("no acknowledgement from the peer".to_string())
+    );
+    assert!(!call.ends[0].is_connected());
+
+    // the peer is told when it can hear
+    let mut call = connected_call(class_2(), class_2());
+    call.ends[0].send(b"lost");
+    call.run(Duration::from_secs(300), &mut |from, chunk| {
+        if from == 1 {
+            chunk.clear();
+        }
+    });
+    assert_eq!(
+        call.events[1][1],
+        Event::Disconnected("disconnected by the peer".to_string())
+    );
+}
@@ -0,0 +1,65 @@
+use modem::mnp5::{Decoder, Encoder};
+use rand::{Rng, SeedableRng};
+
+/// Sends `data` in blocks of `block` characters, returning the size of each
+/// block once compressed.
+fn round_trip(data: &[u8], block: usize) -> Vec<usize> {
+    let mut encoder = Encoder::new();
+    let mut decoder = Decoder::new();
+    let mut received = vec![];
+    let mut sizes = vec![];
+    for chunk in data.chunks(block) {
+        for character in chunk {
+            encoder.put(*character);
+        }
+        let compressed = encoder.finish();
+        sizes.push(compressed.len());
+        decoder.put_block(&compressed, &mut received);
+    }
+    assert_eq!(received, data);
+    assert_eq!(decoder.counters(), encoder.counters());
+    sizes
+}
+
+#[test]
+fn mnp5_text() {
+    let data = b"the quick brown fox jumps over the lazy dog\r\n".repeat(100);
+    let sizes = round_trip(&data, 200);
+    // the frequent characters get shorter codes as the table adapts
+    assert!(sizes.last().unwrap() * 10 < 200 * 7, "{:?}", sizes);
+    assert!(sizes.iter().sum::<usize>() < data.len() * 3 / 4);
+}
+
+#[test]
+fn mnp5_runs() {
+    let mut data = vec![0; 1000];
+    data.extend(b"abc");
+    data.extend([b' '; 3]);
+    data.extend([b'x'; 4]);
+    data.extend([b'y'; 253]);
+    let size = round_trip(&data, 2000)[0];
+    assert!(size < 40, "{}", size);
+    // blocks which end in the middle of a run
+    for block in [1, 2, 3, 4, 5, 7, 250, 253, 254] {
+        round_trip(&data, block);
+    }
+}
+
+#[test]
+fn mnp5_noise() {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(3);
+    let data: Vec<u8> = (0..5000).map(|_| gen.gen()).collect();
+    let sizes = round_trip(&data, 256);
+    // at most 10 bits a character
+    assert!(sizes.iter().all(|size| *size <= 320));
+}
+
+#[test]
+fn mnp5_counts() {
+    // long enough for the counts to be halved
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(4);
+    let data: Vec<u8> = (0..100000)
+        .map(|_| b"etaoin "[gen.gen_range(0..7)])
+        .collect();
+    round_trip(&data, 64);
+}
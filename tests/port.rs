@@ -0,0 +1,145 @@
+use modem::flow::FlowControl;
+use modem::port::{Device, Parity, PortOptions};
+use modem::ppp::AuthProtocol;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::PathBuf;
+
+#[test]
+fn port_options_defaults() {
+    for s in ["", "pty"] {
+        let options: PortOptions = s.parse().unwrap();
+        assert_eq!(options, PortOptions::default());
+    }
+    let options = PortOptions::default();
+    assert_eq!(options.speed, 115200);
+    assert_eq!(
+        (options.data_bits, options.parity, options.stop_bits),
+        (8, Parity::None, 1)
+    );
+    assert_eq!(options.flow_control, FlowControl::RtsCts);
+}
+
+#[test]
+fn port_options_parse() {
+    let options: PortOptions = "/dev/ttyUSB0,9600,7e2,flow=xonxoff".parse().unwrap();
+    assert_eq!(options.device, Device::Path("/dev/ttyUSB0".to_string()));
+    assert_eq!(options.speed, 9600);
+    assert_eq!(
+        (options.data_bits, options.parity, options.stop_bits),
+        (7, Parity::Even, 2)
+    );
+    assert_eq!(options.flow_control, FlowControl::XonXoff);
+
+    let options: PortOptions = "\\\\.\\COM3,300,8O1".parse().unwrap();
+    assert_eq!(options.device, Device::Path("\\\\.\\COM3".to_string()));
+    assert_eq!((options.speed, options.parity), (300, Parity::Odd));
+
+    let options: PortOptions = "pty,mode=0660,link=/tmp/modem,flow=none".parse().unwrap();
+    assert_eq!(options.device, Device::Pty);
+    assert_eq!(options.mode, Some(0o660));
+    assert_eq!(options.link, Some(PathBuf::from("/tmp/modem")));
+    assert_eq!(options.flow_control, FlowControl::None);
+}
+
+#[test]
+fn port_options_tcp() {
+    let options: PortOptions = "tcp-listen:0.0.0.0:2323,telnet".parse().unwrap();
+    assert_eq!(
+        options.device,
+        Device::TcpListen("0.0.0.0:2323".to_string())
+    );
+    assert!(options.telnet);
+
+    let options: PortOptions = "tcp:localhost:7000".parse().unwrap();
+    assert_eq!(
+        options.device,
+        Device::TcpConnect("localhost:7000".to_string())
+    );
+    assert!(!options.telnet);
+
+    let options: PortOptions = "stdio".parse().unwrap();
+    assert_eq!(options.device, Device::Stdio);
+}
+
+#[test]
+fn port_options_tun() {
+    let options: PortOptions = "tun".parse().unwrap();
+    assert_eq!(options.device, Device::Tun(None));
+    assert_eq!(options.mtu, 296);
+
+    let options: PortOptions = "tun:sl0,addr=10.0.0.1,peer=10.0.0.2,mtu=576"
+        .parse()
+        .unwrap();
+    assert_eq!(options.device, Device::Tun(Some("sl0".to_string())));
+    assert_eq!(options.address, Some(Ipv4Addr::new(10, 0, 0, 1)));
+    assert_eq!(options.peer, Some(Ipv4Addr::new(10, 0, 0, 2)));
+    assert_eq!(options.mtu, 576);
+}
+
+#[test]
+fn port_options_ppp() {
+    let options: PortOptions = "tun,ppp,user=joe,password=s3cr3t,auth=chap"
+        .parse()
+        .unwrap();
+    assert!(options.ppp);
+    assert_eq!(
+        (options.user.as_str(), options.password.as_str()),
+        ("joe", "s3cr3t")
+    );
+    assert_eq!(options.auth, Some(AuthProtocol::Chap));
+}
+
+#[test]
+fn port_options_stack() {
+    let options: PortOptions =
+        "stack,ppp,socks=1080,forward=8080:10.0.0.1:80,forward=0.0.0.0:2323:10.0.0.1:23"
+            .parse()
+            .unwrap();
+    assert_eq!(options.device, Device::Stack);
+    assert_eq!(
+        options.socks,
+        Some(SocketAddr::from(([127, 0, 0, 1], 1080)))
+    );
+    assert_eq!(
+        options.forwards,
+        [
+            (
+                SocketAddr::from(([127, 0, 0, 1], 8080)),
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 80)
+            ),
+            (
+                SocketAddr::from(([0, 0, 0, 0], 2323)),
+                SocketAddrV4::new(Ipv4Addr::new(10, 0, 0, 1), 23)
+            ),
+        ]
+    );
+}
+
+#[test]
+fn port_options_errors() {
+    for s in [
+        "pty,speed=fast",
+        "pty,speed=0",
+        "pty,9N1",
+        "pty,8X1",
+        "pty,8N3",
+        "pty,flow=dtr",
+        "pty,mode=0999",
+        "pty,link=",
+        "pty,parity=even",
+        "pty,fast",
+        "pty,telnet",
+        "pty,addr=10.0.0.1",
+        "tun,peer=10.0.0",
+        "tun,mtu=20",
+        "pty,ppp",
+        "tun,user=joe",
+        "tun,ppp,auth=eap",
+        "tun,socks=1080",
+        "stack,addr=10.0.0.2",
+        "stack,socks=1080",
+        "stack,addr=10.0.0.2,forward=8080:example.com:80",
+    ] {
+        assert!(s.parse::<PortOptions>().is_err(), "{}", s);
+    }
+}
@@ -0,0 +1,171 @@
+use modem::md5::md5;
+use modem::ppp::{self, AuthProtocol, Event, FrameDecoder, Ppp, PppConfig, PROTO_IP};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+/// Lets the two ends talk until neither has anything more to say, returning
+/// the events of each.
+fn exchange(a: &mut Ppp, b: &mut Ppp, mut to_b: Vec<u8>, now: Instant) -> [Vec<Event>; 2] {
+    let mut events = [vec![], vec![]];
+    let mut to_a = vec![];
+    while !to_a.is_empty() || !to_b.is_empty() {
+        let mut out = vec![];
+        for byte in std::mem::take(&mut to_b) {
+            events[1].extend(b.put_byte(byte, now, &mut out));
+        }
+        to_a.extend(out);
+        let mut out = vec![];
+        for byte in std::mem::take(&mut to_a) {
+            events[0].extend(a.put_byte(byte, now, &mut out));
+        }
+        to_b.extend(out);
+    }
+    events
+}
+
+fn server_config(auth: Option<AuthProtocol>) -> PppConfig {
+    PppConfig {
+        address: Some(Ipv4Addr::new(10, 0, 0, 1)),
+        peer: Some(Ipv4Addr::new(10, 0, 0, 2)),
+        user: "user".to_string(),
+        password: "secret".to_string(),
+        auth,
+        ..PppConfig::default()
+    }
+}
+
+fn client_config(password: &str) -> PppConfig {
+    PppConfig {
+        mru: 296,
+        user: "user".to_string(),
+        password: password.to_string(),
+        ..PppConfig::default()
+    }
+}
+
+fn connect(auth: Option<AuthProtocol>, password: &str) -> (Ppp, Ppp, [Vec<Event>; 2]) {
+    let now = Instant::now();
+    let mut client = Ppp::new(client_config(password));
+    let mut server = Ppp::new(server_config(auth));
+    let mut out = vec![];
+    client.open(now, &mut out);
+    let events = exchange(&mut client, &mut server, out, now);
+    (client, server, events)
+}
+
+#[test]
+fn ppp_link_up() {
+    for auth in [None, Some(AuthProtocol::Pap), Some(AuthProtocol::Chap)] {
+        let (mut client, mut server, [client_events, server_events]) = connect(auth, "secret");
+        assert_eq!(
+            client_events,
+            [Event::Up {
+                address: Ipv4Addr::new(10, 0, 0, 2),
+                peer: Ipv4Addr::new(10, 0, 0, 1),
+                mtu: 296,
+            }],
+            "{:?}",
+            auth
+        );
+        assert_eq!(
+            server_events,
+            [Event::Up {
+                address: Ipv4Addr::new(10, 0, 0, 1),
+                peer: Ipv4Addr::new(10, 0, 0, 2),
+                mtu: 296,
+            }]
+        );
+
+        let mut out = vec![];
+        assert!(client.send_packet(&[0x45, 0, 0x7e, 0x7d, 0x11], &mut out));
+        let [_, server_events] = exchange(&mut client, &mut server, out, Instant::now());
+        assert_eq!(
+            server_events,
+            [Event::Packet(vec![0x45, 0, 0x7e, 0x7d, 0x11])]
+        );
+    }
+}
+
+#[test]
+fn ppp_wrong_password() {
+    for auth in [AuthProtocol::Pap, AuthProtocol::Chap] {
+        let (client, server, [client_events, server_events]) = connect(Some(auth), "wrong");
+        assert!(matches!(client_events[..], [Event::Down(_)]), "{:?}", auth);
+        assert!(matches!(server_events[..], [Event::Down(_)]));
+        assert!(!client.is_up() && !server.is_up());
+    }
+}
+
+#[test]
+fn ppp_terminate() {
+    let (mut client, mut server, _) = connect(None, "");
+    let mut out = vec![];
+    let now = Instant::now();
+    client.close(now, &mut out);
+    assert!(!client.send_packet(b"x", &mut vec![]));
+    let [client_events, server_events] = exchange(&mut client, &mut server, out, now);
+    assert!(client_events.is_empty());
+    assert_eq!(
+        server_events,
+        [Event::Down("terminated by the peer".to_string())]
+    );
+}
+
+#[test]
+fn ppp_gives_up_without_peer() {
+    let mut ppp = Ppp::new(PppConfig::default());
+    let mut now = Instant::now();
+    let mut out = vec![];
+    ppp.open(now, &mut out);
+    let mut requests = 1;
+    let event = loop {
+        now += Duration::from_secs(3);
+        let len = out.len();
+        if let Some(event) = ppp.poll(now, &mut out) {
+            break event;
+        }
+        assert!(out.len() > len);
+        requests += 1;
+    };
+    assert_eq!(requests, 10);
+    assert_eq!(event, Event::Down("LCP negotiation timed out".to_string()));
+}
+
+#[test]
+fn ppp_framing() {
+    let mut out = vec![];
+    ppp::encode(PROTO_IP, &[0x01, 0x7e, 0x7d, 0x41], 1 << 1, &mut out);
+    // flag, then the address, control and protocol, then the escaped data
+    assert_eq!(
+        out[..10],
+        [0x7e, 0xff, 0x03, 0x00, 0x21, 0x7d, 0x21, 0x7d, 0x5e, 0x7d]
+    );
+    assert_eq!(*out.last().unwrap(), 0x7e);
+
+    let mut decoder = FrameDecoder::new(1500);
+    let frames: Vec<_> = out.iter().filter_map(|b| decoder.put_byte(*b)).collect();
+    assert_eq!(frames.len(), 1);
+    assert_eq!(
+        frames[0].as_ref().unwrap(),
+        &(PROTO_IP, vec![0x01, 0x7e, 0x7d, 0x41])
+    );
+
+    // a corrupted byte fails the FCS
+    out[6] ^= 1;
+    let frames: Vec<_> = out.iter().filter_map(|b| decoder.put_byte(*b)).collect();
+    assert!(frames[0].is_err());
+}
+
+#[test]
+fn md5_digests() {
+    let hex =
+        |digest: [u8; 16]| -> String { digest.iter().map(|b| format!("{:02x}", b)).collect() };
+    assert_eq!(hex(md5(b"")), "d41d8cd98f00b204e9800998ecf8427e");
+    assert_eq!(hex(md5(b"abc")), "900150983cd24fb0d6963f7d28e17f72");
+    assert_eq!(
+        hex(md5(
+            b"12345678901234567890123456789012345678901234567890123456789012345678901234567890"
+        )),
+        "57edf4a22be3c955ac49da2e2107b67a"
+    );
+}
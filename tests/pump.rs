@@ -0,0 +1,92 @@
+use modem::class1;
+use modem::pump::{DataPumpRX, DataPumpTX, Modulation};
+use modem::t30;
+use rand::{Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+
+const MODULATIONS: [Modulation; 4] = [
+    Modulation::V27ter2400,
+    Modulation::V27ter4800,
+    Modulation::V29_7200,
+    Modulation::V29_9600,
+];
+
+fn transmit(srate: usize, modulation: Modulation, data: &[u8]) -> Vec<f32> {
+    let mut tx = DataPumpTX::new(1. / srate as f32, modulation);
+    tx.put_bytes(data);
+    tx.finish();
+
+    let mut samples = vec![0.; srate / 10];
+    let mut chunk = vec![0.; 1024];
+    while !tx.is_idle() {
+        tx.modulate(&mut chunk);
+        samples.extend(&chunk);
+    }
+    samples.extend(vec![0.; srate / 10]);
+    samples
+}
+
+fn receive(srate: usize, modulation: Modulation, samples: &[f32]) -> Vec<u8> {
+    let mut rx = DataPumpRX::new(1. / srate as f32, modulation);
+    let mut received = vec![];
+    for chunk in samples.chunks(1024) {
+        rx.demodulate(chunk, &mut received);
+    }
+    assert!(!rx.carrier());
+    received
+}
+
+#[test]
+fn pump_class1_modulations() {
+    for code in class1::DATA_MODULATIONS {
+        let modulation = Modulation::from_class1(*code).unwrap();
+        assert_eq!(modulation.bit_rate(), 100 * *code as usize);
+    }
+    assert_eq!(Modulation::from_class1(class1::V21_CHANNEL2), None);
+}
+
+#[test]
+fn pump_roundtrip() {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(42);
+    let noise = Normal::new(0., 0.003).unwrap();
+
+    for modulation in MODULATIONS {
+        for srate in [44100, 48000] {
+            let data: Vec<u8> = (0..modulation.bit_rate() / 8).map(|_| gen.gen()).collect();
+            let mut samples = transmit(srate, modulation, &data);
+            assert!(samples.iter().all(|x| x.abs() < 1.));
+            for x in samples.iter_mut() {
+                *x += noise.sample(&mut gen);
+            }
+
+            // a few bytes may be decoded while the carrier fades out
+            let received = receive(srate, modulation, &samples);
+            assert!(
+                received.len() >= data.len() && received[..data.len()] == data[..],
+                "{:?} at {} Hz",
+                modulation,
+                srate
+            );
+        }
+    }
+}
+
+#[test]
+fn pump_tcf() {
+    let srate = 48000;
+    for modulation in MODULATIONS {
+        let tcf = t30::tcf(modulation.bit_rate());
+        assert_eq!(tcf.len(), 3 * modulation.bit_rate() / 16);
+
+        let samples = transmit(srate, modulation, &tcf);
+        let received = receive(srate, modulation, &samples);
+        assert!(t30::tcf_ok(&received, modulation.bit_rate()));
+
+        // errors every 0.6 s leave no whole second of zeros
+        let mut received = received;
+        for i in (0..received.len()).step_by(6 * modulation.bit_rate() / 80) {
+            received[i] = 0x01;
+        }
+        assert!(!t30::tcf_ok(&received, modulation.bit_rate()));
+    }
+}
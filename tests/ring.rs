@@ -0,0 +1,107 @@
+use modem::dtmf::DtmfTX;
+use modem::ring::RingDetector;
+use modem::v25::{self, AnswerSequence};
+use std::f32::consts::PI;
+
+const SRATE: usize = 48000;
+
+/// Plays `cadence`, a list of tone and silence durations in seconds starting
+/// with a tone, as a 425 Hz ring tone.
+fn ring_tone(cadence: &[f32]) -> Vec<f32> {
+    let mut samples = vec![];
+    for (i, duration) in cadence.iter().enumerate() {
+        let len = (duration * SRATE as f32) as usize;
+        if i % 2 == 0 {
+            samples
+                .extend((0..len).map(|n| 0.3 * (2. * PI * 425. * n as f32 / SRATE as f32).sin()));
+        } else {
+            samples.extend(vec![0.; len]);
+        }
+    }
+    samples
+}
+
+fn count_rings(samples: &[f32]) -> usize {
+    let mut detector = RingDetector::new(1. / SRATE as f32);
+    samples
+        .chunks(1024)
+        .map(|chunk| detector.put_samples(chunk))
+        .sum()
+}
+
+#[test]
+fn ring_cadence() {
+    // North American and Brazilian cadences
+    assert_eq!(count_rings(&ring_tone(&[2., 4., 2., 4.])), 2);
+    assert_eq!(count_rings(&ring_tone(&[1., 4., 1., 4., 1., 4.])), 3);
+    // UK double ring, each pair counted once
+    assert_eq!(
+        count_rings(&ring_tone(&[0.4, 0.2, 0.4, 2., 0.4, 0.2, 0.4, 2.])),
+        2
+    );
+    // a click and a carrier are not rings
+    assert_eq!(count_rings(&ring_tone(&[0.05, 1., 6., 1.])), 0);
+}
+
+/// Bell 202 FSK, alternating mark and space at 1200 bauds like the channel
+/// seizure which starts a caller ID message.
+fn caller_id_fsk(duration: f32) -> Vec<f32> {
+    let mut phase = 0f32;
+    (0..(duration * SRATE as f32) as usize)
+        .map(|n| {
+            let bit = n * 1200 / SRATE % 2;
+            let freq = [1200., 2200.][bit];
+            phase += 2. * PI * freq / SRATE as f32;
+            0.3 * phase.sin()
+        })
+        .collect()
+}
+
+#[test]
+fn ring_caller_id() {
+    let mut samples = ring_tone(&[2., 1.]);
+    samples.extend(caller_id_fsk(0.5));
+    samples.extend(ring_tone(&[0., 2.5, 2., 4.]));
+    assert_eq!(count_rings(&samples), 2);
+
+    let mut dtmf_tx = DtmfTX::new(1. / SRATE as f32);
+    dtmf_tx.put_digits("A1234567890C");
+    let mut samples = ring_tone(&[0., 1.]);
+    while !dtmf_tx.is_idle() {
+        let mut chunk = vec![0.; 1024];
+        dtmf_tx.modulate(&mut chunk);
+        samples.extend(chunk);
+    }
+    samples.extend(ring_tone(&[0., 2., 2., 4.]));
+    assert_eq!(count_rings(&samples), 1);
+}
+
+#[test]
+fn ring_answer_sequence() {
+    let mut answer_sequence = AnswerSequence::new(1. / SRATE as f32);
+    let mut samples: Vec<f32> = vec![];
+    let mut chunk = vec![0.; 1000];
+    while !answer_sequence.is_done() {
+        let written = answer_sequence.modulate(&mut chunk);
+        samples.extend(&chunk[..written]);
+    }
+    let duration = v25::BILLING_DELAY + v25::ANSWER_TONE_DURATION + v25::ANSWER_TONE_GAP;
+    assert_eq!(
+        samples.len(),
+        (duration.as_secs_f32() * SRATE as f32) as usize
+    );
+
+    let tone_start = samples.iter().position(|x| *x != 0.).unwrap();
+    let tone_end = samples.iter().rposition(|x| *x != 0.).unwrap();
+    // the tone starts with a zero sample
+    assert_eq!(
+        tone_start,
+        v25::BILLING_DELAY.as_secs() as usize * SRATE + 1
+    );
+    let crossings = samples[tone_start..tone_end]
+        .windows(2)
+        .filter(|w| w[0] < 0. && w[1] >= 0.)
+        .count();
+    let freq = crossings as f32 / v25::ANSWER_TONE_DURATION.as_secs_f32();
+    assert!((freq - v25::ANSWER_TONE).abs() < 2., "{} Hz", freq);
+}
@@ -0,0 +1,43 @@
+use modem::slip::{self, SlipDecoder, END, ESC, ESC_END, ESC_ESC};
+
+fn decode(decoder: &mut SlipDecoder, bytes: &[u8]) -> Vec<Result<Vec<u8>, String>> {
+    bytes
+        .iter()
+        .filter_map(|b| decoder.put_byte(*b))
+        .map(|packet| packet.map_err(|err| err.to_string()))
+        .collect()
+}
+
+#[test]
+fn slip_encode() {
+    let mut out = vec![];
+    slip::encode(&[1, END, 2, ESC, 3], &mut out);
+    assert_eq!(out, [END, 1, ESC, ESC_END, 2, ESC, ESC_ESC, 3, END]);
+}
+
+#[test]
+fn slip_round_trip() {
+    let packets: [&[u8]; 3] = [b"hello", &[END, ESC, END], &[0; 296]];
+    let mut encoded = vec![];
+    for packet in packets {
+        slip::encode(packet, &mut encoded);
+    }
+    let mut decoder = SlipDecoder::new(296);
+    let decoded = decode(&mut decoder, &encoded);
+    assert_eq!(decoded, packets.map(|packet| Ok(packet.to_vec())).to_vec());
+}
+
+#[test]
+fn slip_errors() {
+    let mut decoder = SlipDecoder::new(4);
+    // the rest of a bad packet is skipped, and the next one still decoded
+    let decoded = decode(&mut decoder, &[1, ESC, 2, 3, END, 4, END]);
+    assert_eq!(decoded.len(), 2);
+    assert!(decoded[0].is_err());
+    assert_eq!(decoded[1], Ok(vec![4]));
+
+    let decoded = decode(&mut decoder, &[1, 2, 3, 4, 5, 6, END, 7, END]);
+    assert_eq!(decoded.len(), 2);
+    assert!(decoded[0].is_err());
+    assert_eq!(decoded[1], Ok(vec![7]));
+}
@@ -0,0 +1,171 @@
+use crossbeam_channel::{Receiver, Sender};
+use modem::ip::Network;
+use modem::port::PortOptions;
+use modem::stack::Stack;
+use smoltcp::iface::{Config, Interface, SocketSet};
+use smoltcp::phy::{self, Device, DeviceCapabilities, Medium};
+use smoltcp::socket::tcp;
+use smoltcp::time::Instant;
+use smoltcp::wire::{HardwareAddress, IpCidr};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::time::Duration;
+
+/// The far end of the link, as packets to and from the stack.
+struct Peer {
+    from_stack: Receiver<Vec<u8>>,
+    to_stack: Sender<Vec<u8>>,
+}
+
+struct Received(Vec<u8>);
+
+struct Transmit<'a>(&'a Sender<Vec<u8>>);
+
+impl phy::RxToken for Received {
+    fn consume<R, F: FnOnce(&[u8]) -> R>(self, f: F) -> R {
+        f(&self.0)
+    }
+}
+
+impl phy::TxToken for Transmit<'_> {
+    fn consume<R, F: FnOnce(&mut [u8]) -> R>(self, len: usize, f: F) -> R {
+        let mut packet = vec![0; len];
+        let result = f(&mut packet);
+        self.0.send(packet).unwrap();
+        result
+    }
+}
+
+impl Device for Peer {
+    type RxToken<'a> = Received;
+    type TxToken<'a> = Transmit<'a>;
+
+    fn receive(&mut self, _: Instant) -> Option<(Received, Transmit<'_>)> {
+        let packet = self.from_stack.try_recv().ok()?;
+        Some((Received(packet), Transmit(&self.to_stack)))
+    }
+
+    fn transmit(&mut self, _: Instant) -> Option<Transmit<'_>> {
+        Some(Transmit(&self.to_stack))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.medium = Medium::Ip;
+        capabilities.max_transmission_unit = 296;
+        capabilities
+    }
+}
+
+/// Runs an echo server on port 7 of 10.0.0.1.
+fn echo_server(mut peer: Peer) {
+    let mut iface = Interface::new(Config::new(HardwareAddress::Ip), &mut peer, Instant::now());
+    iface.update_ip_addrs(|addrs| {
+        addrs
+            .push(IpCidr::new(Ipv4Addr::new(10, 0, 0, 1).into(), 32))
+            .unwrap()
+    });
+    iface
+        .routes_mut()
+        .add_default_ipv4_route(Ipv4Addr::new(10, 0, 0, 2))
+        .unwrap();
+    let mut sockets = SocketSet::new(vec![]);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            sockets.add(tcp::Socket::new(
+                tcp::SocketBuffer::new(vec![0; 1024]),
+                tcp::SocketBuffer::new(vec![0; 1024]),
+            ))
+        })
+        .collect();
+    loop {
+        iface.poll(Instant::now(), &mut peer, &mut sockets);
+        for handle in &handles {
+            let socket = sockets.get_mut::<tcp::Socket>(*handle);
+            if !socket.is_open() {
+                socket.listen(7).unwrap();
+            }
+            if socket.can_recv() && socket.can_send() {
+                let mut data = vec![0; socket.send_capacity() - socket.send_queue()];
+                let len = socket.recv_slice(&mut data).unwrap();
+                socket.send_slice(&data[..len]).unwrap();
+            } else if !socket.may_recv() && socket.may_send() {
+                socket.close();
+            }
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
+
+fn socks_connect(port: u16, request: &[u8]) -> (TcpStream, [u8; 10]) {
+    let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    stream.write_all(&[5, 1, 0]).unwrap();
+    let mut method = [0; 2];
+    stream.read_exact(&mut method).unwrap();
+    assert_eq!(method, [5, 0]);
+    stream.write_all(request).unwrap();
+    let mut reply = [0; 10];
+    stream.read_exact(&mut reply).unwrap();
+    (stream, reply)
+}
+
+fn echo(stream: &mut TcpStream, data: &[u8]) {
+    stream.write_all(data).unwrap();
+    let mut echoed = vec![0; data.len()];
+    stream.read_exact(&mut echoed).unwrap();
+    assert_eq!(echoed, data);
+}
+
+#[test]
+fn stack_socks_and_forward() {
+    let options: PortOptions = "stack,addr=10.0.0.2,peer=10.0.0.1,socks=0,forward=0:10.0.0.1:7"
+        .parse()
+        .unwrap();
+    let (mut stack, from_stack) = Stack::open(&options).unwrap();
+    let ports: Vec<_> = stack.listeners().iter().map(|addr| addr.port()).collect();
+
+    // not until the link is up
+    let (_, reply) = socks_connect(ports[0], &[5, 1, 0, 1, 10, 0, 0, 1, 0, 7]);
+    assert_eq!(reply[1], 3);
+
+    stack
+        .up(296, options.address, Some(Ipv4Addr::new(10, 0, 0, 1)))
+        .unwrap();
+    let (to_stack, packets) = crossbeam_channel::unbounded::<Vec<u8>>();
+    std::thread::spawn(move || {
+        for packet in packets {
+            stack.send(&packet).unwrap();
+        }
+    });
+    std::thread::spawn(move || {
+        echo_server(Peer {
+            from_stack,
+            to_stack,
+        })
+    });
+
+    let (mut stream, reply) = socks_connect(ports[0], &[5, 1, 0, 1, 10, 0, 0, 1, 0, 7]);
+    assert_eq!(reply[1], 0);
+    echo(&mut stream, b"hello");
+    echo(&mut stream, &[0x55; 3000]);
+    // the server closes once we do
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+
+    let (_, reply) = socks_connect(ports[0], b"\x05\x01\x00\x03\x0810.0.0.1\x00\x07");
+    assert_eq!(reply[1], 0);
+    let (_, reply) = socks_connect(ports[0], b"\x05\x01\x00\x03\x0bexample.com\x00\x07");
+    assert_eq!(reply[1], 8);
+    // refused by the peer
+    let (_, reply) = socks_connect(ports[0], &[5, 1, 0, 1, 10, 0, 0, 1, 0, 9]);
+    assert_eq!(reply[1], 4);
+
+    let mut stream = TcpStream::connect(("127.0.0.1", ports[1])).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(10)))
+        .unwrap();
+    echo(&mut stream, b"forwarded");
+}
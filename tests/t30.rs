@@ -0,0 +1,97 @@
+use crossbeam_channel::unbounded;
+use modem::afsk::AfskRX;
+use modem::fsk::{FskChannel, FskProfile};
+use modem::hdlc::{self, FcsError, HdlcRx};
+use modem::t30::{self, V21HdlcTx};
+
+/// Demodulates with `AfskRX`, as `V21RX` is left for the reader to
+/// implement.
+fn receive(srate: usize, samples: &[f32], corrupt_at: Option<usize>) -> Vec<hdlc::Frame> {
+    receive_on(&FskProfile::t30().originate, srate, samples, corrupt_at)
+}
+
+fn receive_on(
+    channel: &FskChannel,
+    srate: usize,
+    samples: &[f32],
+    corrupt_at: Option<usize>,
+) -> Vec<hdlc::Frame> {
+    let samples_per_symbol = channel.samples_per_symbol(srate).unwrap();
+    let mut afsk_rx = AfskRX::new(
+        1. / srate as f32,
+        samples_per_symbol,
+        channel.omega_mark(),
+        channel.omega_space(),
+    );
+    let mut levels = vec![0; samples.len()];
+    afsk_rx.demodulate(samples, &mut levels);
+    if let Some(pos) = corrupt_at {
+        levels[pos..pos + samples_per_symbol]
+            .iter_mut()
+            .for_each(|x| *x ^= 1);
+    }
+
+    let (to_host, from_hdlc_rx) = unbounded();
+    let mut hdlc_rx = HdlcRx::new(samples_per_symbol, false, to_host);
+    hdlc_rx.put_samples(&levels);
+    drop(hdlc_rx);
+    from_hdlc_rx.iter().collect()
+}
+
+#[test]
+fn t30_v21_hdlc_frames() {
+    // CSI followed by DIS, as sent by an answering fax machine
+    let csi = [t30::ADDRESS, t30::CONTROL, 0x02, b'4', b'3', b'2', b'1'];
+    let dis = [t30::ADDRESS, t30::CONTROL_FINAL, 0x80, 0x00, 0x46, 0x00];
+
+    for srate in [44100, 48000] {
+        let mut tx = V21HdlcTx::new(srate).unwrap();
+        assert!(tx.is_idle());
+        tx.send_frame(&csi);
+        tx.send_frame(&dis);
+        assert!(!tx.is_idle());
+
+        // the preamble takes one second, and both frames a bit less
+        let mut samples = vec![0.; 2 * srate];
+        tx.get_samples(&mut samples);
+        assert!(tx.is_idle());
+        assert!(samples[..srate].iter().any(|x| x.abs() > 0.5));
+        assert!(samples[samples.len() - srate / 10..]
+            .iter()
+            .all(|x| *x == 0.));
+
+        assert_eq!(
+            receive(srate, &samples, None),
+            vec![Ok(csi.to_vec()), Ok(dis.to_vec())],
+            "at {} Hz",
+            srate
+        );
+
+        // invert one of the bits of the number in the CSI frame
+        let frames = receive(srate, &samples, Some(srate + 3 * srate / 20));
+        assert!(matches!(frames[..], [Err(FcsError { .. }), Ok(_)]));
+    }
+}
+
+#[test]
+fn t30_v21_hdlc_continuous_carrier() {
+    // a link layer on V.21 channel 1, with the carrier kept between frames
+    let srate = 48000;
+    let channel = FskProfile::v21().originate;
+    let mut tx = V21HdlcTx::with_channel(srate, &channel).unwrap();
+    tx.set_carrier_on_demand(false);
+    tx.send_frame(b"first");
+    tx.send_frame(b"second");
+
+    // no preamble, and marks once the frames are sent
+    let mut samples = vec![0.; srate];
+    tx.get_samples(&mut samples);
+    assert!(tx.is_idle());
+    assert!(samples[samples.len() - srate / 10..]
+        .iter()
+        .any(|x| x.abs() > 0.5));
+    assert_eq!(
+        receive_on(&channel, srate, &samples, None),
+        vec![Ok(b"first".to_vec()), Ok(b"second".to_vec())]
+    );
+}
@@ -0,0 +1,67 @@
+use modem::port::PortOptions;
+use modem::telnet::*;
+
+fn feed(telnet: &mut TelnetServer, bytes: &[u8]) -> (Vec<Event>, Vec<u8>) {
+    let mut reply = vec![];
+    let events = bytes
+        .iter()
+        .filter_map(|b| telnet.put_byte(*b, &mut reply))
+        .collect();
+    (events, reply)
+}
+
+#[test]
+fn telnet_data_and_escape() {
+    let mut telnet = TelnetServer::new(&PortOptions::default());
+    let (events, reply) = feed(&mut telnet, &[b'a', IAC, IAC, b'b']);
+    assert_eq!(
+        events,
+        [Event::Data(b'a'), Event::Data(IAC), Event::Data(b'b')]
+    );
+    assert!(reply.is_empty());
+
+    let mut out = vec![];
+    escape(&[1, IAC, 2], &mut out);
+    assert_eq!(out, [1, IAC, IAC, 2]);
+}
+
+#[test]
+fn telnet_negotiation() {
+    let mut telnet = TelnetServer::new(&PortOptions::default());
+    let greeting = telnet.greeting();
+    assert!(greeting.windows(3).any(|w| w == [IAC, DO, COM_PORT]));
+    assert!(greeting.windows(3).any(|w| w == [IAC, WILL, ECHO]));
+
+    // acknowledgements of the greeting are not answered again
+    let (_, reply) = feed(&mut telnet, &[IAC, WILL, COM_PORT, IAC, DO, BINARY]);
+    assert!(reply.is_empty());
+    // unknown options are refused
+    let (_, reply) = feed(&mut telnet, &[IAC, DO, 24, IAC, WILL, 31]);
+    assert_eq!(reply, [IAC, WONT, 24, IAC, DONT, 31]);
+    let (_, reply) = feed(&mut telnet, &[IAC, DONT, ECHO, IAC, DONT, ECHO]);
+    assert_eq!(reply, [IAC, WONT, ECHO]);
+}
+
+#[test]
+fn telnet_com_port_control() {
+    let mut telnet = TelnetServer::new(&PortOptions::default());
+    telnet.greeting();
+
+    let (events, reply) = feed(
+        &mut telnet,
+        &[IAC, SB, COM_PORT, SET_BAUDRATE, 0, 0, 0x25, 0x80, IAC, SE],
+    );
+    assert!(events.is_empty());
+    assert_eq!(reply, [IAC, SB, COM_PORT, 101, 0, 0, 0x25, 0x80, IAC, SE]);
+    // a zero asks for the current value
+    let (_, reply) = feed(&mut telnet, &[IAC, SB, COM_PORT, SET_DATASIZE, 0, IAC, SE]);
+    assert_eq!(reply, [IAC, SB, COM_PORT, 102, 8, IAC, SE]);
+
+    let (events, reply) = feed(&mut telnet, &[IAC, SB, COM_PORT, SET_CONTROL, 9, IAC, SE]);
+    assert_eq!(events, [Event::Dtr(false)]);
+    assert_eq!(reply, [IAC, SB, COM_PORT, 105, 9, IAC, SE]);
+    let (_, reply) = feed(&mut telnet, &[IAC, SB, COM_PORT, SET_CONTROL, 7, IAC, SE]);
+    assert_eq!(reply, [IAC, SB, COM_PORT, 105, 9, IAC, SE]);
+
+    assert_eq!(flow_control(true), [IAC, SB, COM_PORT, 108, IAC, SE]);
+}
@@ -1,6 +1,8 @@
 use crossbeam_channel::unbounded;
 use interp1d::Interp1d;
 use modem::{
+    baudot::{BaudotDecoder, BaudotEncoder, Variant},
+    fsk::FskProfile,
     uart::{UartRx, UartTx},
     v21::{V21RX, V21TX},
 };
@@ -52,6 +54,63 @@ fn uart_noisy_unsync_44100() {
     test_uart(44100, true, true)
 }
 
+#[test]
+fn uart_baudot_stream() {
+    // 5N1.5 characters back to back, 7.5 bits each
+    let profile = FskProfile::rtty();
+    let samples_per_symbol = profile.originate.samples_per_symbol(48000).unwrap();
+    let mut encoder = BaudotEncoder::new(Variant::UsTty, false);
+    let mut codes = vec![];
+    for b in b"RYRYRY THE QUICK BROWN FOX 1234567890 73\r\n" {
+        encoder.encode(*b, &mut codes);
+    }
+    let received = uart_stream(
+        samples_per_symbol,
+        profile.charset.data_bits(),
+        profile.charset.stop_bits(),
+        &codes,
+    );
+    assert_eq!(received, codes);
+
+    let mut decoder = BaudotDecoder::new(Variant::UsTty, false);
+    let text: Vec<u8> = received.iter().filter_map(|c| decoder.decode(*c)).collect();
+    assert_eq!(text, b"RYRYRY THE QUICK BROWN FOX 1234567890 73\r\n");
+}
+
+#[test]
+fn uart_text_telephone_streams() {
+    // EDT: 7 data bits, even parity and 2 stop bits, 11 bits each
+    let edt = FskProfile::edt();
+    let samples_per_symbol = edt.originate.samples_per_symbol(48000).unwrap();
+    let chars: Vec<u8> = b"GA HELLO 123 SK\r\n"
+        .iter()
+        .map(|b| b | ((b.count_ones() as u8 & 1) << 7))
+        .collect();
+    let received = uart_stream(
+        samples_per_symbol,
+        edt.charset.data_bits(),
+        edt.charset.stop_bits(),
+        &chars,
+    );
+    assert_eq!(received, chars);
+
+    // TDD: Baudot at 45.45 baud
+    let tdd = FskProfile::tdd();
+    let samples_per_symbol = tdd.originate.samples_per_symbol(48000).unwrap();
+    let mut encoder = BaudotEncoder::new(Variant::UsTty, false);
+    let mut codes = vec![];
+    for b in b"GA 555 1234 SK\r\n" {
+        encoder.encode(*b, &mut codes);
+    }
+    let received = uart_stream(
+        samples_per_symbol,
+        tdd.charset.data_bits(),
+        tdd.charset.stop_bits(),
+        &codes,
+    );
+    assert_eq!(received, codes);
+}
+
 #[test]
 fn v21_sync_48000() {
     test_v21(48000, false)
@@ -129,6 +188,30 @@ fn test_uart(srate: usize, add_noise: bool, add_timing_offset: bool) {
     }
 }
 
+/// Sends `bytes` without any idle time between them.
+fn uart_stream(
+    samples_per_symbol: usize,
+    data_bits: usize,
+    stop_bits: f32,
+    bytes: &[u8],
+) -> Vec<u8> {
+    let (rx_sender, rx_receiver) = unbounded();
+    let mut uart_tx = UartTx::with_framing(samples_per_symbol, data_bits, stop_bits);
+    let mut uart_rx = UartRx::with_framing(samples_per_symbol, data_bits, stop_bits, rx_sender);
+
+    uart_tx.put_idle(3 * samples_per_symbol);
+    for b in bytes {
+        uart_tx.put_byte(*b);
+    }
+    let mut samples = vec![1; uart_tx.pending_samples() + 2 * samples_per_symbol];
+    uart_tx.get_samples(&mut samples);
+    for chunk in samples.chunks(1000) {
+        uart_rx.put_samples(chunk);
+    }
+    drop(uart_rx);
+    rx_receiver.iter().collect()
+}
+
 fn test_v21(srate: usize, add_timing_offset: bool) {
     const MAX_EBN0_DB: usize = 20;
     let mut ber_ebn0_db = vec![0.; MAX_EBN0_DB];
@@ -0,0 +1,105 @@
+use modem::fsk::{FskProfile, Role};
+use modem::uart::UartTx;
+use modem::v18::{Automode, Detected};
+use modem::v21::V21TX;
+use rand::SeedableRng;
+use rand_distr::{Distribution, Normal};
+
+fn modulate(srate: usize, profile: &FskProfile, role: Role, msg: &[u8]) -> Vec<f32> {
+    let channel = profile.tx_channel(role);
+    let samples_per_symbol = channel.samples_per_symbol(srate).unwrap();
+    let mut uart_tx = UartTx::with_framing(
+        samples_per_symbol,
+        profile.charset.data_bits(),
+        profile.charset.stop_bits(),
+    );
+    let mut v21_tx = V21TX::new(
+        1. / srate as f32,
+        channel.omega_mark(),
+        channel.omega_space(),
+    );
+
+    uart_tx.put_idle(srate / 10);
+    for b in msg {
+        uart_tx.put_byte(*b);
+    }
+    let mut uart_out = vec![1; uart_tx.pending_samples() + srate / 2];
+    uart_tx.get_samples(&mut uart_out);
+
+    let mut out = vec![0.; uart_out.len()];
+    v21_tx.modulate(&uart_out, &mut out);
+    out.iter_mut().for_each(|x| *x *= 0.5);
+    out
+}
+
+fn add_noise(noise_amplitude: f32, samples: &mut [f32]) {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(42);
+    let d = Normal::new(0., noise_amplitude).unwrap();
+    samples.iter_mut().for_each(|x| *x += d.sample(&mut gen));
+}
+
+fn detect(srate: usize, samples: &[f32]) -> Option<Detected> {
+    let mut automode = Automode::new(1. / srate as f32);
+    let silence = vec![0.; srate / 5];
+    automode.put_samples(&silence).or_else(|| {
+        samples
+            .chunks(333)
+            .find_map(|chunk| automode.put_samples(chunk))
+    })
+}
+
+#[test]
+fn v18_automode_detects_far_end() {
+    let msg = b"\x55\xaa\x13\x37GA";
+    for srate in [44100, 48000] {
+        let cases = [
+            (FskProfile::tdd(), Role::Originate, Detected::Tdd),
+            (FskProfile::edt(), Role::Originate, Detected::Edt),
+            (
+                FskProfile::v21(),
+                Role::Originate,
+                Detected::V21(Role::Answer),
+            ),
+            (
+                FskProfile::v21(),
+                Role::Answer,
+                Detected::V21(Role::Originate),
+            ),
+            (
+                FskProfile::bell103(),
+                Role::Originate,
+                Detected::Bell103(Role::Answer),
+            ),
+            (
+                FskProfile::bell103(),
+                Role::Answer,
+                Detected::Bell103(Role::Originate),
+            ),
+        ];
+        for (profile, role, expected) in cases {
+            let mut samples = modulate(srate, &profile, role, msg);
+            // 10 dB SNR over the whole audio band
+            add_noise(0.11, &mut samples);
+            assert_eq!(
+                detect(srate, &samples),
+                Some(expected),
+                "{} {:?} at {} Hz",
+                profile.name,
+                role,
+                srate
+            );
+        }
+    }
+}
+
+#[test]
+fn v18_automode_ignores_silence_and_other_tones() {
+    let srate = 48000;
+    let silence = vec![0.; srate];
+    assert_eq!(detect(srate, &silence), None);
+
+    let answer_tone: Vec<f32> = (0..srate)
+        .map(|i| 0.5 * (2. * std::f32::consts::PI * 2100. * i as f32 / srate as f32).sin())
+        .collect();
+    assert_eq!(detect(srate, &answer_tone), None);
+}
@@ -0,0 +1,291 @@
+use modem::fsk::Role;
+use modem::v42::{Event, Lapm, LapmConfig};
+use modem::v42bis::V42bisConfig;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+const STEP: Duration = Duration::from_millis(10);
+
+/// Two ends of a call, with a line which loses or damages whatever the
+/// closure given to [`Call::run`] tells it to.
+struct Call {
+    ends: [Lapm; 2],
+    now: Instant,
+    events: [Vec<Event>; 2],
+    received: [Vec<u8>; 2],
+    /// What each end sent, one frame or detection pattern at a time
+    sent: [Vec<Vec<u8>>; 2],
+}
+
+impl Call {
+    fn new(originator: LapmConfig, answerer: LapmConfig) -> Self {
+        let now = Instant::now();
+        let mut ends = [
+            Lapm::new(Role::Originate, originator),
+            Lapm::new(Role::Answer, answerer),
+        ];
+        for end in &mut ends {
+            end.start(now);
+        }
+        Self {
+            ends,
+            now,
+            events: [vec![], vec![]],
+            received: [vec![], vec![]],
+            sent: [vec![], vec![]],
+        }
+    }
+
+    fn event(&mut self, end: usize, event: Option<Event>) {
+        match event {
+            Some(Event::Data(data)) => self.received[end].extend(data),
+            Some(event) => self.events[end].push(event),
+            None => {}
+        }
+    }
+
+    fn run(&mut self, duration: Duration, line: &mut impl FnMut(usize, &mut Vec<u8>)) {
+        let end_time = self.now + duration;
+        while self.now < end_time {
+            for end in 0..2 {
+                let mut out = vec![];
+                let event = self.ends[end].poll(self.now, &mut out);
+                self.event(end, event);
+                // replies are answered in turn
+                let mut chunks = VecDeque::from([(end, out)]);
+                while let Some((from, mut chunk)) = chunks.pop_front() {
+                    if chunk.is_empty() {
+                        continue;
+                    }
+                    self.sent[from].push(chunk.clone());
+                    line(from, &mut chunk);
+                    let mut reply = vec![];
+                    for byte in chunk {
+                        let event = self.ends[1 - from].put_byte(byte, self.now, &mut reply);
+                        self.event(1 - from, event);
+                    }
+                    chunks.push_back((1 - from, reply));
+                }
+            }
+            self.now += STEP;
+        }
+    }
+
+    /// Counts the I frames an end sent.
+    fn information_frames(&self, end: usize) -> usize {
+        self.sent[end]
+            .iter()
+            .filter(|chunk| chunk.len() > 3 && chunk[0] == 0x7e && chunk[2] & 1 == 0)
+            .count()
+    }
+
+    /// Counts the frames an end sent with the given control field.
+    fn frames(&self, end: usize, control: u8) -> usize {
+        self.sent[end]
+            .iter()
+            .filter(|chunk| chunk.len() > 3 && chunk[0] == 0x7e && chunk[2] == control)
+            .count()
+    }
+}
+
+fn perfect_line(_: usize, _: &mut Vec<u8>) {}
+
+fn connected_call(originator: LapmConfig, answerer: LapmConfig) -> Call {
+    let mut call = Call::new(originator, answerer);
+    call.run(Duration::from_secs(2), &mut perfect_line);
+    assert_eq!(
+        call.events,
+        [vec![Event::Connected], vec![Event::Connected]]
+    );
+    assert!(call.ends.iter().all(|end| end.is_connected()));
+    call
+}
+
+fn text(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 256) as u8).collect()
+}
+
+#[test]
+fn lapm_transfer() {
+    let mut call = connected_call(LapmConfig::default(), LapmConfig::default());
+    let data = text(3000);
+    call.ends[0].send(&data);
+    call.ends[1].send(b"hello");
+    assert_eq!(call.ends[0].pending(), 3000);
+    call.run(Duration::from_secs(1), &mut perfect_line);
+    assert_eq!(call.received[1], data);
+    assert_eq!(call.received[0], b"hello");
+    assert_eq!(call.ends[0].pending(), 0);
+    // 128 bytes per frame
+    assert_eq!(call.information_frames(0), 24);
+}
+
+#[test]
+fn lapm_negotiation() {
+    let mut call = connected_call(
+        LapmConfig {
+            max_info: 64,
+            ..LapmConfig::default()
+        },
+        LapmConfig {
+            window: 2,
+            srej: false,
+            ..LapmConfig::default()
+        },
+    );
+    call.ends[1].send(&text(1000));
+    call.run(Duration::from_secs(1), &mut perfect_line);
+    assert_eq!(call.received[0], text(1000));
+    assert_eq!(call.information_frames(1), 16);
+}
+
+#[test]
+fn lapm_compression_negotiation() {
+    let call = connected_call(
+        LapmConfig {
+            compression: Some(V42bisConfig {
+                dictionary_size: 4096,
+                max_string: 16,
+            }),
+            ..LapmConfig::default()
+        },
+        LapmConfig::default(),
+    );
+    let agreed = Some(V42bisConfig {
+        dictionary_size: 2048,
+        max_string: 16,
+    });
+    assert!(call.ends.iter().all(|end| end.compression() == agreed));
+
+    let call = connected_call(
+        LapmConfig::default(),
+        LapmConfig {
+            compression: None,
+            ..LapmConfig::default()
+        },
+    );
+    assert!(call.ends.iter().all(|end| end.compression().is_none()));
+}
+
+/// Drops the n-th I frame sent by the originator.
+fn drop_frame(n: usize) -> impl FnMut(usize, &mut Vec<u8>) {
+    let mut count = 0;
+    move |from, chunk| {
+        if from == 0 && chunk.len() > 3 && chunk[2] & 1 == 0 {
+            count += 1;
+            if count == n {
+                chunk.clear();
+            }
+        }
+    }
+}
+
+#[test]
+fn lapm_selective_reject() {
+    let mut call = connected_call(LapmConfig::default(), LapmConfig::default());
+    call.ends[0].send(&text(1024));
+    call.run(Duration::from_secs(1), &mut drop_frame(3));
+    assert_eq!(call.received[1], text(1024));
+    // only the missing frame is sent again
+    assert_eq!(call.frames(1, 0x0d), 1);
+    assert_eq!(call.information_frames(0), 9);
+}
+
+#[test]
+fn lapm_reject() {
+    let config = LapmConfig {
+        srej: false,
+        ..LapmConfig::default()
+    };
+    let mut call = connected_call(config.clone(), config);
+    call.ends[0].send(&text(1024));
+    call.run(Duration::from_secs(1), &mut drop_frame(3));
+    assert_eq!(call.received[1], text(1024));
+    // the missing frame and the one sent after it
+    assert_eq!(call.frames(1, 0x09), 1);
+    assert_eq!(call.information_frames(0), 10);
+}
+
+#[test]
+fn lapm_lost_last_frame() {
+    let mut call = connected_call(LapmConfig::default(), LapmConfig::default());
+    call.ends[0].send(&text(200));
+    call.run(Duration::from_secs(1), &mut drop_frame(2));
+    assert_eq!(call.received[1], text(128));
+    // nothing follows to reveal the loss, until T401 expires and the
+    // originator polls
+    call.run(Duration::from_secs(20), &mut perfect_line);
+    assert_eq!(call.received[1], text(200));
+    assert_eq!(call.frames(0, 0x01), 1);
+}
+
+#[test]
+fn lapm_noisy_line() {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(42);
+    let mut call = connected_call(LapmConfig::default(), LapmConfig::default());
+    let data = text(20000);
+    call.ends[0].send(&data);
+    call.ends[1].send(&data);
+    call.run(Duration::from_secs(300), &mut |_, chunk| {
+        for byte in chunk.iter_mut() {
+            if gen.gen_bool(1e-3) {
+                *byte ^= 1 << gen.gen_range(0..8);
+            }
+        }
+    });
+    assert_eq!(call.received, [data.clone(), data]);
+}
+
+#[test]
+fn lapm_busy() {
+    let mut call = connected_call(LapmConfig::default(), LapmConfig::default());
+    call.ends[1].set_busy(true);
+    call.ends[0].send(b"while busy");
+    call.run(Duration::from_secs(30), &mut perfect_line);
+    assert!(call.received[1].is_empty());
+    assert_eq!(call.ends[0].pending(), 0);
+    call.ends[1].set_busy(false);
+    call.run(Duration::from_secs(1), &mut perfect_line);
+    assert_eq!(call.received[1], b"while busy");
+    assert!(call.events.iter().all(|events| events.len() == 1));
+}
+
+#[test]
+fn lapm_fallback() {
+    // a peer which does not answer ODP
+    let mut call = Call::new(LapmConfig::default(), LapmConfig::default());
+    call.run(Duration::from_secs(2), &mut |from, chunk| {
+        if from == 0 {
+            chunk.clear();
+        }
+    });
+    assert_eq!(call.events[0], [Event::Fallback(vec![])]);
+
+    // nor sends it, but data
+    let now = Instant::now();
+    let mut answerer = Lapm::new(Role::Answer, LapmConfig::default());
+    let mut out = vec![];
+    for byte in b"hello" {
+        assert_eq!(answerer.put_byte(*byte, now, &mut out), None);
+    }
+    answerer.start(now);
+    assert_eq!(answerer.poll(now + Duration::from_secs(1), &mut out), None);
+    assert_eq!(
+        answerer.poll(now + Duration::from_secs(2), &mut out),
+        Some(Event::Fallback(b"hello".to_vec()))
+    );
+    assert!(out.is_empty());
+}
+
+#[test]
+fn lapm_disconnect() {
+    let mut call = connected_call(LapmConfig::default(), LapmConfig::default());
+    call.ends[0].send(b"lost");
+    call.run(Duration::from_secs(200), &mut |_, chunk| chunk.clear());
+    assert_eq!(
+        call.events[0][1],
+        Event::Disconnected("no acknowledgement from the peer".to_string())
+    );
+    assert!(!call.ends[0].is_connected());
+}
@@ -0,0 +1,148 @@
+use modem::v42bis::{CompressionSettings, Decoder, Encoder, V42bisConfig};
+use rand::{Rng, SeedableRng};
+
+const WORDS: [&str; 12] = [
+    "the ",
+    "modem ",
+    "sends ",
+    "data ",
+    "over ",
+    "a ",
+    "slow ",
+    "line ",
+    "and ",
+    "text ",
+    "compresses ",
+    "well\r\n",
+];
+
+fn text(len: usize) -> Vec<u8> {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(1);
+    let mut text = vec![];
+    while text.len() < len {
+        text.extend(WORDS[gen.gen_range(0..WORDS.len())].bytes());
+    }
+    text.truncate(len);
+    text
+}
+
+fn noise(len: usize) -> Vec<u8> {
+    let mut gen = rand_pcg::Pcg32::seed_from_u64(2);
+    (0..len).map(|_| gen.gen()).collect()
+}
+
+/// Sends `data` through an encoder and a decoder, in chunks of `chunk`
+/// bytes each followed by a flush, returning what went over the link.
+fn round_trip(config: V42bisConfig, data: &[u8], chunk: usize) -> (Encoder, Vec<u8>) {
+    let mut encoder = Encoder::new(&config);
+    let mut decoder = Decoder::new(&config);
+    let mut line = vec![];
+    let mut received = vec![];
+    for chunk in data.chunks(chunk) {
+        let mut out = vec![];
+        encoder.put(chunk, &mut out);
+        encoder.flush(&mut out);
+        decoder.put(&out, &mut received);
+        line.extend(out);
+    }
+    assert_eq!(received, data);
+    assert_eq!(encoder.counters().characters, data.len() as u64);
+    assert_eq!(encoder.counters().bytes, line.len() as u64);
+    assert_eq!(decoder.counters(), encoder.counters());
+    (encoder, line)
+}
+
+#[test]
+fn v42bis_text() {
+    let data = text(20000);
+    let (encoder, line) = round_trip(V42bisConfig::default(), &data, 1000);
+    assert!(encoder.is_compressed());
+    assert!(encoder.counters().ratio() > 2., "{:?}", encoder.counters());
+    assert!(line.len() < data.len() / 2);
+}
+
+#[test]
+fn v42bis_noise() {
+    let data = noise(20000);
+    let (encoder, line) = round_trip(V42bisConfig::default(), &data, 1000);
+    // transparent, with a few escapes
+    assert!(!encoder.is_compressed());
+    assert!(line.len() < data.len() + data.len() / 100);
+}
+
+#[test]
+fn v42bis_escape() {
+    // the escape character, and the values it takes in turn
+    let data: Vec<u8> = (0..200).map(|i| (i % 6 * 51) as u8).collect();
+    let (encoder, _) = round_trip(V42bisConfig::default(), &data, 7);
+    assert!(!encoder.is_compressed());
+}
+
+#[test]
+fn v42bis_mode_switching() {
+    let mut data = vec![];
+    for _ in 0..4 {
+        data.extend(text(3000));
+        data.extend(noise(3000));
+    }
+    let mut encoder = Encoder::new(&V42bisConfig::default());
+    let mut decoder = Decoder::new(&V42bisConfig::default());
+    let mut received = vec![];
+    let mut modes = vec![];
+    for chunk in data.chunks(500) {
+        let mut out = vec![];
+        encoder.put(chunk, &mut out);
+        modes.push(encoder.is_compressed());
+        decoder.put(&out, &mut received);
+    }
+    let mut out = vec![];
+    encoder.flush(&mut out);
+    decoder.put(&out, &mut received);
+    assert_eq!(received, data);
+    let switches = modes.windows(2).filter(|pair| pair[0] != pair[1]).count();
+    assert!(switches >= 6, "{:?}", modes);
+}
+
+#[test]
+fn v42bis_small_dictionary() {
+    // the dictionary fills up and its leaves are reused
+    let config = V42bisConfig {
+        dictionary_size: 512,
+        max_string: 6,
+    };
+    let mut data = text(50000);
+    data.extend(noise(5000));
+    data.extend(text(50000));
+    let (encoder, _) = round_trip(config, &data, 4096);
+    assert!(encoder.counters().ratio() > 1.2, "{:?}", encoder.counters());
+}
+
+#[test]
+fn v42bis_typing() {
+    // each character flushed on its own, as when typed by hand
+    let data = text(2000);
+    round_trip(V42bisConfig::default(), &data, 1);
+    round_trip(V42bisConfig::default(), &data, 3);
+}
+
+#[test]
+fn v42bis_settings() {
+    let mut settings = CompressionSettings::default();
+    assert_eq!(settings.report(), "+DS: 3,0,2048,32");
+    assert!(settings.update([None, Some(1), Some(4096), None]));
+    assert_eq!(settings.report(), "+DS: 3,1,4096,32");
+    assert_eq!(
+        settings.offer(),
+        Some(V42bisConfig {
+            dictionary_size: 4096,
+            max_string: 32
+        })
+    );
+    // one direction only, a dictionary too small, strings too long
+    assert!(!settings.update([Some(1), None, None, None]));
+    assert!(!settings.update([None, None, Some(256), None]));
+    assert!(!settings.update([Some(0), None, None, Some(300)]));
+    assert!(settings.update([Some(0), None, None, None]));
+    assert_eq!(settings.report(), "+DS: 0,1,4096,32");
+    assert_eq!(settings.offer(), None);
+}